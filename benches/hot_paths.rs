@@ -0,0 +1,331 @@
+//! Benchmarks for request hot paths.
+//!
+//! Handlers are driven directly against in-memory stores, skipping HTTP, so
+//! the numbers track handler and store cost. Run with `cargo bench`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bytes::Bytes;
+use futures::StreamExt;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use azurite_rs::auth::{build_string_to_sign, sign_string, validate_shared_key};
+use azurite_rs::context::{format_http_date, RequestContext};
+use azurite_rs::handlers;
+use azurite_rs::models::{BlobModel, BlobType, ContainerModel, ExtentChunk};
+use azurite_rs::storage::MetadataState;
+use azurite_rs::{
+    Config, ExtentStore, MemoryExtentStore, MemoryMetadataStore, MetadataStore, DEFAULT_ACCOUNT,
+    DEFAULT_ACCOUNT_KEY,
+};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Counts heap allocations, for benchmarks that report them.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Builds the context of a request to `/{account}/{container}[/{blob}]`.
+fn context(
+    method: Method,
+    container: &str,
+    blob: Option<&str>,
+    query: &[(&str, &str)],
+    headers: &[(&'static str, String)],
+) -> RequestContext {
+    let mut path = format!("/{}/{}", DEFAULT_ACCOUNT, container);
+    if let Some(blob) = blob {
+        path.push('/');
+        path.push_str(blob);
+    }
+    if !query.is_empty() {
+        let query: Vec<String> = query.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        path.push('?');
+        path.push_str(&query.join("&"));
+    }
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert("x-ms-version", HeaderValue::from_static("2021-10-04"));
+    header_map.insert("x-ms-date", HeaderValue::from_str(&format_http_date(&chrono::Utc::now())).unwrap());
+    for (name, value) in headers {
+        header_map.insert(HeaderName::from_static(name), HeaderValue::from_str(value).unwrap());
+    }
+
+    let mut path_params = HashMap::new();
+    path_params.insert("account".to_string(), DEFAULT_ACCOUNT.to_string());
+    path_params.insert("container".to_string(), container.to_string());
+    if let Some(blob) = blob {
+        path_params.insert("blob".to_string(), blob.to_string());
+    }
+    let query_pairs = query.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+
+    RequestContext::new(method, path.parse::<Uri>().unwrap(), header_map, path_params, query_pairs).unwrap()
+}
+
+/// A metadata store holding one empty container named `container`.
+fn store_with_container(container: &str) -> MetadataState {
+    MetadataState {
+        containers: vec![ContainerModel::new(DEFAULT_ACCOUNT.to_string(), container.to_string(), chrono::Utc::now())],
+        ..MetadataState::default()
+    }
+}
+
+fn put_block_blob(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let data = Bytes::from(vec![0x5a; 4 * MIB as usize]);
+    let ctx = context(
+        Method::PUT,
+        "bench",
+        Some("upload.bin"),
+        &[],
+        &[("x-ms-blob-type", "BlockBlob".to_string())],
+    );
+
+    let mut group = c.benchmark_group("put_block_blob");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("4MiB", |b| {
+        // Fresh stores per iteration so extents do not pile up
+        b.to_async(&runtime).iter_batched(
+            || {
+                let metadata: Arc<dyn MetadataStore> =
+                    Arc::new(MemoryMetadataStore::from_state(store_with_container("bench")));
+                let extents: Arc<dyn ExtentStore> = Arc::new(MemoryExtentStore::new());
+                (metadata, extents, data.clone())
+            },
+            |(metadata, extents, data)| {
+                let ctx = &ctx;
+                async move { handlers::upload_block_blob(ctx, metadata, extents, data).await.unwrap() }
+            },
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+}
+
+fn stage_blocks(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let block_size = 64 * 1024;
+    let ctxs: Vec<RequestContext> = (0..1000)
+        .map(|i| {
+            let block_id = BASE64.encode(format!("block-{:06}", i));
+            context(
+                Method::PUT,
+                "bench",
+                Some("staged.bin"),
+                &[("comp", "block"), ("blockid", &block_id)],
+                &[("content-length", block_size.to_string())],
+            )
+        })
+        .collect();
+    // Every block arrives in its own request buffer
+    let setup = || {
+        let metadata: Arc<dyn MetadataStore> = Arc::new(MemoryMetadataStore::from_state(store_with_container("bench")));
+        let extents: Arc<dyn ExtentStore> = Arc::new(MemoryExtentStore::new());
+        let bodies: Vec<Bytes> = (0..ctxs.len()).map(|_| Bytes::from(vec![0x3c; block_size])).collect();
+        (metadata, extents, bodies)
+    };
+    let upload = |metadata: Arc<dyn MetadataStore>, extents: Arc<dyn ExtentStore>, bodies: Vec<Bytes>| {
+        let ctxs = &ctxs;
+        async move {
+            for (ctx, body) in ctxs.iter().zip(bodies) {
+                handlers::stage_block(ctx, metadata.clone(), extents.clone(), body).await.unwrap();
+            }
+        }
+    };
+
+    // What the upload allocates, and what is still held once the request
+    // buffers are gone
+    let (metadata, extents, bodies) = setup();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let live = LIVE_ALLOCATIONS.load(Ordering::Relaxed);
+    runtime.block_on(upload(metadata.clone(), extents.clone(), bodies));
+    println!(
+        "stage_blocks/1000x64KiB: {} allocations, {} more live afterwards",
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        LIVE_ALLOCATIONS.load(Ordering::Relaxed) as isize - live as isize,
+    );
+    drop((metadata, extents));
+
+    let mut group = c.benchmark_group("stage_blocks");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(ctxs.len() as u64 * block_size as u64));
+    group.bench_function("1000x64KiB", |b| {
+        b.to_async(&runtime).iter_batched(
+            setup,
+            |(metadata, extents, bodies)| upload(metadata, extents, bodies),
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+}
+
+/// Consumes a response body as sent, without joining it.
+async fn body_length(response: axum::response::Response) -> usize {
+    let mut body = response.into_body().into_data_stream();
+    let mut length = 0;
+    while let Some(frame) = body.next().await {
+        length += frame.unwrap().len();
+    }
+    length
+}
+
+fn ranged_get(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let chunk_size = 4 * MIB;
+    let blob_size = 1024 * MIB;
+
+    // Every chunk of the 1 GiB blob references the same 4 MiB extent
+    let extents = Arc::new(MemoryExtentStore::new());
+    let extent = runtime
+        .block_on(extents.write(Bytes::from(vec![0xa5; chunk_size as usize])))
+        .unwrap();
+    let mut blob = BlobModel::new(
+        DEFAULT_ACCOUNT.to_string(),
+        "bench".to_string(),
+        "large.bin".to_string(),
+        BlobType::BlockBlob,
+        blob_size,
+        chrono::Utc::now(),
+    );
+    blob.extent_chunks = (0..blob_size / chunk_size)
+        .map(|_| ExtentChunk::new(extent.id.clone(), 0, chunk_size))
+        .collect();
+    let mut state = store_with_container("bench");
+    state.blobs.push(blob);
+    let metadata: Arc<dyn MetadataStore> = Arc::new(MemoryMetadataStore::from_state(state));
+    let extents: Arc<dyn ExtentStore> = extents;
+
+    // 64 KiB from the middle of the blob, spanning a chunk boundary
+    let start = blob_size / 2 - 32 * 1024;
+    let end = start + 64 * 1024 - 1;
+    let ctx = context(
+        Method::GET,
+        "bench",
+        Some("large.bin"),
+        &[],
+        &[("x-ms-range", format!("bytes={}-{}", start, end))],
+    );
+
+    // 64 MiB starting and ending inside chunks
+    let large_start = blob_size / 2 - 3 * MIB / 2;
+    let large_ctx = context(
+        Method::GET,
+        "bench",
+        Some("large.bin"),
+        &[],
+        &[("x-ms-range", format!("bytes={}-{}", large_start, large_start + 64 * MIB - 1))],
+    );
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let response = runtime.block_on(handlers::download_blob(&large_ctx, metadata.clone(), extents.clone())).unwrap();
+    assert_eq!(runtime.block_on(body_length(response)), 64 * MIB as usize);
+    println!(
+        "ranged_get/64MiB_of_1GiB: {} allocations, {} bytes allocated",
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated,
+    );
+
+    let mut group = c.benchmark_group("ranged_get");
+    group.throughput(Throughput::Bytes(64 * 1024));
+    group.bench_function("64KiB_of_1GiB", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let response = handlers::download_blob(&ctx, metadata.clone(), extents.clone()).await.unwrap();
+            axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+        });
+    });
+    group.throughput(Throughput::Bytes(64 * MIB));
+    group.bench_function("64MiB_of_1GiB", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let response = handlers::download_blob(&large_ctx, metadata.clone(), extents.clone()).await.unwrap();
+            body_length(response).await
+        });
+    });
+    group.finish();
+}
+
+fn list_blobs(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let entries = 100_000;
+
+    let mut state = store_with_container("bench");
+    state.blobs = (0..entries)
+        .map(|i| {
+            BlobModel::new(
+                DEFAULT_ACCOUNT.to_string(),
+                "bench".to_string(),
+                format!("dir{}/blob{:06}", i % 10, i),
+                BlobType::BlockBlob,
+                0,
+                chrono::Utc::now(),
+            )
+        })
+        .collect();
+    let metadata: Arc<dyn MetadataStore> = Arc::new(MemoryMetadataStore::from_state(state));
+
+    let ctx = context(
+        Method::GET,
+        "bench",
+        None,
+        &[("restype", "container"), ("comp", "list"), ("prefix", "dir3/")],
+        &[],
+    );
+
+    let mut group = c.benchmark_group("list_blobs");
+    group.sample_size(20);
+    group.bench_function("100k_with_prefix", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let response = handlers::list_blobs(&ctx, metadata.clone()).await.unwrap();
+            axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+        });
+    });
+    group.finish();
+}
+
+fn shared_key(c: &mut Criterion) {
+    let config = Config::default();
+    let mut ctx = context(
+        Method::PUT,
+        "bench",
+        Some("signed.bin"),
+        &[("comp", "block"), ("blockid", "YmxvY2sx")],
+        &[("content-length", "1024".to_string())],
+    );
+    let signature = sign_string(&build_string_to_sign(&ctx).unwrap(), DEFAULT_ACCOUNT_KEY).unwrap();
+    ctx.headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("SharedKey {}:{}", DEFAULT_ACCOUNT, signature)).unwrap(),
+    );
+
+    c.bench_function("shared_key_validation", |b| {
+        b.iter(|| validate_shared_key(&ctx, &config).unwrap());
+    });
+}
+
+criterion_group!(benches, put_block_blob, stage_blocks, ranged_get, list_blobs, shared_key);
+criterion_main!(benches);
@@ -72,7 +72,7 @@ impl AccountSasParameters {
         ctx: &RequestContext,
         config: &Config,
         resource_type: char,
-        required_permission: char,
+        required_permissions: &str,
     ) -> StorageResult<()> {
         // Check if blob service is allowed
         if !self.signed_services.contains('b') {
@@ -87,14 +87,17 @@ impl AccountSasParameters {
         }
 
         // Check permission
-        if !self.signed_permissions.contains(required_permission) {
+        if !required_permissions
+            .chars()
+            .any(|p| self.signed_permissions.contains(p))
+        {
             return Err(StorageError::new(
                 ErrorCode::AuthorizationPermissionMismatch,
             ));
         }
 
         // Check expiry
-        let now = Utc::now();
+        let now = ctx.timestamp;
         if now > self.signed_expiry {
             return Err(StorageError::with_message(
                 ErrorCode::AuthenticationFailed,
@@ -139,7 +142,10 @@ impl AccountSasParameters {
                 provided_signature,
                 string_to_sign
             );
-            return Err(StorageError::new(ErrorCode::AuthenticationFailed));
+            return Err(StorageError::authentication_failed(format!(
+                "Signature did not match. String to sign used was {}",
+                string_to_sign
+            )));
         }
 
         Ok(())
@@ -221,22 +227,3 @@ pub fn get_resource_type(ctx: &RequestContext) -> char {
         'o' // object (blob)
     }
 }
-
-/// Returns the required permission character for the request method.
-pub fn get_required_permission(ctx: &RequestContext) -> char {
-    match ctx.method.as_str() {
-        "GET" | "HEAD" => 'r', // read
-        "PUT" => {
-            if ctx.comp() == Some("block") || ctx.comp() == Some("appendblock") {
-                'a' // add (for staging blocks)
-            } else if ctx.blob.is_some() && ctx.query_params.is_empty() {
-                'c' // create
-            } else {
-                'w' // write
-            }
-        }
-        "DELETE" => 'd', // delete
-        "POST" => 'w',   // write
-        _ => 'r',
-    }
-}
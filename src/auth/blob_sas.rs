@@ -7,9 +7,11 @@ use sha2::Sha256;
 use std::collections::HashMap;
 
 use crate::config::Config;
-use crate::context::RequestContext;
+use crate::context::{RequestContext, ResponseHeaderOverrides};
 use crate::error::{ErrorCode, StorageError, StorageResult};
 
+use super::user_delegation::{UserDelegationKeyRegistry, UserDelegationSasFields};
+
 type HmacSha256 = Hmac<Sha256>;
 
 /// Blob/Container SAS token parameters.
@@ -41,6 +43,8 @@ pub struct BlobSasParameters {
     pub content_language: Option<String>,
     /// Content-Type override (rsct) - optional.
     pub content_type: Option<String>,
+    /// User delegation key parameters (sk*) - present for user delegation SAS.
+    pub user_delegation: Option<UserDelegationSasFields>,
     /// Signature (sig).
     pub signature: String,
 }
@@ -67,6 +71,7 @@ impl BlobSasParameters {
         let content_encoding = params.get("rsce").cloned();
         let content_language = params.get("rscl").cloned();
         let content_type = params.get("rsct").cloned();
+        let user_delegation = UserDelegationSasFields::from_query(params);
         let signature = params.get("sig")?.clone();
 
         Some(Self {
@@ -83,16 +88,29 @@ impl BlobSasParameters {
             content_encoding,
             content_language,
             content_type,
+            user_delegation,
             signature,
         })
     }
 
+    /// Returns the response header overrides carried by this token.
+    pub fn response_overrides(&self) -> ResponseHeaderOverrides {
+        ResponseHeaderOverrides {
+            cache_control: self.cache_control.clone(),
+            content_disposition: self.content_disposition.clone(),
+            content_encoding: self.content_encoding.clone(),
+            content_language: self.content_language.clone(),
+            content_type: self.content_type.clone(),
+        }
+    }
+
     /// Validates the blob SAS token.
     pub fn validate(
         &self,
         ctx: &RequestContext,
         config: &Config,
-        required_permission: char,
+        delegation_keys: &UserDelegationKeyRegistry,
+        required_permissions: &str,
     ) -> StorageResult<()> {
         // Check resource type matches request
         match self.signed_resource.as_str() {
@@ -115,14 +133,17 @@ impl BlobSasParameters {
         }
 
         // Check permission
-        if !self.signed_permissions.contains(required_permission) {
+        if !required_permissions
+            .chars()
+            .any(|p| self.signed_permissions.contains(p))
+        {
             return Err(StorageError::new(
                 ErrorCode::AuthorizationPermissionMismatch,
             ));
         }
 
         // Check expiry
-        let now = Utc::now();
+        let now = ctx.timestamp;
         if now > self.signed_expiry {
             return Err(StorageError::with_message(
                 ErrorCode::AuthenticationFailed,
@@ -141,19 +162,52 @@ impl BlobSasParameters {
         }
 
         // Validate signature
-        self.validate_signature(ctx, config)?;
+        self.validate_signature(ctx, config, delegation_keys)?;
 
         Ok(())
     }
 
-    /// Validates the signature.
-    fn validate_signature(&self, ctx: &RequestContext, config: &Config) -> StorageResult<()> {
-        let account_key = config
-            .get_account_key(&ctx.account)
-            .ok_or_else(|| StorageError::new(ErrorCode::AuthorizationFailure))?;
+    /// Validates the signature, using the account key for a service SAS or
+    /// the issued delegation key for a user delegation SAS.
+    fn validate_signature(
+        &self,
+        ctx: &RequestContext,
+        config: &Config,
+        delegation_keys: &UserDelegationKeyRegistry,
+    ) -> StorageResult<()> {
+        let (string_to_sign, expected_signature) = match self.user_delegation {
+            Some(ref fields) => {
+                let key = delegation_keys.get(&ctx.account, fields).ok_or_else(|| {
+                    StorageError::with_message(
+                        ErrorCode::AuthenticationFailed,
+                        "The user delegation key used to sign the SAS was not issued by this server",
+                    )
+                })?;
+
+                let key_expiry = parse_sas_datetime(&key.signed_expiry).ok_or_else(|| {
+                    StorageError::new(ErrorCode::AuthenticationFailed)
+                })?;
+                if ctx.timestamp > key_expiry {
+                    return Err(StorageError::with_message(
+                        ErrorCode::AuthenticationFailed,
+                        "The user delegation key has expired",
+                    ));
+                }
 
-        let string_to_sign = self.build_string_to_sign(ctx);
-        let expected_signature = compute_signature(&string_to_sign, account_key)?;
+                let string_to_sign = self.build_user_delegation_string_to_sign(ctx, fields);
+                let signature = compute_signature(&string_to_sign, &key.value)?;
+                (string_to_sign, signature)
+            }
+            None => {
+                let account_key = config
+                    .get_account_key(&ctx.account)
+                    .ok_or_else(|| StorageError::new(ErrorCode::AuthorizationFailure))?;
+
+                let string_to_sign = self.build_string_to_sign(ctx);
+                let signature = compute_signature(&string_to_sign, account_key)?;
+                (string_to_sign, signature)
+            }
+        };
 
         // URL-decode the provided signature for comparison
         let provided_signature = percent_encoding::percent_decode_str(&self.signature)
@@ -176,7 +230,10 @@ impl BlobSasParameters {
                 provided_signature,
                 string_to_sign
             );
-            return Err(StorageError::new(ErrorCode::AuthenticationFailed));
+            return Err(StorageError::authentication_failed(format!(
+                "Signature did not match. String to sign used was {}",
+                string_to_sign
+            )));
         }
 
         tracing::debug!("BLOB SAS: Signature validated successfully");
@@ -221,6 +278,46 @@ impl BlobSasParameters {
         parts.join("\n")
     }
 
+    /// Builds the string-to-sign for a user delegation SAS.
+    fn build_user_delegation_string_to_sign(
+        &self,
+        ctx: &RequestContext,
+        fields: &UserDelegationSasFields,
+    ) -> String {
+        let parts = [
+            self.signed_permissions.clone(),
+            self.signed_start
+                .map(|dt| format_sas_datetime(&dt))
+                .unwrap_or_default(),
+            format_sas_datetime(&self.signed_expiry),
+            self.build_canonicalized_resource(ctx),
+            fields.signed_oid.clone(),
+            fields.signed_tid.clone(),
+            fields.signed_key_start.clone(),
+            fields.signed_key_expiry.clone(),
+            fields.signed_key_service.clone(),
+            fields.signed_key_version.clone(),
+            fields.authorized_oid.clone().unwrap_or_default(),
+            fields.unauthorized_oid.clone().unwrap_or_default(),
+            fields.correlation_id.clone().unwrap_or_default(),
+            self.signed_ip.clone().unwrap_or_default(),
+            self.signed_protocol.clone().unwrap_or_default(),
+            self.signed_version.clone(),
+            self.signed_resource.clone(),
+            // Snapshot time
+            String::new(),
+            // Encryption scope
+            String::new(),
+            self.cache_control.clone().unwrap_or_default(),
+            self.content_disposition.clone().unwrap_or_default(),
+            self.content_encoding.clone().unwrap_or_default(),
+            self.content_language.clone().unwrap_or_default(),
+            self.content_type.clone().unwrap_or_default(),
+        ];
+
+        parts.join("\n")
+    }
+
     /// Builds the canonicalized resource for blob SAS.
     fn build_canonicalized_resource(&self, ctx: &RequestContext) -> String {
         let mut resource = format!("/blob/{}", ctx.account);
@@ -283,24 +380,3 @@ fn compute_signature(string_to_sign: &str, account_key: &str) -> StorageResult<S
 
     Ok(BASE64.encode(result.into_bytes()))
 }
-
-/// Returns the required permission character for the blob operation.
-pub fn get_blob_required_permission(ctx: &RequestContext) -> char {
-    match ctx.method.as_str() {
-        "GET" | "HEAD" => 'r', // read
-        "PUT" => {
-            if ctx.comp() == Some("block") || ctx.comp() == Some("appendblock") {
-                'a' // add
-            } else if ctx.comp() == Some("blocklist") {
-                'w' // write
-            } else if ctx.copy_source().is_some() {
-                'w' // write (copy)
-            } else {
-                'c' // create
-            }
-        }
-        "DELETE" => 'd', // delete
-        "POST" => 'w',   // write
-        _ => 'r',
-    }
-}
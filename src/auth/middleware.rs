@@ -3,13 +3,15 @@
 use std::sync::Arc;
 
 use crate::config::Config;
-use crate::context::RequestContext;
-use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::context::{RequestContext, ResponseHeaderOverrides};
+use crate::error::{StorageError, StorageResult};
 
 use super::{
-    account_sas::{get_required_permission, get_resource_type, AccountSasParameters},
-    blob_sas::{get_blob_required_permission, BlobSasParameters},
+    account_sas::{get_resource_type, AccountSasParameters},
+    blob_sas::BlobSasParameters,
+    permissions::required_permissions,
     shared_key::validate_shared_key,
+    user_delegation::UserDelegationKeyRegistry,
 };
 
 /// Authentication result containing the authenticated account.
@@ -17,10 +19,68 @@ use super::{
 pub struct AuthResult {
     pub account: String,
     pub is_anonymous: bool,
+    /// Permissions granted by a validated SAS token (None for SharedKey or
+    /// anonymous requests).
+    pub sas_permissions: Option<String>,
+    /// Response header overrides from a validated blob/container SAS.
+    pub response_overrides: ResponseHeaderOverrides,
+}
+
+/// A replacement for [`authenticate`], installed with
+/// [`crate::BlobServerBuilder::with_authenticator`]. A request it accepts
+/// is handled as if signed with the account key; its error is returned to
+/// the client.
+pub type Authenticator = Arc<dyn Fn(&RequestContext, &Config) -> StorageResult<()> + Send + Sync>;
+
+/// A credential found on a request.
+enum Credential {
+    AccountSas(AccountSasParameters),
+    /// A blob or container SAS, signed with the account key or a user
+    /// delegation key.
+    BlobSas(Box<BlobSasParameters>),
+    SharedKey,
+}
+
+impl Credential {
+    /// Name reported in the `AuthenticationMechanism` error detail.
+    fn mechanism(&self) -> &'static str {
+        match self {
+            Credential::AccountSas(_) => "AccountSas",
+            Credential::BlobSas(sas) if sas.user_delegation.is_some() => "UserDelegationSas",
+            Credential::BlobSas(_) => "ServiceSas",
+            Credential::SharedKey => "SharedKey",
+        }
+    }
+}
+
+/// Lists the credentials a request carries, in the order they are tried: a
+/// SAS token first, since the URL it is part of was issued deliberately,
+/// while some SDK pipelines add a placeholder Authorization header to every
+/// request.
+fn credentials(ctx: &RequestContext) -> Vec<Credential> {
+    let mut credentials = Vec::new();
+    if let Some(account_sas) = AccountSasParameters::from_query(&ctx.query_params) {
+        credentials.push(Credential::AccountSas(account_sas));
+    } else if let Some(blob_sas) = BlobSasParameters::from_query(&ctx.query_params) {
+        credentials.push(Credential::BlobSas(Box::new(blob_sas)));
+    }
+    if ctx.header("authorization").is_some() {
+        credentials.push(Credential::SharedKey);
+    }
+    credentials
 }
 
 /// Authenticates a request using available authentication methods.
-pub fn authenticate(ctx: &RequestContext, config: &Config) -> StorageResult<AuthResult> {
+///
+/// Each credential the request carries is tried in turn and the first valid
+/// one wins. If none is, the error is that of the first credential, with the
+/// mechanism tried in its `AuthenticationMechanism` detail. Requests without
+/// credentials are anonymous.
+pub fn authenticate(
+    ctx: &RequestContext,
+    config: &Config,
+    delegation_keys: &UserDelegationKeyRegistry,
+) -> StorageResult<AuthResult> {
     // Log all incoming requests for debugging
     tracing::debug!(
         "AUTH REQUEST: method={} account={} container={:?} blob={:?}",
@@ -31,47 +91,23 @@ pub fn authenticate(ctx: &RequestContext, config: &Config) -> StorageResult<Auth
     );
     tracing::debug!("AUTH QUERY PARAMS: {:?}", ctx.query_params);
 
-    // Check for Authorization header (SharedKey)
-    if ctx.header("authorization").is_some() {
-        tracing::debug!("AUTH: Using SharedKey authentication");
-        validate_shared_key(ctx, config)?;
-        return Ok(AuthResult {
-            account: ctx.account.clone(),
-            is_anonymous: false,
-        });
-    }
-
-    // Check for Account SAS token
-    if let Some(account_sas) = AccountSasParameters::from_query(&ctx.query_params) {
-        tracing::debug!("AUTH: Found Account SAS token");
-        let resource_type = get_resource_type(ctx);
-        let required_permission = get_required_permission(ctx);
-        account_sas.validate(ctx, config, resource_type, required_permission)?;
-        return Ok(AuthResult {
-            account: ctx.account.clone(),
-            is_anonymous: false,
-        });
+    let credentials = credentials(ctx);
+    let mut first_error = None;
+    for credential in &credentials {
+        tracing::debug!("AUTH: Trying {} authentication", credential.mechanism());
+        match validate_credential(ctx, config, delegation_keys, credential) {
+            Ok(auth) => return Ok(auth),
+            Err(e) => {
+                tracing::debug!("AUTH: {} authentication failed - {}", credential.mechanism(), e.message);
+                first_error.get_or_insert(e.with_detail("AuthenticationMechanism", credential.mechanism()));
+            }
+        }
     }
-
-    // Check for Blob SAS token
-    if let Some(blob_sas) = BlobSasParameters::from_query(&ctx.query_params) {
-        tracing::debug!(
-            "AUTH: Found Blob SAS token - sr={} sp={} se={} sig={}",
-            blob_sas.signed_resource,
-            blob_sas.signed_permissions,
-            blob_sas.signed_expiry,
-            &blob_sas.signature[..20.min(blob_sas.signature.len())]
-        );
-        let required_permission = get_blob_required_permission(ctx);
-        tracing::debug!("AUTH: Required permission: {}", required_permission);
-        blob_sas.validate(ctx, config, required_permission)?;
-        return Ok(AuthResult {
-            account: ctx.account.clone(),
-            is_anonymous: false,
-        });
+    if let Some(e) = first_error {
+        return Err(e);
     }
 
-    tracing::debug!("AUTH: No SAS token found in query params, checking anonymous access");
+    tracing::debug!("AUTH: No credentials found, checking anonymous access");
 
     // Check if account exists (for anonymous access)
     if config.get_account_key(&ctx.account).is_some() {
@@ -79,11 +115,65 @@ pub fn authenticate(ctx: &RequestContext, config: &Config) -> StorageResult<Auth
         return Ok(AuthResult {
             account: ctx.account.clone(),
             is_anonymous: true,
+            sas_permissions: None,
+            response_overrides: ResponseHeaderOverrides::default(),
         });
     }
 
-    // No valid authentication
-    Err(StorageError::new(ErrorCode::AuthenticationFailed))
+    Err(StorageError::authentication_failed(format!(
+        "No credentials were found in the request, and anonymous requests need a known account; '{}' is not one.",
+        ctx.account
+    ))
+    .with_detail("AuthenticationMechanism", "Anonymous"))
+}
+
+/// Validates a single credential found by [`credentials`].
+fn validate_credential(
+    ctx: &RequestContext,
+    config: &Config,
+    delegation_keys: &UserDelegationKeyRegistry,
+    credential: &Credential,
+) -> StorageResult<AuthResult> {
+    match credential {
+        Credential::AccountSas(account_sas) => {
+            let resource_type = get_resource_type(ctx);
+            let required = required_permissions(ctx);
+            account_sas.validate(ctx, config, resource_type, required)?;
+            Ok(AuthResult {
+                account: ctx.account.clone(),
+                is_anonymous: false,
+                sas_permissions: Some(account_sas.signed_permissions.clone()),
+                response_overrides: ResponseHeaderOverrides::default(),
+            })
+        }
+        Credential::BlobSas(blob_sas) => {
+            tracing::debug!(
+                "AUTH: Blob SAS token - sr={} sp={} se={} sig={}",
+                blob_sas.signed_resource,
+                blob_sas.signed_permissions,
+                blob_sas.signed_expiry,
+                &blob_sas.signature[..20.min(blob_sas.signature.len())]
+            );
+            let required = required_permissions(ctx);
+            tracing::debug!("AUTH: Required permissions (any of): {}", required);
+            blob_sas.validate(ctx, config, delegation_keys, required)?;
+            Ok(AuthResult {
+                account: ctx.account.clone(),
+                is_anonymous: false,
+                sas_permissions: Some(blob_sas.signed_permissions.clone()),
+                response_overrides: blob_sas.response_overrides(),
+            })
+        }
+        Credential::SharedKey => {
+            validate_shared_key(ctx, config)?;
+            Ok(AuthResult {
+                account: ctx.account.clone(),
+                is_anonymous: false,
+                sas_permissions: None,
+                response_overrides: ResponseHeaderOverrides::default(),
+            })
+        }
+    }
 }
 
 /// Checks if a request requires authentication.
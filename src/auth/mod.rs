@@ -3,9 +3,13 @@
 mod account_sas;
 mod blob_sas;
 mod middleware;
+mod permissions;
 mod shared_key;
+mod user_delegation;
 
 pub use account_sas::*;
 pub use blob_sas::*;
 pub use middleware::*;
+pub use permissions::*;
 pub use shared_key::*;
+pub use user_delegation::*;
@@ -0,0 +1,90 @@
+//! Operation to SAS permission mapping for Azure Blob Storage API.
+
+use crate::context::RequestContext;
+use crate::models::PublicAccessLevel;
+use crate::operation::Operation;
+
+/// Returns the SAS permissions that authorize the request. Granting any one
+/// of the returned permissions is sufficient.
+///
+/// The same table serves account and service SAS tokens; service-level
+/// operations are only reachable with an account SAS.
+pub fn required_permissions(ctx: &RequestContext) -> &'static str {
+    if ctx.is_blob_request() {
+        blob_permissions(ctx)
+    } else if ctx.is_container_request() {
+        container_permissions(ctx)
+    } else {
+        service_permissions(ctx)
+    }
+}
+
+/// Permissions for service-level operations.
+fn service_permissions(ctx: &RequestContext) -> &'static str {
+    match (ctx.method.as_str(), ctx.comp()) {
+        ("GET", Some("list")) => "l",
+        ("GET", Some("blobs")) => "f",
+        ("POST", Some("batch")) => "d",
+        ("GET" | "HEAD", _) => "r",
+        _ => "w",
+    }
+}
+
+/// Permissions for container-level operations.
+fn container_permissions(ctx: &RequestContext) -> &'static str {
+    match (ctx.method.as_str(), ctx.comp()) {
+        ("GET", Some("list")) => "l",
+        ("GET", Some("blobs")) => "f",
+        ("GET" | "HEAD", _) => "r",
+        ("PUT", None) => "cw",
+        ("DELETE", _) => "d",
+        ("POST", Some("batch")) => "d",
+        _ => "w",
+    }
+}
+
+/// Permissions for blob-level operations.
+///
+/// Operations that may create a new blob accept either create ('c') or write
+/// ('w'); overwriting an existing blob with only 'c' is rejected by the
+/// handler, which knows whether the blob exists.
+fn blob_permissions(ctx: &RequestContext) -> &'static str {
+    match (ctx.method.as_str(), ctx.comp()) {
+        ("GET" | "HEAD", Some("tags")) => "t",
+        ("GET" | "HEAD", _) => "r",
+        ("DELETE", _) => {
+            if ctx.version_id().is_some() {
+                "x"
+            } else if ctx.query_param("deletetype") == Some("permanent") {
+                "y"
+            } else {
+                "d"
+            }
+        }
+        ("PUT", None | Some("block") | Some("blocklist") | Some("snapshot")) => "cw",
+        ("PUT", Some("appendblock")) => "aw",
+        ("PUT", Some("tags")) => "t",
+        ("PUT", Some("immutabilitypolicies") | Some("legalhold")) => "i",
+        ("POST", Some("query")) => "r",
+        _ => "w",
+    }
+}
+
+/// Returns whether a container's public access level lets anonymous
+/// requests perform `operation`.
+///
+/// `blob` grants reads of blobs only; `container` additionally grants List
+/// Blobs and reads of the container's properties. Private containers grant
+/// nothing.
+pub fn public_access_allows(level: PublicAccessLevel, operation: Operation) -> bool {
+    let blob_read = matches!(
+        operation,
+        Operation::GetBlob | Operation::GetBlobProperties | Operation::GetBlockList | Operation::GetPageRanges
+    );
+    let container_read = matches!(operation, Operation::ListBlobs | Operation::GetContainerProperties);
+    match level {
+        PublicAccessLevel::None => false,
+        PublicAccessLevel::Blob => blob_read,
+        PublicAccessLevel::Container => blob_read || container_read,
+    }
+}
@@ -1,8 +1,9 @@
 //! SharedKey authentication for Azure Blob Storage API.
 
+use axum::http::StatusCode;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Duration;
 use hmac::{Hmac, Mac};
-use percent_encoding;
 use sha2::Sha256;
 use std::collections::BTreeMap;
 
@@ -12,6 +13,12 @@ use crate::error::{ErrorCode, StorageError, StorageResult};
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// Maximum allowed difference between the request date and server time.
+const MAX_CLOCK_SKEW_MINUTES: i64 = 15;
+
+/// First API version that signs a zero Content-Length as an empty string.
+const CONTENT_LENGTH_EMPTY_VERSION: &str = "2015-02-21";
+
 /// Validates SharedKey authentication.
 pub fn validate_shared_key(
     ctx: &RequestContext,
@@ -27,12 +34,19 @@ pub fn validate_shared_key(
         .ok_or_else(|| StorageError::new(ErrorCode::AuthenticationFailed))?;
 
     if scheme != "SharedKey" && scheme != "SharedKeyLite" {
-        return Err(StorageError::new(ErrorCode::AuthenticationFailed));
+        return Err(StorageError::authentication_failed(format!(
+            "Unsupported authorization scheme '{}'.",
+            scheme
+        )));
     }
 
     let (account, provided_signature) = credentials
         .split_once(':')
-        .ok_or_else(|| StorageError::new(ErrorCode::AuthenticationFailed))?;
+        .ok_or_else(|| {
+            StorageError::authentication_failed(
+                "The Authorization header must be in the form 'SharedKey account:signature'.",
+            )
+        })?;
 
     // Verify account matches
     if account != ctx.account {
@@ -62,14 +76,60 @@ pub fn validate_shared_key(
             string_to_sign,
             string_to_sign
         );
-        return Err(StorageError::new(ErrorCode::AuthenticationFailed));
+        return Err(StorageError::authentication_failed(format!(
+            "The MAC signature found in the HTTP request '{}' is not the same as any computed \
+             signature. Server used following string to sign: '{}'.",
+            provided_signature, string_to_sign
+        )));
+    }
+
+    if !config.loose {
+        check_clock_skew(ctx)?;
+    }
+
+    Ok(())
+}
+
+/// Rejects requests whose x-ms-date (or Date) is missing or more than
+/// [`MAX_CLOCK_SKEW_MINUTES`] away from server time.
+fn check_clock_skew(ctx: &RequestContext) -> StorageResult<()> {
+    let raw_date = ctx
+        .header("x-ms-date")
+        .or_else(|| ctx.header("date"))
+        .unwrap_or("");
+
+    let date = ctx.request_date().ok_or_else(|| {
+        StorageError::with_message(
+            ErrorCode::AuthenticationFailed,
+            format!(
+                "Server failed to authenticate the request. The request must carry a valid \
+                 x-ms-date or Date header, got '{}'.",
+                raw_date
+            ),
+        )
+    })?;
+
+    let skew = ctx.timestamp.signed_duration_since(date);
+    let max_skew = Duration::minutes(MAX_CLOCK_SKEW_MINUTES);
+    if skew > max_skew || skew < -max_skew {
+        let direction = if skew > max_skew { "too old" } else { "too far in the future" };
+        // Azure answers a skewed clock with 403 rather than the usual 401
+        return Err(StorageError::with_message(
+            ErrorCode::AuthenticationFailed,
+            format!(
+                "Server failed to authenticate the request. Request date header {}: '{}'. \
+                 The request date must be within {} minutes of the server time.",
+                direction, raw_date, MAX_CLOCK_SKEW_MINUTES
+            ),
+        )
+        .with_status(StatusCode::FORBIDDEN));
     }
 
     Ok(())
 }
 
 /// Builds the string-to-sign for SharedKey authentication.
-fn build_string_to_sign(ctx: &RequestContext) -> StorageResult<String> {
+pub fn build_string_to_sign(ctx: &RequestContext) -> StorageResult<String> {
     let mut parts = Vec::new();
 
     // VERB
@@ -86,11 +146,7 @@ fn build_string_to_sign(ctx: &RequestContext) -> StorageResult<String> {
 
     for header in &content_headers {
         let value = if *header == "content-length" {
-            // Content-Length should be empty string if 0 or not present
-            match ctx.content_length() {
-                Some(0) | None => String::new(),
-                Some(len) => len.to_string(),
-            }
+            content_length_to_sign(ctx)
         } else {
             ctx.header(header).unwrap_or("").to_string()
         };
@@ -128,6 +184,22 @@ fn build_string_to_sign(ctx: &RequestContext) -> StorageResult<String> {
     Ok(format!("{}\n{}{}", headers_str, canonicalized_headers, canonicalized_resource))
 }
 
+/// Returns the Content-Length slot of the string-to-sign. Since 2015-02-21 a
+/// zero length is signed as an empty string; older versions sign the header
+/// value as sent, including "0".
+fn content_length_to_sign(ctx: &RequestContext) -> String {
+    let zero_is_empty = ctx
+        .api_version
+        .as_deref()
+        .is_none_or(|version| version >= CONTENT_LENGTH_EMPTY_VERSION);
+
+    match ctx.content_length() {
+        None => String::new(),
+        Some(0) if zero_is_empty => String::new(),
+        Some(len) => len.to_string(),
+    }
+}
+
 /// Builds the string-to-sign for SharedKeyLite authentication.
 fn build_string_to_sign_lite(ctx: &RequestContext) -> StorageResult<String> {
     let mut parts = Vec::new();
@@ -180,24 +252,24 @@ fn build_canonicalized_headers_with_trailing_newline(ctx: &RequestContext) -> St
 fn build_canonicalized_resource(ctx: &RequestContext) -> String {
     // The canonicalized resource is /{account}{path}
     // where path already includes the account (e.g., /devstoreaccount1/container)
-    // So the result is /devstoreaccount1/devstoreaccount1/container
-    let mut resource = format!("/{}{}", ctx.account, ctx.uri.path());
-
-    // Add query parameters (sorted alphabetically by lowercase key)
-    if !ctx.query_params.is_empty() {
-        let mut sorted_params: Vec<_> = ctx.query_params.iter().collect();
-        sorted_params.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
-
-        for (key, value) in sorted_params {
-            resource.push('\n');
-            resource.push_str(&key.to_lowercase());
-            resource.push(':');
-            // URL-decode the value (Azure SDK sends encoded values)
-            let decoded = percent_encoding::percent_decode_str(value)
-                .decode_utf8_lossy()
-                .to_string();
-            resource.push_str(&decoded);
-        }
+    // So the result is /devstoreaccount1/devstoreaccount1/container. When the
+    // router is nested the path keeps the mount prefix the client signed.
+    let mut resource = format!("/{}{}", ctx.account, ctx.request_path());
+
+    // Add query parameters: names lowercased and sorted, values of a repeated
+    // parameter sorted and joined with commas. Values are signed decoded, as
+    // the router decoded them; decoding again would turn `%25` into `%`
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, value) in &ctx.query_pairs {
+        grouped.entry(key.to_lowercase()).or_default().push(value.clone());
+    }
+
+    for (key, mut values) in grouped {
+        values.sort();
+        resource.push('\n');
+        resource.push_str(&key);
+        resource.push(':');
+        resource.push_str(&values.join(","));
     }
 
     resource
@@ -206,16 +278,12 @@ fn build_canonicalized_resource(ctx: &RequestContext) -> String {
 /// Builds canonicalized resource string for SharedKeyLite.
 fn build_canonicalized_resource_lite(ctx: &RequestContext) -> String {
     // The canonicalized resource is /{account}{path}
-    let mut resource = format!("/{}{}", ctx.account, ctx.uri.path());
+    let mut resource = format!("/{}{}", ctx.account, ctx.request_path());
 
-    // Only add comp parameter for Lite
-    if let Some(comp) = ctx.query_param("comp") {
+    // Only add comp parameter for Lite, as the client spelled it
+    if let Some(comp) = ctx.query_values("comp").first() {
         resource.push_str("?comp=");
-        // URL-decode the value
-        let decoded = percent_encoding::percent_decode_str(comp)
-            .decode_utf8_lossy()
-            .to_string();
-        resource.push_str(&decoded);
+        resource.push_str(comp);
     }
 
     resource
@@ -249,11 +317,168 @@ pub fn sign_string(string_to_sign: &str, account_key: &str) -> StorageResult<Str
 mod tests {
     use super::*;
 
+    use crate::config::DEFAULT_ACCOUNT_KEY;
+    use crate::context::format_http_date;
+    use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+    use chrono::Utc;
+    use std::collections::HashMap;
+
+    fn signed_context(config: &Config, date_header: &'static str, date: &str) -> RequestContext {
+        let mut headers = HeaderMap::new();
+        headers.insert(date_header, HeaderValue::from_str(date).unwrap());
+        headers.insert("x-ms-version", HeaderValue::from_static("2021-10-04"));
+
+        let mut path_params = HashMap::new();
+        path_params.insert("account".to_string(), "devstoreaccount1".to_string());
+        path_params.insert("container".to_string(), "container".to_string());
+
+        let mut ctx = RequestContext::new(
+            Method::GET,
+            Uri::from_static("/devstoreaccount1/container"),
+            headers,
+            path_params,
+            Vec::new(),
+        )
+        .unwrap();
+
+        let string_to_sign = build_string_to_sign(&ctx).unwrap();
+        let key = config.get_account_key("devstoreaccount1").unwrap();
+        let signature = compute_signature(&string_to_sign, key).unwrap();
+        ctx.headers.insert(
+            "authorization",
+            HeaderValue::from_str(&format!("SharedKey devstoreaccount1:{}", signature)).unwrap(),
+        );
+        ctx
+    }
+
     #[test]
     fn test_compute_signature() {
-        let key = "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+        let key = DEFAULT_ACCOUNT_KEY;
         let string_to_sign = "test string";
         let signature = compute_signature(string_to_sign, key).unwrap();
         assert!(!signature.is_empty());
     }
+
+    fn empty_put_context(version: &'static str) -> RequestContext {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ms-date", HeaderValue::from_static("Mon, 02 Jan 2023 10:00:00 GMT"));
+        headers.insert("x-ms-version", HeaderValue::from_static(version));
+        headers.insert("content-length", HeaderValue::from_static("0"));
+
+        let mut path_params = HashMap::new();
+        path_params.insert("account".to_string(), "devstoreaccount1".to_string());
+        path_params.insert("container".to_string(), "container".to_string());
+
+        RequestContext::new(
+            Method::PUT,
+            Uri::from_static("/devstoreaccount1/container?restype=container"),
+            headers,
+            path_params,
+            vec![("restype".to_string(), "container".to_string())],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_content_length_zero_before_2015_02_21() {
+        let ctx = empty_put_context("2014-02-14");
+        let string_to_sign = build_string_to_sign(&ctx).unwrap();
+        assert_eq!(string_to_sign.lines().nth(3), Some("0"));
+        assert_eq!(
+            compute_signature(&string_to_sign, DEFAULT_ACCOUNT_KEY).unwrap(),
+            "eAzLCm76HMHSNUXCMGditBRSKK8lcRzeDamOchto+l8="
+        );
+    }
+
+    #[test]
+    fn test_content_length_zero_since_2015_02_21() {
+        let ctx = empty_put_context("2021-10-04");
+        let string_to_sign = build_string_to_sign(&ctx).unwrap();
+        assert_eq!(string_to_sign.lines().nth(3), Some(""));
+        assert_eq!(
+            compute_signature(&string_to_sign, DEFAULT_ACCOUNT_KEY).unwrap(),
+            "suT6QflM5xO7zrfY1hAEOL0mZt3omAFI/587BJnx7wY="
+        );
+    }
+
+    #[test]
+    fn test_canonicalized_headers_join_repeated_and_mixed_case() {
+        let mut ctx = empty_put_context("2021-10-04");
+        for (name, value) in [("X-Ms-Meta-Color", "red"), ("x-ms-meta-color", "blue   green"), ("X-MS-META-A", "1")] {
+            ctx.headers.append(
+                axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_static(value),
+            );
+        }
+
+        let canonicalized = build_canonicalized_headers_with_trailing_newline(&ctx);
+        assert_eq!(
+            canonicalized,
+            "x-ms-date:Mon, 02 Jan 2023 10:00:00 GMT\n\
+             x-ms-meta-a:1\n\
+             x-ms-meta-color:red,blue green\n\
+             x-ms-version:2021-10-04\n"
+        );
+    }
+
+    #[test]
+    fn test_standard_date_header() {
+        let config = Config::default();
+        let now = format_http_date(&Utc::now());
+
+        let ctx = signed_context(&config, "date", &now);
+        let string_to_sign = build_string_to_sign(&ctx).unwrap();
+        assert_eq!(string_to_sign.lines().nth(6), Some(now.as_str()));
+        assert!(validate_shared_key(&ctx, &config).is_ok());
+
+        // With x-ms-date the Date slot stays empty
+        let ctx = signed_context(&config, "x-ms-date", &now);
+        let string_to_sign = build_string_to_sign(&ctx).unwrap();
+        assert_eq!(string_to_sign.lines().nth(6), Some(""));
+        assert!(validate_shared_key(&ctx, &config).is_ok());
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let mut config = Config::default();
+        let stale = format_http_date(&(Utc::now() - Duration::minutes(20)));
+
+        let ctx = signed_context(&config, "x-ms-date", &stale);
+        let err = validate_shared_key(&ctx, &config).unwrap_err();
+        assert_eq!(err.code, ErrorCode::AuthenticationFailed);
+        assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+        assert!(err.message.contains("too old"));
+
+        let future = format_http_date(&(Utc::now() + Duration::minutes(20)));
+        let ctx = signed_context(&config, "date", &future);
+        assert!(validate_shared_key(&ctx, &config).is_err());
+
+        // Skew is measured against the request time, which the server
+        // reads from its clock
+        let mut ctx = signed_context(&config, "x-ms-date", &stale);
+        ctx.timestamp -= Duration::minutes(20);
+        assert!(validate_shared_key(&ctx, &config).is_ok());
+
+        config.loose = true;
+        let ctx = signed_context(&config, "x-ms-date", &stale);
+        assert!(validate_shared_key(&ctx, &config).is_ok());
+    }
+
+    #[test]
+    fn test_signature_mismatch_echoes_string_to_sign() {
+        let config = Config::default();
+        let now = format_http_date(&Utc::now());
+        let mut ctx = signed_context(&config, "x-ms-date", &now);
+        ctx.headers.insert(
+            "authorization",
+            HeaderValue::from_static("SharedKey devstoreaccount1:bogus"),
+        );
+
+        let err = validate_shared_key(&ctx, &config).unwrap_err();
+        assert_eq!(err.code, ErrorCode::AuthenticationFailed);
+        let (name, detail) = &err.details[0];
+        assert_eq!(name, "AuthenticationErrorDetail");
+        assert!(detail.contains("'bogus'"));
+        assert!(detail.contains(&build_string_to_sign(&ctx).unwrap()));
+    }
 }
@@ -0,0 +1,101 @@
+//! User delegation keys and user delegation SAS validation.
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use std::collections::HashMap;
+
+use crate::models::UserDelegationKey;
+
+/// Key-related parameters (`sk*`) of a user delegation SAS.
+#[derive(Debug, Clone)]
+pub struct UserDelegationSasFields {
+    /// Signed key object ID (skoid).
+    pub signed_oid: String,
+    /// Signed key tenant ID (sktid).
+    pub signed_tid: String,
+    /// Signed key start (skt).
+    pub signed_key_start: String,
+    /// Signed key expiry (ske).
+    pub signed_key_expiry: String,
+    /// Signed key service (sks).
+    pub signed_key_service: String,
+    /// Signed key version (skv).
+    pub signed_key_version: String,
+    /// Signed authorized object ID (saoid) - optional.
+    pub authorized_oid: Option<String>,
+    /// Signed unauthorized object ID (suoid) - optional.
+    pub unauthorized_oid: Option<String>,
+    /// Signed correlation ID (scid) - optional.
+    pub correlation_id: Option<String>,
+}
+
+impl UserDelegationSasFields {
+    /// Parses the `sk*` parameters; returns None if this is not a user delegation SAS.
+    pub fn from_query(params: &HashMap<String, String>) -> Option<Self> {
+        if !params.contains_key("skoid") {
+            return None;
+        }
+
+        Some(Self {
+            signed_oid: params.get("skoid")?.clone(),
+            signed_tid: params.get("sktid").cloned().unwrap_or_default(),
+            signed_key_start: params.get("skt").cloned().unwrap_or_default(),
+            signed_key_expiry: params.get("ske").cloned().unwrap_or_default(),
+            signed_key_service: params.get("sks").cloned().unwrap_or_default(),
+            signed_key_version: params.get("skv").cloned().unwrap_or_default(),
+            authorized_oid: params.get("saoid").cloned(),
+            unauthorized_oid: params.get("suoid").cloned(),
+            correlation_id: params.get("scid").cloned(),
+        })
+    }
+}
+
+/// Registry of the user delegation keys issued by Get User Delegation Key.
+#[derive(Debug, Default)]
+pub struct UserDelegationKeyRegistry {
+    keys: DashMap<String, UserDelegationKey>,
+}
+
+impl UserDelegationKeyRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a key issued for the account.
+    pub fn insert(&self, account: &str, key: UserDelegationKey) {
+        let id = registry_key(
+            account,
+            &key.signed_oid,
+            &key.signed_tid,
+            &key.signed_start,
+            &key.signed_expiry,
+        );
+        self.keys.insert(id, key);
+    }
+
+    /// Looks up the key a user delegation SAS was signed with.
+    pub fn get(&self, account: &str, fields: &UserDelegationSasFields) -> Option<UserDelegationKey> {
+        let id = registry_key(
+            account,
+            &fields.signed_oid,
+            &fields.signed_tid,
+            &fields.signed_key_start,
+            &fields.signed_key_expiry,
+        );
+        self.keys.get(&id).map(|entry| entry.clone())
+    }
+
+    /// Drops keys that expired before `now`.
+    pub fn purge_expired(&self, now: DateTime<Utc>) {
+        self.keys.retain(|_, key| {
+            DateTime::parse_from_rfc3339(&key.signed_expiry)
+                .map(|expiry| expiry.with_timezone(&Utc) >= now)
+                .unwrap_or(true)
+        });
+    }
+}
+
+fn registry_key(account: &str, oid: &str, tid: &str, start: &str, expiry: &str) -> String {
+    format!("{}\n{}\n{}\n{}\n{}", account, oid, tid, start, expiry)
+}
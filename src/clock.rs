@@ -0,0 +1,24 @@
+//! Source of the current time for time-dependent behavior.
+//!
+//! Lease expiry, SAS validity windows, the SharedKey clock skew check and
+//! every stored timestamp (Last-Modified, creation, snapshot and block
+//! staging times) read the time from the [`Clock`] the server was built
+//! with, so tests can substitute [`crate::testing::MockClock`] instead of
+//! sleeping.
+
+use chrono::{DateTime, Utc};
+
+/// Provides the current time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The wall clock. Used unless another clock is configured.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
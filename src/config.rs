@@ -2,6 +2,9 @@
 
 use clap::Parser;
 use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use url::Url;
 
 /// Default account name for development storage.
 pub const DEFAULT_ACCOUNT: &str = "devstoreaccount1";
@@ -16,20 +19,40 @@ pub const DEFAULT_BLOB_PORT: u16 = 10000;
 /// Default API version.
 pub const DEFAULT_API_VERSION: &str = "2021-10-04";
 
+/// Blob service REST API versions published by Azure, oldest first.
+pub const SUPPORTED_API_VERSIONS: &[&str] = &[
+    "2009-09-19", "2011-08-18", "2012-02-12", "2013-08-15", "2014-02-14", "2015-02-21",
+    "2015-04-05", "2015-07-08", "2015-12-11", "2016-05-31", "2017-04-17", "2017-07-29",
+    "2017-11-09", "2018-03-28", "2018-11-09", "2019-02-02", "2019-07-07", "2019-10-10",
+    "2019-12-12", "2020-02-10", "2020-04-08", "2020-06-12", "2020-08-04", "2020-10-02",
+    "2020-12-06", "2021-02-12", "2021-04-10", "2021-06-08", "2021-08-06", "2021-10-04",
+    "2021-12-02", "2022-11-02", "2023-01-03", "2023-05-03", "2023-08-03", "2023-11-03",
+    "2024-02-04", "2024-05-04",
+];
+
 /// Command-line arguments for the server.
 #[derive(Parser, Debug, Clone)]
 #[command(name = "azurite-rs")]
 #[command(about = "Azure Blob Storage emulator in Rust")]
 #[command(version)]
 pub struct Args {
-    /// Host address to bind to.
-    #[arg(long, default_value = "127.0.0.1")]
-    pub host: String,
+    /// Host addresses to bind to, comma-separated (e.g. `127.0.0.1,::1`).
+    #[arg(
+        long = "host",
+        visible_alias = "blobHost",
+        value_delimiter = ',',
+        default_value = "127.0.0.1"
+    )]
+    pub hosts: Vec<String>,
 
     /// Port for blob service.
     #[arg(long, default_value_t = DEFAULT_BLOB_PORT)]
     pub blob_port: u16,
 
+    /// Serve on a Unix domain socket at this path instead of TCP.
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
     /// Location for workspace data.
     #[arg(long, short = 'l')]
     pub location: Option<PathBuf>,
@@ -73,13 +96,94 @@ pub struct Args {
     /// Enable PWD-based certificates.
     #[arg(long)]
     pub pwd: Option<String>,
+
+    /// Load a state archive (.tar.zst) into storage before serving.
+    #[arg(long, value_name = "PATH")]
+    pub import_state: Option<PathBuf>,
+
+    /// Write storage to a state archive (.tar.zst) on Ctrl+C.
+    #[arg(long, value_name = "PATH")]
+    pub export_state: Option<PathBuf>,
+
+    /// Take the host, port, account name and key from an Azure Storage
+    /// connection string.
+    #[arg(long, conflicts_with_all = ["hosts", "blob_port"])]
+    pub connection_string: Option<String>,
+
+    /// Memory budget for blob data in bytes (0 = unlimited). Writes beyond
+    /// it are rejected unless `--extent-spill-dir` is given.
+    #[arg(long, value_name = "BYTES", default_value_t = 0)]
+    pub extent_memory_limit: u64,
+
+    /// Spill least recently used blob data to this directory once the
+    /// memory budget is exceeded.
+    #[arg(long, value_name = "PATH", requires = "extent_memory_limit")]
+    pub extent_spill_dir: Option<PathBuf>,
+
+    /// Serve storage statistics as JSON at `/__admin/stats`, without
+    /// authentication.
+    #[arg(long)]
+    pub admin: bool,
+
+    /// Report the blob count and bytes of a container in
+    /// `x-azurite-blob-count` and `x-azurite-blob-bytes` headers of Get
+    /// Container Properties.
+    #[arg(long)]
+    pub container_stats_headers: bool,
+
+    /// Seconds between garbage collection passes, which purge expired
+    /// soft-deleted data and uncommitted blocks (0 = never).
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub gc_interval: u64,
+
+    /// Seconds an idle keep-alive connection waits for its next request
+    /// before it is closed (0 = no limit).
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    pub keep_alive_timeout: u64,
+
+    /// Most connections served at once; requests on further connections
+    /// fail with 503 ServerBusy (0 = unlimited).
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    pub max_connections: usize,
+
+    /// Disable Nagle's algorithm on accepted TCP connections.
+    #[arg(long)]
+    pub tcp_nodelay: bool,
+
+    /// Most metadata pairs a request may set; more fail with 400
+    /// MetadataTooLarge (0 = unlimited).
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    pub max_metadata_count: usize,
+
+    /// Let Copy Blob copy from another account of this server when the
+    /// source URL carries a SAS for it or its container is public. Without
+    /// it such copies fail with CopyAcrossAccountsNotSupported.
+    #[arg(long)]
+    pub cross_account_copy: bool,
+
+    /// Simulate a completed object replication policy on every container,
+    /// reported in `x-ms-or-*` headers of blob reads and in list results
+    /// with `include=objectreplicationmetadata`.
+    #[arg(long)]
+    pub object_replication: bool,
+
+    /// Let anonymous requests use private containers, as before public
+    /// access levels were enforced. Loose mode implies it.
+    #[arg(long)]
+    pub anonymous_private_access: bool,
+
+    /// Don't print the startup banner with account keys and connection
+    /// strings.
+    #[arg(long)]
+    pub quiet_banner: bool,
 }
 
 impl Default for Args {
     fn default() -> Self {
         Self {
-            host: "127.0.0.1".to_string(),
+            hosts: vec!["127.0.0.1".to_string()],
             blob_port: DEFAULT_BLOB_PORT,
+            socket: None,
             location: None,
             loose: false,
             skip_api_version_check: false,
@@ -91,6 +195,22 @@ impl Default for Args {
             cert: None,
             key: None,
             pwd: None,
+            import_state: None,
+            export_state: None,
+            connection_string: None,
+            extent_memory_limit: 0,
+            extent_spill_dir: None,
+            admin: false,
+            container_stats_headers: false,
+            gc_interval: 60,
+            keep_alive_timeout: 0,
+            max_connections: 0,
+            tcp_nodelay: false,
+            max_metadata_count: 0,
+            cross_account_copy: false,
+            object_replication: false,
+            anonymous_private_access: false,
+            quiet_banner: false,
         }
     }
 }
@@ -98,10 +218,13 @@ impl Default for Args {
 /// Server configuration derived from command-line arguments.
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// Host address to bind to.
-    pub host: String,
+    /// Host addresses to bind to. The first one is the primary address
+    /// reported by [`Config::blob_bind_address`].
+    pub hosts: Vec<String>,
     /// Port for blob service.
     pub blob_port: u16,
+    /// Unix domain socket to serve on instead of the TCP hosts.
+    pub socket: Option<PathBuf>,
     /// Location for workspace data.
     pub location: Option<PathBuf>,
     /// Enable loose mode (skip strict validation).
@@ -114,6 +237,49 @@ pub struct Config {
     pub debug: bool,
     /// Default account credentials.
     pub accounts: Vec<AccountConfig>,
+    /// Memory budget of the default in-memory extent store (0 = unlimited).
+    pub extent_memory_limit: u64,
+    /// Where the default extent store spills data beyond its budget. Writes
+    /// beyond the budget are rejected when unset.
+    pub extent_spill_dir: Option<PathBuf>,
+    /// Serve the unauthenticated `/__admin/stats` endpoint.
+    pub admin: bool,
+    /// Add the blob count and bytes of a container to Get Container
+    /// Properties responses. Off by default since the service has no such
+    /// headers.
+    pub container_stats_headers: bool,
+    /// Time between garbage collection passes while the server runs. Zero
+    /// disables the background task.
+    pub gc_interval: Duration,
+    /// How long a deleted container's name stays reserved, failing requests
+    /// with `ContainerBeingDeleted` as the service does while it cleans up.
+    /// Zero frees the name immediately.
+    pub container_delete_linger: Duration,
+    /// How long a keep-alive connection may sit idle between requests, from
+    /// the end of one response to the start of the next request, before it
+    /// is closed. Zero leaves it open until the client closes it.
+    pub keep_alive_timeout: Duration,
+    /// Most connections served at once (0 = unlimited). Requests on
+    /// connections beyond it fail with 503 `ServerBusy`.
+    pub max_connections: usize,
+    /// Set TCP_NODELAY on accepted TCP connections.
+    pub tcp_nodelay: bool,
+    /// Most `x-ms-meta-*` pairs a request may set (0 = unlimited, as in
+    /// Azure, which limits only their total size).
+    pub max_metadata_count: usize,
+    /// Allow Copy Blob from another account, deep-copying the data. The
+    /// source must be authorized by a SAS in its URL or be public, as the
+    /// copying request's credentials are for the destination account only.
+    pub cross_account_copy: bool,
+    /// Report blobs as sources of an object replication policy, one per
+    /// container, that has replicated them all. Nothing is copied; the
+    /// policy only exists so replication-aware clients see well-formed
+    /// status headers and list elements.
+    pub object_replication: bool,
+    /// Let anonymous requests do anything with private containers instead
+    /// of failing with `ResourceNotFound` as in Azure. For clients that send
+    /// no credentials; loose mode implies it.
+    pub anonymous_private_access: bool,
 }
 
 /// Account configuration.
@@ -126,8 +292,9 @@ pub struct AccountConfig {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            host: "127.0.0.1".to_string(),
+            hosts: vec!["127.0.0.1".to_string()],
             blob_port: DEFAULT_BLOB_PORT,
+            socket: None,
             location: None,
             loose: false,
             skip_api_version_check: false,
@@ -137,6 +304,19 @@ impl Default for Config {
                 name: DEFAULT_ACCOUNT.to_string(),
                 key: DEFAULT_ACCOUNT_KEY.to_string(),
             }],
+            extent_memory_limit: 0,
+            extent_spill_dir: None,
+            admin: false,
+            container_stats_headers: false,
+            gc_interval: Duration::from_secs(60),
+            container_delete_linger: Duration::ZERO,
+            keep_alive_timeout: Duration::ZERO,
+            max_connections: 0,
+            tcp_nodelay: false,
+            max_metadata_count: 0,
+            cross_account_copy: false,
+            object_replication: false,
+            anonymous_private_access: false,
         }
     }
 }
@@ -145,8 +325,9 @@ impl From<Args> for Config {
     fn from(args: Args) -> Self {
         let in_memory = args.in_memory || args.location.is_none();
         Self {
-            host: args.host,
+            hosts: args.hosts,
             blob_port: args.blob_port,
+            socket: args.socket,
             location: args.location,
             loose: args.loose,
             skip_api_version_check: args.skip_api_version_check,
@@ -156,6 +337,19 @@ impl From<Args> for Config {
                 name: DEFAULT_ACCOUNT.to_string(),
                 key: DEFAULT_ACCOUNT_KEY.to_string(),
             }],
+            extent_memory_limit: args.extent_memory_limit,
+            extent_spill_dir: args.extent_spill_dir,
+            admin: args.admin,
+            container_stats_headers: args.container_stats_headers,
+            gc_interval: Duration::from_secs(args.gc_interval),
+            container_delete_linger: Duration::ZERO,
+            keep_alive_timeout: Duration::from_secs(args.keep_alive_timeout),
+            max_connections: args.max_connections,
+            tcp_nodelay: args.tcp_nodelay,
+            max_metadata_count: args.max_metadata_count,
+            cross_account_copy: args.cross_account_copy,
+            object_replication: args.object_replication,
+            anonymous_private_access: args.anonymous_private_access,
         }
     }
 }
@@ -169,8 +363,305 @@ impl Config {
             .map(|a| a.key.as_str())
     }
 
-    /// Returns the bind address for the blob service.
+    /// Returns the primary bind address for the blob service.
     pub fn blob_bind_address(&self) -> String {
-        format!("{}:{}", self.host, self.blob_port)
+        let host = self.hosts.first().map(String::as_str).unwrap_or("127.0.0.1");
+        socket_address(host, self.blob_port)
+    }
+
+    /// Returns the bind addresses of all configured hosts, primary first.
+    pub fn blob_bind_addresses(&self) -> Vec<String> {
+        self.hosts
+            .iter()
+            .map(|host| socket_address(host, self.blob_port))
+            .collect()
+    }
+
+    /// Returns the primary address as clients reach it. An unspecified bind
+    /// address (`0.0.0.0` or `::`) is shown as the loopback address of the
+    /// same family, since clients cannot connect to it.
+    pub fn blob_display_address(&self) -> String {
+        let host = match self.hosts.first().map(String::as_str).unwrap_or("127.0.0.1") {
+            "0.0.0.0" => "127.0.0.1",
+            "::" | "[::]" => "::1",
+            host => host,
+        };
+        socket_address(host, self.blob_port)
+    }
+
+    /// Returns the base URL of the blob service on the primary address.
+    pub fn blob_base_url(&self) -> String {
+        format!("http://{}", self.blob_display_address())
+    }
+
+    /// Creates a default configuration serving the endpoint and account of
+    /// an Azure Storage connection string.
+    pub fn from_connection_string(connection_string: &str) -> Result<Self, ConnectionStringError> {
+        Self::default().with_connection_string(connection_string)
+    }
+
+    /// Replaces the host, port and accounts with those of an Azure Storage
+    /// connection string, keeping all other settings.
+    ///
+    /// `UseDevelopmentStorage=true` selects the default emulator endpoint
+    /// and account. Otherwise `AccountName`, `AccountKey` and a path-style
+    /// `BlobEndpoint` (`http://host:port/account`) are required. Endpoints of
+    /// other services are ignored.
+    pub fn with_connection_string(mut self, connection_string: &str) -> Result<Self, ConnectionStringError> {
+        let mut protocol = None;
+        let mut account_name = None;
+        let mut account_key = None;
+        let mut blob_endpoint = None;
+        let mut development_storage = false;
+
+        for segment in connection_string.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = segment
+                .split_once('=')
+                .ok_or_else(|| ConnectionStringError::MalformedSegment(segment.to_string()))?;
+            match key {
+                "DefaultEndpointsProtocol" => protocol = Some(value),
+                "AccountName" => account_name = Some(value),
+                "AccountKey" => account_key = Some(value),
+                "BlobEndpoint" => blob_endpoint = Some(value),
+                "UseDevelopmentStorage" => development_storage = value.eq_ignore_ascii_case("true"),
+                "QueueEndpoint" | "TableEndpoint" | "FileEndpoint" | "EndpointSuffix" => {}
+                _ => return Err(ConnectionStringError::UnsupportedKey(key.to_string())),
+            }
+        }
+
+        if development_storage {
+            let defaults = Self::default();
+            self.hosts = defaults.hosts;
+            self.blob_port = defaults.blob_port;
+            self.accounts = defaults.accounts;
+            return Ok(self);
+        }
+
+        if let Some(protocol) = protocol {
+            if protocol != "http" {
+                return Err(ConnectionStringError::UnsupportedProtocol(protocol.to_string()));
+            }
+        }
+        let name = account_name.ok_or(ConnectionStringError::MissingKey("AccountName"))?;
+        let key = account_key.ok_or(ConnectionStringError::MissingKey("AccountKey"))?;
+        if base64::Engine::decode(&base64::engine::general_purpose::STANDARD, key).is_err() {
+            return Err(ConnectionStringError::InvalidAccountKey);
+        }
+        let endpoint = blob_endpoint.ok_or(ConnectionStringError::MissingKey("BlobEndpoint"))?;
+        let (host, port) = parse_blob_endpoint(endpoint, name)?;
+
+        self.hosts = vec![host];
+        self.blob_port = port;
+        self.accounts = vec![AccountConfig {
+            name: name.to_string(),
+            key: key.to_string(),
+        }];
+        Ok(self)
+    }
+
+    /// Returns a connection string for the primary address and the first
+    /// account. [`Config::from_connection_string`] parses it back into the
+    /// same endpoint and account.
+    pub fn connection_string(&self) -> String {
+        match self.accounts.first() {
+            Some(account) => self.account_connection_string(account),
+            None => self.account_connection_string(&AccountConfig {
+                name: DEFAULT_ACCOUNT.to_string(),
+                key: DEFAULT_ACCOUNT_KEY.to_string(),
+            }),
+        }
+    }
+
+    /// Returns a connection string for `account` on the primary address.
+    pub fn account_connection_string(&self, account: &AccountConfig) -> String {
+        format!(
+            "DefaultEndpointsProtocol=http;AccountName={};AccountKey={};BlobEndpoint={}/{};",
+            account.name,
+            account.key,
+            self.blob_base_url(),
+            account.name
+        )
+    }
+
+    /// Returns the banner printed at startup: where the service listens and
+    /// the key and connection string of every account.
+    pub fn banner(&self) -> String {
+        let mut banner = match &self.socket {
+            Some(path) => format!("Azurite Blob service is starting on Unix socket {}\n", path.display()),
+            None => format!("Azurite Blob service is starting at {}\n", self.blob_base_url()),
+        };
+        if self.socket.is_none() && self.hosts.len() > 1 {
+            banner.push_str(&format!("Also listening on {}\n", self.blob_bind_addresses()[1..].join(", ")));
+        }
+        for account in &self.accounts {
+            banner.push_str(&format!(
+                "\nAccount: {}\nKey: {}\nConnection string:\n{}\n",
+                account.name,
+                account.key,
+                self.account_connection_string(account)
+            ));
+        }
+        banner.push_str("\nPress Ctrl+C to stop the server.\n");
+        banner
+    }
+}
+
+/// Error parsing an Azure Storage connection string.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConnectionStringError {
+    #[error("malformed connection string segment '{0}', expected Key=Value")]
+    MalformedSegment(String),
+    #[error("connection string is missing {0}")]
+    MissingKey(&'static str),
+    #[error("connection string key '{0}' is not supported by the emulator")]
+    UnsupportedKey(String),
+    #[error("protocol '{0}' is not supported, only http is served")]
+    UnsupportedProtocol(String),
+    #[error("AccountKey is not valid base64")]
+    InvalidAccountKey,
+    #[error("invalid BlobEndpoint '{endpoint}': {reason}")]
+    InvalidBlobEndpoint { endpoint: String, reason: String },
+}
+
+/// Splits a path-style `http://host:port/account` endpoint into the host and
+/// port to bind.
+fn parse_blob_endpoint(endpoint: &str, account: &str) -> Result<(String, u16), ConnectionStringError> {
+    let invalid = |reason: &str| ConnectionStringError::InvalidBlobEndpoint {
+        endpoint: endpoint.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let url = Url::parse(endpoint).map_err(|e| invalid(&e.to_string()))?;
+    if url.scheme() != "http" {
+        return Err(invalid("only http endpoints are served"));
+    }
+    let host = url.host_str().ok_or_else(|| invalid("missing host"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let port = url.port_or_known_default().ok_or_else(|| invalid("missing port"))?;
+    if url.path().trim_end_matches('/') != format!("/{}", account) {
+        return Err(invalid("the path must be the account name (path-style URL)"));
+    }
+    if url.query().is_some() {
+        return Err(invalid("unexpected query string"));
+    }
+
+    Ok((host, port))
+}
+
+/// Formats `host:port`, bracketing IPv6 literals.
+fn socket_address(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_string_round_trip() {
+        let config = Config {
+            hosts: vec!["::1".to_string()],
+            blob_port: 10100,
+            ..Config::default()
+        };
+        let connection_string = config.connection_string();
+        assert_eq!(
+            connection_string,
+            format!(
+                "DefaultEndpointsProtocol=http;AccountName={};AccountKey={};BlobEndpoint=http://[::1]:10100/{};",
+                DEFAULT_ACCOUNT, DEFAULT_ACCOUNT_KEY, DEFAULT_ACCOUNT
+            )
+        );
+
+        let parsed = Config::from_connection_string(&connection_string).unwrap();
+        assert_eq!(parsed.hosts, vec!["::1".to_string()]);
+        assert_eq!(parsed.blob_port, 10100);
+        assert_eq!(parsed.connection_string(), connection_string);
+    }
+
+    #[test]
+    fn test_connection_string_per_account() {
+        let mut config = Config {
+            hosts: vec!["0.0.0.0".to_string(), "::".to_string()],
+            blob_port: 10200,
+            ..Config::default()
+        };
+        config.accounts.push(AccountConfig {
+            name: "tenantb".to_string(),
+            key: "a2V5".to_string(),
+        });
+        assert_eq!(config.blob_bind_address(), "0.0.0.0:10200");
+        assert_eq!(config.blob_display_address(), "127.0.0.1:10200");
+
+        for account in &config.accounts {
+            let connection_string = config.account_connection_string(account);
+            assert!(connection_string.contains(&format!("BlobEndpoint=http://127.0.0.1:10200/{};", account.name)));
+            let parsed = Config::from_connection_string(&connection_string).unwrap();
+            assert_eq!(parsed.blob_bind_address(), "127.0.0.1:10200");
+            assert_eq!(parsed.get_account_key(&account.name), Some(account.key.as_str()));
+            assert_eq!(parsed.account_connection_string(account), connection_string);
+        }
+
+        let banner = config.banner();
+        assert!(banner.starts_with("Azurite Blob service is starting at http://127.0.0.1:10200\n"), "{}", banner);
+        assert!(banner.contains("Also listening on [::]:10200\n"), "{}", banner);
+        for account in &config.accounts {
+            assert!(banner.contains(&config.account_connection_string(account)), "{}", banner);
+        }
+
+        config.hosts = vec!["::".to_string()];
+        assert_eq!(config.blob_base_url(), "http://[::1]:10200");
+    }
+
+    #[test]
+    fn test_connection_string_account() {
+        let config = Config::from_connection_string(
+            "DefaultEndpointsProtocol=http;AccountName=myaccount;AccountKey=a2V5;\
+             BlobEndpoint=http://azurite:10000/myaccount/;QueueEndpoint=http://azurite:10001/myaccount;",
+        )
+        .unwrap();
+        assert_eq!(config.hosts, vec!["azurite".to_string()]);
+        assert_eq!(config.blob_port, 10000);
+        assert_eq!(config.get_account_key("myaccount"), Some("a2V5"));
+        assert_eq!(config.get_account_key(DEFAULT_ACCOUNT), None);
+
+        let config = Config::from_connection_string("UseDevelopmentStorage=true").unwrap();
+        assert_eq!(config.blob_bind_address(), "127.0.0.1:10000");
+        assert_eq!(config.get_account_key(DEFAULT_ACCOUNT), Some(DEFAULT_ACCOUNT_KEY));
+    }
+
+    #[test]
+    fn test_connection_string_errors() {
+        let parse = |s: &str| Config::from_connection_string(s).unwrap_err();
+
+        assert_eq!(
+            parse("AccountName=a;AccountKey=a2V5"),
+            ConnectionStringError::MissingKey("BlobEndpoint")
+        );
+        assert_eq!(
+            parse("AccountName=a;SharedAccessSignature=sv=2021;BlobEndpoint=http://h:1/a"),
+            ConnectionStringError::UnsupportedKey("SharedAccessSignature".to_string())
+        );
+        assert_eq!(parse("AccountName"), ConnectionStringError::MalformedSegment("AccountName".to_string()));
+        assert_eq!(
+            parse("AccountName=a;AccountKey=!!;BlobEndpoint=http://h:1/a"),
+            ConnectionStringError::InvalidAccountKey
+        );
+        assert!(matches!(
+            parse("AccountName=a;AccountKey=a2V5;BlobEndpoint=http://h:1/other"),
+            ConnectionStringError::InvalidBlobEndpoint { .. }
+        ));
+        assert!(matches!(
+            parse("AccountName=a;AccountKey=a2V5;BlobEndpoint=not a url"),
+            ConnectionStringError::InvalidBlobEndpoint { .. }
+        ));
+        assert_eq!(
+            parse("DefaultEndpointsProtocol=https;AccountName=a;AccountKey=a2V5;BlobEndpoint=http://h:1/a"),
+            ConnectionStringError::UnsupportedProtocol("https".to_string())
+        );
     }
 }
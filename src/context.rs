@@ -1,14 +1,124 @@
 //! Request context extraction and handling.
 
 use axum::{
-    extract::{FromRequestParts, Path, Query},
+    extract::{Path, Query},
     http::{header::HeaderMap, request::Parts, HeaderValue, Method, Uri},
 };
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::config::Config;
 use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::header_case::HeaderCase;
+use crate::models::{EtagClock, Metadata};
+use crate::operation::Operation;
+
+/// Maximum value accepted for the `timeout` query parameter, in seconds.
+pub const MAX_TIMEOUT_SECONDS: u32 = 3600;
+
+/// Largest total size of metadata names and values Azure accepts, in bytes.
+pub const MAX_METADATA_SIZE: usize = 8 * 1024;
+
+/// Most blob index tags a blob may carry.
+pub const MAX_TAG_COUNT: usize = 10;
+
+/// `x-ms-` request headers of the Blob service REST API, sorted. Any other
+/// `x-ms-` header, besides `x-ms-meta-*`, is unsupported; see
+/// [`RequestContext::unsupported_headers`]. Headers the emulator does not
+/// implement are listed too, since the service accepts them.
+const BLOB_HEADERS: &[&str] = &[
+    "x-ms-access-tier",
+    "x-ms-blob-cache-control",
+    "x-ms-blob-condition-appendpos",
+    "x-ms-blob-condition-maxsize",
+    "x-ms-blob-content-disposition",
+    "x-ms-blob-content-encoding",
+    "x-ms-blob-content-language",
+    "x-ms-blob-content-length",
+    "x-ms-blob-content-md5",
+    "x-ms-blob-content-type",
+    "x-ms-blob-public-access",
+    "x-ms-blob-sequence-number",
+    "x-ms-blob-type",
+    "x-ms-client-request-id",
+    "x-ms-content-crc64",
+    "x-ms-copy-action",
+    "x-ms-copy-source",
+    "x-ms-copy-source-authorization",
+    "x-ms-copy-source-blob-properties",
+    "x-ms-copy-source-tag-option",
+    "x-ms-date",
+    "x-ms-default-encryption-scope",
+    "x-ms-delete-snapshots",
+    "x-ms-delete-type-permanent",
+    "x-ms-deleted-container-name",
+    "x-ms-deleted-container-version",
+    "x-ms-deny-encryption-scope-override",
+    "x-ms-encryption-algorithm",
+    "x-ms-encryption-key",
+    "x-ms-encryption-key-sha256",
+    "x-ms-encryption-scope",
+    "x-ms-expiry-option",
+    "x-ms-expiry-time",
+    "x-ms-if-sequence-number-eq",
+    "x-ms-if-sequence-number-le",
+    "x-ms-if-sequence-number-lt",
+    "x-ms-if-tags",
+    "x-ms-immutability-policy-mode",
+    "x-ms-immutability-policy-until-date",
+    "x-ms-immutable-storage-with-versioning-enabled",
+    "x-ms-lease-action",
+    "x-ms-lease-break-period",
+    "x-ms-lease-duration",
+    "x-ms-lease-id",
+    "x-ms-legal-hold",
+    "x-ms-page-write",
+    "x-ms-previous-snapshot-url",
+    "x-ms-proposed-lease-id",
+    "x-ms-range",
+    "x-ms-range-get-content-crc64",
+    "x-ms-range-get-content-md5",
+    "x-ms-rehydrate-priority",
+    "x-ms-requires-sync",
+    "x-ms-return-client-request-id",
+    "x-ms-sequence-number-action",
+    "x-ms-source-container-name",
+    "x-ms-source-content-crc64",
+    "x-ms-source-content-md5",
+    "x-ms-source-if-match",
+    "x-ms-source-if-modified-since",
+    "x-ms-source-if-none-match",
+    "x-ms-source-if-tags",
+    "x-ms-source-if-unmodified-since",
+    "x-ms-source-lease-id",
+    "x-ms-source-range",
+    "x-ms-structured-body",
+    "x-ms-structured-content-length",
+    "x-ms-tags",
+    "x-ms-useragent",
+    "x-ms-version",
+];
+
+/// Headers of the Data Lake Storage (dfs) endpoint that clients send to the
+/// blob endpoint by mistake, sorted.
+const DFS_HEADERS: &[&str] = &[
+    "x-ms-acl",
+    "x-ms-cache-control",
+    "x-ms-content-disposition",
+    "x-ms-content-encoding",
+    "x-ms-content-language",
+    "x-ms-content-md5",
+    "x-ms-content-type",
+    "x-ms-group",
+    "x-ms-owner",
+    "x-ms-permissions",
+    "x-ms-properties",
+    "x-ms-rename-source",
+    "x-ms-umask",
+    "x-ms-upn",
+];
 
 /// Extracted request context containing all relevant information.
 #[derive(Debug, Clone)]
@@ -25,16 +135,57 @@ pub struct RequestContext {
     pub container: Option<String>,
     /// Blob name (if present).
     pub blob: Option<String>,
-    /// Query parameters.
+    /// Query parameters by lowercased name, as Azure matches names
+    /// case-insensitively. `comp` and `restype` values are lowercased too.
+    /// Values of a repeated parameter are joined with commas.
     pub query_params: HashMap<String, String>,
+    /// Query parameters in request order, including repeated names, spelled
+    /// as sent and percent-decoded once. Signatures are computed over these.
+    pub query_pairs: Vec<(String, String)>,
     /// Request headers.
     pub headers: HeaderMap,
+    /// How the client spelled its `x-ms-meta-*` header names, where known.
+    pub header_case: HeaderCase,
     /// API version from x-ms-version header.
     pub api_version: Option<String>,
     /// Client request ID from x-ms-client-request-id header.
     pub client_request_id: Option<String>,
-    /// Request timestamp.
+    /// Request timestamp, read from the server clock. Handlers use it as
+    /// the current time.
     pub timestamp: DateTime<Utc>,
+    /// The server's source of ETags for the objects the request writes.
+    pub etags: Arc<EtagClock>,
+    /// Permissions granted by the SAS token the request was authorized with.
+    pub sas_permissions: Option<String>,
+    /// Response header overrides requested by the SAS token, if any.
+    pub response_overrides: ResponseHeaderOverrides,
+    /// Path prefix the service is mounted under when nested in a larger
+    /// router, e.g. `/azure`. Empty at the root.
+    pub mount_path: String,
+    /// Base URL of the blob service from the server configuration, used for
+    /// the parts of [`RequestContext::base_url`] the request does not name.
+    pub configured_base_url: String,
+    /// Whether the server runs in loose mode, relaxing checks that would
+    /// otherwise reject the request.
+    pub loose: bool,
+    /// Most metadata pairs the request may set (0 = unlimited).
+    pub max_metadata_count: usize,
+    /// Whether blobs report the simulated object replication rule of their
+    /// container.
+    pub object_replication: bool,
+    /// The operation the router classified the request as; `Unknown` until
+    /// it has.
+    pub operation: Operation,
+}
+
+/// Response header overrides carried by a service SAS (rscc, rscd, rsce, rscl, rsct).
+#[derive(Debug, Clone, Default)]
+pub struct ResponseHeaderOverrides {
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_encoding: Option<String>,
+    pub content_language: Option<String>,
+    pub content_type: Option<String>,
 }
 
 impl RequestContext {
@@ -44,7 +195,7 @@ impl RequestContext {
         uri: Uri,
         headers: HeaderMap,
         path_params: HashMap<String, String>,
-        query_params: HashMap<String, String>,
+        query_pairs: Vec<(String, String)>,
     ) -> StorageResult<Self> {
         let request_id = Uuid::new_v4().to_string();
         let timestamp = Utc::now();
@@ -57,6 +208,13 @@ impl RequestContext {
         let container = path_params.get("container").cloned();
         let blob = path_params.get("blob").cloned();
 
+        if let Some(ref name) = container {
+            validate_container_name(name)?;
+        }
+        if let Some(ref name) = blob {
+            validate_blob_name(name)?;
+        }
+
         let api_version = headers
             .get("x-ms-version")
             .and_then(|v| v.to_str().ok())
@@ -67,6 +225,40 @@ impl RequestContext {
             .and_then(|v| v.to_str().ok())
             .map(String::from);
 
+        let mut query_params: HashMap<String, String> = HashMap::new();
+        for (name, value) in &query_pairs {
+            let name = name.to_ascii_lowercase();
+            let value = match name.as_str() {
+                "comp" | "restype" => value.to_ascii_lowercase(),
+                _ => value.clone(),
+            };
+            query_params
+                .entry(name)
+                .and_modify(|existing| {
+                    existing.push(',');
+                    existing.push_str(&value);
+                })
+                .or_insert(value);
+        }
+
+        if let Some(timeout) = query_params.get("timeout") {
+            validate_timeout(timeout)?;
+        }
+
+        // Version IDs are timestamps in the same format as snapshots
+        for name in ["snapshot", "prevsnapshot", "versionid"] {
+            if let Some(value) = query_params.get_mut(name) {
+                *value = normalize_snapshot(name, value)?;
+            }
+        }
+        if let (Some(_), Some(version_id)) = (query_params.get("snapshot"), query_params.get("versionid")) {
+            return Err(StorageError::invalid_query_parameter(
+                "versionid",
+                version_id,
+                "snapshot and versionid cannot both be specified.",
+            ));
+        }
+
         Ok(Self {
             request_id,
             method,
@@ -75,41 +267,116 @@ impl RequestContext {
             container,
             blob,
             query_params,
+            query_pairs,
             headers,
+            header_case: HeaderCase::default(),
             api_version,
             client_request_id,
             timestamp,
+            etags: Arc::new(EtagClock::new()),
+            sas_permissions: None,
+            response_overrides: ResponseHeaderOverrides::default(),
+            mount_path: String::new(),
+            configured_base_url: Config::default().blob_base_url(),
+            loose: false,
+            max_metadata_count: 0,
+            object_replication: false,
+            operation: Operation::Unknown,
         })
     }
 
-    /// Returns the value of a query parameter.
+    /// Returns the request path as the client sent it, including the mount
+    /// prefix.
+    pub fn request_path(&self) -> String {
+        format!("{}{}", self.mount_path, self.uri.path())
+    }
+
+    /// Returns the URL the client reached the service at, up to the mount
+    /// prefix. The scheme and authority come from an absolute request URI,
+    /// else the authority from the Host header, and whatever is still
+    /// missing from the configured base URL.
+    pub fn base_url(&self) -> String {
+        let (configured_scheme, configured_authority) = self
+            .configured_base_url
+            .split_once("://")
+            .unwrap_or(("http", self.configured_base_url.as_str()));
+        let scheme = self.uri.scheme_str().unwrap_or(configured_scheme);
+        let authority = self
+            .uri
+            .authority()
+            .map(|authority| authority.as_str())
+            .or_else(|| self.header("host"))
+            .unwrap_or(configured_authority);
+        format!("{}://{}{}", scheme, authority, self.mount_path)
+    }
+
+    /// Returns the `ServiceEndpoint` URL reported in listings: the
+    /// [`base_url`](Self::base_url) followed by the account.
+    pub fn service_endpoint(&self) -> String {
+        format!("{}/{}/", self.base_url(), self.account)
+    }
+
+    /// Returns the value of a query parameter, by lowercase name.
     pub fn query_param(&self, name: &str) -> Option<&str> {
         self.query_params.get(name).map(|s| s.as_str())
     }
 
-    /// Returns the value of a header.
+    /// Returns every value given for a query parameter, in request order and
+    /// as sent. The name matches case-insensitively.
+    pub fn query_values(&self, name: &str) -> Vec<&str> {
+        self.query_pairs
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Returns the value of a header. Names match case-insensitively; when the
+    /// header is repeated the first value is returned.
     pub fn header(&self, name: &str) -> Option<&str> {
         self.headers.get(name).and_then(|v| v.to_str().ok())
     }
 
-    /// Returns the x-ms-* headers sorted alphabetically.
-    pub fn ms_headers(&self) -> Vec<(&str, &str)> {
-        let mut headers: Vec<_> = self
+    /// Returns all values of a header joined with commas, in the order they
+    /// were sent, or None if the header is absent.
+    pub fn header_values(&self, name: &str) -> Option<String> {
+        let values: Vec<&str> = self
             .headers
+            .get_all(name)
             .iter()
-            .filter_map(|(name, value)| {
-                let name_str = name.as_str();
-                if name_str.starts_with("x-ms-") {
-                    value.to_str().ok().map(|v| (name_str, v))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        (!values.is_empty()).then(|| values.join(","))
+    }
+
+    /// Returns the x-ms-* headers sorted alphabetically, one entry per name
+    /// with repeated values comma-joined.
+    pub fn ms_headers(&self) -> Vec<(&str, String)> {
+        let mut headers: Vec<_> = self
+            .headers
+            .keys()
+            .filter(|name| name.as_str().starts_with("x-ms-"))
+            .filter_map(|name| self.header_values(name.as_str()).map(|value| (name.as_str(), value)))
             .collect();
         headers.sort_by(|a, b| a.0.cmp(b.0));
         headers
     }
 
+    /// Returns the `x-ms-` headers the Blob service does not accept, in
+    /// request order: Data Lake headers and misspellings such as
+    /// `x-ms-metadata-key`, which would otherwise be dropped unnoticed.
+    pub fn unsupported_headers(&self) -> Vec<&str> {
+        self.headers
+            .keys()
+            .map(|name| name.as_str())
+            .filter(|name| {
+                name.starts_with("x-ms-")
+                    && !name.starts_with("x-ms-meta-")
+                    && BLOB_HEADERS.binary_search(name).is_err()
+            })
+            .collect()
+    }
+
     /// Returns the Content-MD5 header value.
     pub fn content_md5(&self) -> Option<&str> {
         self.header("content-md5")
@@ -131,6 +398,17 @@ impl RequestContext {
         self.header("range").or_else(|| self.header("x-ms-range")).and_then(parse_range_header)
     }
 
+    /// Returns every range of a Range header such as "bytes=0-99,1000-1099",
+    /// in request order. `None` if the header is absent or malformed.
+    pub fn ranges(&self) -> Option<Vec<(u64, Option<u64>)>> {
+        let value = self.header("range").or_else(|| self.header("x-ms-range"))?;
+        let specs = value.strip_prefix("bytes=")?;
+        specs
+            .split(',')
+            .map(|spec| parse_range_header(&format!("bytes={}", spec.trim())))
+            .collect()
+    }
+
     /// Returns the If-Match header value.
     pub fn if_match(&self) -> Option<&str> {
         self.header("if-match")
@@ -141,6 +419,11 @@ impl RequestContext {
         self.header("if-none-match")
     }
 
+    /// Returns the If-Range header value.
+    pub fn if_range(&self) -> Option<&str> {
+        self.header("if-range")
+    }
+
     /// Returns the If-Modified-Since header value.
     pub fn if_modified_since(&self) -> Option<DateTime<Utc>> {
         self.header("if-modified-since").and_then(parse_http_date)
@@ -151,6 +434,14 @@ impl RequestContext {
         self.header("if-unmodified-since").and_then(parse_http_date)
     }
 
+    /// Returns the request date, taken from x-ms-date or, failing that, the
+    /// standard Date header.
+    pub fn request_date(&self) -> Option<DateTime<Utc>> {
+        self.header("x-ms-date")
+            .or_else(|| self.header("date"))
+            .and_then(parse_http_date)
+    }
+
     /// Returns the x-ms-lease-id header value.
     pub fn lease_id(&self) -> Option<&str> {
         self.header("x-ms-lease-id")
@@ -166,22 +457,89 @@ impl RequestContext {
         self.header("x-ms-copy-source")
     }
 
-    /// Returns user-defined metadata from x-ms-meta-* headers.
-    pub fn metadata(&self) -> HashMap<String, String> {
-        self.headers
-            .iter()
-            .filter_map(|(name, value)| {
-                let name_str = name.as_str();
-                if let Some(key) = name_str.strip_prefix("x-ms-meta-") {
-                    value.to_str().ok().map(|v| (key.to_string(), v.to_string()))
-                } else {
-                    None
-                }
-            })
-            .collect()
+    /// Returns the x-ms-copy-source-authorization header value, the
+    /// credentials to present to the copy source.
+    pub fn copy_source_authorization(&self) -> Option<&str> {
+        self.header("x-ms-copy-source-authorization")
     }
 
-    /// Returns the snapshot query parameter.
+    /// Returns user-defined metadata from x-ms-meta-* headers. A repeated
+    /// key's values are comma-joined, as Azure stores them.
+    ///
+    /// Keys keep the spelling the client sent them in, as Azure does, when
+    /// [`RequestContext::header_case`] knows it; otherwise they are
+    /// lowercase, as the HTTP layer hands header names over. A key repeated
+    /// in several spellings keeps the first.
+    ///
+    /// A bare `x-ms-meta-` header fails with `EmptyMetadataKey`. More pairs
+    /// than [`RequestContext::max_metadata_count`], or names and values
+    /// totalling over [`MAX_METADATA_SIZE`] bytes, fail with
+    /// `MetadataTooLarge`.
+    pub fn metadata(&self) -> StorageResult<Metadata> {
+        let mut metadata = Metadata::new();
+        let mut size = 0;
+        for name in self.headers.keys() {
+            let Some(key) = name.as_str().strip_prefix("x-ms-meta-") else {
+                continue;
+            };
+            if key.is_empty() {
+                return Err(StorageError::new(ErrorCode::EmptyMetadataKey));
+            }
+            let Some(value) = self.header_values(name.as_str()) else {
+                continue;
+            };
+            let key = match self.header_case.spelling(name.as_str()) {
+                Some(spelling) => &spelling[name.as_str().len() - key.len()..],
+                None => key,
+            };
+            size += key.len() + value.len();
+            metadata.insert(key.to_string(), value);
+        }
+
+        if self.max_metadata_count > 0 && metadata.len() > self.max_metadata_count {
+            return Err(StorageError::with_message(
+                ErrorCode::MetadataTooLarge,
+                format!(
+                    "The request sets {} metadata pairs, more than the limit of {}.",
+                    metadata.len(),
+                    self.max_metadata_count
+                ),
+            ));
+        }
+        if size > MAX_METADATA_SIZE {
+            return Err(StorageError::with_message(
+                ErrorCode::MetadataTooLarge,
+                format!(
+                    "The metadata names and values total {} bytes, more than the limit of {}.",
+                    size, MAX_METADATA_SIZE
+                ),
+            ));
+        }
+        Ok(metadata)
+    }
+
+    /// Returns the blob index tags from the `x-ms-tags` header, which is
+    /// encoded like a query string (`k1=v1&k2=v2`). The tags are checked
+    /// with [`validate_tags`].
+    pub fn tags(&self) -> StorageResult<HashMap<String, String>> {
+        let Some(value) = self.header("x-ms-tags") else {
+            return Ok(HashMap::new());
+        };
+        let mut tags = HashMap::new();
+        for (key, value) in url::form_urlencoded::parse(value.as_bytes()) {
+            if tags.insert(key.into_owned(), value.into_owned()).is_some() {
+                return Err(StorageError::with_message(
+                    ErrorCode::InvalidTag,
+                    "The x-ms-tags header repeats a tag key.",
+                ));
+            }
+        }
+        validate_tags(&tags)?;
+        Ok(tags)
+    }
+
+    /// Returns the snapshot query parameter, normalized to the stored
+    /// 7-fractional-digit format.
     pub fn snapshot(&self) -> Option<&str> {
         self.query_param("snapshot")
     }
@@ -196,14 +554,16 @@ impl RequestContext {
         self.query_param("timeout").and_then(|v| v.parse().ok())
     }
 
-    /// Returns the restype query parameter.
+    /// Returns the lowercased restype query parameter. An empty value counts
+    /// as absent.
     pub fn restype(&self) -> Option<&str> {
-        self.query_param("restype")
+        self.query_param("restype").filter(|restype| !restype.is_empty())
     }
 
-    /// Returns the comp query parameter.
+    /// Returns the lowercased comp query parameter. An empty value counts as
+    /// absent.
     pub fn comp(&self) -> Option<&str> {
-        self.query_param("comp")
+        self.query_param("comp").filter(|comp| !comp.is_empty())
     }
 
     /// Returns whether this is a service-level request.
@@ -222,6 +582,152 @@ impl RequestContext {
     }
 }
 
+/// Name of the root container, addressable as `/{account}/{blob}`.
+pub const ROOT_CONTAINER: &str = "$root";
+
+/// System containers, hidden from List Containers unless `include=system`.
+pub const SYSTEM_CONTAINERS: [&str; 2] = ["$logs", "$web"];
+
+/// Returns whether `name` is one of the [`SYSTEM_CONTAINERS`].
+pub fn is_system_container(name: &str) -> bool {
+    SYSTEM_CONTAINERS.contains(&name)
+}
+
+/// Validates a container name: 3-63 lowercase letters, digits and single
+/// hyphens, starting and ending with a letter or digit. The root container
+/// and the system containers are also accepted; any other name starting
+/// with `$` is reserved.
+pub fn validate_container_name(name: &str) -> StorageResult<()> {
+    if name == ROOT_CONTAINER || is_system_container(name) {
+        return Ok(());
+    }
+
+    if name.starts_with('$') {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            "Container names starting with '$' are reserved for system containers",
+        ));
+    }
+
+    // Container names must be 3-63 characters
+    if name.len() < 3 || name.len() > 63 {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            "Container name must be between 3 and 63 characters",
+        ));
+    }
+
+    // Can only contain lowercase letters, numbers, and hyphens
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            "Container name can only contain lowercase letters, numbers, and hyphens",
+        ));
+    }
+
+    // Must start and end with a letter or number
+    if name.starts_with('-') || name.ends_with('-') {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            "Container name must start and end with a letter or number",
+        ));
+    }
+
+    // Cannot have consecutive hyphens
+    if name.contains("--") {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            "Container name cannot have consecutive hyphens",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Error for a request carrying `name`, one of
+/// [`RequestContext::unsupported_headers`].
+pub fn unsupported_header(name: &str) -> StorageError {
+    let message = if DFS_HEADERS.binary_search(&name).is_ok() {
+        format!("The header {} is only supported by the Data Lake Storage endpoint.", name)
+    } else {
+        format!("The header {} is not supported by the Blob service.", name)
+    };
+    StorageError::with_message(ErrorCode::UnsupportedHeader, message).with_detail("HeaderName", name)
+}
+
+/// Maximum length of a blob name, in characters.
+pub const MAX_BLOB_NAME_LENGTH: usize = 1024;
+
+/// Validates a blob name, as decoded from the request path.
+///
+/// Names are 1 to [`MAX_BLOB_NAME_LENGTH`] characters and may not contain
+/// empty segments (`a//b`). As in Azurite, a trailing `/` or `.` is kept as
+/// part of the name, and `\` is an ordinary character rather than a
+/// separator. Signatures cover the path as sent, not this decoded name.
+pub fn validate_blob_name(name: &str) -> StorageResult<()> {
+    let length = name.chars().count();
+    if length == 0 || length > MAX_BLOB_NAME_LENGTH {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            format!("Blob name must be between 1 and {} characters", MAX_BLOB_NAME_LENGTH),
+        ));
+    }
+
+    let segments = name.strip_suffix('/').unwrap_or(name);
+    if segments.split('/').any(str::is_empty) {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidResourceName,
+            "Blob name cannot contain empty path segments",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validates the `timeout` query parameter: a positive number of seconds
+/// no larger than [`MAX_TIMEOUT_SECONDS`].
+fn validate_timeout(value: &str) -> StorageResult<()> {
+    parse_ranged_query_param("timeout", value, 1, MAX_TIMEOUT_SECONDS).map(|_| ())
+}
+
+/// Validates a snapshot timestamp and normalizes it to the format snapshots
+/// are stored under (`2024-01-27T12:34:56.1234567Z`), so values sent with a
+/// different fractional precision or offset still find the snapshot.
+pub fn normalize_snapshot(name: &str, value: &str) -> StorageResult<String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| format_iso8601(&dt.with_timezone(&Utc)))
+        .map_err(|_| {
+            StorageError::invalid_query_parameter(
+                name,
+                value,
+                "The value must be an ISO 8601 date-time.",
+            )
+        })
+}
+
+/// Parses an integer query parameter that must lie within `min..=max`.
+/// Non-integers are `InvalidQueryParameterValue`; integers outside the range
+/// are `OutOfRangeQueryParameterValue`.
+pub fn parse_ranged_query_param(name: &str, value: &str, min: u32, max: u32) -> StorageResult<u32> {
+    let parsed: i64 = value.parse().map_err(|_| {
+        StorageError::invalid_query_parameter(name, value, "The value must be an integer.")
+    })?;
+
+    if parsed < i64::from(min) || parsed > i64::from(max) {
+        return Err(StorageError::out_of_range_query_parameter(
+            name,
+            value,
+            i64::from(min),
+            i64::from(max),
+        ));
+    }
+
+    Ok(parsed as u32)
+}
+
 /// Parses a Range header value like "bytes=0-1023" or "bytes=0-".
 fn parse_range_header(value: &str) -> Option<(u64, Option<u64>)> {
     let value = value.strip_prefix("bytes=")?;
@@ -239,7 +745,7 @@ fn parse_range_header(value: &str) -> Option<(u64, Option<u64>)> {
 }
 
 /// Parses an HTTP date in RFC 1123 format.
-fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
     DateTime::parse_from_rfc2822(value)
         .ok()
         .map(|dt| dt.with_timezone(&Utc))
@@ -251,6 +757,29 @@ fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
         })
 }
 
+/// Checks blob index tags against the service's rules: at most
+/// [`MAX_TAG_COUNT`] tags, keys of 1 to 128 and values of up to 256
+/// characters, made of letters, digits, spaces and `+-./:=_`.
+pub fn validate_tags(tags: &HashMap<String, String>) -> StorageResult<()> {
+    if tags.len() > MAX_TAG_COUNT {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidTag,
+            format!("{} tags were given, more than the limit of {}.", tags.len(), MAX_TAG_COUNT),
+        ));
+    }
+    let permitted = |s: &str| {
+        s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '+' | '-' | '.' | '/' | ':' | '=' | '_'))
+    };
+    for (key, value) in tags {
+        let key_len = key.chars().count();
+        if key_len == 0 || key_len > 128 || value.chars().count() > 256 || !permitted(key) || !permitted(value) {
+            return Err(StorageError::new(ErrorCode::InvalidTag));
+        }
+    }
+    Ok(())
+}
+
 /// Query parameters for list operations.
 #[derive(Debug, Clone, Default)]
 pub struct ListParams {
@@ -261,20 +790,47 @@ pub struct ListParams {
     pub include: Vec<String>,
 }
 
+/// Largest `maxresults` accepted by list operations.
+pub const MAX_LIST_RESULTS: u32 = 5000;
+
 impl ListParams {
-    pub fn from_query(query: &HashMap<String, String>) -> Self {
+    /// Builds list parameters from query pairs. `include` may be repeated
+    /// and each value may itself be a comma-separated list. `maxresults`
+    /// must be an integer between 1 and [`MAX_LIST_RESULTS`]. Names match
+    /// case-insensitively, like every query parameter name.
+    pub fn from_query(query: &[(String, String)]) -> StorageResult<Self> {
+        let get = |name: &str| {
+            query
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+
         let include = query
-            .get("include")
-            .map(|s| s.split(',').map(String::from).collect())
-            .unwrap_or_default();
-
-        Self {
-            prefix: query.get("prefix").cloned(),
-            delimiter: query.get("delimiter").cloned(),
-            marker: query.get("marker").cloned(),
-            maxresults: query.get("maxresults").and_then(|v| v.parse().ok()),
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("include"))
+            .flat_map(|(_, value)| value.split(','))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_ascii_lowercase())
+            .collect();
+
+        let maxresults = get("maxresults")
+            .map(|v| parse_ranged_query_param("maxresults", &v, 1, MAX_LIST_RESULTS))
+            .transpose()?;
+
+        Ok(Self {
+            prefix: get("prefix"),
+            delimiter: get("delimiter"),
+            marker: get("marker"),
+            maxresults,
             include,
-        }
+        })
+    }
+
+    /// Returns whether `include` lists the given dataset (e.g. "copy").
+    pub fn includes(&self, dataset: &str) -> bool {
+        self.include.iter().any(|value| value.eq_ignore_ascii_case(dataset))
     }
 }
 
@@ -285,5 +841,164 @@ pub fn format_http_date(dt: &DateTime<Utc>) -> String {
 
 /// Formats a DateTime as ISO 8601 format.
 pub fn format_iso8601(dt: &DateTime<Utc>) -> String {
-    dt.format("%Y-%m-%dT%H:%M:%S.%7fZ").to_string()
+    // chrono has no 7-digit fraction specifier; emit 100-nanosecond units
+    format!(
+        "{}.{:07}Z",
+        dt.format("%Y-%m-%dT%H:%M:%S"),
+        dt.timestamp_subsec_nanos() / 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unsupported_header, validate_blob_name, RequestContext, BLOB_HEADERS, DFS_HEADERS, MAX_METADATA_SIZE};
+    use crate::error::ErrorCode;
+
+    use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+    use std::collections::HashMap;
+
+    fn context_with_headers(headers: &[(&str, &str)]) -> RequestContext {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.append(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        let mut path_params = HashMap::new();
+        path_params.insert("account".to_string(), "devstoreaccount1".to_string());
+        RequestContext::new(Method::GET, Uri::from_static("/devstoreaccount1"), header_map, path_params, Vec::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_header_lookup_ignores_case() {
+        let ctx = context_with_headers(&[("X-Ms-Lease-Id", "abc"), ("x-ms-lease-id", "def")]);
+        assert_eq!(ctx.header("x-ms-lease-id"), Some("abc"));
+        assert_eq!(ctx.header("X-MS-LEASE-ID"), Some("abc"));
+        assert_eq!(ctx.header_values("X-MS-Lease-Id").as_deref(), Some("abc,def"));
+        assert_eq!(ctx.header_values("x-ms-missing"), None);
+    }
+
+    #[test]
+    fn test_service_endpoint_follows_request_then_config() {
+        let mut ctx = context_with_headers(&[]);
+        ctx.configured_base_url = "https://blob.example:8443".to_string();
+        assert_eq!(ctx.service_endpoint(), "https://blob.example:8443/devstoreaccount1/");
+
+        let mut ctx = context_with_headers(&[("host", "localhost:9000")]);
+        ctx.configured_base_url = "https://blob.example:8443".to_string();
+        ctx.mount_path = "/azure".to_string();
+        assert_eq!(ctx.service_endpoint(), "https://localhost:9000/azure/devstoreaccount1/");
+
+        ctx.uri = Uri::from_static("http://proxy.example/devstoreaccount1?comp=list");
+        assert_eq!(ctx.service_endpoint(), "http://proxy.example/azure/devstoreaccount1/");
+    }
+
+    #[test]
+    fn test_metadata_joins_repeated_keys() {
+        let ctx = context_with_headers(&[
+            ("X-Ms-Meta-Color", "red"),
+            ("x-ms-meta-color", "blue"),
+            ("x-ms-meta-Size", "1"),
+        ]);
+        let metadata = ctx.metadata().unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata.get("color"), Some("red,blue"));
+        assert_eq!(metadata.get("size"), Some("1"));
+    }
+
+    #[test]
+    fn test_metadata_keeps_client_spelling() {
+        let headers = [("x-ms-meta-MyKey", "1"), ("X-Ms-Meta-Other", "2"), ("x-ms-meta-other", "3")];
+        let mut ctx = context_with_headers(&headers);
+        for (name, _) in headers {
+            ctx.header_case.push(name.to_string());
+        }
+        let metadata = ctx.metadata().unwrap();
+        assert_eq!(metadata.iter().collect::<Vec<_>>(), [("MyKey", "1"), ("Other", "2,3")]);
+    }
+
+    #[test]
+    fn test_metadata_rejects_empty_key() {
+        let ctx = context_with_headers(&[("x-ms-meta-a", "1"), ("x-ms-meta-", "orphan")]);
+        assert_eq!(ctx.metadata().unwrap_err().code, ErrorCode::EmptyMetadataKey);
+    }
+
+    #[test]
+    fn test_metadata_count_limit() {
+        let mut ctx = context_with_headers(&[("x-ms-meta-a", "1"), ("x-ms-meta-b", "2"), ("X-Ms-Meta-B", "3")]);
+        assert_eq!(ctx.metadata().unwrap().len(), 2);
+
+        // Keys merged by case count once
+        ctx.max_metadata_count = 2;
+        assert_eq!(ctx.metadata().unwrap().len(), 2);
+        ctx.max_metadata_count = 1;
+        assert_eq!(ctx.metadata().unwrap_err().code, ErrorCode::MetadataTooLarge);
+    }
+
+    #[test]
+    fn test_metadata_size_limit() {
+        let value = "v".repeat(MAX_METADATA_SIZE - 2);
+        let ctx = context_with_headers(&[("x-ms-meta-ab", &value)]);
+        assert_eq!(ctx.metadata().unwrap().get("ab"), Some(value.as_str()));
+
+        let ctx = context_with_headers(&[("x-ms-meta-abc", &value)]);
+        assert_eq!(ctx.metadata().unwrap_err().code, ErrorCode::MetadataTooLarge);
+    }
+
+    #[test]
+    fn test_unsupported_headers() {
+        for list in [BLOB_HEADERS, DFS_HEADERS] {
+            assert!(list.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+
+        let ctx = context_with_headers(&[
+            ("x-ms-version", "2021-10-04"),
+            ("x-ms-meta-key", "value"),
+            ("x-ms-blob-content-typo", "text/plain"),
+            ("x-ms-metadata-key", "value"),
+            ("x-ms-upn", "true"),
+            ("content-type", "text/plain"),
+        ]);
+        let mut unsupported = ctx.unsupported_headers();
+        unsupported.sort();
+        assert_eq!(unsupported, ["x-ms-blob-content-typo", "x-ms-metadata-key", "x-ms-upn"]);
+
+        let error = unsupported_header("x-ms-upn");
+        assert_eq!(error.code, ErrorCode::UnsupportedHeader);
+        assert!(error.message.contains("Data Lake"));
+        let error = unsupported_header("x-ms-metadata-key");
+        assert!(!error.message.contains("Data Lake"));
+    }
+
+    #[test]
+    fn test_ms_headers_are_sorted_and_joined() {
+        let ctx = context_with_headers(&[
+            ("x-ms-version", "2021-10-04"),
+            ("X-Ms-Meta-B", "2"),
+            ("x-ms-meta-a", "1"),
+            ("x-ms-meta-b", "3"),
+            ("content-type", "text/plain"),
+        ]);
+        assert_eq!(
+            ctx.ms_headers(),
+            vec![
+                ("x-ms-meta-a", "1".to_string()),
+                ("x-ms-meta-b", "2,3".to_string()),
+                ("x-ms-version", "2021-10-04".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blob_name_validation() {
+        for name in ["a", "dir/file.txt", "dir/", "name.", "back\\slash", "with space#"] {
+            assert!(validate_blob_name(name).is_ok(), "{}", name);
+        }
+        assert!(validate_blob_name(&"é".repeat(1024)).is_ok());
+        for name in ["", "a//b", "/a", "dir//", &"a".repeat(1025)] {
+            assert!(validate_blob_name(name).is_err(), "{}", name);
+        }
+    }
 }
@@ -95,6 +95,7 @@ pub enum ErrorCode {
     InvalidPageRange,
     InvalidSourceBlobType,
     InvalidSourceBlobUrl,
+    InvalidTag,
     InvalidVersionForPageBlobOperation,
     LeaseAlreadyBroken,
     LeaseAlreadyPresent,
@@ -219,6 +220,7 @@ impl ErrorCode {
             ErrorCode::InvalidPageRange => "InvalidPageRange",
             ErrorCode::InvalidSourceBlobType => "InvalidSourceBlobType",
             ErrorCode::InvalidSourceBlobUrl => "InvalidSourceBlobUrl",
+            ErrorCode::InvalidTag => "InvalidTag",
             ErrorCode::InvalidVersionForPageBlobOperation => "InvalidVersionForPageBlobOperation",
             ErrorCode::LeaseAlreadyBroken => "LeaseAlreadyBroken",
             ErrorCode::LeaseAlreadyPresent => "LeaseAlreadyPresent",
@@ -272,14 +274,12 @@ impl ErrorCode {
             | ErrorCode::InvalidMd5
             | ErrorCode::InvalidMetadata
             | ErrorCode::InvalidQueryParameterValue
-            | ErrorCode::InvalidRange
             | ErrorCode::InvalidResourceName
             | ErrorCode::InvalidUri
             | ErrorCode::InvalidXmlDocument
             | ErrorCode::InvalidXmlNodeValue
             | ErrorCode::Md5Mismatch
             | ErrorCode::MetadataTooLarge
-            | ErrorCode::MissingContentLengthHeader
             | ErrorCode::MissingRequiredQueryParameter
             | ErrorCode::MissingRequiredHeader
             | ErrorCode::MissingRequiredXmlNode
@@ -300,6 +300,7 @@ impl ErrorCode {
             | ErrorCode::InvalidPageRange
             | ErrorCode::InvalidSourceBlobType
             | ErrorCode::InvalidSourceBlobUrl
+            | ErrorCode::InvalidTag
             | ErrorCode::InvalidVersionForPageBlobOperation
             | ErrorCode::BlockCountExceedsLimit
             | ErrorCode::BlockListTooLong
@@ -334,10 +335,12 @@ impl ErrorCode {
             | ErrorCode::AccountBeingCreated
             | ErrorCode::BlobAlreadyExists
             | ErrorCode::BlobArchived
+            | ErrorCode::BlobTierInadequateForContentLength
             | ErrorCode::BlobBeingRehydrated
             | ErrorCode::BlobImmutableDueToPolicy
             | ErrorCode::BlobNotArchived
             | ErrorCode::BlobOverwritten
+            | ErrorCode::CannotChangeToLowerTier
             | ErrorCode::ContainerAlreadyExists
             | ErrorCode::ContainerBeingDeleted
             | ErrorCode::ContainerDisabled
@@ -360,6 +363,9 @@ impl ErrorCode {
             | ErrorCode::SnapshotsPresent
             | ErrorCode::SystemInUse => StatusCode::CONFLICT,
 
+            // 411 Length Required
+            ErrorCode::MissingContentLengthHeader => StatusCode::LENGTH_REQUIRED,
+
             // 412 Precondition Failed
             ErrorCode::AppendPositionConditionNotMet
             | ErrorCode::ConditionNotMet
@@ -395,20 +401,35 @@ impl ErrorCode {
             ErrorCode::AuthorizationFailure => {
                 "This request is not authorized to perform this operation."
             }
+            ErrorCode::AuthorizationPermissionMismatch => {
+                "This request is not authorized to perform this operation using this permission."
+            }
             ErrorCode::BlobNotFound => "The specified blob does not exist.",
+            ErrorCode::CannotVerifyCopySource => "Could not verify the copy source within the specified time.",
             ErrorCode::ContainerAlreadyExists => "The specified container already exists.",
+            ErrorCode::ConditionNotMet => "The condition specified using HTTP conditional header(s) is not met.",
             ErrorCode::ContainerNotFound => "The specified container does not exist.",
+            ErrorCode::EmptyMetadataKey => "The key for one of the metadata key-value pairs is empty.",
             ErrorCode::InvalidBlockId => "The specified block ID is invalid.",
             ErrorCode::InvalidBlockList => "The specified block list is invalid.",
             ErrorCode::InvalidHeaderValue => "The value for one of the HTTP headers is not valid.",
             ErrorCode::InvalidRange => "The range specified is invalid for the current size of the resource.",
             ErrorCode::InvalidResourceName => "The specified resource name contains invalid characters.",
+            ErrorCode::InvalidTag => "The tags specified are invalid. It contains characters that are not permitted.",
             ErrorCode::InvalidXmlDocument => "The XML request body is invalid.",
+            ErrorCode::InvalidXmlNodeValue => "The value for one of the XML nodes is not in the correct format.",
             ErrorCode::LeaseIdMissing => "There is currently a lease on the resource and no lease ID was specified in the request.",
+            ErrorCode::MetadataTooLarge => "The metadata specified exceeds the maximum permitted size.",
             ErrorCode::MissingRequiredHeader => "A required header was not specified.",
             ErrorCode::MissingRequiredQueryParameter => "A required query parameter was not specified.",
             ErrorCode::ResourceNotFound => "The specified resource does not exist.",
             ErrorCode::InternalError => "The server encountered an internal error. Please retry the request.",
+            ErrorCode::InvalidQueryParameterValue => "Value for one of the query parameters specified in the request URI is invalid.",
+            ErrorCode::OperationTimedOut => "The operation could not be completed within the permitted time.",
+            ErrorCode::OutOfRangeQueryParameterValue => "One of the query parameters specified in the request URI is outside the permissible range.",
+            ErrorCode::UnsupportedHeader => "One of the HTTP headers specified in the request is not supported.",
+            ErrorCode::UnsupportedXmlNode => "One of the XML nodes specified in the request body is not supported.",
+            ErrorCode::SequenceNumberIncrementTooLarge => "The sequence number increment cannot be performed because it would result in overflow of the sequence number.",
             _ => "An error occurred while processing the request.",
         }
     }
@@ -421,6 +442,13 @@ pub struct StorageError {
     pub code: ErrorCode,
     pub message: String,
     pub request_id: Option<String>,
+    /// Time stamped in the error body; the current time if unset.
+    pub time: Option<chrono::DateTime<chrono::Utc>>,
+    /// Status to respond with instead of the one implied by `code`.
+    pub status: Option<StatusCode>,
+    /// Additional elements emitted after `<Message>` in the error body,
+    /// e.g. `QueryParameterName`.
+    pub details: Vec<(String, String)>,
 }
 
 impl StorageError {
@@ -430,6 +458,9 @@ impl StorageError {
             message: code.default_message().to_string(),
             code,
             request_id: None,
+            time: None,
+            status: None,
+            details: Vec::new(),
         }
     }
 
@@ -439,45 +470,151 @@ impl StorageError {
             code,
             message: message.into(),
             request_id: None,
+            time: None,
+            status: None,
+            details: Vec::new(),
         }
     }
 
+    /// Creates an `InvalidQueryParameterValue` error naming the parameter.
+    pub fn invalid_query_parameter(
+        name: &str,
+        value: &str,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self::new(ErrorCode::InvalidQueryParameterValue)
+            .with_detail("QueryParameterName", name)
+            .with_detail("QueryParameterValue", value)
+            .with_detail("Reason", reason)
+    }
+
+    /// Creates an `OutOfRangeQueryParameterValue` error naming the parameter
+    /// and its permitted range.
+    pub fn out_of_range_query_parameter(name: &str, value: &str, min: i64, max: i64) -> Self {
+        Self::new(ErrorCode::OutOfRangeQueryParameterValue)
+            .with_detail("QueryParameterName", name)
+            .with_detail("QueryParameterValue", value)
+            .with_detail(
+                "Reason",
+                format!("The value must be between {} and {}.", min, max),
+            )
+            .with_detail("MinimumAllowed", min.to_string())
+            .with_detail("MaximumAllowed", max.to_string())
+    }
+
+    /// Creates an `InvalidXmlNodeValue` error naming the XML element and the
+    /// value it carried.
+    pub fn invalid_xml_node_value(name: &str, value: &str, reason: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidXmlNodeValue)
+            .with_detail("XmlNodeName", name)
+            .with_detail("XmlNodeValue", value)
+            .with_detail("Reason", reason)
+    }
+
+    /// Creates an `UnsupportedXmlNode` error naming the XML element.
+    pub fn unsupported_xml_node(name: &str) -> Self {
+        Self::new(ErrorCode::UnsupportedXmlNode).with_detail("XmlNodeName", name)
+    }
+
+    /// Creates a `MissingRequiredHeader` error naming the header.
+    pub fn missing_required_header(name: &str) -> Self {
+        Self::new(ErrorCode::MissingRequiredHeader).with_detail("HeaderName", name)
+    }
+
+    /// Creates a `MissingRequiredQueryParameter` error naming the parameter.
+    pub fn missing_required_query_parameter(name: &str) -> Self {
+        Self::new(ErrorCode::MissingRequiredQueryParameter).with_detail("QueryParameterName", name)
+    }
+
+    /// Creates an `InvalidHeaderValue` error naming the header and the
+    /// value it carried.
+    pub fn invalid_header_value(name: &str, value: &str) -> Self {
+        Self::new(ErrorCode::InvalidHeaderValue).with_header_detail(name, value)
+    }
+
+    /// Creates an `AuthenticationFailed` error explaining why the request
+    /// could not be authenticated.
+    pub fn authentication_failed(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::AuthenticationFailed).with_detail("AuthenticationErrorDetail", detail)
+    }
+
+    /// Adds `HeaderName` and `HeaderValue` detail elements to the error body.
+    pub fn with_header_detail(self, name: &str, value: &str) -> Self {
+        self.with_detail("HeaderName", name).with_detail("HeaderValue", value)
+    }
+
+    /// Adds a detail element to the error body.
+    pub fn with_detail(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.details.push((name.into(), value.into()));
+        self
+    }
+
+    /// Responds with `status` instead of the status implied by the code.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Returns the HTTP status of the error response.
+    pub fn status_code(&self) -> StatusCode {
+        self.status.unwrap_or_else(|| self.code.status_code())
+    }
+
     /// Sets the request ID for this error.
     pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
         self.request_id = Some(request_id.into());
         self
     }
 
-    /// Converts the error to an XML error response body.
-    pub fn to_xml(&self) -> String {
-        format!(
-            r#"<?xml version="1.0" encoding="utf-8"?><Error><Code>{}</Code><Message>{}</Message></Error>"#,
-            self.code.as_str(),
-            xml_escape(&self.message)
-        )
+    /// Sets the time stamped in the error body.
+    pub fn with_time(mut self, time: chrono::DateTime<chrono::Utc>) -> Self {
+        self.time = Some(time);
+        self
     }
-}
 
-impl IntoResponse for StorageError {
-    fn into_response(self) -> Response {
-        let status = self.code.status_code();
-        let request_id = self.request_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-        let timestamp = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+    /// Renders the XML error body served to clients, stamped with the given
+    /// request ID. Batch sub-responses embed the same body.
+    pub fn error_body(&self, request_id: &str) -> String {
+        let timestamp = self.time.unwrap_or_else(chrono::Utc::now).format("%Y-%m-%dT%H:%M:%S%.3fZ");
+
+        let details: String = self
+            .details
+            .iter()
+            .map(|(name, value)| format!("  <{}>{}</{}>\n", name, xml_escape(value), name))
+            .collect();
 
         // Match original Azurite's XML format with pretty-printing and included RequestId/Time
-        let xml = format!(
+        format!(
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
 <Error>
   <Code>{}</Code>
   <Message>{}
 RequestId:{}
 Time:{}</Message>
-</Error>"#,
+{}</Error>"#,
             self.code.as_str(),
             xml_escape(&self.message),
             request_id,
-            timestamp
-        );
+            timestamp,
+            details
+        )
+    }
+
+    /// Converts the error to an XML error response body.
+    pub fn to_xml(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><Error><Code>{}</Code><Message>{}</Message></Error>"#,
+            self.code.as_str(),
+            xml_escape(&self.message)
+        )
+    }
+}
+
+impl IntoResponse for StorageError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let request_id = self.request_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let xml = self.error_body(&request_id);
 
         // Build the response
         let mut response = Response::builder()
@@ -0,0 +1,59 @@
+//! Administrative endpoints, served only when enabled in the configuration.
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::storage::{verify_extents, AccountStats, ContainerStats, ExtentStats, ExtentStore, MetadataStore};
+
+use super::build_response;
+
+/// Document returned by [`storage_stats`].
+#[derive(Debug, Serialize)]
+struct StorageStats {
+    /// Record counts by account.
+    accounts: BTreeMap<String, AccountStats>,
+    /// Blob counts by account, then container.
+    containers: BTreeMap<String, BTreeMap<String, ContainerStats>>,
+    /// Extents across all accounts; extent data is not attributed to accounts.
+    extents: ExtentStats,
+}
+
+/// GET /__admin/stats - Record counts and stored bytes, as JSON.
+pub async fn storage_stats(
+    metadata: Arc<dyn MetadataStore>,
+    extents: Arc<dyn ExtentStore>,
+) -> StorageResult<Response<Body>> {
+    let metadata_stats = metadata.stats().await;
+    let stats = StorageStats {
+        accounts: metadata_stats.accounts,
+        containers: metadata_stats.containers,
+        extents: extents.stats().await,
+    };
+    json_response(&stats, "storage stats")
+}
+
+/// GET /__admin/verify - References to extents missing from the extent
+/// store, as a JSON array. Empty when the stores agree.
+pub async fn verify_storage(
+    metadata: Arc<dyn MetadataStore>,
+    extents: Arc<dyn ExtentStore>,
+) -> StorageResult<Response<Body>> {
+    let dangling = verify_extents(&*metadata, &*extents).await?;
+    json_response(&dangling, "verification report")
+}
+
+fn json_response(value: &impl Serialize, what: &str) -> StorageResult<Response<Body>> {
+    let body = serde_json::to_vec(value).map_err(|e| {
+        StorageError::with_message(ErrorCode::InternalError, format!("Failed to encode {}: {}", what, e))
+    })?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(build_response(StatusCode::OK, headers, Body::from(body)))
+}
@@ -10,9 +10,15 @@ use std::sync::Arc;
 use crate::context::{format_http_date, RequestContext};
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::models::{BlobModel, BlobType};
+use crate::router::AppState;
 use crate::storage::{ExtentStore, MetadataStore};
 
-use super::{add_blob_headers, blob::check_blob_lease, build_response, common_headers};
+use super::{
+    add_blob_headers, add_request_server_encrypted,
+    blob::{check_blob_lease, check_sas_overwrite_permission, request_tags},
+    blob_content_type, build_response, common_headers,
+    copy_source::fetch_copy_source, require_content_length,
+};
 
 /// Maximum number of append blocks (50,000).
 const MAX_APPEND_BLOCK_COUNT: u32 = 50_000;
@@ -23,6 +29,7 @@ const MAX_APPEND_BLOCK_SIZE: u64 = 100 * 1024 * 1024;
 pub async fn create_append_blob(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
+    body: Bytes,
 ) -> StorageResult<Response<Body>> {
     let container = ctx
         .container
@@ -33,14 +40,26 @@ pub async fn create_append_blob(
         .as_ref()
         .ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    require_content_length(ctx)?;
+
     // Verify container exists
     if !metadata.container_exists(&ctx.account, container).await {
         return Err(StorageError::new(ErrorCode::ContainerNotFound));
     }
 
+    // Blocks are added with Append Block; the create body must be empty
+    if !body.is_empty() {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidOperation,
+            "The request body must be empty when creating an append blob.",
+        ));
+    }
+
     // Check if blob exists and validate lease
-    if let Ok(existing_blob) = metadata.get_blob(&ctx.account, container, blob_name, "").await {
-        check_blob_lease(&existing_blob, ctx.lease_id())?;
+    let existing_blob = metadata.get_blob(&ctx.account, container, blob_name, "").await.ok();
+    if let Some(existing_blob) = &existing_blob {
+        check_blob_lease(existing_blob, ctx)?;
+        check_sas_overwrite_permission(ctx)?;
     }
 
     // Create append blob model
@@ -50,12 +69,12 @@ pub async fn create_append_blob(
         blob_name.clone(),
         BlobType::AppendBlob,
         0, // Initial size is 0
+        ctx.timestamp,
     );
+    blob.properties.renew_etag(&ctx.etags);
 
     // Set content properties from headers
-    if let Some(ct) = ctx.header("x-ms-blob-content-type") {
-        blob.properties.content_type = Some(ct.to_string());
-    }
+    blob.properties.content_type = Some(blob_content_type(ctx, false));
     if let Some(ce) = ctx.header("x-ms-blob-content-encoding") {
         blob.properties.content_encoding = Some(ce.to_string());
     }
@@ -76,15 +95,10 @@ pub async fn create_append_blob(
     blob.properties.committed_block_count = Some(0);
     blob.properties.is_sealed = Some(false);
 
-    // Set access tier
-    if let Some(tier) = ctx.header("x-ms-access-tier") {
-        if let Some(t) = crate::models::AccessTier::from_str(tier) {
-            blob.properties.access_tier = t;
-        }
-    }
 
-    // Set metadata
-    blob.metadata = ctx.metadata();
+    // Set metadata and tags
+    blob.metadata = ctx.metadata()?;
+    blob.tags = request_tags(ctx)?;
 
     // Create blob
     metadata.create_blob(blob.clone()).await?;
@@ -95,10 +109,7 @@ pub async fn create_append_blob(
         &blob.properties.etag,
         &blob.properties.last_modified,
     );
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
-    );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -129,64 +140,76 @@ pub async fn append_block(
         ));
     }
 
-    let mut blob = metadata
-        .get_blob(&ctx.account, container, blob_name, "")
-        .await?;
-
-    // Verify blob type
-    if blob.properties.blob_type != BlobType::AppendBlob {
-        return Err(StorageError::new(ErrorCode::InvalidBlobType));
-    }
-
-    // Check if blob is sealed
-    if blob.properties.is_sealed == Some(true) {
-        return Err(StorageError::with_message(
-            ErrorCode::InvalidOperation,
-            "Cannot append to a sealed blob",
-        ));
-    }
-
-    // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
-
-    // Check block count limit
-    let current_block_count = blob.properties.committed_block_count.unwrap_or(0);
-    if current_block_count >= MAX_APPEND_BLOCK_COUNT {
-        return Err(StorageError::new(ErrorCode::BlockCountExceedsLimit));
-    }
-
-    // Check appendpos condition
-    if let Some(expected_pos) = ctx.header("x-ms-blob-condition-appendpos") {
-        let expected: u64 = expected_pos.parse().map_err(|_| {
-            StorageError::new(ErrorCode::InvalidHeaderValue)
-        })?;
-        if blob.properties.content_length != expected {
-            return Err(StorageError::new(ErrorCode::AppendPositionConditionNotMet));
-        }
-    }
-
-    // Check maxsize condition
-    if let Some(max_size) = ctx.header("x-ms-blob-condition-maxsize") {
-        let max: u64 = max_size.parse().map_err(|_| {
-            StorageError::new(ErrorCode::InvalidHeaderValue)
-        })?;
-        if blob.properties.content_length + block_size > max {
-            return Err(StorageError::new(ErrorCode::MaxBlobSizeConditionNotMet));
-        }
-    }
-
-    let append_offset = blob.properties.content_length;
-
-    // Store block data
+    let append_position = ctx
+        .header("x-ms-blob-condition-appendpos")
+        .map(|value| {
+            value
+                .parse::<u64>()
+                .map_err(|_| StorageError::invalid_header_value("x-ms-blob-condition-appendpos", value))
+        })
+        .transpose()?;
+    let max_size = ctx
+        .header("x-ms-blob-condition-maxsize")
+        .map(|value| {
+            value
+                .parse::<u64>()
+                .map_err(|_| StorageError::invalid_header_value("x-ms-blob-condition-maxsize", value))
+        })
+        .transpose()?;
+
+    // Store block data, then claim the next offset. Conditions are checked
+    // and the block appended in one store update, so concurrent appends get
+    // distinct, contiguous offsets.
     let extent_chunk = extents.write(body).await?;
-
-    // Update blob
-    blob.extent_chunks.push(extent_chunk);
-    blob.properties.content_length += block_size;
-    blob.properties.committed_block_count = Some(current_block_count + 1);
-    blob.properties.update_etag();
-
-    metadata.update_blob(blob.clone()).await?;
+    let extent_id = extent_chunk.id.clone();
+    let mut append_offset = 0;
+    let result = metadata
+        .modify_blob(&ctx.account, container, blob_name, "", &mut |blob| {
+            // Verify blob type
+            if blob.properties.blob_type != BlobType::AppendBlob {
+                return Err(StorageError::new(ErrorCode::InvalidBlobType));
+            }
+
+            // Check if blob is sealed
+            if blob.properties.is_sealed == Some(true) {
+                return Err(StorageError::with_message(
+                    ErrorCode::InvalidOperation,
+                    "Cannot append to a sealed blob",
+                ));
+            }
+
+            // Check lease
+            check_blob_lease(blob, ctx)?;
+
+            // Check block count limit
+            let current_block_count = blob.committed_block_count().unwrap_or(0);
+            if current_block_count >= MAX_APPEND_BLOCK_COUNT {
+                return Err(StorageError::new(ErrorCode::BlockCountExceedsLimit));
+            }
+
+            // Check appendpos and maxsize conditions
+            if append_position.is_some_and(|expected| blob.properties.content_length != expected) {
+                return Err(StorageError::new(ErrorCode::AppendPositionConditionNotMet));
+            }
+            if max_size.is_some_and(|max| blob.properties.content_length + block_size > max) {
+                return Err(StorageError::new(ErrorCode::MaxBlobSizeConditionNotMet));
+            }
+
+            append_offset = blob.properties.content_length;
+            blob.extent_chunks.push(extent_chunk.clone());
+            blob.properties.content_length += block_size;
+            blob.properties.committed_block_count = Some(current_block_count + 1);
+            blob.properties.update_etag(&ctx.etags, ctx.timestamp);
+            Ok(())
+        })
+        .await;
+    let blob = match result {
+        Ok(blob) => blob,
+        Err(e) => {
+            let _ = extents.delete(&extent_id).await;
+            return Err(e);
+        }
+    };
 
     let mut headers = common_headers();
     add_blob_headers(
@@ -200,28 +223,17 @@ pub async fn append_block(
     );
     headers.insert(
         "x-ms-blob-committed-block-count",
-        HeaderValue::from_str(&blob.properties.committed_block_count.unwrap_or(0).to_string())
-            .unwrap(),
-    );
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
+        HeaderValue::from_str(&blob.committed_block_count().unwrap_or(0).to_string()).unwrap(),
     );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
 
 /// PUT /{container}/{blob}?comp=appendblock&fromURL - Append block from URL.
-pub async fn append_block_from_url(
-    ctx: &RequestContext,
-    metadata: Arc<dyn MetadataStore>,
-    extents: Arc<dyn ExtentStore>,
-) -> StorageResult<Response<Body>> {
-    // Simplified implementation - would need to fetch from source URL
-    Err(StorageError::with_message(
-        ErrorCode::InvalidOperation,
-        "Append block from URL not implemented",
-    ))
+pub async fn append_block_from_url(ctx: &RequestContext, state: &AppState) -> StorageResult<Response<Body>> {
+    let data = fetch_copy_source(ctx, &state.copy_source_client).await?;
+    append_block(ctx, state.metadata.clone(), state.extents.clone(), data).await
 }
 
 /// PUT /{container}/{blob}?comp=seal - Seal append blob.
@@ -256,11 +268,11 @@ pub async fn seal_append_blob(
     }
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Seal the blob
     blob.properties.is_sealed = Some(true);
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
 
     metadata.update_blob(blob.clone()).await?;
 
@@ -16,7 +16,7 @@ use crate::context::RequestContext;
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::storage::{ExtentStore, MetadataStore};
 
-use super::{build_response, common_headers};
+use super::{build_response, common_headers, release_extents};
 
 /// A parsed sub-request from a batch body.
 struct SubRequest {
@@ -34,13 +34,14 @@ pub async fn submit_batch(
 ) -> StorageResult<Response<Body>> {
     let content_type = ctx
         .content_type()
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("Content-Type"))?;
 
     if !content_type.starts_with("multipart/mixed") {
         return Err(StorageError::with_message(
             ErrorCode::InvalidHeaderValue,
             "Content-Type must be multipart/mixed for batch requests",
-        ));
+        )
+        .with_header_detail("Content-Type", content_type));
     }
 
     let batch_boundary = extract_boundary(content_type)?;
@@ -59,25 +60,44 @@ pub async fn submit_batch(
 
     let mut response_body = String::new();
     for req in &sub_requests {
-        let (status_code, status_text, resp_headers, resp_body) = execute_sub_request(
-            ctx, &metadata, &extents, &req.method, &req.path,
-        ).await;
+        let request_id = uuid::Uuid::new_v4().to_string();
 
         response_body.push_str(&format!("--{}\r\n", response_boundary));
         response_body.push_str("Content-Type: application/http\r\n");
         response_body.push_str("Content-Transfer-Encoding: binary\r\n");
         response_body.push_str(&format!("Content-ID: {}\r\n", req.content_id));
         response_body.push_str("\r\n");
-        response_body.push_str(&format!("HTTP/1.1 {} {}\r\n", status_code, status_text));
-        response_body.push_str(&format!("x-ms-request-id: {}\r\n", uuid::Uuid::new_v4()));
-        response_body.push_str("x-ms-version: 2021-10-04\r\n");
-        for (name, value) in &resp_headers {
-            response_body.push_str(&format!("{}: {}\r\n", name, value));
-        }
-        response_body.push_str("\r\n");
-        if !resp_body.is_empty() {
-            response_body.push_str(&resp_body);
-            response_body.push_str("\r\n");
+
+        match execute_sub_request(ctx, &metadata, &extents, &req.method, &req.path).await {
+            Ok((status, resp_headers)) => {
+                response_body.push_str(&format!(
+                    "HTTP/1.1 {} {}\r\n",
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or("")
+                ));
+                push_sub_response_headers(&mut response_body, &request_id);
+                for (name, value) in &resp_headers {
+                    response_body.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                response_body.push_str("\r\n");
+            }
+            Err(e) => {
+                // Failed parts carry the same status line, error code and
+                // XML body as a standalone error response
+                let error_body = e.error_body(&request_id);
+                response_body.push_str(&format!(
+                    "HTTP/1.1 {} {}\r\n",
+                    e.status_code().as_u16(),
+                    e.message
+                ));
+                push_sub_response_headers(&mut response_body, &request_id);
+                response_body.push_str(&format!("x-ms-error-code: {}\r\n", e.code.as_str()));
+                response_body.push_str("Content-Type: application/xml\r\n");
+                response_body.push_str(&format!("Content-Length: {}\r\n", error_body.len()));
+                response_body.push_str("\r\n");
+                response_body.push_str(&error_body);
+                response_body.push_str("\r\n");
+            }
         }
     }
     response_body.push_str(&format!("--{}--", response_boundary));
@@ -108,6 +128,7 @@ fn extract_boundary(content_type: &str) -> StorageResult<String> {
                 ErrorCode::InvalidHeaderValue,
                 "Missing boundary in Content-Type",
             )
+            .with_header_detail("Content-Type", content_type)
         })
 }
 
@@ -244,19 +265,27 @@ fn parse_sub_request(part: &str) -> Option<SubRequest> {
     })
 }
 
+/// Writes the headers shared by every batch sub-response.
+fn push_sub_response_headers(out: &mut String, request_id: &str) {
+    out.push_str(&format!("x-ms-request-id: {}\r\n", request_id));
+    out.push_str("x-ms-version: 2021-10-04\r\n");
+}
+
 /// Executes a single sub-request.
-/// Returns (status_code, status_text, extra_headers, body).
+/// Returns the success status and extra headers, or the error to embed.
 async fn execute_sub_request(
     ctx: &RequestContext,
     metadata: &Arc<dyn MetadataStore>,
     extents: &Arc<dyn ExtentStore>,
     method: &str,
     path: &str,
-) -> (u16, &'static str, Vec<(&'static str, String)>, String) {
-    // URL-decode the path since Azure SDK URL-encodes blob paths in batch sub-requests
-    let decoded_path = percent_decode_str(path).decode_utf8_lossy();
-    let path_clean = decoded_path.split('?').next().unwrap_or(&decoded_path);
-    let segments: Vec<&str> = path_clean
+) -> StorageResult<(StatusCode, Vec<(&'static str, String)>)> {
+    // URL-decode the path since Azure SDK URL-encodes blob paths in batch
+    // sub-requests. The query is split off first, so an encoded `?` stays
+    // part of the blob name.
+    let path_clean = path.split('?').next().unwrap_or(path);
+    let decoded_path = percent_decode_str(path_clean).decode_utf8_lossy();
+    let segments: Vec<&str> = decoded_path
         .trim_start_matches('/')
         .splitn(3, '/')
         .collect();
@@ -270,36 +299,28 @@ async fn execute_sub_request(
                 (ctx.account.as_str(), segments[0], segments[1])
             }
         }
-        _ => return (400, "Bad Request", vec![], String::new()),
+        _ => return Err(StorageError::new(ErrorCode::InvalidUri)),
     };
+    // The batch was authorized for its own account only
+    if account != ctx.account {
+        return Err(StorageError::with_message(
+            ErrorCode::AuthorizationFailure,
+            format!("A batch request to account {} cannot change account {}.", ctx.account, account),
+        ));
+    }
 
     match method {
         "DELETE" => {
-            let blob = match metadata.get_blob(account, container, blob_name, "").await {
-                Ok(blob) => blob,
-                Err(_) => {
-                    let error_body = format!(
-                        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
-                         <Error>\n  <Code>BlobNotFound</Code>\n  \
-                         <Message>The specified blob does not exist.\nRequestId:{}\n\
-                         Time:{}</Message>\n</Error>",
-                        uuid::Uuid::new_v4(),
-                        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
-                    );
-                    return (404, "The specified blob does not exist.", vec![], error_body);
-                }
-            };
-
-            if let Err(_) = metadata.delete_blob(account, container, blob_name, "").await {
-                return (404, "The specified blob does not exist.", vec![], String::new());
-            }
+            let blob = metadata.get_blob(account, container, blob_name, "").await?;
+            metadata.delete_blob(account, container, blob_name, "").await?;
 
-            for chunk in &blob.extent_chunks {
-                let _ = extents.delete(&chunk.id).await;
-            }
+            release_extents(&**metadata, &**extents, &blob.extent_chunks).await;
 
-            (202, "Accepted", vec![("x-ms-delete-type-permanent", "true".to_string())], String::new())
+            Ok((
+                StatusCode::ACCEPTED,
+                vec![("x-ms-delete-type-permanent", "true".to_string())],
+            ))
         }
-        _ => (400, "Bad Request", vec![], String::new()),
+        _ => Err(StorageError::new(ErrorCode::UnsupportedHttpVerb)),
     }
 }
@@ -2,22 +2,33 @@
 
 use axum::{
     body::Body,
-    http::{header::HeaderName, HeaderMap, HeaderValue, Response, StatusCode},
+    http::{header::HeaderName, HeaderMap, HeaderValue, Method, Response, StatusCode},
 };
 use bytes::Bytes;
-use chrono::Utc;
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
 
-use crate::context::{format_http_date, format_iso8601, RequestContext};
+use crate::auth::public_access_allows;
+use crate::router::{authenticate_request, AppState};
+
+use crate::context::{format_http_date, format_iso8601, normalize_snapshot, parse_http_date, validate_tags, RequestContext};
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::models::{
-    AccessTier, BlobModel, BlobType, CopyStatus, ExtentChunk, LeaseDuration, LeaseState,
-    LeaseStatus,
+    etag_list_matches, etag_matches, AccessTier, BlobModel, BlobType, CopyStatus, ExtentChunk,
+    LeaseDuration, LeaseState, LeaseStatus, ObjectReplicationRule, PublicAccessLevel,
+    OBJECT_REPLICATION_STATUS,
 };
+use crate::operation::Operation;
 use crate::storage::{ExtentStore, MetadataStore};
 use crate::xml::{deserialize::parse_tags, serialize::serialize_tags};
 
-use super::{add_blob_headers, build_response, common_headers};
+use super::{
+    add_blob_headers, add_metadata_headers, add_request_server_encrypted, apply_response_overrides, block_blob::upload_block_blob, build_response,
+    common_headers, copy_source::fetch_copy_source, read_blob_extent, page_blob::{check_tier_capacity, read_page_blob_range}, release_extents,
+    BodyPiece,
+};
 
 /// GET /{container}/{blob} - Download blob.
 pub async fn download_blob(
@@ -27,80 +38,61 @@ pub async fn download_blob(
 ) -> StorageResult<Response<Body>> {
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
-    let snapshot = ctx.snapshot().unwrap_or("");
 
-    let blob = metadata.get_blob(&ctx.account, container, blob_name, snapshot).await?;
+    let blob = get_addressed_blob(ctx, &*metadata, container, blob_name).await?;
 
     // Check conditional headers
     check_conditional_headers(ctx, &blob)?;
 
-    // Check lease for non-snapshot reads
-    if snapshot.is_empty() {
-        // Lease check is not required for reads
-    }
-
-    // Handle range request
-    let (data, status, content_range) = if let Some((start, end)) = ctx.range() {
-        let end = end.unwrap_or(blob.properties.content_length.saturating_sub(1));
+    let content_length = blob.properties.content_length;
+    let ranges = requested_ranges(ctx, &blob);
 
-        if start >= blob.properties.content_length {
-            return Err(StorageError::new(ErrorCode::InvalidRange));
+    let mut multipart_boundary = None;
+    let (pieces, status, content_range) = match ranges.as_deref() {
+        None => {
+            let pieces = read_blob_range(extents.as_ref(), &blob, 0, content_length).await?;
+            (pieces, StatusCode::OK, None)
         }
-
-        let actual_end = end.min(blob.properties.content_length.saturating_sub(1));
-        let length = actual_end - start + 1;
-
-        // Read range from extents
-        let mut result = Vec::new();
-        let mut bytes_read = 0u64;
-        let mut current_pos = 0u64;
-
-        for chunk in &blob.extent_chunks {
-            let chunk_end = current_pos + chunk.count;
-
-            if current_pos < start + length && chunk_end > start {
-                let chunk_start = if current_pos < start {
-                    start - current_pos
-                } else {
-                    0
-                };
-                let chunk_read_end = (chunk.count).min(start + length - current_pos);
-                let bytes_to_read = chunk_read_end - chunk_start;
-
-                let data = extents.read_range(chunk, chunk_start, bytes_to_read).await?;
-                result.extend_from_slice(&data);
-                bytes_read += bytes_to_read;
-            }
-
-            current_pos = chunk_end;
-            if bytes_read >= length {
-                break;
-            }
+        Some([]) => return Err(StorageError::new(ErrorCode::InvalidRange)),
+        Some(&[(start, end)]) => {
+            let pieces = read_blob_range(extents.as_ref(), &blob, start, end - start + 1).await?;
+            let range_str = format!("bytes {}-{}/{}", start, end, content_length);
+            (pieces, StatusCode::PARTIAL_CONTENT, Some(range_str))
         }
+        Some(ranges) => {
+            // multipart/byteranges: one part per range, each with its own
+            // Content-Type and Content-Range
+            let boundary = uuid::Uuid::new_v4().simple().to_string();
+            let part_type = ctx
+                .response_overrides
+                .content_type
+                .as_deref()
+                .or(blob.properties.content_type.as_deref())
+                .unwrap_or("application/octet-stream");
+
+            let mut pieces = Vec::new();
+            for &(start, end) in ranges {
+                pieces.push(BodyPiece::Data(Bytes::from(format!(
+                    "--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    boundary, part_type, start, end, content_length
+                ))));
+                pieces.extend(read_blob_range(extents.as_ref(), &blob, start, end - start + 1).await?);
+                pieces.push(BodyPiece::Data(Bytes::from_static(b"\r\n")));
+            }
+            pieces.push(BodyPiece::Data(Bytes::from(format!("--{}--\r\n", boundary))));
 
-        let range_str = format!(
-            "bytes {}-{}/{}",
-            start,
-            actual_end,
-            blob.properties.content_length
-        );
-        (Bytes::from(result), StatusCode::PARTIAL_CONTENT, Some(range_str))
-    } else {
-        // Read full blob
-        let mut result = Vec::new();
-        for chunk in &blob.extent_chunks {
-            let data = extents.read(chunk).await?;
-            result.extend_from_slice(&data);
+            multipart_boundary = Some(boundary);
+            (pieces, StatusCode::PARTIAL_CONTENT, None)
         }
-        (Bytes::from(result), StatusCode::OK, None)
     };
+    let body_length: u64 = pieces.iter().map(BodyPiece::len).sum();
 
     let mut headers = common_headers();
     add_blob_headers(&mut headers, &blob.properties.etag, &blob.properties.last_modified);
 
     headers.insert(
         "Content-Length",
-        HeaderValue::from_str(&data.len().to_string()).unwrap(),
+        HeaderValue::from_str(&body_length.to_string()).unwrap(),
     );
     headers.insert(
         "x-ms-blob-type",
@@ -125,9 +117,16 @@ pub async fn download_blob(
     if let Some(ref cc) = blob.properties.cache_control {
         headers.insert("Cache-Control", HeaderValue::from_str(cc).unwrap());
     }
+    apply_response_overrides(&mut headers, &ctx.response_overrides);
     if let Some(range) = content_range {
         headers.insert("Content-Range", HeaderValue::from_str(&range).unwrap());
     }
+    if let Some(boundary) = multipart_boundary {
+        headers.insert(
+            "Content-Type",
+            HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary)).unwrap(),
+        );
+    }
 
     headers.insert(
         "x-ms-lease-status",
@@ -137,6 +136,10 @@ pub async fn download_blob(
         "x-ms-lease-state",
         HeaderValue::from_static(blob.properties.lease_state.as_str()),
     );
+    if let Some(duration) = blob.properties.active_lease_duration() {
+        headers.insert("x-ms-lease-duration", HeaderValue::from_static(duration.as_str()));
+    }
+    add_object_replication_headers(&mut headers, ctx, &blob);
     headers.insert(
         "x-ms-server-encrypted",
         HeaderValue::from_str(&blob.properties.server_encrypted.to_string()).unwrap(),
@@ -150,17 +153,100 @@ pub async fn download_blob(
         HeaderValue::from_static("bytes"),
     );
 
-    // Add metadata headers
-    for (key, value) in &blob.metadata {
-        if let Ok(header_value) = HeaderValue::from_str(value) {
-            headers.insert(
-                format!("x-ms-meta-{}", key).parse::<HeaderName>().unwrap(),
-                header_value,
-            );
+    let header_case = add_metadata_headers(&mut headers, &blob.metadata);
+
+    let mut response = build_response(status, headers, pieces_body(pieces));
+    response.extensions_mut().insert(header_case);
+    Ok(response)
+}
+
+/// Sends `pieces` as one body without joining them, so extents read as
+/// slices of in-memory data are not copied.
+fn pieces_body(mut pieces: Vec<BodyPiece>) -> Body {
+    if let [BodyPiece::Data(data)] = pieces.as_mut_slice() {
+        return Body::from(std::mem::take(data));
+    }
+    let chunks = pieces.into_iter().flat_map(BodyPiece::into_chunks);
+    Body::from_stream(futures::stream::iter(chunks.map(Ok::<_, Infallible>)))
+}
+
+/// Adds the status of the simulated object replication rule of the blob's
+/// container, when the server simulates replication.
+fn add_object_replication_headers(headers: &mut HeaderMap, ctx: &RequestContext, blob: &BlobModel) {
+    if !ctx.object_replication {
+        return;
+    }
+    let rule = ObjectReplicationRule::for_container(&blob.account, &blob.container);
+    headers.insert(
+        format!("x-ms-or-{}", rule.id()).parse::<HeaderName>().unwrap(),
+        HeaderValue::from_static(OBJECT_REPLICATION_STATUS),
+    );
+}
+
+/// Returns the requested ranges that the blob can satisfy, clamped to its
+/// length; unsatisfiable ones are dropped. `None` when no range applies,
+/// including when If-Range fails and the request becomes a full read.
+fn requested_ranges(ctx: &RequestContext, blob: &BlobModel) -> Option<Vec<(u64, u64)>> {
+    let content_length = blob.properties.content_length;
+    let ranges = ctx.ranges().filter(|_| if_range_matches(ctx, blob))?;
+    Some(
+        ranges
+            .into_iter()
+            .filter(|&(start, _)| start < content_length)
+            .map(|(start, end)| {
+                let end = end.unwrap_or(u64::MAX).min(content_length - 1);
+                (start, end)
+            })
+            .filter(|&(start, end)| start <= end)
+            .collect(),
+    )
+}
+
+/// Evaluates If-Range: true when the header is absent, or names the blob's
+/// current ETag (strong comparison) or its exact Last-Modified time.
+fn if_range_matches(ctx: &RequestContext, blob: &BlobModel) -> bool {
+    let Some(value) = ctx.if_range() else {
+        return true;
+    };
+    if let Some(date) = parse_http_date(value) {
+        return blob.properties.last_modified.timestamp() == date.timestamp();
+    }
+    !value.starts_with("W/") && etag_matches(value, &blob.properties.etag)
+}
+
+/// Reads `length` bytes of a blob's content starting at `start`, as the
+/// pieces the extent store returns, in order. Ranges starting or ending
+/// inside an extent read only the part they cover.
+async fn read_blob_range(
+    extents: &dyn ExtentStore,
+    blob: &BlobModel,
+    start: u64,
+    length: u64,
+) -> StorageResult<Vec<BodyPiece>> {
+    if blob.properties.blob_type == BlobType::PageBlob {
+        return read_page_blob_range(extents, blob, start, length).await;
+    }
+
+    let mut pieces = Vec::new();
+    let mut current_pos = 0u64;
+    for chunk in &blob.extent_chunks {
+        let chunk_end = current_pos + chunk.count;
+
+        if current_pos < start + length && chunk_end > start {
+            let chunk_start = start.saturating_sub(current_pos);
+            let chunk_read_end = chunk.count.min(start + length - current_pos);
+            pieces.push(BodyPiece::Data(
+                read_blob_extent(extents, blob, chunk, chunk_start, chunk_read_end - chunk_start).await?,
+            ));
+        }
+
+        current_pos = chunk_end;
+        if current_pos >= start + length {
+            break;
         }
     }
 
-    Ok(build_response(status, headers, Body::from(data)))
+    Ok(pieces)
 }
 
 /// HEAD /{container}/{blob} - Get blob properties.
@@ -170,20 +256,32 @@ pub async fn get_blob_properties(
 ) -> StorageResult<Response<Body>> {
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
-    let snapshot = ctx.snapshot().unwrap_or("");
 
-    let blob = metadata.get_blob(&ctx.account, container, blob_name, snapshot).await?;
+    let blob = get_addressed_blob(ctx, &*metadata, container, blob_name).await?;
 
     // Check conditional headers
     check_conditional_headers(ctx, &blob)?;
 
+    // A single range describes the slice a GET would return. Multiple
+    // ranges are ignored, as their multipart length depends on the body.
+    let content_length = blob.properties.content_length;
+    let (status, range) = match requested_ranges(ctx, &blob).as_deref() {
+        Some([]) => return Err(StorageError::new(ErrorCode::InvalidRange)),
+        Some(&[range]) => (StatusCode::PARTIAL_CONTENT, Some(range)),
+        _ => (StatusCode::OK, None),
+    };
+
     let mut headers = common_headers();
     add_blob_headers(&mut headers, &blob.properties.etag, &blob.properties.last_modified);
 
-    headers.insert(
-        "Content-Length",
-        HeaderValue::from_str(&blob.properties.content_length.to_string()).unwrap(),
-    );
+    let length = range.map_or(content_length, |(start, end)| end - start + 1);
+    headers.insert("Content-Length", HeaderValue::from_str(&length.to_string()).unwrap());
+    if let Some((start, end)) = range {
+        headers.insert(
+            "Content-Range",
+            HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, content_length)).unwrap(),
+        );
+    }
     headers.insert(
         "x-ms-blob-type",
         HeaderValue::from_static(blob.properties.blob_type.as_str()),
@@ -207,6 +305,7 @@ pub async fn get_blob_properties(
     if let Some(ref cc) = blob.properties.cache_control {
         headers.insert("Cache-Control", HeaderValue::from_str(cc).unwrap());
     }
+    apply_response_overrides(&mut headers, &ctx.response_overrides);
 
     headers.insert(
         "x-ms-lease-status",
@@ -216,14 +315,20 @@ pub async fn get_blob_properties(
         "x-ms-lease-state",
         HeaderValue::from_static(blob.properties.lease_state.as_str()),
     );
+    if let Some(duration) = blob.properties.active_lease_duration() {
+        headers.insert("x-ms-lease-duration", HeaderValue::from_static(duration.as_str()));
+    }
+    add_object_replication_headers(&mut headers, ctx, &blob);
     headers.insert(
         "x-ms-server-encrypted",
         HeaderValue::from_str(&blob.properties.server_encrypted.to_string()).unwrap(),
     );
-    headers.insert(
-        "x-ms-access-tier",
-        HeaderValue::from_static(blob.properties.access_tier.as_str()),
-    );
+    if blob.properties.access_tier.is_reported_for(blob.properties.blob_type) {
+        headers.insert(
+            "x-ms-access-tier",
+            HeaderValue::from_static(blob.properties.access_tier.as_str()),
+        );
+    }
     headers.insert("Accept-Ranges", HeaderValue::from_static("bytes"));
     headers.insert(
         "x-ms-creation-time",
@@ -242,7 +347,7 @@ pub async fn get_blob_properties(
 
     // Append blob specific
     if blob.properties.blob_type == BlobType::AppendBlob {
-        if let Some(count) = blob.properties.committed_block_count {
+        if let Some(count) = blob.committed_block_count() {
             headers.insert(
                 "x-ms-blob-committed-block-count",
                 HeaderValue::from_str(&count.to_string()).unwrap(),
@@ -275,18 +380,23 @@ pub async fn get_blob_properties(
             HeaderValue::from_str(copy_progress).unwrap(),
         );
     }
-
-    // Add metadata headers
-    for (key, value) in &blob.metadata {
-        if let Ok(header_value) = HeaderValue::from_str(value) {
-            headers.insert(
-                format!("x-ms-meta-{}", key).parse::<HeaderName>().unwrap(),
-                header_value,
-            );
+    if let Some(ref completion_time) = blob.properties.copy_completion_time {
+        headers.insert(
+            "x-ms-copy-completion-time",
+            HeaderValue::from_str(&format_http_date(completion_time)).unwrap(),
+        );
+    }
+    if let Some(ref description) = blob.properties.copy_status_description {
+        if let Ok(value) = HeaderValue::from_str(description) {
+            headers.insert("x-ms-copy-status-description", value);
         }
     }
 
-    Ok(build_response(StatusCode::OK, headers, Body::empty()))
+    let header_case = add_metadata_headers(&mut headers, &blob.metadata);
+
+    let mut response = build_response(status, headers, Body::empty());
+    response.extensions_mut().insert(header_case);
+    Ok(response)
 }
 
 /// DELETE /{container}/{blob} - Delete blob.
@@ -299,10 +409,10 @@ pub async fn delete_blob(
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
     let snapshot = ctx.snapshot().unwrap_or("");
 
-    let blob = metadata.get_blob(&ctx.account, container, blob_name, snapshot).await?;
+    let blob = get_addressed_blob(ctx, &*metadata, container, blob_name).await?;
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Check conditional headers
     check_conditional_headers(ctx, &blob)?;
@@ -315,10 +425,8 @@ pub async fn delete_blob(
         .delete_blob(&ctx.account, container, blob_name, snapshot)
         .await?;
 
-    // Clean up extent data
-    for chunk in &blob.extent_chunks {
-        let _ = extents.delete(&chunk.id).await;
-    }
+    // Clean up extent data no snapshot or copy shares
+    release_extents(&*metadata, &*extents, &blob.extent_chunks).await;
 
     let mut headers = common_headers();
     headers.insert(
@@ -337,35 +445,28 @@ pub async fn set_blob_properties(
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    reject_snapshot_write(ctx, "Blob properties cannot be set on a snapshot.")?;
+
     let mut blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Check conditional headers
     check_conditional_headers(ctx, &blob)?;
 
-    // Update content headers
-    if let Some(ct) = ctx.header("x-ms-blob-content-type") {
-        blob.properties.content_type = Some(ct.to_string());
-    }
-    if let Some(ce) = ctx.header("x-ms-blob-content-encoding") {
-        blob.properties.content_encoding = Some(ce.to_string());
-    }
-    if let Some(cl) = ctx.header("x-ms-blob-content-language") {
-        blob.properties.content_language = Some(cl.to_string());
-    }
-    if let Some(md5) = ctx.header("x-ms-blob-content-md5") {
-        blob.properties.content_md5 = Some(md5.to_string());
-    }
-    if let Some(cd) = ctx.header("x-ms-blob-content-disposition") {
-        blob.properties.content_disposition = Some(cd.to_string());
-    }
-    if let Some(cc) = ctx.header("x-ms-blob-cache-control") {
-        blob.properties.cache_control = Some(cc.to_string());
-    }
+    // Set Blob HTTP Headers replaces the whole set: omitted headers are cleared
+    let header = |name: &str| ctx.header(name).map(|v| v.to_string());
+    blob.properties.content_type = Some(
+        header("x-ms-blob-content-type").unwrap_or_else(|| "application/octet-stream".to_string()),
+    );
+    blob.properties.content_encoding = header("x-ms-blob-content-encoding");
+    blob.properties.content_language = header("x-ms-blob-content-language");
+    blob.properties.content_md5 = header("x-ms-blob-content-md5");
+    blob.properties.content_disposition = header("x-ms-blob-content-disposition");
+    blob.properties.cache_control = header("x-ms-blob-cache-control");
 
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_blob(blob.clone()).await?;
 
     let mut headers = common_headers();
@@ -382,21 +483,24 @@ pub async fn set_blob_metadata(
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    reject_snapshot_write(ctx, "Blob metadata cannot be set on a snapshot.")?;
+
     let mut blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Check conditional headers
     check_conditional_headers(ctx, &blob)?;
 
-    blob.metadata = ctx.metadata();
-    blob.properties.update_etag();
+    blob.metadata = ctx.metadata()?;
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
 
     metadata.update_blob(blob.clone()).await?;
 
     let mut headers = common_headers();
     add_blob_headers(&mut headers, &blob.properties.etag, &blob.properties.last_modified);
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::OK, headers, Body::empty()))
 }
@@ -409,21 +513,23 @@ pub async fn create_snapshot(
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    reject_snapshot_write(ctx, "A snapshot cannot be taken of a snapshot.")?;
+
     let blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Check conditional headers
     check_conditional_headers(ctx, &blob)?;
 
     // Create snapshot
-    let snapshot = blob.create_snapshot();
+    let snapshot = blob.create_snapshot(ctx.timestamp);
     let snapshot_time = snapshot.snapshot.clone();
 
     // Apply any metadata from request
     let mut snapshot = snapshot;
-    let request_metadata = ctx.metadata();
+    let request_metadata = ctx.metadata()?;
     if !request_metadata.is_empty() {
         snapshot.metadata = request_metadata;
     }
@@ -436,6 +542,7 @@ pub async fn create_snapshot(
         "x-ms-snapshot",
         HeaderValue::from_str(&snapshot_time).unwrap(),
     );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -448,11 +555,15 @@ pub async fn blob_lease(
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    reject_snapshot_write(ctx, "A snapshot cannot be leased.")?;
+
     let action = ctx
         .header("x-ms-lease-action")
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-lease-action"))?;
 
     let mut blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
+    check_conditional_headers(ctx, &blob)?;
+    blob.properties.refresh_lease(ctx.timestamp);
     let mut headers = common_headers();
 
     match action.to_lowercase().as_str() {
@@ -478,7 +589,7 @@ pub async fn blob_lease(
                 Some(LeaseDuration::Infinite)
             } else {
                 blob.properties.lease_expiry =
-                    Some(Utc::now() + chrono::Duration::seconds(duration as i64));
+                    Some(ctx.timestamp + chrono::Duration::seconds(duration as i64));
                 Some(LeaseDuration::Fixed)
             };
 
@@ -508,13 +619,15 @@ pub async fn blob_lease(
                 return Err(StorageError::new(ErrorCode::LeaseIdMismatchWithBlobOperation));
             }
 
-            if blob.properties.lease_state != LeaseState::Leased {
+            if !matches!(blob.properties.lease_state, LeaseState::Leased | LeaseState::Expired) {
                 return Err(StorageError::new(ErrorCode::LeaseIsBrokenAndCannotBeRenewed));
             }
+            blob.properties.lease_state = LeaseState::Leased;
+            blob.properties.lease_status = LeaseStatus::Locked;
 
             if let Some(LeaseDuration::Fixed) = blob.properties.lease_duration {
                 blob.properties.lease_expiry =
-                    Some(Utc::now() + chrono::Duration::seconds(60));
+                    Some(ctx.timestamp + chrono::Duration::seconds(60));
             }
 
             headers.insert(
@@ -540,7 +653,7 @@ pub async fn blob_lease(
             } else {
                 blob.properties.lease_state = LeaseState::Breaking;
                 blob.properties.lease_break_time =
-                    Some(Utc::now() + chrono::Duration::seconds(break_period as i64));
+                    Some(ctx.timestamp + chrono::Duration::seconds(break_period as i64));
                 headers.insert(
                     "x-ms-lease-time",
                     HeaderValue::from_str(&break_period.to_string()).unwrap(),
@@ -558,20 +671,17 @@ pub async fn blob_lease(
 
             let new_lease_id = ctx
                 .header("x-ms-proposed-lease-id")
-                .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+                .ok_or_else(|| StorageError::missing_required_header("x-ms-proposed-lease-id"))?;
 
             blob.properties.lease_id = Some(new_lease_id.to_string());
             headers.insert("x-ms-lease-id", HeaderValue::from_str(new_lease_id).unwrap());
         }
         _ => {
-            return Err(StorageError::with_message(
-                ErrorCode::InvalidHeaderValue,
-                "Invalid x-ms-lease-action header value",
-            ));
+            return Err(StorageError::invalid_header_value("x-ms-lease-action", action));
         }
     }
 
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_blob(blob.clone()).await?;
 
     add_blob_headers(&mut headers, &blob.properties.etag, &blob.properties.last_modified);
@@ -589,14 +699,43 @@ pub async fn set_blob_tier(
 
     let tier = ctx
         .header("x-ms-access-tier")
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-access-tier"))?;
 
     let access_tier = AccessTier::from_str(tier)
         .ok_or_else(|| StorageError::new(ErrorCode::InvalidBlobTier))?;
 
-    let mut blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
+    // A snapshot's tier is its own; the base blob is left as is
+    let snapshot = ctx.snapshot().unwrap_or("");
+    let mut blob = metadata.get_blob(&ctx.account, container, blob_name, snapshot).await?;
+
+    // Block blobs take the standard tiers and page blobs the premium ones.
+    // A premium page blob can only move up to a tier that holds it.
+    let blob_type = blob.properties.blob_type;
+    if !access_tier.applies_to(blob_type) {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidBlobTier,
+            format!("The {} tier does not apply to a {}.", access_tier.as_str(), blob_type.as_str()),
+        ));
+    }
+    if blob_type == BlobType::PageBlob {
+        check_tier_capacity(access_tier, blob.properties.content_length)?;
+        if let (Some(current), Some(requested)) = (
+            blob.properties.access_tier.max_content_length(),
+            access_tier.max_content_length(),
+        ) {
+            if requested < current {
+                return Err(StorageError::new(ErrorCode::CannotChangeToLowerTier));
+            }
+        }
+    }
+
+    // Setting the current tier, such as Archive on an archived blob, is a no-op
+    if blob.properties.access_tier == access_tier {
+        return Ok(build_response(StatusCode::OK, common_headers(), Body::empty()));
+    }
+
     blob.properties.access_tier = access_tier;
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
 
     metadata.update_blob(blob).await?;
 
@@ -612,9 +751,8 @@ pub async fn get_blob_tags(
 ) -> StorageResult<Response<Body>> {
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
-    let snapshot = ctx.snapshot().unwrap_or("");
 
-    let blob = metadata.get_blob(&ctx.account, container, blob_name, snapshot).await?;
+    let blob = get_addressed_blob(ctx, &*metadata, container, blob_name).await?;
     let xml = serialize_tags(&blob.tags);
 
     let mut headers = common_headers();
@@ -632,16 +770,19 @@ pub async fn set_blob_tags(
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    reject_snapshot_write(ctx, "Blob tags cannot be set on a snapshot.")?;
+
     let mut blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Parse tags from body
     if !body.is_empty() {
         let xml = std::str::from_utf8(&body)
             .map_err(|_| StorageError::new(ErrorCode::InvalidXmlDocument))?;
         blob.tags = parse_tags(xml)?;
+        validate_tags(&blob.tags)?;
     } else {
         blob.tags.clear();
     }
@@ -654,20 +795,46 @@ pub async fn set_blob_tags(
 }
 
 /// PUT /{container}/{blob} with x-ms-copy-source - Copy blob.
-pub async fn copy_blob(
-    ctx: &RequestContext,
-    metadata: Arc<dyn MetadataStore>,
-    extents: Arc<dyn ExtentStore>,
-) -> StorageResult<Response<Body>> {
+///
+/// A source in another account is copied only with
+/// [`Config::cross_account_copy`], and must then be authorized on its own:
+/// the request's credentials are for the destination account.
+pub async fn copy_blob(ctx: &RequestContext, state: &AppState) -> StorageResult<Response<Body>> {
+    let (metadata, extents) = (&*state.metadata, &*state.extents);
     let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
     let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
     let copy_source = ctx
         .copy_source()
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-copy-source"))?;
+    // Tags come from the request unless the source's are explicitly copied
+    let copy_source_tags = ctx
+        .header("x-ms-copy-source-tag-option")
+        .is_some_and(|option| option.eq_ignore_ascii_case("COPY"));
+    let request_tags = if copy_source_tags { None } else { Some(request_tags(ctx)?) };
+
+    // Overwriting the destination needs write permission and its lease
+    let existing_dest = metadata.get_blob(&ctx.account, container, blob_name, "").await.ok();
+    if let Some(ref existing) = existing_dest {
+        check_sas_overwrite_permission(ctx)?;
+        check_blob_lease(existing, ctx)?;
+    }
 
     // Parse source URL to extract account, container, blob
     let source_parts = parse_copy_source(copy_source)?;
+    let cross_account = source_parts.account != ctx.account;
+    if cross_account {
+        if !state.config.cross_account_copy {
+            return Err(StorageError::with_message(
+                ErrorCode::CopyAcrossAccountsNotSupported,
+                format!(
+                    "The copy source is in account {}, and copies across accounts are not enabled.",
+                    source_parts.account
+                ),
+            ));
+        }
+        authorize_copy_source(ctx, state, &source_parts).await?;
+    }
 
     // Get source blob
     let source_blob = metadata
@@ -687,7 +854,9 @@ pub async fn copy_blob(
         blob_name.clone(),
         source_blob.properties.blob_type,
         source_blob.properties.content_length,
+        ctx.timestamp,
     );
+    dest_blob.properties.renew_etag(&ctx.etags);
 
     // Copy properties
     dest_blob.properties.content_type = source_blob.properties.content_type.clone();
@@ -697,13 +866,47 @@ pub async fn copy_blob(
     dest_blob.properties.content_disposition = source_blob.properties.content_disposition.clone();
     dest_blob.properties.cache_control = source_blob.properties.cache_control.clone();
 
-    // Copy extent references (for same-account copies)
-    if source_parts.account == ctx.account {
-        dest_blob.extent_chunks = source_blob.extent_chunks.clone();
-    } else {
-        // For cross-account copies, we would need to copy the actual data
-        // For simplicity, we'll just reference the same extents
-        dest_blob.extent_chunks = source_blob.extent_chunks.clone();
+    dest_blob.page_ranges = source_blob.page_ranges.clone();
+
+    // The tier must be one the copied blob's type takes, as in Set Blob Tier
+    if let Some(tier) = ctx.header("x-ms-access-tier") {
+        let access_tier = AccessTier::from_str(tier)
+            .ok_or_else(|| StorageError::invalid_header_value("x-ms-access-tier", tier))?;
+        let blob_type = dest_blob.properties.blob_type;
+        if !access_tier.applies_to(blob_type) {
+            return Err(StorageError::with_message(
+                ErrorCode::InvalidBlobTier,
+                format!("The {} tier does not apply to a {}.", access_tier.as_str(), blob_type.as_str()),
+            ));
+        }
+        if blob_type == BlobType::PageBlob {
+            check_tier_capacity(access_tier, dest_blob.properties.content_length)?;
+        }
+        dest_blob.properties.access_tier = access_tier;
+    }
+
+    dest_blob.tags = request_tags.unwrap_or_else(|| source_blob.tags.clone());
+
+    // The new blob starts unleased, but a lease on the destination survives the copy
+    dest_blob.properties.clear_lease();
+    if let Some(existing) = existing_dest.filter(|b| b.properties.lease_state == LeaseState::Leased) {
+        dest_blob.properties.lease_state = existing.properties.lease_state;
+        dest_blob.properties.lease_status = existing.properties.lease_status;
+        dest_blob.properties.lease_duration = existing.properties.lease_duration;
+        dest_blob.properties.lease_id = existing.properties.lease_id;
+        dest_blob.properties.lease_expiry = existing.properties.lease_expiry;
+    }
+
+    // Same-account copies share the source's extents; copies into another
+    // account get their own, so no extent is referenced by two accounts
+    dest_blob.extent_chunks = source_blob.extent_chunks.clone();
+    if cross_account {
+        let copies = copy_extents(extents, &source_blob).await?;
+        let page_chunks = dest_blob.page_ranges.iter_mut().filter_map(|range| range.extent_chunk.as_mut());
+        for chunk in dest_blob.extent_chunks.iter_mut().chain(page_chunks) {
+            let (start, copy) = &copies[&chunk.id];
+            *chunk = ExtentChunk::new(copy.id.clone(), chunk.offset - start, chunk.count);
+        }
     }
 
     // Set copy metadata
@@ -714,10 +917,26 @@ pub async fn copy_blob(
         "{}/{}",
         source_blob.properties.content_length, source_blob.properties.content_length
     ));
-    dest_blob.properties.copy_completion_time = Some(Utc::now());
+    dest_blob.properties.copy_completion_time = Some(ctx.timestamp);
+
+    // In loose mode a source URL with simulate=copy-failure stands for a copy
+    // that failed halfway: the destination keeps no data and reports the
+    // partial progress. The response says the copy is pending, as Azure does
+    // for copies that have not finished.
+    let simulate_failure = ctx.loose && source_parts.simulate_failure;
+    if simulate_failure {
+        let content_length = source_blob.properties.content_length;
+        dest_blob.properties.content_length = 0;
+        dest_blob.page_ranges.clear();
+        dest_blob.extent_chunks.clear();
+        dest_blob.properties.copy_status = Some(CopyStatus::Failed);
+        dest_blob.properties.copy_progress = Some(format!("{}/{}", content_length / 2, content_length));
+        dest_blob.properties.copy_status_description =
+            Some("500 InternalError \"Copy failed partway through (simulated by the copy source).\"".to_string());
+    }
 
     // Apply request metadata (overrides source metadata)
-    let request_metadata = ctx.metadata();
+    let request_metadata = ctx.metadata()?;
     dest_blob.metadata = if request_metadata.is_empty() {
         source_blob.metadata.clone()
     } else {
@@ -733,11 +952,46 @@ pub async fn copy_blob(
         &dest_blob.properties.last_modified,
     );
     headers.insert("x-ms-copy-id", HeaderValue::from_str(&copy_id).unwrap());
-    headers.insert("x-ms-copy-status", HeaderValue::from_static("success"));
+    let status = if simulate_failure { CopyStatus::Pending } else { CopyStatus::Success };
+    headers.insert("x-ms-copy-status", HeaderValue::from_static(status.as_str()));
 
     Ok(build_response(StatusCode::ACCEPTED, headers, Body::empty()))
 }
 
+/// PUT /{container}/{blob} with x-ms-copy-source and x-ms-requires-sync -
+/// Copy blob from URL.
+///
+/// The source is read over HTTP and written as a block blob before the
+/// response, which reports the copy as already successful.
+pub async fn copy_blob_from_url(ctx: &RequestContext, state: &AppState) -> StorageResult<Response<Body>> {
+    let container = ctx.container.as_ref().ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
+    let blob_name = ctx.blob.as_ref().ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
+    let copy_source = ctx
+        .copy_source()
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-copy-source"))?;
+
+    let data = fetch_copy_source(ctx, &state.copy_source_client).await?;
+    let content_length = data.len() as u64;
+    let metadata = &state.metadata;
+    let mut response = upload_block_blob(ctx, metadata.clone(), state.extents.clone(), data).await?;
+
+    let copy_id = uuid::Uuid::new_v4().to_string();
+    let mut blob = metadata.get_blob(&ctx.account, container, blob_name, "").await?;
+    blob.properties.copy_id = Some(copy_id.clone());
+    blob.properties.copy_source = Some(copy_source.to_string());
+    blob.properties.copy_status = Some(CopyStatus::Success);
+    blob.properties.copy_progress = Some(format!("{}/{}", content_length, content_length));
+    blob.properties.copy_completion_time = Some(ctx.timestamp);
+    metadata.update_blob(blob).await?;
+
+    *response.status_mut() = StatusCode::ACCEPTED;
+    let headers = response.headers_mut();
+    headers.insert("x-ms-copy-id", HeaderValue::from_str(&copy_id).unwrap());
+    headers.insert("x-ms-copy-status", HeaderValue::from_static("success"));
+
+    Ok(response)
+}
+
 /// PUT /{container}/{blob}?comp=copy&copyid={id} - Abort copy.
 pub async fn abort_copy(
     ctx: &RequestContext,
@@ -758,10 +1012,66 @@ pub async fn undelete_blob(
     Ok(build_response(StatusCode::OK, headers, Body::empty()))
 }
 
+/// Loads the snapshot, version or base blob a request addresses with its
+/// `snapshot` or `versionid` parameter.
+///
+/// Only the current version of a blob is kept, so a `versionid` other than
+/// the base blob's version is `BlobNotFound`.
+pub async fn get_addressed_blob(
+    ctx: &RequestContext,
+    metadata: &dyn MetadataStore,
+    container: &str,
+    blob_name: &str,
+) -> StorageResult<BlobModel> {
+    let snapshot = ctx.snapshot().unwrap_or("");
+    let blob = metadata.get_blob(&ctx.account, container, blob_name, snapshot).await?;
+
+    match ctx.version_id() {
+        Some(version_id) if blob.properties.version_id.as_deref() != Some(version_id) => {
+            Err(StorageError::new(ErrorCode::BlobNotFound))
+        }
+        _ => Ok(blob),
+    }
+}
+
+/// Rejects overwriting an existing blob when the request was authorized by a
+/// SAS that grants create ('c') but not write ('w').
+pub fn check_sas_overwrite_permission(ctx: &RequestContext) -> StorageResult<()> {
+    match ctx.sas_permissions.as_deref() {
+        Some(permissions) if !permissions.contains('w') => {
+            Err(StorageError::new(ErrorCode::AuthorizationPermissionMismatch))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the tags a write sets with `x-ms-tags`. Setting tags through a
+/// SAS takes the tag ('t') permission besides the write's own.
+pub fn request_tags(ctx: &RequestContext) -> StorageResult<HashMap<String, String>> {
+    if ctx.header("x-ms-tags").is_some() {
+        if let Some(permissions) = ctx.sas_permissions.as_deref() {
+            if !permissions.contains('t') {
+                return Err(StorageError::new(ErrorCode::AuthorizationPermissionMismatch));
+            }
+        }
+    }
+    ctx.tags()
+}
+
+/// Rejects a request that would modify a snapshot. Snapshots are read-only
+/// apart from Delete Blob and Set Blob Tier, so the snapshot a request
+/// addresses must never fall through to the base blob.
+fn reject_snapshot_write(ctx: &RequestContext, reason: &str) -> StorageResult<()> {
+    match ctx.snapshot().filter(|s| !s.is_empty()) {
+        Some(snapshot) => Err(StorageError::invalid_query_parameter("snapshot", snapshot, reason)),
+        None => Ok(()),
+    }
+}
+
 /// Checks if the blob lease allows the operation.
-pub fn check_blob_lease(blob: &BlobModel, provided_lease_id: Option<&str>) -> StorageResult<()> {
-    if blob.properties.lease_state == LeaseState::Leased {
-        match (blob.properties.lease_id.as_deref(), provided_lease_id) {
+pub fn check_blob_lease(blob: &BlobModel, ctx: &RequestContext) -> StorageResult<()> {
+    if blob.properties.is_leased(ctx.timestamp) {
+        match (blob.properties.lease_id.as_deref(), ctx.lease_id()) {
             (Some(expected), Some(provided)) if expected == provided => Ok(()),
             (Some(_), Some(_)) => Err(StorageError::new(ErrorCode::LeaseIdMismatchWithBlobOperation)),
             (Some(_), None) => Err(StorageError::new(ErrorCode::LeaseIdMissing)),
@@ -772,32 +1082,35 @@ pub fn check_blob_lease(blob: &BlobModel, provided_lease_id: Option<&str>) -> St
     }
 }
 
-/// Checks conditional request headers.
+/// Checks conditional request headers. ETag headers may list several
+/// values; dates are compared at the one-second resolution of HTTP dates.
 fn check_conditional_headers(ctx: &RequestContext, blob: &BlobModel) -> StorageResult<()> {
     // If-Match
-    if let Some(etag) = ctx.if_match() {
-        if etag != "*" && etag != blob.properties.etag {
+    if let Some(header) = ctx.if_match() {
+        if !etag_list_matches(header, &blob.properties.etag) {
             return Err(StorageError::new(ErrorCode::ConditionNotMet));
         }
     }
 
     // If-None-Match
-    if let Some(etag) = ctx.if_none_match() {
-        if etag == "*" || etag == blob.properties.etag {
+    if let Some(header) = ctx.if_none_match() {
+        if etag_list_matches(header, &blob.properties.etag) {
             return Err(StorageError::new(ErrorCode::ConditionNotMet));
         }
     }
 
+    let last_modified = blob.properties.last_modified.timestamp();
+
     // If-Modified-Since
     if let Some(since) = ctx.if_modified_since() {
-        if blob.properties.last_modified <= since {
+        if last_modified <= since.timestamp() {
             return Err(StorageError::new(ErrorCode::ConditionNotMet));
         }
     }
 
     // If-Unmodified-Since
     if let Some(since) = ctx.if_unmodified_since() {
-        if blob.properties.last_modified > since {
+        if last_modified > since.timestamp() {
             return Err(StorageError::new(ErrorCode::ConditionNotMet));
         }
     }
@@ -805,22 +1118,108 @@ fn check_conditional_headers(ctx: &RequestContext, blob: &BlobModel) -> StorageR
     Ok(())
 }
 
+/// Copies each extent `blob` references, through its chunks or its page
+/// ranges, to a new extent once, however many chunks share it. Returns the
+/// copies by source extent ID, with the offset in the source extent each
+/// copy starts at: only the span the chunks cover is copied.
+async fn copy_extents(
+    extents: &dyn ExtentStore,
+    blob: &BlobModel,
+) -> StorageResult<HashMap<String, (u64, ExtentChunk)>> {
+    let page_chunks = blob.page_ranges.iter().filter_map(|range| range.extent_chunk.as_ref());
+    let mut spans: HashMap<&str, (u64, u64)> = HashMap::new();
+    for chunk in blob.extent_chunks.iter().chain(page_chunks) {
+        let end = chunk.offset + chunk.count;
+        spans
+            .entry(&chunk.id)
+            .and_modify(|(start, span_end)| {
+                *start = (*start).min(chunk.offset);
+                *span_end = (*span_end).max(end);
+            })
+            .or_insert((chunk.offset, end));
+    }
+
+    let mut copies = HashMap::with_capacity(spans.len());
+    for (id, (start, end)) in spans {
+        let span = ExtentChunk::new(id.to_string(), start, end - start);
+        let data = read_blob_extent(extents, blob, &span, 0, span.count).await?;
+        copies.insert(id.to_string(), (start, extents.write(data).await?));
+    }
+    Ok(copies)
+}
+
+/// Authorizes reading a copy source in another account: a read of its URL
+/// must pass the server's authentication, the custom authenticator if one
+/// is installed, with a SAS that grants read access, or its container must
+/// allow anonymous blob reads. Fails with `CannotVerifyCopySource`
+/// otherwise.
+async fn authorize_copy_source(ctx: &RequestContext, state: &AppState, source: &CopySourceParts) -> StorageResult<()> {
+    let unverified = |reason: String| {
+        StorageError::with_message(ErrorCode::CannotVerifyCopySource, reason).with_status(StatusCode::FORBIDDEN)
+    };
+
+    let mut path = format!("/{}/{}/{}", source.account, source.container, source.blob);
+    if !source.query.is_empty() {
+        path.push('?');
+        path.push_str(&source.query);
+    }
+    let uri = path.parse().map_err(|_| StorageError::new(ErrorCode::InvalidSourceBlobUrl))?;
+    let path_params = HashMap::from([
+        ("account".to_string(), source.account.clone()),
+        ("container".to_string(), source.container.clone()),
+        ("blob".to_string(), source.blob.clone()),
+    ]);
+    let query_pairs = url::form_urlencoded::parse(source.query.as_bytes()).into_owned().collect();
+    let mut source_ctx = RequestContext::new(Method::GET, uri, HeaderMap::new(), path_params, query_pairs)?;
+    source_ctx.timestamp = ctx.timestamp;
+    source_ctx.loose = ctx.loose;
+    source_ctx.operation = Operation::GetBlob;
+
+    let auth = authenticate_request(state, &source_ctx)
+        .map_err(|e| unverified(format!("The SAS of the copy source was rejected: {}", e.message)))?;
+    if !auth.is_anonymous {
+        return Ok(());
+    }
+    let public_access = state
+        .metadata
+        .get_container(&source.account, &source.container)
+        .await
+        .map(|container| container.properties.public_access)
+        .unwrap_or(PublicAccessLevel::None);
+    if public_access_allows(public_access, Operation::GetBlob) {
+        return Ok(());
+    }
+    Err(unverified(format!(
+        "The copy source in account {} is not public and its URL carries no SAS.",
+        source.account
+    )))
+}
+
 /// Parsed copy source URL components.
 struct CopySourceParts {
     account: String,
     container: String,
     blob: String,
     snapshot: String,
+    /// Query string of the source URL, without the `?`.
+    query: String,
+    /// The source asks for the copy to fail, with `simulate=copy-failure`.
+    simulate_failure: bool,
 }
 
+/// Query parameter value of a copy source that makes Copy Blob fail in
+/// loose mode, for testing how clients handle failed copies.
+const SIMULATE_COPY_FAILURE: &str = "simulate=copy-failure";
+
 /// Parses a copy source URL.
 fn parse_copy_source(url: &str) -> StorageResult<CopySourceParts> {
     // Handle both full URLs and relative paths
     let path = if url.starts_with("http://") || url.starts_with("https://") {
-        url::Url::parse(url)
-            .map_err(|_| StorageError::new(ErrorCode::InvalidSourceBlobUrl))?
-            .path()
-            .to_string()
+        let url = url::Url::parse(url).map_err(|_| StorageError::new(ErrorCode::InvalidSourceBlobUrl))?;
+        match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        }
     } else {
         url.to_string()
     };
@@ -831,22 +1230,32 @@ fn parse_copy_source(url: &str) -> StorageResult<CopySourceParts> {
         return Err(StorageError::new(ErrorCode::InvalidSourceBlobUrl));
     }
 
-    let account = parts[0].to_string();
-    let container = parts[1].to_string();
+    // Names are percent-encoded in the URL and stored decoded
+    let decode = |segment: &str| percent_decode_str(segment).decode_utf8_lossy().into_owned();
+    let account = decode(parts[0]);
+    let container = decode(parts[1]);
     let blob_and_query = parts[2];
 
+    let mut simulate_failure = false;
+    let mut source_query = String::new();
     let (blob, snapshot) = if let Some(idx) = blob_and_query.find('?') {
         let blob = &blob_and_query[..idx];
         let query = &blob_and_query[idx + 1..];
+        source_query = query.to_string();
+        simulate_failure = query.split('&').any(|s| s == SIMULATE_COPY_FAILURE);
         let snapshot = query
             .split('&')
             .find(|s| s.starts_with("snapshot="))
             .map(|s| s.strip_prefix("snapshot=").unwrap_or(""))
-            .unwrap_or("")
-            .to_string();
-        (blob.to_string(), snapshot)
+            .map(|s| {
+                let decoded = percent_decode_str(s).decode_utf8_lossy();
+                normalize_snapshot("snapshot", &decoded)
+            })
+            .transpose()?
+            .unwrap_or_default();
+        (decode(blob), snapshot)
     } else {
-        (blob_and_query.to_string(), String::new())
+        (decode(blob_and_query), String::new())
     };
 
     Ok(CopySourceParts {
@@ -854,5 +1263,7 @@ fn parse_copy_source(url: &str) -> StorageResult<CopySourceParts> {
         container,
         blob,
         snapshot,
+        query: source_query,
+        simulate_failure,
     })
 }
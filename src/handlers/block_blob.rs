@@ -6,16 +6,22 @@ use axum::{
 };
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use bytes::Bytes;
-use md5::{Digest, Md5};
 use std::sync::Arc;
 
 use crate::context::{format_http_date, RequestContext};
 use crate::error::{ErrorCode, StorageError, StorageResult};
-use crate::models::{BlobModel, BlobType, BlockModel, BlockState, ExtentChunk};
+use crate::models::{BlobModel, BlobType, BlockListType, BlockModel, BlockState, ExtentChunk};
+use crate::router::AppState;
 use crate::storage::{ExtentStore, MetadataStore};
 use crate::xml::{deserialize::BlockListRequest, serialize::serialize_block_list};
 
-use super::{add_blob_headers, blob::check_blob_lease, build_response, common_headers};
+use super::{
+    add_blob_headers, add_request_server_encrypted, check_extents_present,
+    blob::{check_blob_lease, check_sas_overwrite_permission, request_tags},
+    blob_content_type, build_response, common_headers, content_md5,
+    copy_source::fetch_copy_source,
+    release_extents, verify_md5,
+};
 
 /// PUT /{container}/{blob} - Upload block blob (single PUT).
 pub async fn upload_block_blob(
@@ -39,17 +45,19 @@ pub async fn upload_block_blob(
     }
 
     // Check if blob exists and validate lease
-    if let Ok(existing_blob) = metadata.get_blob(&ctx.account, container, blob_name, "").await {
-        check_blob_lease(&existing_blob, ctx.lease_id())?;
+    let existing_blob = metadata.get_blob(&ctx.account, container, blob_name, "").await.ok();
+    if let Some(existing_blob) = &existing_blob {
+        check_blob_lease(existing_blob, ctx)?;
+        check_sas_overwrite_permission(ctx)?;
     }
 
-    // Validate Content-MD5 if provided
-    if let Some(expected_md5) = ctx.content_md5() {
-        let computed_md5 = BASE64.encode(Md5::digest(&body));
-        if computed_md5 != expected_md5 {
-            return Err(StorageError::new(ErrorCode::Md5Mismatch));
-        }
+    // Put Blob verifies both the transport and the blob-level MD5
+    let computed_md5 = content_md5(&body);
+    for expected_md5 in [ctx.content_md5(), ctx.header("x-ms-blob-content-md5")].into_iter().flatten() {
+        verify_md5(expected_md5, &body)?;
     }
+    let request_metadata = ctx.metadata()?;
+    let request_tags = request_tags(ctx)?;
 
     // Store blob data in extent store
     let content_length = body.len() as u64;
@@ -66,21 +74,19 @@ pub async fn upload_block_blob(
         blob_name.clone(),
         BlobType::BlockBlob,
         content_length,
+        ctx.timestamp,
     );
+    blob.properties.renew_etag(&ctx.etags);
 
     // Set content properties from headers
-    if let Some(ct) = ctx.header("x-ms-blob-content-type").or_else(|| ctx.content_type()) {
-        blob.properties.content_type = Some(ct.to_string());
-    }
+    blob.properties.content_type = Some(blob_content_type(ctx, true));
     if let Some(ce) = ctx.header("x-ms-blob-content-encoding") {
         blob.properties.content_encoding = Some(ce.to_string());
     }
     if let Some(cl) = ctx.header("x-ms-blob-content-language") {
         blob.properties.content_language = Some(cl.to_string());
     }
-    if let Some(md5) = ctx.header("x-ms-blob-content-md5").or_else(|| ctx.content_md5()) {
-        blob.properties.content_md5 = Some(md5.to_string());
-    }
+    blob.properties.content_md5 = Some(computed_md5.clone());
     if let Some(cd) = ctx.header("x-ms-blob-content-disposition") {
         blob.properties.content_disposition = Some(cd.to_string());
     }
@@ -90,13 +96,14 @@ pub async fn upload_block_blob(
 
     // Set access tier
     if let Some(tier) = ctx.header("x-ms-access-tier") {
-        if let Some(t) = crate::models::AccessTier::from_str(tier) {
+        if let Some(t) = crate::models::AccessTier::from_str(tier).filter(|t| t.applies_to(BlobType::BlockBlob)) {
             blob.properties.access_tier = t;
         }
     }
 
-    // Set metadata
-    blob.metadata = ctx.metadata();
+    // Set metadata and tags
+    blob.metadata = request_metadata;
+    blob.tags = request_tags;
 
     // Set extent chunks
     if let Some(chunk) = extent_chunk {
@@ -106,10 +113,15 @@ pub async fn upload_block_blob(
     // Create or update blob
     metadata.create_blob(blob.clone()).await?;
 
-    // Clear any staged blocks for this blob
-    metadata
+    // Clear any staged blocks for this blob, then free what neither they nor
+    // the replaced blob's snapshots still reference
+    let mut released = metadata
         .delete_staged_blocks(&ctx.account, container, blob_name)
         .await?;
+    if let Some(existing_blob) = existing_blob {
+        released.extend(existing_blob.extent_chunks);
+    }
+    release_extents(&*metadata, &*extents, &released).await;
 
     let mut headers = common_headers();
     add_blob_headers(
@@ -118,13 +130,8 @@ pub async fn upload_block_blob(
         &blob.properties.last_modified,
     );
 
-    // Compute and return Content-MD5 if we computed it
-    let content_md5 = BASE64.encode(Md5::digest(&[]));
-    headers.insert("Content-MD5", HeaderValue::from_str(&content_md5).unwrap());
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
-    );
+    headers.insert("Content-MD5", HeaderValue::from_str(&computed_md5).unwrap());
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -164,17 +171,17 @@ pub async fn stage_block(
         ));
     }
 
-    // Check lease if blob exists
+    // Blocks can only be staged for block blobs; check lease if blob exists
     if let Ok(existing_blob) = metadata.get_blob(&ctx.account, container, blob_name, "").await {
-        check_blob_lease(&existing_blob, ctx.lease_id())?;
+        if existing_blob.properties.blob_type != BlobType::BlockBlob {
+            return Err(StorageError::new(ErrorCode::InvalidBlobType));
+        }
+        check_blob_lease(&existing_blob, ctx)?;
     }
 
     // Validate Content-MD5 if provided
     if let Some(expected_md5) = ctx.content_md5() {
-        let computed_md5 = BASE64.encode(Md5::digest(&body));
-        if computed_md5 != expected_md5 {
-            return Err(StorageError::new(ErrorCode::Md5Mismatch));
-        }
+        verify_md5(expected_md5, &body)?;
     }
 
     // Store block data
@@ -189,16 +196,14 @@ pub async fn stage_block(
         block_id.to_string(),
         block_size,
         extent_chunk,
+        ctx.timestamp,
     );
 
     // Stage the block
     metadata.stage_block(block).await?;
 
     let mut headers = common_headers();
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
-    );
+    add_request_server_encrypted(&mut headers);
     headers.insert(
         "x-ms-content-crc64",
         HeaderValue::from_static("AAAAAAAAAA=="),
@@ -234,7 +239,13 @@ pub async fn commit_block_list(
         .await
         .ok();
     if let Some(ref blob) = existing_blob {
-        check_blob_lease(blob, ctx.lease_id())?;
+        check_blob_lease(blob, ctx)?;
+        check_sas_overwrite_permission(ctx)?;
+    }
+
+    // Content-MD5 covers the block list XML itself
+    if let Some(expected_md5) = ctx.content_md5() {
+        verify_md5(expected_md5, &body)?;
     }
 
     // Parse block list from request body
@@ -279,7 +290,13 @@ pub async fn commit_block_list(
         }
     }
 
+    check_extents_present(&*extents, container, blob_name, &extent_chunks).await?;
+
     // Create or update blob
+    let replaced_chunks = existing_blob
+        .as_ref()
+        .map(|blob| blob.extent_chunks.clone())
+        .unwrap_or_default();
     let mut blob = existing_blob.unwrap_or_else(|| {
         BlobModel::new(
             ctx.account.clone(),
@@ -287,26 +304,24 @@ pub async fn commit_block_list(
             blob_name.clone(),
             BlobType::BlockBlob,
             0,
+            ctx.timestamp,
         )
     });
 
     blob.properties.content_length = total_size;
     blob.extent_chunks = extent_chunks;
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
 
     // Set content properties from headers
-    if let Some(ct) = ctx.header("x-ms-blob-content-type") {
-        blob.properties.content_type = Some(ct.to_string());
-    }
+    blob.properties.content_type = Some(blob_content_type(ctx, false));
     if let Some(ce) = ctx.header("x-ms-blob-content-encoding") {
         blob.properties.content_encoding = Some(ce.to_string());
     }
     if let Some(cl) = ctx.header("x-ms-blob-content-language") {
         blob.properties.content_language = Some(cl.to_string());
     }
-    if let Some(md5) = ctx.header("x-ms-blob-content-md5") {
-        blob.properties.content_md5 = Some(md5.to_string());
-    }
+    // The blob-level MD5 is stored as given, without verification
+    blob.properties.content_md5 = ctx.header("x-ms-blob-content-md5").map(String::from);
     if let Some(cd) = ctx.header("x-ms-blob-content-disposition") {
         blob.properties.content_disposition = Some(cd.to_string());
     }
@@ -316,24 +331,30 @@ pub async fn commit_block_list(
 
     // Set access tier
     if let Some(tier) = ctx.header("x-ms-access-tier") {
-        if let Some(t) = crate::models::AccessTier::from_str(tier) {
+        if let Some(t) = crate::models::AccessTier::from_str(tier).filter(|t| t.applies_to(BlobType::BlockBlob)) {
             blob.properties.access_tier = t;
         }
     }
 
-    // Set metadata
-    let request_metadata = ctx.metadata();
+    // Set metadata and tags
+    let request_metadata = ctx.metadata()?;
     if !request_metadata.is_empty() {
         blob.metadata = request_metadata;
     }
+    let request_tags = request_tags(ctx)?;
+    if !request_tags.is_empty() {
+        blob.tags = request_tags;
+    }
 
     // Save blob
     metadata.create_blob(blob.clone()).await?;
 
-    // Clear staged blocks
-    metadata
+    // Clear staged blocks; the committed ones now belong to the blob
+    let mut released = metadata
         .delete_staged_blocks(&ctx.account, container, blob_name)
         .await?;
+    released.extend(replaced_chunks);
+    release_extents(&*metadata, &*extents, &released).await;
 
     let mut headers = common_headers();
     add_blob_headers(
@@ -341,10 +362,7 @@ pub async fn commit_block_list(
         &blob.properties.etag,
         &blob.properties.last_modified,
     );
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
-    );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -364,10 +382,16 @@ pub async fn get_block_list(
         .ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
     let snapshot = ctx.snapshot().unwrap_or("");
 
-    let block_list_type = ctx
-        .query_param("blocklisttype")
-        .map(|s| crate::models::BlockListType::from_str(s))
-        .unwrap_or(crate::models::BlockListType::All);
+    let block_list_type = match ctx.query_param("blocklisttype") {
+        Some(value) => BlockListType::parse(value).ok_or_else(|| {
+            StorageError::invalid_query_parameter(
+                "blocklisttype",
+                value,
+                "The value must be one of committed, uncommitted or all.",
+            )
+        })?,
+        None => BlockListType::All,
+    };
 
     // Get blob (may not exist yet if only staging blocks)
     let blob = metadata
@@ -376,7 +400,7 @@ pub async fn get_block_list(
         .ok();
 
     // Get staged blocks
-    let staged_blocks = if snapshot.is_empty() {
+    let staged_blocks = if snapshot.is_empty() && block_list_type != BlockListType::Committed {
         metadata
             .get_staged_blocks(&ctx.account, container, blob_name)
             .await?
@@ -384,6 +408,12 @@ pub async fn get_block_list(
         Vec::new()
     };
 
+    // Without a committed version only staged blocks can be listed, and
+    // the blob exists only if there are some
+    if blob.is_none() && staged_blocks.is_empty() {
+        return Err(StorageError::new(ErrorCode::BlobNotFound));
+    }
+
     // Build committed blocks list
     // In a full implementation, we'd track block IDs with the committed blob
     let committed_blocks: Vec<BlockModel> = Vec::new();
@@ -409,25 +439,13 @@ pub async fn get_block_list(
 }
 
 /// PUT /{container}/{blob}?comp=block&blockid={id}&fromURL - Stage block from URL.
-pub async fn stage_block_from_url(
-    ctx: &RequestContext,
-    metadata: Arc<dyn MetadataStore>,
-    extents: Arc<dyn ExtentStore>,
-) -> StorageResult<Response<Body>> {
-    // Simplified implementation - would need to fetch from source URL
-    Err(StorageError::with_message(
-        ErrorCode::InvalidOperation,
-        "Stage block from URL not implemented",
-    ))
+pub async fn stage_block_from_url(ctx: &RequestContext, state: &AppState) -> StorageResult<Response<Body>> {
+    let data = fetch_copy_source(ctx, &state.copy_source_client).await?;
+    stage_block(ctx, state.metadata.clone(), state.extents.clone(), data).await
 }
 
 /// PUT /{container}/{blob} with x-ms-copy-source-url - Put blob from URL.
-pub async fn put_blob_from_url(
-    ctx: &RequestContext,
-    metadata: Arc<dyn MetadataStore>,
-    extents: Arc<dyn ExtentStore>,
-) -> StorageResult<Response<Body>> {
-    // This is similar to copy_blob but synchronous
-    // For simplicity, we'll delegate to copy_blob
-    super::blob::copy_blob(ctx, metadata, extents).await
+pub async fn put_blob_from_url(ctx: &RequestContext, state: &AppState) -> StorageResult<Response<Body>> {
+    let data = fetch_copy_source(ctx, &state.copy_source_client).await?;
+    upload_block_blob(ctx, state.metadata.clone(), state.extents.clone(), data).await
 }
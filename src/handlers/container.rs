@@ -2,24 +2,24 @@
 
 use axum::{
     body::Body,
-    http::{header::HeaderName, HeaderMap, HeaderValue, Response, StatusCode},
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
 };
 use bytes::Bytes;
-use chrono::Utc;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::context::{format_http_date, ListParams, RequestContext};
+use crate::context::{format_http_date, ListParams, RequestContext, MAX_LIST_RESULTS};
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::models::{
     ContainerModel, LeaseDuration, LeaseState, LeaseStatus, PublicAccessLevel,
 };
-use crate::storage::MetadataStore;
+use crate::storage::{ExtentStore, MetadataStore};
 use crate::xml::{
     deserialize::parse_signed_identifiers,
-    serialize::{serialize_blob_list, serialize_signed_identifiers},
+    serialize::{serialize_blob_list, serialize_signed_identifiers, BlobListIncludes, BlobListPage},
 };
 
-use super::{add_blob_headers, build_response, common_headers};
+use super::{add_blob_headers, add_metadata_headers, build_response, common_headers};
 
 /// PUT /{container}?restype=container - Create container.
 pub async fn create_container(
@@ -31,10 +31,8 @@ pub async fn create_container(
         .as_ref()
         .ok_or_else(|| StorageError::new(ErrorCode::InvalidResourceName))?;
 
-    // Validate container name
-    validate_container_name(container_name)?;
-
-    let mut container = ContainerModel::new(ctx.account.clone(), container_name.clone());
+    let mut container = ContainerModel::new(ctx.account.clone(), container_name.clone(), ctx.timestamp);
+    container.properties.renew_etag(&ctx.etags);
 
     // Set public access level from header
     if let Some(access) = ctx.header("x-ms-blob-public-access") {
@@ -43,7 +41,7 @@ pub async fn create_container(
     }
 
     // Set metadata
-    container.metadata = ctx.metadata();
+    container.metadata = ctx.metadata()?;
 
     metadata.create_container(container.clone()).await?;
 
@@ -58,9 +56,14 @@ pub async fn create_container(
 }
 
 /// DELETE /{container}?restype=container - Delete container.
+///
+/// With a non-zero `linger` the name stays reserved for that long, and
+/// requests for the container fail with `ContainerBeingDeleted`.
 pub async fn delete_container(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
+    extents: Arc<dyn ExtentStore>,
+    linger: Duration,
 ) -> StorageResult<Response<Body>> {
     let container_name = ctx
         .container
@@ -69,9 +72,17 @@ pub async fn delete_container(
 
     // Check lease
     let container = metadata.get_container(&ctx.account, container_name).await?;
-    check_container_lease(&container, ctx.lease_id())?;
+    check_container_lease(&container, ctx)?;
+    check_container_conditional_headers(ctx, &container)?;
+
+    let reserve_until = (!linger.is_zero())
+        .then(|| ctx.timestamp + chrono::Duration::from_std(linger).unwrap_or(chrono::Duration::MAX));
+    let extent_ids = metadata.delete_container(&ctx.account, container_name, reserve_until).await?;
 
-    metadata.delete_container(&ctx.account, container_name).await?;
+    // Clean up extent data of the deleted blobs and staged blocks
+    for extent_id in &extent_ids {
+        let _ = extents.delete(extent_id).await;
+    }
 
     let headers = common_headers();
 
@@ -79,9 +90,13 @@ pub async fn delete_container(
 }
 
 /// GET/HEAD /{container}?restype=container - Get container properties.
+///
+/// With `stats_headers`, the container's blob count and their total content
+/// length are added as `x-azurite-blob-count` and `x-azurite-blob-bytes`.
 pub async fn get_container_properties(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
+    stats_headers: bool,
 ) -> StorageResult<Response<Body>> {
     let container_name = ctx
         .container
@@ -104,6 +119,9 @@ pub async fn get_container_properties(
         "x-ms-lease-state",
         HeaderValue::from_static(container.properties.lease_state.as_str()),
     );
+    if let Some(duration) = container.properties.active_lease_duration() {
+        headers.insert("x-ms-lease-duration", HeaderValue::from_static(duration.as_str()));
+    }
     if container.properties.public_access != PublicAccessLevel::None {
         headers.insert(
             "x-ms-blob-public-access",
@@ -118,18 +136,17 @@ pub async fn get_container_properties(
         "x-ms-has-legal-hold",
         HeaderValue::from_str(&container.properties.has_legal_hold.to_string()).unwrap(),
     );
-
-    // Add metadata headers
-    for (key, value) in &container.metadata {
-        if let Ok(header_value) = HeaderValue::from_str(value) {
-            headers.insert(
-                format!("x-ms-meta-{}", key).parse::<HeaderName>().unwrap(),
-                header_value,
-            );
-        }
+    if stats_headers {
+        let stats = metadata.container_stats(&ctx.account, container_name).await;
+        headers.insert("x-azurite-blob-count", HeaderValue::from(stats.blobs));
+        headers.insert("x-azurite-blob-bytes", HeaderValue::from(stats.bytes));
     }
 
-    Ok(build_response(StatusCode::OK, headers, Body::empty()))
+    let header_case = add_metadata_headers(&mut headers, &container.metadata);
+
+    let mut response = build_response(StatusCode::OK, headers, Body::empty());
+    response.extensions_mut().insert(header_case);
+    Ok(response)
 }
 
 /// PUT /{container}?restype=container&comp=metadata - Set container metadata.
@@ -143,10 +160,11 @@ pub async fn set_container_metadata(
         .ok_or_else(|| StorageError::new(ErrorCode::InvalidResourceName))?;
 
     let mut container = metadata.get_container(&ctx.account, container_name).await?;
-    check_container_lease(&container, ctx.lease_id())?;
+    check_container_lease(&container, ctx)?;
+    check_container_conditional_headers(ctx, &container)?;
 
-    container.metadata = ctx.metadata();
-    container.properties.update_etag();
+    container.metadata = ctx.metadata()?;
+    container.properties.update_etag(&ctx.etags, ctx.timestamp);
 
     metadata.update_container(container.clone()).await?;
 
@@ -202,7 +220,8 @@ pub async fn set_container_acl(
         .ok_or_else(|| StorageError::new(ErrorCode::InvalidResourceName))?;
 
     let mut container = metadata.get_container(&ctx.account, container_name).await?;
-    check_container_lease(&container, ctx.lease_id())?;
+    check_container_lease(&container, ctx)?;
+    check_container_conditional_headers(ctx, &container)?;
 
     // Parse signed identifiers from body
     if !body.is_empty() {
@@ -221,7 +240,7 @@ pub async fn set_container_acl(
         container.properties.public_access = PublicAccessLevel::None;
     }
 
-    container.properties.update_etag();
+    container.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_container(container.clone()).await?;
 
     let mut headers = common_headers();
@@ -244,11 +263,13 @@ pub async fn list_blobs(
         .as_ref()
         .ok_or_else(|| StorageError::new(ErrorCode::InvalidResourceName))?;
 
-    let list_params = ListParams::from_query(&ctx.query_params);
-    let include_snapshots = list_params.include.contains(&"snapshots".to_string());
-    let include_deleted = list_params.include.contains(&"deleted".to_string());
+    let list_params = ListParams::from_query(&ctx.query_pairs)?;
+    let include_snapshots = list_params.includes("snapshots");
+    let include_deleted = list_params.includes("deleted");
+    let mut includes = BlobListIncludes::from_params(&list_params);
+    includes.object_replication &= ctx.object_replication;
 
-    let maxresults = list_params.maxresults.unwrap_or(5000);
+    let maxresults = list_params.maxresults.unwrap_or(MAX_LIST_RESULTS);
 
     let (blobs, prefixes, next_marker) = metadata
         .list_blobs(
@@ -263,17 +284,16 @@ pub async fn list_blobs(
         )
         .await?;
 
-    let xml = serialize_blob_list(
-        &blobs,
-        &prefixes,
-        list_params.prefix.as_deref(),
-        list_params.delimiter.as_deref(),
-        list_params.marker.as_deref(),
+    let service_endpoint = ctx.service_endpoint();
+    let page = BlobListPage {
+        prefix: list_params.prefix.as_deref(),
+        delimiter: list_params.delimiter.as_deref(),
+        marker: list_params.marker.as_deref(),
         maxresults,
-        next_marker.as_deref(),
-        &ctx.account,
-        container_name,
-    );
+        next_marker: next_marker.as_deref(),
+        service_endpoint: &service_endpoint,
+    };
+    let xml = serialize_blob_list(&blobs, &prefixes, &page, container_name, &includes);
 
     let mut headers = common_headers();
     headers.insert("Content-Type", HeaderValue::from_static("application/xml"));
@@ -293,9 +313,11 @@ pub async fn container_lease(
 
     let action = ctx
         .header("x-ms-lease-action")
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-lease-action"))?;
 
     let mut container = metadata.get_container(&ctx.account, container_name).await?;
+    check_container_conditional_headers(ctx, &container)?;
+    container.properties.refresh_lease(ctx.timestamp);
     let mut headers = common_headers();
 
     match action.to_lowercase().as_str() {
@@ -321,7 +343,7 @@ pub async fn container_lease(
             container.properties.lease_duration = if duration == -1 {
                 Some(LeaseDuration::Infinite)
             } else {
-                container.properties.lease_expiry = Some(Utc::now() + chrono::Duration::seconds(duration as i64));
+                container.properties.lease_expiry = Some(ctx.timestamp + chrono::Duration::seconds(duration as i64));
                 Some(LeaseDuration::Fixed)
             };
 
@@ -351,13 +373,15 @@ pub async fn container_lease(
                 return Err(StorageError::new(ErrorCode::LeaseIdMismatchWithContainerOperation));
             }
 
-            if container.properties.lease_state != LeaseState::Leased {
+            if !matches!(container.properties.lease_state, LeaseState::Leased | LeaseState::Expired) {
                 return Err(StorageError::new(ErrorCode::LeaseIsBrokenAndCannotBeRenewed));
             }
+            container.properties.lease_state = LeaseState::Leased;
+            container.properties.lease_status = LeaseStatus::Locked;
 
             // Renew the lease expiry
             if let Some(LeaseDuration::Fixed) = container.properties.lease_duration {
-                container.properties.lease_expiry = Some(Utc::now() + chrono::Duration::seconds(60));
+                container.properties.lease_expiry = Some(ctx.timestamp + chrono::Duration::seconds(60));
             }
 
             headers.insert(
@@ -382,7 +406,7 @@ pub async fn container_lease(
                 headers.insert("x-ms-lease-time", HeaderValue::from_static("0"));
             } else {
                 container.properties.lease_state = LeaseState::Breaking;
-                container.properties.lease_break_time = Some(Utc::now() + chrono::Duration::seconds(break_period as i64));
+                container.properties.lease_break_time = Some(ctx.timestamp + chrono::Duration::seconds(break_period as i64));
                 headers.insert(
                     "x-ms-lease-time",
                     HeaderValue::from_str(&break_period.to_string()).unwrap(),
@@ -400,20 +424,17 @@ pub async fn container_lease(
 
             let new_lease_id = ctx
                 .header("x-ms-proposed-lease-id")
-                .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+                .ok_or_else(|| StorageError::missing_required_header("x-ms-proposed-lease-id"))?;
 
             container.properties.lease_id = Some(new_lease_id.to_string());
             headers.insert("x-ms-lease-id", HeaderValue::from_str(new_lease_id).unwrap());
         }
         _ => {
-            return Err(StorageError::with_message(
-                ErrorCode::InvalidHeaderValue,
-                "Invalid x-ms-lease-action header value",
-            ));
+            return Err(StorageError::invalid_header_value("x-ms-lease-action", action));
         }
     }
 
-    container.properties.update_etag();
+    container.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_container(container.clone()).await?;
 
     headers.insert("ETag", HeaderValue::from_str(&container.properties.etag).unwrap());
@@ -437,43 +458,23 @@ pub async fn restore_container(
     Ok(build_response(StatusCode::OK, headers, Body::empty()))
 }
 
-/// Validates a container name.
-fn validate_container_name(name: &str) -> StorageResult<()> {
-    // Container names must be 3-63 characters
-    if name.len() < 3 || name.len() > 63 {
-        return Err(StorageError::with_message(
-            ErrorCode::InvalidResourceName,
-            "Container name must be between 3 and 63 characters",
-        ));
-    }
-
-    // Must start with a letter or number
-    let first_char = name.chars().next().unwrap();
-    if !first_char.is_ascii_alphanumeric() {
-        return Err(StorageError::with_message(
-            ErrorCode::InvalidResourceName,
-            "Container name must start with a letter or number",
-        ));
-    }
+/// Evaluates If-Modified-Since / If-Unmodified-Since against the container.
+/// HTTP dates carry whole seconds, so Last-Modified is compared at that precision.
+fn check_container_conditional_headers(
+    ctx: &RequestContext,
+    container: &ContainerModel,
+) -> StorageResult<()> {
+    let last_modified = container.properties.last_modified.timestamp();
 
-    // Can only contain lowercase letters, numbers, and hyphens
-    // Note: $root and $logs are special containers
-    if name != "$root" && name != "$logs" && name != "$web" {
-        for c in name.chars() {
-            if !c.is_ascii_lowercase() && !c.is_ascii_digit() && c != '-' {
-                return Err(StorageError::with_message(
-                    ErrorCode::InvalidResourceName,
-                    "Container name can only contain lowercase letters, numbers, and hyphens",
-                ));
-            }
+    if let Some(since) = ctx.if_modified_since() {
+        if last_modified <= since.timestamp() {
+            return Err(StorageError::new(ErrorCode::ConditionNotMet));
         }
+    }
 
-        // Cannot have consecutive hyphens
-        if name.contains("--") {
-            return Err(StorageError::with_message(
-                ErrorCode::InvalidResourceName,
-                "Container name cannot have consecutive hyphens",
-            ));
+    if let Some(since) = ctx.if_unmodified_since() {
+        if last_modified > since.timestamp() {
+            return Err(StorageError::new(ErrorCode::ConditionNotMet));
         }
     }
 
@@ -481,9 +482,9 @@ fn validate_container_name(name: &str) -> StorageResult<()> {
 }
 
 /// Checks if the container lease allows the operation.
-fn check_container_lease(container: &ContainerModel, provided_lease_id: Option<&str>) -> StorageResult<()> {
-    if container.properties.lease_state == LeaseState::Leased {
-        match (container.properties.lease_id.as_deref(), provided_lease_id) {
+fn check_container_lease(container: &ContainerModel, ctx: &RequestContext) -> StorageResult<()> {
+    if container.properties.is_leased(ctx.timestamp) {
+        match (container.properties.lease_id.as_deref(), ctx.lease_id()) {
             (Some(expected), Some(provided)) if expected == provided => Ok(()),
             (Some(_), Some(_)) => Err(StorageError::new(ErrorCode::LeaseIdMismatchWithContainerOperation)),
             (Some(_), None) => Err(StorageError::new(ErrorCode::LeaseIdMissing)),
@@ -0,0 +1,165 @@
+//! Fetching the source of from-URL operations.
+//!
+//! Stage Block From URL, Append Block From URL, Put Blob From URL and
+//! synchronous Copy Blob all read their source over HTTP through
+//! [`fetch_copy_source`], so source validation and failure reporting are the
+//! same for every operation.
+
+use axum::http::StatusCode;
+use bytes::{Bytes, BytesMut};
+use reqwest::header::{self, HeaderValue};
+use std::time::Duration;
+
+use crate::context::{format_http_date, RequestContext};
+use crate::error::{ErrorCode, StorageError, StorageResult};
+
+use super::verify_md5;
+
+/// Longest a copy source may take to accept the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest a copy source may go without sending anything, before its
+/// response headers or between parts of its body.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Most bytes read from a copy source: 5000 MiB, the largest blob Put Blob
+/// From URL creates.
+pub(crate) const MAX_COPY_SOURCE_SIZE: u64 = 5000 * 1024 * 1024;
+
+/// Builds the client [`fetch_copy_source`] reads sources with. It is created
+/// once per server and shared, so connections to a source are reused.
+pub(crate) fn copy_source_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .no_proxy()
+        .build()
+        .expect("the copy source client has a valid configuration")
+}
+
+/// Downloads the source named by `x-ms-copy-source`.
+///
+/// `x-ms-source-range` is forwarded as the `Range` of the source request and
+/// `x-ms-copy-source-authorization` as its `Authorization`. As in Azure,
+/// credentials are only sent to https sources; since only http sources can
+/// be read here, forwarding them requires loose mode. When
+/// `x-ms-source-content-md5` is given, the fetched bytes must hash to it.
+///
+/// Sources that cannot be reached or answer with an error status fail with
+/// `CannotVerifyCopySource`. Like Azure, a 4xx or 5xx status from the source
+/// is returned as the status of the response and reported in the
+/// `CopySourceStatusCode` and `CopySourceErrorCode` elements. So do sources
+/// that stop answering for [`READ_TIMEOUT`]; sources larger than
+/// [`MAX_COPY_SOURCE_SIZE`] fail with `RequestBodyTooLarge`.
+pub(crate) async fn fetch_copy_source(ctx: &RequestContext, client: &reqwest::Client) -> StorageResult<Bytes> {
+    let source = ctx
+        .copy_source()
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-copy-source"))?;
+    let url = url::Url::parse(source).map_err(|_| StorageError::new(ErrorCode::InvalidSourceBlobUrl))?;
+    if url.scheme() != "http" {
+        return Err(StorageError::with_message(
+            ErrorCode::CannotVerifyCopySource,
+            format!("Copy source scheme '{}' is not supported, only http sources can be read.", url.scheme()),
+        ));
+    }
+
+    let mut request = client
+        .get(url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", format_http_date(&ctx.timestamp));
+    if let Some(range) = ctx.header("x-ms-source-range") {
+        request = request.header(header::RANGE, range);
+    }
+    if let Some(authorization) = ctx.copy_source_authorization() {
+        if !ctx.loose {
+            return Err(StorageError::with_message(
+                ErrorCode::CannotVerifyCopySource,
+                "x-ms-copy-source-authorization is only sent to https copy sources.",
+            ));
+        }
+        let value = HeaderValue::from_str(authorization)
+            .map_err(|_| StorageError::invalid_header_value("x-ms-copy-source-authorization", authorization))?;
+        request = request.header(header::AUTHORIZATION, value);
+    }
+
+    let mut response = tokio::time::timeout(READ_TIMEOUT, request.send())
+        .await
+        .map_err(|_| read_timed_out())?
+        .map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::CannotVerifyCopySource,
+                format!("Could not connect to the copy source: {}", e),
+            )
+        })?;
+
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    if response.content_length().is_some_and(|length| length > MAX_COPY_SOURCE_SIZE) {
+        return Err(source_too_large());
+    }
+    let mut body = BytesMut::new();
+    while let Some(chunk) = tokio::time::timeout(READ_TIMEOUT, response.chunk())
+        .await
+        .map_err(|_| read_timed_out())?
+        .map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::CannotVerifyCopySource,
+                format!("Could not read the copy source: {}", e),
+            )
+        })?
+    {
+        if (body.len() + chunk.len()) as u64 > MAX_COPY_SOURCE_SIZE {
+            return Err(source_too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+
+    if !status.is_success() {
+        return Err(source_status_error(status, &body));
+    }
+
+    if let Some(expected) = ctx.header("x-ms-source-content-md5") {
+        verify_md5(expected, &body)?;
+    }
+
+    Ok(body)
+}
+
+fn read_timed_out() -> StorageError {
+    StorageError::with_message(
+        ErrorCode::CannotVerifyCopySource,
+        format!("The copy source sent nothing for {} seconds.", READ_TIMEOUT.as_secs()),
+    )
+}
+
+fn source_too_large() -> StorageError {
+    StorageError::with_message(
+        ErrorCode::RequestBodyTooLarge,
+        format!("The copy source is larger than the {} MiB that can be copied.", MAX_COPY_SOURCE_SIZE >> 20),
+    )
+}
+
+/// Builds the error for a source that answered with an error status,
+/// carrying over the Azure error code of the source response if it has one.
+fn source_status_error(status: StatusCode, body: &[u8]) -> StorageError {
+    let source_code = std::str::from_utf8(body)
+        .ok()
+        .and_then(|xml| xml.split_once("<Code>"))
+        .and_then(|(_, rest)| rest.split_once("</Code>"))
+        .map(|(code, _)| code.to_string());
+
+    let reason = status.canonical_reason().unwrap_or("Unknown");
+    let message = match &source_code {
+        Some(code) => format!("The copy source responded with {} {} ({}).", status.as_u16(), reason, code),
+        None => format!("The copy source responded with {} {}.", status.as_u16(), reason),
+    };
+
+    let mut error = StorageError::with_message(ErrorCode::CannotVerifyCopySource, message);
+    if status.is_client_error() || status.is_server_error() {
+        error = error.with_status(status);
+    }
+    let error = error.with_detail("CopySourceStatusCode", status.as_u16().to_string());
+    match source_code {
+        Some(code) => error.with_detail("CopySourceErrorCode", code),
+        None => error,
+    }
+}
@@ -1,13 +1,16 @@
 //! Request handlers for Azure Blob Storage API.
 
+mod admin;
 mod append_blob;
 mod batch;
 mod blob;
 mod block_blob;
 mod container;
+mod copy_source;
 mod page_blob;
 mod service;
 
+pub use admin::*;
 pub use append_blob::*;
 pub use batch::*;
 pub use blob::*;
@@ -15,13 +18,21 @@ pub use block_blob::*;
 pub use container::*;
 pub use page_blob::*;
 pub use service::*;
+pub(crate) use copy_source::copy_source_client;
 
 use axum::body::Body;
-use axum::http::{HeaderMap, HeaderValue, Response, StatusCode};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
+use bytes::Bytes;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use chrono::Utc;
+use md5::{Digest, Md5};
 use uuid::Uuid;
 
-use crate::context::format_http_date;
+use crate::context::{format_http_date, RequestContext, ResponseHeaderOverrides};
+use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::header_case::HeaderCase;
+use crate::models::{BlobModel, ExtentChunk, Metadata};
+use crate::storage::{release_extents, ExtentStore};
 
 /// Creates common response headers for Azure Blob Storage API responses.
 pub fn common_headers() -> HeaderMap {
@@ -39,6 +50,100 @@ pub fn add_blob_headers(headers: &mut HeaderMap, etag: &str, last_modified: &chr
     headers.insert("Last-Modified", HeaderValue::from_str(&format_http_date(last_modified)).unwrap());
 }
 
+/// Adds `x-ms-request-server-encrypted` to the response of a write. Azure
+/// encrypts all data at rest, so every write that stores content or
+/// metadata reports it.
+pub fn add_request_server_encrypted(headers: &mut HeaderMap) {
+    headers.insert("x-ms-request-server-encrypted", HeaderValue::from_static("true"));
+}
+
+/// Adds an `x-ms-meta-*` header for each metadata pair, returning the
+/// spelling the names are sent with. Put it in the response's extensions
+/// so mixed-case keys go out as stored.
+pub fn add_metadata_headers(headers: &mut HeaderMap, metadata: &Metadata) -> HeaderCase {
+    let mut case = HeaderCase::new();
+    for (key, value) in metadata {
+        let name = format!("x-ms-meta-{}", key);
+        let (Ok(header_name), Ok(header_value)) = (name.parse::<HeaderName>(), HeaderValue::from_str(value)) else {
+            continue;
+        };
+        headers.insert(header_name, header_value);
+        if key.bytes().any(|b| b.is_ascii_uppercase()) {
+            case.push(name);
+        }
+    }
+    case
+}
+
+/// Returns the declared Content-Length, failing with 411
+/// `MissingContentLengthHeader` for chunked requests. Bodies are buffered
+/// before the handler runs, so only operations Azure refuses to accept
+/// chunked call this. A request with neither header has an empty body.
+pub fn require_content_length(ctx: &RequestContext) -> StorageResult<u64> {
+    match ctx.content_length() {
+        Some(length) => Ok(length),
+        None if ctx.header("transfer-encoding").is_some() => {
+            Err(StorageError::new(ErrorCode::MissingContentLengthHeader))
+        }
+        None => Ok(0),
+    }
+}
+
+/// Applies SAS response header overrides on top of the stored blob headers.
+pub fn apply_response_overrides(headers: &mut HeaderMap, overrides: &ResponseHeaderOverrides) {
+    let pairs = [
+        ("Cache-Control", &overrides.cache_control),
+        ("Content-Disposition", &overrides.content_disposition),
+        ("Content-Encoding", &overrides.content_encoding),
+        ("Content-Language", &overrides.content_language),
+        ("Content-Type", &overrides.content_type),
+    ];
+    for (name, value) in pairs {
+        if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Returns the base64 MD5 of `data`, as carried in Content-MD5 headers.
+pub fn content_md5(data: &[u8]) -> String {
+    BASE64.encode(Md5::digest(data))
+}
+
+/// Checks `data` against a base64 MD5 sent by the client, failing with
+/// `Md5Mismatch` naming both hashes.
+pub fn verify_md5(expected: &str, data: &[u8]) -> StorageResult<()> {
+    let computed = content_md5(data);
+    if computed != expected {
+        return Err(StorageError::new(ErrorCode::Md5Mismatch)
+            .with_detail("UserSpecifiedMd5", expected)
+            .with_detail("ServerCalculatedMd5", computed));
+    }
+    Ok(())
+}
+
+/// Content type assigned to blobs created without one.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Returns the content type a blob write stores.
+///
+/// `x-ms-blob-content-type` wins when sent, and an empty value means the
+/// default rather than the transport header. The transport `Content-Type`
+/// describes the blob only when the body is the blob's content, as for Put
+/// Blob with data; Put Block List's body is the block list XML.
+pub fn blob_content_type(ctx: &RequestContext, body_is_content: bool) -> String {
+    let content_type = match ctx.header("x-ms-blob-content-type") {
+        Some(content_type) => content_type,
+        None if body_is_content => ctx.content_type().unwrap_or(""),
+        None => "",
+    };
+    if content_type.is_empty() {
+        DEFAULT_CONTENT_TYPE.to_string()
+    } else {
+        content_type.to_string()
+    }
+}
+
 /// Builds a response with the given status, headers, and body.
 pub fn build_response(status: StatusCode, headers: HeaderMap, body: Body) -> Response<Body> {
     let mut response = Response::builder()
@@ -48,3 +153,76 @@ pub fn build_response(status: StatusCode, headers: HeaderMap, body: Body) -> Res
     *response.headers_mut() = headers;
     response
 }
+
+/// Zeros that [`BodyPiece::Zeros`] runs are sent from.
+static ZEROS: [u8; 64 * 1024] = [0; 64 * 1024];
+
+/// Part of a response body: bytes read from an extent or built by the
+/// handler, or a run of zeros, such as the unwritten pages of a page blob,
+/// sent in chunks of a shared static buffer instead of being allocated.
+pub(crate) enum BodyPiece {
+    Data(Bytes),
+    Zeros(u64),
+}
+
+impl BodyPiece {
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            BodyPiece::Data(data) => data.len() as u64,
+            BodyPiece::Zeros(len) => *len,
+        }
+    }
+
+    /// The buffers the piece is sent as.
+    pub(crate) fn into_chunks(self) -> Box<dyn Iterator<Item = Bytes> + Send> {
+        match self {
+            BodyPiece::Data(data) => Box::new(std::iter::once(data)),
+            BodyPiece::Zeros(len) => Box::new((0..len).step_by(ZEROS.len()).map(move |offset| {
+                Bytes::from_static(&ZEROS[..(len - offset).min(ZEROS.len() as u64) as usize])
+            })),
+        }
+    }
+}
+
+/// Reads part of an extent referenced by `blob`. A read of an extent the
+/// store has lost fails with `InternalError` naming the blob and extent.
+pub(crate) async fn read_blob_extent(
+    extents: &dyn ExtentStore,
+    blob: &BlobModel,
+    chunk: &ExtentChunk,
+    offset: u64,
+    count: u64,
+) -> StorageResult<Bytes> {
+    match extents.read_range(chunk, offset, count).await {
+        Err(e) if !extents.contains(&chunk.id).await => Err(StorageError::with_message(
+            e.code,
+            format!(
+                "Blob {}/{} references extent {}, which is missing from the extent store.",
+                blob.container, blob.name, chunk.id
+            ),
+        )),
+        result => result,
+    }
+}
+
+/// Fails with `InternalError` if the extent store has lost an extent of
+/// `chunks`, so a write does not commit `container/blob` over missing data.
+pub(crate) async fn check_extents_present(
+    extents: &dyn ExtentStore,
+    container: &str,
+    blob: &str,
+    chunks: &[ExtentChunk],
+) -> StorageResult<()> {
+    for chunk in chunks {
+        if !extents.contains(&chunk.id).await {
+            return Err(StorageError::with_message(
+                ErrorCode::InternalError,
+                format!(
+                    "Blob {}/{} would reference extent {}, which is missing from the extent store.",
+                    container, blob, chunk.id
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
@@ -7,20 +7,26 @@ use axum::{
 use bytes::Bytes;
 use std::sync::Arc;
 
-use crate::context::{format_http_date, RequestContext};
+use crate::context::{format_http_date, parse_ranged_query_param, RequestContext};
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::models::{
-    BlobModel, BlobType, ExtentChunk, PageRange, PageRangeDiff, PAGE_SIZE,
+    diff_page_ranges, merge_page_ranges, truncate_page_ranges, update_page_ranges, AccessTier,
+    BlobModel, BlobType, ExtentChunk, PageRange, PageRangeDiff, MAX_PAGE_BLOB_SIZE, PAGE_SIZE,
 };
 use crate::storage::{ExtentStore, MetadataStore};
 use crate::xml::serialize::{serialize_page_ranges, serialize_page_ranges_diff};
 
-use super::{add_blob_headers, blob::check_blob_lease, build_response, common_headers};
+use super::{
+    add_blob_headers, add_request_server_encrypted,
+    blob::{check_blob_lease, check_sas_overwrite_permission, request_tags},
+    blob_content_type, build_response, common_headers, read_blob_extent, BodyPiece, release_extents, require_content_length,
+};
 
 /// PUT /{container}/{blob} (x-ms-blob-type: PageBlob) - Create page blob.
 pub async fn create_page_blob(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
+    body: Bytes,
 ) -> StorageResult<Response<Body>> {
     let container = ctx
         .container
@@ -31,28 +37,34 @@ pub async fn create_page_blob(
         .as_ref()
         .ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
+    require_content_length(ctx)?;
+
     // Verify container exists
     if !metadata.container_exists(&ctx.account, container).await {
         return Err(StorageError::new(ErrorCode::ContainerNotFound));
     }
 
-    // Get content length (required for page blobs)
-    let content_length: u64 = ctx
-        .header("x-ms-blob-content-length")
-        .and_then(|s| s.parse().ok())
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
-
-    // Content length must be aligned to 512 bytes
-    if content_length % PAGE_SIZE != 0 {
+    // Put Blob for a page blob only sets its size; the body must be empty
+    if !body.is_empty() {
         return Err(StorageError::with_message(
-            ErrorCode::InvalidHeaderValue,
-            "Page blob size must be aligned to 512 bytes",
+            ErrorCode::InvalidOperation,
+            "The request body must be empty when creating a page blob.",
         ));
     }
 
+    // Get content length (required for page blobs)
+    let content_length = page_blob_size(ctx)?;
+    let sequence_number = ctx
+        .header("x-ms-blob-sequence-number")
+        .map(parse_sequence_number)
+        .transpose()?
+        .unwrap_or(0);
+
     // Check if blob exists and validate lease
-    if let Ok(existing_blob) = metadata.get_blob(&ctx.account, container, blob_name, "").await {
-        check_blob_lease(&existing_blob, ctx.lease_id())?;
+    let existing_blob = metadata.get_blob(&ctx.account, container, blob_name, "").await.ok();
+    if let Some(existing_blob) = &existing_blob {
+        check_blob_lease(existing_blob, ctx)?;
+        check_sas_overwrite_permission(ctx)?;
     }
 
     // Create page blob model
@@ -62,12 +74,12 @@ pub async fn create_page_blob(
         blob_name.clone(),
         BlobType::PageBlob,
         content_length,
+        ctx.timestamp,
     );
+    blob.properties.renew_etag(&ctx.etags);
 
     // Set content properties from headers
-    if let Some(ct) = ctx.header("x-ms-blob-content-type") {
-        blob.properties.content_type = Some(ct.to_string());
-    }
+    blob.properties.content_type = Some(blob_content_type(ctx, false));
     if let Some(ce) = ctx.header("x-ms-blob-content-encoding") {
         blob.properties.content_encoding = Some(ce.to_string());
     }
@@ -84,22 +96,19 @@ pub async fn create_page_blob(
         blob.properties.cache_control = Some(cc.to_string());
     }
 
-    // Set sequence number
-    if let Some(seq) = ctx.header("x-ms-blob-sequence-number") {
-        blob.properties.sequence_number = seq.parse().ok();
-    } else {
-        blob.properties.sequence_number = Some(0);
-    }
+    blob.properties.sequence_number = Some(sequence_number);
 
-    // Set access tier
+    // Set access tier; only the premium tiers apply to page blobs
     if let Some(tier) = ctx.header("x-ms-access-tier") {
-        if let Some(t) = crate::models::AccessTier::from_str(tier) {
+        if let Some(t) = AccessTier::from_str(tier).filter(|t| t.applies_to(BlobType::PageBlob)) {
+            check_tier_capacity(t, content_length)?;
             blob.properties.access_tier = t;
         }
     }
 
-    // Set metadata
-    blob.metadata = ctx.metadata();
+    // Set metadata and tags
+    blob.metadata = ctx.metadata()?;
+    blob.tags = request_tags(ctx)?;
 
     // Create blob
     metadata.create_blob(blob.clone()).await?;
@@ -110,10 +119,7 @@ pub async fn create_page_blob(
         &blob.properties.etag,
         &blob.properties.last_modified,
     );
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
-    );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -135,6 +141,7 @@ pub async fn upload_pages(
         .ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
     let page_write = ctx.header("x-ms-page-write").unwrap_or("update");
+    require_content_length(ctx)?;
 
     let mut blob = metadata
         .get_blob(&ctx.account, container, blob_name, "")
@@ -146,12 +153,12 @@ pub async fn upload_pages(
     }
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Parse range
     let (start, end) = ctx
         .range()
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-range"))?;
     let end = end.ok_or_else(|| StorageError::new(ErrorCode::InvalidRange))?;
 
     // Validate alignment
@@ -169,44 +176,49 @@ pub async fn upload_pages(
 
     // Check sequence number conditions
     if let Some(if_seq_le) = ctx.header("x-ms-if-sequence-number-le") {
-        let expected: u64 = if_seq_le.parse().map_err(|_| {
-            StorageError::new(ErrorCode::InvalidHeaderValue)
-        })?;
+        let expected: u64 = if_seq_le
+            .parse()
+            .map_err(|_| StorageError::invalid_header_value("x-ms-if-sequence-number-le", if_seq_le))?;
         if blob.properties.sequence_number.unwrap_or(0) > expected {
             return Err(StorageError::new(ErrorCode::SequenceNumberConditionNotMet));
         }
     }
     if let Some(if_seq_lt) = ctx.header("x-ms-if-sequence-number-lt") {
-        let expected: u64 = if_seq_lt.parse().map_err(|_| {
-            StorageError::new(ErrorCode::InvalidHeaderValue)
-        })?;
+        let expected: u64 = if_seq_lt
+            .parse()
+            .map_err(|_| StorageError::invalid_header_value("x-ms-if-sequence-number-lt", if_seq_lt))?;
         if blob.properties.sequence_number.unwrap_or(0) >= expected {
             return Err(StorageError::new(ErrorCode::SequenceNumberConditionNotMet));
         }
     }
     if let Some(if_seq_eq) = ctx.header("x-ms-if-sequence-number-eq") {
-        let expected: u64 = if_seq_eq.parse().map_err(|_| {
-            StorageError::new(ErrorCode::InvalidHeaderValue)
-        })?;
+        let expected: u64 = if_seq_eq
+            .parse()
+            .map_err(|_| StorageError::invalid_header_value("x-ms-if-sequence-number-eq", if_seq_eq))?;
         if blob.properties.sequence_number.unwrap_or(0) != expected {
             return Err(StorageError::new(ErrorCode::SequenceNumberConditionNotMet));
         }
     }
 
     if page_write == "update" {
-        // Store page data
-        let extent_chunk = extents.write(body).await?;
+        if body.len() as u64 != end - start + 1 {
+            return Err(StorageError::with_message(
+                ErrorCode::InvalidHeaderValue,
+                "The request body length must match the page range",
+            ));
+        }
 
-        // Simplified page management - in a full implementation, we'd need to
-        // track page ranges and merge/split as needed
-        // For now, we'll just append the extent chunk
-        blob.extent_chunks.push(extent_chunk);
+        // Store page data and point the range at it
+        let extent_chunk = extents.write(body).await?;
+        update_page_ranges(&mut blob.page_ranges, start, end, Some(extent_chunk));
     } else if page_write == "clear" {
-        // Clear pages - in a full implementation, we'd mark the range as cleared
+        update_page_ranges(&mut blob.page_ranges, start, end, None);
     }
+    let replaced = sync_page_extents(&mut blob);
 
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_blob(blob.clone()).await?;
+    release_extents(metadata.as_ref(), extents.as_ref(), &replaced).await;
 
     let mut headers = common_headers();
     add_blob_headers(
@@ -218,10 +230,7 @@ pub async fn upload_pages(
         "x-ms-blob-sequence-number",
         HeaderValue::from_str(&blob.properties.sequence_number.unwrap_or(0).to_string()).unwrap(),
     );
-    headers.insert(
-        "x-ms-request-server-encrypted",
-        HeaderValue::from_static("true"),
-    );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -230,6 +239,7 @@ pub async fn upload_pages(
 pub async fn clear_pages(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
+    extents: Arc<dyn ExtentStore>,
 ) -> StorageResult<Response<Body>> {
     let container = ctx
         .container
@@ -250,12 +260,12 @@ pub async fn clear_pages(
     }
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     // Parse range
     let (start, end) = ctx
         .range()
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-range"))?;
     let end = end.ok_or_else(|| StorageError::new(ErrorCode::InvalidRange))?;
 
     // Validate alignment
@@ -266,9 +276,17 @@ pub async fn clear_pages(
         ));
     }
 
-    // In a full implementation, we'd mark the page range as cleared
-    blob.properties.update_etag();
+    // Validate range is within blob size
+    if end >= blob.properties.content_length {
+        return Err(StorageError::new(ErrorCode::InvalidPageRange));
+    }
+
+    update_page_ranges(&mut blob.page_ranges, start, end, None);
+    let replaced = sync_page_extents(&mut blob);
+
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_blob(blob.clone()).await?;
+    release_extents(metadata.as_ref(), extents.as_ref(), &replaced).await;
 
     let mut headers = common_headers();
     add_blob_headers(
@@ -280,6 +298,7 @@ pub async fn clear_pages(
         "x-ms-blob-sequence-number",
         HeaderValue::from_str(&blob.properties.sequence_number.unwrap_or(0).to_string()).unwrap(),
     );
+    add_request_server_encrypted(&mut headers);
 
     Ok(build_response(StatusCode::CREATED, headers, Body::empty()))
 }
@@ -308,21 +327,17 @@ pub async fn get_page_ranges(
         return Err(StorageError::new(ErrorCode::InvalidBlobType));
     }
 
-    // Build page ranges from extent chunks
-    // Simplified - in a full implementation, we'd track actual page ranges
-    let ranges: Vec<PageRange> = if !blob.extent_chunks.is_empty() {
-        // Return a single range covering all written data
-        let total_size: u64 = blob.extent_chunks.iter().map(|c| c.count).sum();
-        if total_size > 0 {
-            vec![PageRange::new(0, total_size - 1)]
-        } else {
-            vec![]
-        }
-    } else {
-        vec![]
-    };
+    let ranges = merge_page_ranges(&blob.page_ranges)
+        .into_iter()
+        .map(|range| PageRangeDiff::new(range.start, range.end, false))
+        .collect();
+    let (ranges, next_marker) = scope_page_ranges(ctx, ranges)?;
+    let ranges: Vec<PageRange> = ranges
+        .into_iter()
+        .map(|range| PageRange::new(range.start, range.end))
+        .collect();
 
-    let xml = serialize_page_ranges(&ranges);
+    let xml = serialize_page_ranges(&ranges, next_marker.as_deref());
 
     let mut headers = common_headers();
     headers.insert("Content-Type", HeaderValue::from_static("application/xml"));
@@ -362,7 +377,7 @@ pub async fn get_page_ranges_diff(
     let current_blob = metadata
         .get_blob(&ctx.account, container, blob_name, current_snapshot)
         .await?;
-    let _prev_blob = metadata
+    let prev_blob = metadata
         .get_blob(&ctx.account, container, blob_name, prev_snapshot)
         .await?;
 
@@ -371,10 +386,10 @@ pub async fn get_page_ranges_diff(
         return Err(StorageError::new(ErrorCode::InvalidBlobType));
     }
 
-    // Simplified diff - in a full implementation, we'd compare actual page ranges
-    let ranges: Vec<PageRangeDiff> = vec![];
+    let ranges = diff_page_ranges(&prev_blob.page_ranges, &current_blob.page_ranges);
+    let (ranges, next_marker) = scope_page_ranges(ctx, ranges)?;
 
-    let xml = serialize_page_ranges_diff(&ranges);
+    let xml = serialize_page_ranges_diff(&ranges, next_marker.as_deref());
 
     let mut headers = common_headers();
     headers.insert("Content-Type", HeaderValue::from_static("application/xml"));
@@ -395,6 +410,7 @@ pub async fn get_page_ranges_diff(
 pub async fn resize_page_blob(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
+    extents: Arc<dyn ExtentStore>,
 ) -> StorageResult<Response<Body>> {
     let container = ctx
         .container
@@ -405,18 +421,7 @@ pub async fn resize_page_blob(
         .as_ref()
         .ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
 
-    let new_size: u64 = ctx
-        .header("x-ms-blob-content-length")
-        .and_then(|s| s.parse().ok())
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
-
-    // Validate alignment
-    if new_size % PAGE_SIZE != 0 {
-        return Err(StorageError::with_message(
-            ErrorCode::InvalidHeaderValue,
-            "Page blob size must be aligned to 512 bytes",
-        ));
-    }
+    let new_size = page_blob_size(ctx)?;
 
     let mut blob = metadata
         .get_blob(&ctx.account, container, blob_name, "")
@@ -428,12 +433,22 @@ pub async fn resize_page_blob(
     }
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
+    check_tier_capacity(blob.properties.access_tier, new_size)?;
+
+    // Shrinking discards the pages beyond the new size, so growing again
+    // later exposes zeros rather than stale data
+    let mut replaced = Vec::new();
+    if new_size < blob.properties.content_length {
+        truncate_page_ranges(&mut blob.page_ranges, new_size);
+        replaced = sync_page_extents(&mut blob);
+    }
 
     blob.properties.content_length = new_size;
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
 
     metadata.update_blob(blob.clone()).await?;
+    release_extents(metadata.as_ref(), extents.as_ref(), &replaced).await;
 
     let mut headers = common_headers();
     add_blob_headers(
@@ -465,7 +480,7 @@ pub async fn update_sequence_number(
 
     let action = ctx
         .header("x-ms-sequence-number-action")
-        .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-sequence-number-action"))?;
 
     let mut blob = metadata
         .get_blob(&ctx.account, container, blob_name, "")
@@ -477,37 +492,41 @@ pub async fn update_sequence_number(
     }
 
     // Check lease
-    check_blob_lease(&blob, ctx.lease_id())?;
+    check_blob_lease(&blob, ctx)?;
 
     let current_seq = blob.properties.sequence_number.unwrap_or(0);
 
     match action.to_lowercase().as_str() {
         "max" => {
-            let new_seq: u64 = ctx
+            let new_seq = ctx
                 .header("x-ms-blob-sequence-number")
-                .and_then(|s| s.parse().ok())
-                .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+                .map(parse_sequence_number)
+                .ok_or_else(|| StorageError::missing_required_header("x-ms-blob-sequence-number"))??;
             blob.properties.sequence_number = Some(current_seq.max(new_seq));
         }
         "update" => {
-            let new_seq: u64 = ctx
+            let new_seq = ctx
                 .header("x-ms-blob-sequence-number")
-                .and_then(|s| s.parse().ok())
-                .ok_or_else(|| StorageError::new(ErrorCode::MissingRequiredHeader))?;
+                .map(parse_sequence_number)
+                .ok_or_else(|| StorageError::missing_required_header("x-ms-blob-sequence-number"))??;
             blob.properties.sequence_number = Some(new_seq);
         }
         "increment" => {
-            blob.properties.sequence_number = Some(current_seq + 1);
+            if let Some(value) = ctx.header("x-ms-blob-sequence-number") {
+                return Err(StorageError::with_message(
+                    ErrorCode::InvalidHeaderValue,
+                    "x-ms-blob-sequence-number must not be set with action increment",
+                )
+                .with_header_detail("x-ms-blob-sequence-number", value));
+            }
+            blob.properties.sequence_number = Some(increment_sequence_number(current_seq)?);
         }
         _ => {
-            return Err(StorageError::with_message(
-                ErrorCode::InvalidHeaderValue,
-                "Invalid x-ms-sequence-number-action",
-            ));
+            return Err(StorageError::invalid_header_value("x-ms-sequence-number-action", action));
         }
     }
 
-    blob.properties.update_etag();
+    blob.properties.update_etag(&ctx.etags, ctx.timestamp);
     metadata.update_blob(blob.clone()).await?;
 
     let mut headers = common_headers();
@@ -535,3 +554,175 @@ pub async fn copy_incremental(
         "Incremental copy not implemented",
     ))
 }
+
+/// Applies the optional Range header and the `marker` / `maxresults` query
+/// parameters to a sorted page list. Returns the ranges to report and the
+/// marker (the next start offset) to resume from.
+fn scope_page_ranges(
+    ctx: &RequestContext,
+    ranges: Vec<PageRangeDiff>,
+) -> StorageResult<(Vec<PageRangeDiff>, Option<String>)> {
+    let (mut window_start, window_end) = match ctx.range() {
+        Some((start, end)) => (start, end.unwrap_or(u64::MAX)),
+        None => (0, u64::MAX),
+    };
+
+    if let Some(marker) = ctx.query_param("marker") {
+        let offset: u64 = marker.parse().map_err(|_| {
+            StorageError::invalid_query_parameter("marker", marker, "The marker is not valid for this page list.")
+        })?;
+        window_start = window_start.max(offset);
+    }
+
+    let maxresults = ctx
+        .query_param("maxresults")
+        .map(|value| parse_ranged_query_param("maxresults", value, 1, u32::MAX))
+        .transpose()?
+        .map_or(usize::MAX, |max| max as usize);
+
+    let mut scoped = ranges
+        .into_iter()
+        .filter(|range| range.end >= window_start && range.start <= window_end)
+        .map(|range| {
+            PageRangeDiff::new(range.start.max(window_start), range.end.min(window_end), range.is_clear)
+        });
+
+    let page: Vec<PageRangeDiff> = scoped.by_ref().take(maxresults).collect();
+    let next_marker = scoped.next().map(|range| range.start.to_string());
+
+    Ok((page, next_marker))
+}
+
+/// Reads `start..start + length` of a page blob; unwritten pages are zeros.
+pub(crate) async fn read_page_blob_range(
+    extents: &dyn ExtentStore,
+    blob: &BlobModel,
+    start: u64,
+    length: u64,
+) -> StorageResult<Vec<BodyPiece>> {
+    let mut pieces = Vec::new();
+    let end = start + length;
+    let mut position = start;
+
+    for range in &blob.page_ranges {
+        let Some(chunk) = range.extent_chunk.as_ref() else {
+            continue;
+        };
+        if range.end < start || range.start >= end {
+            continue;
+        }
+
+        let read_start = range.start.max(start);
+        let read_end = (range.end + 1).min(end);
+        if read_start > position {
+            pieces.push(BodyPiece::Zeros(read_start - position));
+        }
+        let bytes = read_blob_extent(extents, blob, chunk, read_start - range.start, read_end - read_start).await?;
+        pieces.push(BodyPiece::Data(bytes));
+        position = read_end;
+    }
+    if end > position {
+        pieces.push(BodyPiece::Zeros(end - position));
+    }
+
+    Ok(pieces)
+}
+
+/// Rebuilds the blob's extent references from its page map and returns the
+/// chunks it referenced before, for [`release_extents`] once it is stored.
+fn sync_page_extents(blob: &mut BlobModel) -> Vec<ExtentChunk> {
+    let chunks = blob
+        .page_ranges
+        .iter()
+        .filter_map(|range| range.extent_chunk.clone())
+        .collect();
+    std::mem::replace(&mut blob.extent_chunks, chunks)
+}
+
+/// Reads `x-ms-blob-content-length` for page blob create and resize: a
+/// multiple of 512 bytes no larger than 8 TiB.
+fn page_blob_size(ctx: &RequestContext) -> StorageResult<u64> {
+    let value = ctx
+        .header("x-ms-blob-content-length")
+        .ok_or_else(|| StorageError::missing_required_header("x-ms-blob-content-length"))?;
+
+    let size: u64 = value.parse().map_err(|_| {
+        StorageError::with_message(
+            ErrorCode::InvalidHeaderValue,
+            format!("Invalid x-ms-blob-content-length: {}", value),
+        )
+        .with_header_detail("x-ms-blob-content-length", value)
+    })?;
+
+    if size % PAGE_SIZE != 0 {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidHeaderValue,
+            "Page blob size must be aligned to 512 bytes",
+        )
+        .with_header_detail("x-ms-blob-content-length", value));
+    }
+    if size > MAX_PAGE_BLOB_SIZE {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidHeaderValue,
+            "Page blob size must not exceed 8 TiB",
+        )
+        .with_header_detail("x-ms-blob-content-length", value));
+    }
+
+    Ok(size)
+}
+
+/// Fails with `BlobTierInadequateForContentLength` if a page blob of
+/// `content_length` bytes does not fit a premium `tier`.
+pub(crate) fn check_tier_capacity(tier: AccessTier, content_length: u64) -> StorageResult<()> {
+    match tier.max_content_length() {
+        Some(max) if content_length > max => Err(StorageError::with_message(
+            ErrorCode::BlobTierInadequateForContentLength,
+            format!("The {} tier holds page blobs of up to {} bytes.", tier.as_str(), max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Largest page blob sequence number (2^63 - 1).
+const MAX_SEQUENCE_NUMBER: u64 = i64::MAX as u64;
+
+/// Parses `x-ms-blob-sequence-number`, which must be in 0..=2^63-1.
+fn parse_sequence_number(value: &str) -> StorageResult<u64> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|seq| *seq <= MAX_SEQUENCE_NUMBER)
+        .ok_or_else(|| {
+            StorageError::with_message(
+                ErrorCode::InvalidHeaderValue,
+                format!("Invalid x-ms-blob-sequence-number: {}", value),
+            )
+            .with_header_detail("x-ms-blob-sequence-number", value)
+        })
+}
+
+/// Applies `x-ms-sequence-number-action: increment`, refusing to pass the cap.
+fn increment_sequence_number(current: u64) -> StorageResult<u64> {
+    current
+        .checked_add(1)
+        .filter(|seq| *seq <= MAX_SEQUENCE_NUMBER)
+        .ok_or_else(|| StorageError::new(ErrorCode::SequenceNumberIncrementTooLarge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_number_bounds() {
+        assert_eq!(parse_sequence_number("9223372036854775807").unwrap(), MAX_SEQUENCE_NUMBER);
+        assert!(parse_sequence_number("9223372036854775808").is_err());
+        assert!(parse_sequence_number("-1").is_err());
+
+        assert_eq!(increment_sequence_number(MAX_SEQUENCE_NUMBER - 1).unwrap(), MAX_SEQUENCE_NUMBER);
+        let err = increment_sequence_number(MAX_SEQUENCE_NUMBER).unwrap_err();
+        assert_eq!(err.code, ErrorCode::SequenceNumberIncrementTooLarge);
+        assert_eq!(err.code.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
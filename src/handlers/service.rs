@@ -7,9 +7,11 @@ use axum::{
 use bytes::Bytes;
 use std::sync::Arc;
 
-use crate::context::RequestContext;
+use crate::auth::UserDelegationKeyRegistry;
+use crate::config::SUPPORTED_API_VERSIONS;
+use crate::context::{ListParams, RequestContext, MAX_LIST_RESULTS};
 use crate::error::{ErrorCode, StorageError, StorageResult};
-use crate::models::{AccountKind, ServiceProperties, ServiceStats, SkuName, UserDelegationKey};
+use crate::models::{AccountKind, DeleteRetentionPolicy, ServiceProperties, ServiceStats, SkuName, UserDelegationKey};
 use crate::storage::MetadataStore;
 use crate::xml::{
     deserialize::{parse_service_properties, parse_user_delegation_key_request},
@@ -26,15 +28,20 @@ pub async fn list_containers(
     ctx: &RequestContext,
     metadata: Arc<dyn MetadataStore>,
 ) -> StorageResult<Response<Body>> {
-    let prefix = ctx.query_param("prefix");
-    let marker = ctx.query_param("marker");
-    let maxresults = ctx
-        .query_param("maxresults")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(5000u32);
+    let list_params = ListParams::from_query(&ctx.query_pairs)?;
+    let prefix = list_params.prefix.as_deref();
+    let marker = list_params.marker.as_deref();
+    let maxresults = list_params.maxresults.unwrap_or(MAX_LIST_RESULTS);
 
     let (containers, next_marker) = metadata
-        .list_containers(&ctx.account, prefix, marker, Some(maxresults))
+        .list_containers(
+            &ctx.account,
+            prefix,
+            marker,
+            Some(maxresults),
+            list_params.includes("deleted"),
+            list_params.includes("system"),
+        )
         .await?;
 
     let xml = serialize_container_list(
@@ -43,7 +50,8 @@ pub async fn list_containers(
         marker,
         maxresults,
         next_marker.as_deref(),
-        &ctx.account,
+        &ctx.service_endpoint(),
+        list_params.includes("metadata"),
     );
 
     let mut headers = common_headers();
@@ -66,6 +74,74 @@ pub async fn get_service_properties(
     Ok(build_response(StatusCode::OK, headers, Body::from(xml)))
 }
 
+/// Maximum number of CORS rules per service.
+const MAX_CORS_RULES: usize = 5;
+
+/// Maximum number of allowed origins per CORS rule.
+const MAX_CORS_ORIGINS: usize = 64;
+
+/// Methods a CORS rule may allow.
+const CORS_METHODS: &[&str] = &["DELETE", "GET", "HEAD", "MERGE", "OPTIONS", "PATCH", "POST", "PUT"];
+
+/// Retention periods are between 1 and 365 days.
+const MAX_RETENTION_DAYS: u32 = 365;
+
+/// Applies the limits Azure enforces on service properties, naming the
+/// offending element in the error.
+fn validate_service_properties(properties: &ServiceProperties) -> StorageResult<()> {
+    if properties.cors.len() > MAX_CORS_RULES {
+        return Err(StorageError::with_message(
+            ErrorCode::InvalidXmlDocument,
+            format!("At most {} CorsRule elements are allowed.", MAX_CORS_RULES),
+        )
+        .with_detail("XmlNodeName", "Cors"));
+    }
+
+    for rule in &properties.cors {
+        if rule.allowed_origins.len() > MAX_CORS_ORIGINS {
+            return Err(StorageError::invalid_xml_node_value(
+                "AllowedOrigins",
+                &rule.allowed_origins.join(","),
+                format!("At most {} allowed origins are allowed per rule.", MAX_CORS_ORIGINS),
+            ));
+        }
+        if let Some(method) = rule.allowed_methods.iter().find(|m| !CORS_METHODS.contains(&m.as_str())) {
+            return Err(StorageError::invalid_xml_node_value(
+                "AllowedMethods",
+                method,
+                format!("Allowed methods are {}.", CORS_METHODS.join(", ")),
+            ));
+        }
+    }
+
+    let DeleteRetentionPolicy { enabled, days, .. } = properties.delete_retention_policy;
+    match days {
+        Some(days) if enabled && !(1..=MAX_RETENTION_DAYS).contains(&days) => {
+            return Err(StorageError::invalid_xml_node_value(
+                "Days",
+                &days.to_string(),
+                format!("The retention period must be between 1 and {} days.", MAX_RETENTION_DAYS),
+            ));
+        }
+        None if enabled => {
+            return Err(StorageError::new(ErrorCode::MissingRequiredXmlNode).with_detail("XmlNodeName", "Days"));
+        }
+        _ => {}
+    }
+
+    if let Some(version) = properties.default_service_version.as_deref() {
+        if !SUPPORTED_API_VERSIONS.contains(&version) {
+            return Err(StorageError::invalid_xml_node_value(
+                "DefaultServiceVersion",
+                version,
+                "The value is not a known service version.",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 /// PUT /?restype=service&comp=properties - Set service properties.
 pub async fn set_service_properties(
     ctx: &RequestContext,
@@ -75,7 +151,10 @@ pub async fn set_service_properties(
     let xml = std::str::from_utf8(&body)
         .map_err(|_| StorageError::new(ErrorCode::InvalidXmlDocument))?;
 
-    let properties = parse_service_properties(xml)?;
+    // Sections the request omits keep their stored settings
+    let mut properties = metadata.get_service_properties(&ctx.account).await?;
+    parse_service_properties(xml)?.apply(&mut properties);
+    validate_service_properties(&properties)?;
     metadata
         .set_service_properties(&ctx.account, properties)
         .await?;
@@ -113,6 +192,7 @@ pub async fn get_account_info(
 /// POST /?restype=service&comp=userdelegationkey - Get user delegation key.
 pub async fn get_user_delegation_key(
     ctx: &RequestContext,
+    delegation_keys: Arc<UserDelegationKeyRegistry>,
     body: Bytes,
 ) -> StorageResult<Response<Body>> {
     let xml = std::str::from_utf8(&body)
@@ -136,6 +216,10 @@ pub async fn get_user_delegation_key(
 
     let xml = serialize_user_delegation_key(&key);
 
+    // Remember the key so SAS tokens signed with it can be validated
+    delegation_keys.purge_expired(ctx.timestamp);
+    delegation_keys.insert(&ctx.account, key);
+
     let mut headers = common_headers();
     headers.insert("Content-Type", HeaderValue::from_static("application/xml"));
 
@@ -0,0 +1,619 @@
+//! Spelling of metadata header names on HTTP/1 connections.
+//!
+//! Azure keeps the casing a client gives metadata names (`x-ms-meta-MyKey`)
+//! and sends it back, but hyper lowercases header names both ways and has no
+//! public API for their original case. So [`track`] watches the bytes of a
+//! connection: it notes the mixed-case `x-ms-meta-*` names of each request
+//! head as it is read, and respells the names a response lists in its
+//! [`HeaderCase`] extension as its head is written. Finding the heads means
+//! following the body framing in between. A side that meets something it
+//! cannot follow (HTTP/2, protocol upgrades, malformed messages) leaves the
+//! rest of its stream untouched, and those names stay lowercase.
+
+use std::collections::VecDeque;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use axum::http::{Method, Request, Response, StatusCode, Version};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Header names spelled as on the wire. On a request, the mixed-case
+/// `x-ms-meta-*` names it was sent with, in order; absent when there are
+/// none or the spelling is unknown. On a response, the names to write in
+/// this spelling instead of lowercase.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderCase(Vec<String>);
+
+impl HeaderCase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the spelling of a header name.
+    pub fn push(&mut self, name: String) {
+        self.0.push(name);
+    }
+
+    /// Returns the first spelling of `name`, matched case-insensitively.
+    pub fn spelling(&self, name: &str) -> Option<&str> {
+        self.0.iter().map(String::as_str).find(|spelling| spelling.eq_ignore_ascii_case(name))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Most headers a message head may have, and its longest length; hyper's
+/// defaults, so heads hyper accepts are followed.
+const MAX_HEADERS: usize = 100;
+const MAX_HEAD_LEN: usize = 400 * 1024;
+
+/// Wraps a connection so its requests learn, and its responses get, the
+/// spelling of their metadata header names. The [`Tracker`] connects the
+/// two sides in the service serving the connection.
+pub(crate) fn track<I>(io: I) -> (Tracked<I>, Tracker) {
+    let tracker = Tracker(Arc::new(Mutex::new(State {
+        reading: true,
+        requests: VecDeque::new(),
+        writing: true,
+        responses: VecDeque::new(),
+    })));
+    let tracked = Tracked {
+        io,
+        tracker: tracker.clone(),
+        reader: Reader { framing: Framing::Head, head: Vec::new() },
+        writer: Writer { framing: Framing::Head, head: Vec::new(), pending: Vec::new(), written: 0 },
+    };
+    (tracked, tracker)
+}
+
+/// The state a tracked connection shares with its service.
+#[derive(Clone)]
+pub(crate) struct Tracker(Arc<Mutex<State>>);
+
+struct State {
+    /// Cleared once the requests could not be followed.
+    reading: bool,
+    /// Spellings of the requests read but not yet handed to the service.
+    requests: VecDeque<HeaderCase>,
+    /// Cleared once the responses could not be followed.
+    writing: bool,
+    /// What the writer needs to know of the responses the service returned
+    /// but that have not been written yet, in order.
+    responses: VecDeque<Reply>,
+}
+
+/// A response as the writer sees it.
+#[derive(Default)]
+struct Reply {
+    case: HeaderCase,
+    /// Answers HEAD, so no body follows whatever its `Content-Length`.
+    no_body: bool,
+}
+
+impl Tracker {
+    /// Attaches to `request` the spelling its headers were read with,
+    /// taking it from the requests read in order.
+    pub(crate) fn on_request<B>(&self, request: &mut Request<B>) {
+        if request.version() > Version::HTTP_11 {
+            return;
+        }
+        let case = self.0.lock().unwrap().requests.pop_front();
+        if let Some(case) = case.filter(|case| !case.is_empty()) {
+            request.extensions_mut().insert(case);
+        }
+    }
+
+    /// Queues a response to a request of `method` and `version` for the
+    /// writer, with the names its [`HeaderCase`] extension respells.
+    pub(crate) fn on_response<B>(&self, method: &Method, version: Version, response: &mut Response<B>) {
+        let case = response.extensions_mut().remove::<HeaderCase>().unwrap_or_default();
+        if version > Version::HTTP_11 {
+            return;
+        }
+        let mut state = self.0.lock().unwrap();
+        if state.writing {
+            state.responses.push_back(Reply { case, no_body: method == Method::HEAD });
+        }
+    }
+
+    fn push_request(&self, case: HeaderCase) {
+        let mut state = self.0.lock().unwrap();
+        if state.reading {
+            state.requests.push_back(case);
+        }
+    }
+
+    /// Takes what the writer needs to know of the next response.
+    fn next_response(&self) -> Reply {
+        self.0.lock().unwrap().responses.pop_front().unwrap_or_default()
+    }
+
+    /// Stops recording requests. Those already read keep their spelling,
+    /// as hyper hands them to the service in the same order.
+    fn stop_reading(&self) {
+        self.0.lock().unwrap().reading = false;
+    }
+
+    fn stop_writing(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.writing = false;
+        state.responses.clear();
+    }
+}
+
+/// A connection whose message heads are followed; see [`track`].
+pub(crate) struct Tracked<I> {
+    io: I,
+    tracker: Tracker,
+    reader: Reader,
+    writer: Writer,
+}
+
+/// Where a byte stream is within its messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// At or within a message head.
+    Head,
+    /// Within a body with this many bytes left.
+    Length(u64),
+    /// Within a chunked body.
+    Chunked(Chunk),
+    /// No longer followed; the rest of the stream passes untouched.
+    Lost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chunk {
+    /// Reading a chunk size, or the extensions after it.
+    Size { size: u64, extension: bool },
+    SizeLf { size: u64 },
+    Data(u64),
+    DataCr,
+    DataLf,
+    /// Reading the trailer lines after the last chunk; `empty` while the
+    /// current line has no bytes yet.
+    Trailer { empty: bool },
+    TrailerLf { empty: bool },
+}
+
+impl Framing {
+    fn body(length: u64) -> Self {
+        if length == 0 {
+            Framing::Head
+        } else {
+            Framing::Length(length)
+        }
+    }
+
+    /// Steps over the body bytes at the start of `bytes`, returning how
+    /// many there were. Stops where the body ends, leaving the framing at
+    /// the next head.
+    fn skip_body(&mut self, bytes: &[u8]) -> usize {
+        let mut consumed = 0;
+        while consumed < bytes.len() {
+            let rest = &bytes[consumed..];
+            *self = match *self {
+                Framing::Head => break,
+                Framing::Lost => return bytes.len(),
+                Framing::Length(left) => {
+                    let take = left.min(rest.len() as u64);
+                    consumed += take as usize;
+                    Framing::body(left - take)
+                }
+                Framing::Chunked(Chunk::Data(left)) => {
+                    let take = left.min(rest.len() as u64);
+                    consumed += take as usize;
+                    Framing::Chunked(if take == left { Chunk::DataCr } else { Chunk::Data(left - take) })
+                }
+                Framing::Chunked(chunk) => {
+                    consumed += 1;
+                    chunk_step(chunk, rest[0])
+                }
+            };
+        }
+        consumed
+    }
+}
+
+/// Advances over one byte of chunked framing outside chunk data.
+fn chunk_step(chunk: Chunk, byte: u8) -> Framing {
+    let next = match (chunk, byte) {
+        (Chunk::Size { size, extension: false }, _) if byte.is_ascii_hexdigit() => {
+            let digit = (byte as char).to_digit(16).unwrap() as u64;
+            match size.checked_mul(16) {
+                Some(size) => Chunk::Size { size: size + digit, extension: false },
+                None => return Framing::Lost,
+            }
+        }
+        (Chunk::Size { size, .. }, b'\r') => Chunk::SizeLf { size },
+        (Chunk::Size { size, .. }, b';' | b' ' | b'\t') => Chunk::Size { size, extension: true },
+        (Chunk::Size { size, extension: true }, _) => Chunk::Size { size, extension: true },
+        (Chunk::SizeLf { size: 0 }, b'\n') => Chunk::Trailer { empty: true },
+        (Chunk::SizeLf { size }, b'\n') => Chunk::Data(size),
+        (Chunk::DataCr, b'\r') => Chunk::DataLf,
+        (Chunk::DataLf, b'\n') => Chunk::Size { size: 0, extension: false },
+        (Chunk::Trailer { empty }, b'\r') => Chunk::TrailerLf { empty },
+        (Chunk::Trailer { .. }, _) => Chunk::Trailer { empty: false },
+        (Chunk::TrailerLf { empty: true }, b'\n') => return Framing::Head,
+        (Chunk::TrailerLf { empty: false }, b'\n') => Chunk::Trailer { empty: true },
+        _ => return Framing::Lost,
+    };
+    Framing::Chunked(next)
+}
+
+/// Returns the body framing declared by `headers`: chunked, a length, or
+/// None when neither is given. A transfer coding other than a final
+/// `chunked`, or an unreadable length, cannot be followed.
+fn declared_framing(headers: &[httparse::Header]) -> Option<Framing> {
+    let mut length = None;
+    for header in headers {
+        if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            let chunked = std::str::from_utf8(header.value)
+                .ok()
+                .and_then(|value| value.rsplit(',').next())
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+            let chunk = Chunk::Size { size: 0, extension: false };
+            return Some(if chunked { Framing::Chunked(chunk) } else { Framing::Lost });
+        }
+        if header.name.eq_ignore_ascii_case("content-length") {
+            let value = std::str::from_utf8(header.value).ok().and_then(|value| value.trim().parse::<u64>().ok());
+            match (value, length) {
+                (Some(value), None) => length = Some(value),
+                (Some(value), Some(previous)) if value == previous => {}
+                _ => return Some(Framing::Lost),
+            }
+        }
+    }
+    length.map(Framing::body)
+}
+
+/// Follows requests as they are read.
+struct Reader {
+    framing: Framing,
+    /// The start of a head that has not been read in full.
+    head: Vec<u8>,
+}
+
+impl Reader {
+    fn read(&mut self, mut bytes: &[u8], tracker: &Tracker) {
+        while !bytes.is_empty() {
+            let consumed = match self.framing {
+                Framing::Lost => return,
+                Framing::Head => self.read_head(bytes, tracker),
+                _ => self.framing.skip_body(bytes),
+            };
+            bytes = &bytes[consumed..];
+        }
+    }
+
+    /// Reads head bytes, returning how many belong to the head.
+    fn read_head(&mut self, bytes: &[u8], tracker: &Tracker) -> usize {
+        let buffered = self.head.len();
+        if buffered > 0 {
+            self.head.extend_from_slice(bytes);
+        }
+        let head = if buffered > 0 { &self.head[..] } else { bytes };
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        let (end, framing) = match request.parse(head) {
+            Ok(httparse::Status::Complete(end)) => {
+                let mut case = HeaderCase::new();
+                for header in request.headers.iter() {
+                    let metadata = header.name.len() > 10 && header.name[..10].eq_ignore_ascii_case("x-ms-meta-");
+                    if metadata && header.name.bytes().any(|b| b.is_ascii_uppercase()) {
+                        case.push(header.name.to_string());
+                    }
+                }
+                tracker.push_request(case);
+                let framing = match request.method {
+                    Some("CONNECT") => Framing::Lost,
+                    _ => declared_framing(request.headers).unwrap_or(Framing::Head),
+                };
+                (end, framing)
+            }
+            Ok(httparse::Status::Partial) if head.len() <= MAX_HEAD_LEN => {
+                if buffered == 0 {
+                    self.head.extend_from_slice(bytes);
+                }
+                return bytes.len();
+            }
+            _ => (head.len(), Framing::Lost),
+        };
+
+        self.head.clear();
+        self.framing = framing;
+        if framing == Framing::Lost {
+            tracker.stop_reading();
+        }
+        end - buffered
+    }
+}
+
+/// Follows responses as they are written, respelling names in their heads.
+struct Writer {
+    framing: Framing,
+    /// The start of a head that has not been written in full.
+    head: Vec<u8>,
+    /// A rewritten head waiting to go out, and how much of it has.
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl Writer {
+    /// Takes head bytes, returning how many belong to the head. A complete
+    /// head is rewritten into `pending`; anything else that cannot be
+    /// followed goes there as it is.
+    fn write_head(&mut self, bytes: &[u8], tracker: &Tracker) -> usize {
+        let buffered = self.head.len();
+        if buffered > 0 {
+            self.head.extend_from_slice(bytes);
+        }
+        let head = if buffered > 0 { &self.head[..] } else { bytes };
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        let end = match response.parse(head) {
+            Ok(httparse::Status::Complete(end)) => end,
+            Ok(httparse::Status::Partial) if head.len() <= MAX_HEAD_LEN => {
+                if buffered == 0 {
+                    self.head.extend_from_slice(bytes);
+                }
+                return bytes.len();
+            }
+            _ => {
+                self.pending.extend_from_slice(head);
+                self.head.clear();
+                self.framing = Framing::Lost;
+                tracker.stop_writing();
+                return bytes.len();
+            }
+        };
+
+        // Interim responses come from hyper, not the service
+        let status = response.code.and_then(|code| StatusCode::from_u16(code).ok());
+        let reply = match status {
+            Some(status) if status.is_informational() && status != StatusCode::SWITCHING_PROTOCOLS => Reply::default(),
+            _ => tracker.next_response(),
+        };
+        self.framing = match status {
+            Some(StatusCode::SWITCHING_PROTOCOLS) => Framing::Lost,
+            Some(status) if status.is_informational() => Framing::Head,
+            Some(StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED) => Framing::Head,
+            _ if reply.no_body => Framing::Head,
+            _ => declared_framing(response.headers).unwrap_or(Framing::Lost),
+        };
+        if self.framing == Framing::Lost {
+            tracker.stop_writing();
+        }
+        respell_head(&head[..end], &reply.case, &mut self.pending);
+        self.head.clear();
+        end - buffered
+    }
+}
+
+/// Copies a response head into `out` with the names `case` lists spelled
+/// its way.
+fn respell_head(head: &[u8], case: &HeaderCase, out: &mut Vec<u8>) {
+    if case.is_empty() {
+        out.extend_from_slice(head);
+        return;
+    }
+    let mut lines = head.split_inclusive(|&b| b == b'\n');
+    if let Some(status_line) = lines.next() {
+        out.extend_from_slice(status_line);
+    }
+    for line in lines {
+        let name_len = line.iter().position(|&b| b == b':').unwrap_or(0);
+        let name = std::str::from_utf8(&line[..name_len]).unwrap_or("");
+        match case.spelling(name).filter(|spelling| spelling.len() == name_len) {
+            Some(spelling) => {
+                out.extend_from_slice(spelling.as_bytes());
+                out.extend_from_slice(&line[name_len..]);
+            }
+            None => out.extend_from_slice(line),
+        }
+    }
+}
+
+impl<I: AsyncWrite + Unpin> Tracked<I> {
+    /// Writes out the pending head.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let writer = &mut self.writer;
+        while writer.written < writer.pending.len() {
+            let n = ready!(Pin::new(&mut self.io).poll_write(cx, &writer.pending[writer.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            writer.written += n;
+        }
+        writer.pending.clear();
+        writer.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for Tracked<I> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+        this.reader.read(&buf.filled()[before..], &this.tracker);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for Tracked<I> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        match this.writer.framing {
+            Framing::Lost => Pin::new(&mut this.io).poll_write(cx, buf),
+            Framing::Head => Poll::Ready(Ok(this.writer.write_head(buf, &this.tracker))),
+            mut probe => {
+                let body = probe.skip_body(buf);
+                let n = ready!(Pin::new(&mut this.io).poll_write(cx, &buf[..body]))?;
+                this.writer.framing.skip_body(&buf[..n]);
+                Poll::Ready(Ok(n))
+            }
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        match this.writer.framing {
+            Framing::Lost => Pin::new(&mut this.io).poll_write_vectored(cx, bufs),
+            Framing::Head => {
+                let buf = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &buf[..]);
+                Poll::Ready(Ok(this.writer.write_head(buf, &this.tracker)))
+            }
+            mut probe => {
+                // Pass on the slices up to the end of the body
+                let mut body = Vec::with_capacity(bufs.len());
+                for buf in bufs {
+                    let n = probe.skip_body(buf);
+                    if n > 0 {
+                        body.push(IoSlice::new(&buf[..n]));
+                    }
+                    if n < buf.len() || probe == Framing::Head {
+                        break;
+                    }
+                }
+                let n = ready!(Pin::new(&mut this.io).poll_write_vectored(cx, &body))?;
+                let mut left = n;
+                for buf in &body {
+                    let take = left.min(buf.len());
+                    this.writer.framing.skip_body(&buf[..take]);
+                    left -= take;
+                }
+                Poll::Ready(Ok(n))
+            }
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.writer.head.is_empty() {
+            // A head flushed before it is complete is sent as it is
+            let head = std::mem::take(&mut this.writer.head);
+            this.writer.pending.extend_from_slice(&head);
+            this.writer.framing = Framing::Lost;
+            this.tracker.stop_writing();
+        }
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader() -> Reader {
+        Reader { framing: Framing::Head, head: Vec::new() }
+    }
+
+    fn writer() -> Writer {
+        Writer { framing: Framing::Head, head: Vec::new(), pending: Vec::new(), written: 0 }
+    }
+
+    #[test]
+    fn test_skip_body_stops_at_the_next_head() {
+        let mut framing = Framing::Length(3);
+        assert_eq!(framing.skip_body(b"abcGET"), 3);
+        assert_eq!(framing, Framing::Head);
+
+        let mut framing = Framing::Chunked(Chunk::Size { size: 0, extension: false });
+        let body = b"5;ext=1\r\nhello\r\nA\r\n0123456789\r\n0\r\nx-trailer: 1\r\n\r\nGET";
+        assert_eq!(framing.skip_body(body), body.len() - 3);
+        assert_eq!(framing, Framing::Head);
+
+        let mut framing = Framing::Chunked(Chunk::Size { size: 0, extension: false });
+        assert_eq!(framing.skip_body(b"zz"), 2);
+        assert_eq!(framing, Framing::Lost);
+    }
+
+    #[test]
+    fn test_reader_records_metadata_spellings_across_split_heads() {
+        let (_, tracker) = track(());
+        let mut reader = reader();
+        reader.read(b"PUT /a HTTP/1.1\r\nx-ms-meta-MyKey: 1\r\nx-ms-meta-low: 2\r\nContent-Le", &tracker);
+        reader.read(b"ngth: 2\r\n\r\nhiGET /b HTTP/1.1\r\nX-Ms-Version: x\r\n\r\n", &tracker);
+
+        let mut first = Request::new(());
+        tracker.on_request(&mut first);
+        assert_eq!(first.extensions().get::<HeaderCase>(), Some(&HeaderCase(vec!["x-ms-meta-MyKey".to_string()])));
+
+        // Heads without mixed-case metadata names carry no spelling
+        let mut second = Request::new(());
+        tracker.on_request(&mut second);
+        assert!(second.extensions().get::<HeaderCase>().is_none());
+        assert_eq!(reader.framing, Framing::Head);
+    }
+
+    #[test]
+    fn test_reader_stops_at_http2() {
+        let (_, tracker) = track(());
+        let mut reader = reader();
+        reader.read(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n", &tracker);
+        assert_eq!(reader.framing, Framing::Lost);
+        assert!(!tracker.0.lock().unwrap().reading);
+    }
+
+    #[test]
+    fn test_writer_respells_queued_responses_after_the_reader_stops() {
+        let (_, tracker) = track(());
+        let mut case = HeaderCase::new();
+        case.push("x-ms-meta-MyKey".to_string());
+        let mut head = Response::new(());
+        head.extensions_mut().insert(case.clone());
+        tracker.on_response(&Method::HEAD, Version::HTTP_11, &mut head);
+        let mut get = Response::new(());
+        get.extensions_mut().insert(case);
+        tracker.on_response(&Method::GET, Version::HTTP_11, &mut get);
+        tracker.stop_reading();
+
+        let mut writer = writer();
+        let bytes = b"HTTP/1.1 100 Continue\r\n\r\n\
+            HTTP/1.1 200 OK\r\nx-ms-meta-mykey: v\r\ncontent-length: 5\r\n\r\n\
+            HTTP/1.1 200 OK\r\nx-ms-meta-mykey: v\r\ncontent-length: 5\r\n\r\nhello";
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let n = match writer.framing {
+                Framing::Head => writer.write_head(rest, &tracker),
+                _ => {
+                    let n = writer.framing.skip_body(rest);
+                    writer.pending.extend_from_slice(&rest[..n]);
+                    n
+                }
+            };
+            rest = &rest[n..];
+        }
+        assert_eq!(writer.framing, Framing::Head);
+        assert_eq!(
+            String::from_utf8(writer.pending).unwrap(),
+            "HTTP/1.1 100 Continue\r\n\r\n\
+             HTTP/1.1 200 OK\r\nx-ms-meta-MyKey: v\r\ncontent-length: 5\r\n\r\n\
+             HTTP/1.1 200 OK\r\nx-ms-meta-MyKey: v\r\ncontent-length: 5\r\n\r\nhello"
+        );
+    }
+}
@@ -16,18 +16,27 @@
 //! ```
 
 pub mod auth;
+pub mod clock;
 pub mod config;
 pub mod context;
 pub mod error;
 pub mod handlers;
+pub mod header_case;
 pub mod models;
+pub mod observer;
+pub mod operation;
 pub mod router;
 pub mod server;
 pub mod storage;
+pub mod testing;
 pub mod xml;
 
 // Re-exports for convenience
-pub use config::{Args, Config, DEFAULT_ACCOUNT, DEFAULT_ACCOUNT_KEY, DEFAULT_BLOB_PORT};
+pub use clock::{Clock, SystemClock};
+pub use config::{Args, Config, ConnectionStringError, DEFAULT_ACCOUNT, DEFAULT_ACCOUNT_KEY, DEFAULT_BLOB_PORT};
 pub use error::{ErrorCode, StorageError, StorageResult};
+pub use observer::RequestObserver;
+pub use operation::Operation;
 pub use server::{BlobServer, BlobServerBuilder};
-pub use storage::{ExtentStore, MemoryExtentStore, MemoryMetadataStore, MetadataStore};
+pub use storage::{ExtentStore, ExtentUsage, MemoryExtentStore, MemoryMetadataStore, MetadataStore};
+pub use testing::Fixtures;
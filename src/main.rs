@@ -35,26 +35,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .expect("Failed to set tracing subscriber");
 
     // Create configuration from arguments
-    let config = Config::from(args);
+    let import_state = args.import_state.clone();
+    let export_state = args.export_state.clone();
+    let connection_string = args.connection_string.clone();
+    let quiet_banner = args.quiet_banner;
+    let mut config = Config::from(args);
+    if let Some(connection_string) = connection_string {
+        config = config.with_connection_string(&connection_string)?;
+    }
+    let banner = (!quiet_banner).then(|| config.banner());
 
     // Create and run the server
     let server = BlobServer::new(config);
 
-    println!(
-        r#"
-Azurite Blob service is starting at {}
+    if let Some(path) = import_state {
+        server.import_state(&path).await?;
+        tracing::info!("Imported state from {}", path.display());
+    }
 
-Default account: devstoreaccount1
-Default key: Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==
+    if let Some(banner) = banner {
+        println!("\n{}", banner);
+    }
 
-Connection string:
-DefaultEndpointsProtocol=http;AccountName=devstoreaccount1;AccountKey=Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==;BlobEndpoint={}/devstoreaccount1;
-
-Press Ctrl+C to stop the server.
-"#,
-        server.bind_address(),
-        server.base_url()
-    );
+    let Some(path) = export_state else {
+        return server.run().await;
+    };
 
-    server.run().await
+    // Keep a handle on storage so it can be exported once the server stops
+    let fixtures = server.fixtures();
+    tokio::select! {
+        result = server.run() => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    fixtures.export_state(&path).await?;
+    tracing::info!("Exported state to {}", path.display());
+    Ok(())
 }
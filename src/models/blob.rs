@@ -4,6 +4,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::etag::{generate_etag, EtagClock};
+use super::metadata::Metadata;
+use super::page::PersistencyPageRange;
+
 /// Blob types supported by Azure Blob Storage.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BlobType {
@@ -31,7 +35,8 @@ impl BlobType {
     }
 }
 
-/// Access tiers for blobs.
+/// Access tiers for blobs: the standard tiers of block blobs and the
+/// premium tiers (P4 to P80) of page blobs.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum AccessTier {
     #[default]
@@ -39,6 +44,17 @@ pub enum AccessTier {
     Cool,
     Cold,
     Archive,
+    P4,
+    P6,
+    P10,
+    P15,
+    P20,
+    P30,
+    P40,
+    P50,
+    P60,
+    P70,
+    P80,
 }
 
 impl AccessTier {
@@ -48,6 +64,17 @@ impl AccessTier {
             AccessTier::Cool => "Cool",
             AccessTier::Cold => "Cold",
             AccessTier::Archive => "Archive",
+            AccessTier::P4 => "P4",
+            AccessTier::P6 => "P6",
+            AccessTier::P10 => "P10",
+            AccessTier::P15 => "P15",
+            AccessTier::P20 => "P20",
+            AccessTier::P30 => "P30",
+            AccessTier::P40 => "P40",
+            AccessTier::P50 => "P50",
+            AccessTier::P60 => "P60",
+            AccessTier::P70 => "P70",
+            AccessTier::P80 => "P80",
         }
     }
 
@@ -57,9 +84,67 @@ impl AccessTier {
             "cool" => Some(AccessTier::Cool),
             "cold" => Some(AccessTier::Cold),
             "archive" => Some(AccessTier::Archive),
+            "p4" => Some(AccessTier::P4),
+            "p6" => Some(AccessTier::P6),
+            "p10" => Some(AccessTier::P10),
+            "p15" => Some(AccessTier::P15),
+            "p20" => Some(AccessTier::P20),
+            "p30" => Some(AccessTier::P30),
+            "p40" => Some(AccessTier::P40),
+            "p50" => Some(AccessTier::P50),
+            "p60" => Some(AccessTier::P60),
+            "p70" => Some(AccessTier::P70),
+            "p80" => Some(AccessTier::P80),
             _ => None,
         }
     }
+
+    /// Largest page blob a premium tier holds, in bytes. None for the
+    /// standard tiers.
+    pub fn max_content_length(&self) -> Option<u64> {
+        const GIB: u64 = 1 << 30;
+        match self {
+            AccessTier::Hot | AccessTier::Cool | AccessTier::Cold | AccessTier::Archive => None,
+            AccessTier::P4 => Some(32 * GIB),
+            AccessTier::P6 => Some(64 * GIB),
+            AccessTier::P10 => Some(128 * GIB),
+            AccessTier::P15 => Some(256 * GIB),
+            AccessTier::P20 => Some(512 * GIB),
+            AccessTier::P30 => Some(1024 * GIB),
+            AccessTier::P40 => Some(2048 * GIB),
+            AccessTier::P50 => Some(4096 * GIB),
+            AccessTier::P60 => Some(8192 * GIB),
+            AccessTier::P70 => Some(16384 * GIB),
+            AccessTier::P80 => Some(32768 * GIB),
+        }
+    }
+
+    /// Whether this is a premium page blob tier.
+    pub fn is_premium(&self) -> bool {
+        self.max_content_length().is_some()
+    }
+
+    /// Whether a blob of `blob_type` can be set to this tier. Block blobs
+    /// take the standard tiers, page blobs the premium ones and append
+    /// blobs none.
+    pub fn applies_to(&self, blob_type: BlobType) -> bool {
+        match blob_type {
+            BlobType::BlockBlob => !self.is_premium(),
+            BlobType::PageBlob => self.is_premium(),
+            BlobType::AppendBlob => false,
+        }
+    }
+
+    /// Whether listings and properties report the tier of a blob of
+    /// `blob_type`: block blobs always have one, page blobs only once a
+    /// premium tier was set.
+    pub fn is_reported_for(&self, blob_type: BlobType) -> bool {
+        match blob_type {
+            BlobType::BlockBlob => true,
+            BlobType::PageBlob => self.is_premium(),
+            BlobType::AppendBlob => false,
+        }
+    }
 }
 
 /// Lease state for containers and blobs.
@@ -119,6 +204,22 @@ impl LeaseDuration {
     }
 }
 
+/// Priority of a rehydration out of the archive tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RehydratePriority {
+    Standard,
+    High,
+}
+
+impl RehydratePriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RehydratePriority::Standard => "Standard",
+            RehydratePriority::High => "High",
+        }
+    }
+}
+
 /// Copy status for blob copy operations.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CopyStatus {
@@ -201,23 +302,40 @@ pub struct BlobProperties {
     pub version_id: Option<String>,
     /// Whether this is the current version.
     pub is_current_version: Option<bool>,
+    /// Priority of a pending rehydration from the archive tier.
+    pub rehydrate_priority: Option<RehydratePriority>,
+    /// Time of the last read or write, when access time tracking is on.
+    pub last_accessed_on: Option<DateTime<Utc>>,
+    /// Expiry of the blob's time-based immutability policy.
+    pub immutability_policy_until: Option<DateTime<Utc>>,
+    /// Whether the blob is under a legal hold.
+    #[serde(default)]
+    pub legal_hold: bool,
 }
 
 impl Default for BlobProperties {
+    /// Properties of an empty block blob created now by the wall clock.
     fn default() -> Self {
-        let now = Utc::now();
-        Self {
-            content_length: 0,
+        Self::new(BlobType::BlockBlob, 0, Utc::now())
+    }
+}
+
+impl BlobProperties {
+    /// Creates new blob properties for the given blob type, created and
+    /// last modified at `now`.
+    pub fn new(blob_type: BlobType, content_length: u64, now: DateTime<Utc>) -> Self {
+        let mut props = Self {
+            content_length,
             content_type: Some("application/octet-stream".to_string()),
             content_encoding: None,
             content_language: None,
             content_md5: None,
             content_disposition: None,
             cache_control: None,
-            etag: format!("\"0x{}\"", uuid::Uuid::new_v4().simple()),
+            etag: generate_etag(now),
             last_modified: now,
             created_on: now,
-            blob_type: BlobType::BlockBlob,
+            blob_type,
             access_tier: AccessTier::Hot,
             lease_state: LeaseState::Available,
             lease_status: LeaseStatus::Unlocked,
@@ -237,16 +355,11 @@ impl Default for BlobProperties {
             copy_status_description: None,
             version_id: None,
             is_current_version: None,
-        }
-    }
-}
-
-impl BlobProperties {
-    /// Creates new blob properties for the given blob type.
-    pub fn new(blob_type: BlobType, content_length: u64) -> Self {
-        let mut props = Self::default();
-        props.blob_type = blob_type;
-        props.content_length = content_length;
+            rehydrate_priority: None,
+            last_accessed_on: None,
+            immutability_policy_until: None,
+            legal_hold: false,
+        };
 
         match blob_type {
             BlobType::PageBlob => {
@@ -262,10 +375,60 @@ impl BlobProperties {
         props
     }
 
-    /// Updates the ETag and last modified time.
-    pub fn update_etag(&mut self) {
-        self.etag = format!("\"0x{}\"", uuid::Uuid::new_v4().simple());
-        self.last_modified = Utc::now();
+    /// Takes a new ETag from `etags` and sets the last modified time to
+    /// `now`.
+    pub fn update_etag(&mut self, etags: &EtagClock, now: DateTime<Utc>) {
+        self.etag = etags.next(now);
+        self.last_modified = now;
+    }
+
+    /// Takes a new ETag from `etags` for a blob written at its last modified
+    /// time, such as a new blob replacing another.
+    pub fn renew_etag(&mut self, etags: &EtagClock) {
+        self.etag = etags.next(self.last_modified);
+    }
+
+    /// Resets the lease to available/unlocked, as on a newly created object.
+    pub fn clear_lease(&mut self) {
+        self.lease_state = LeaseState::Available;
+        self.lease_status = LeaseStatus::Unlocked;
+        self.lease_duration = None;
+        self.lease_id = None;
+        self.lease_expiry = None;
+        self.lease_break_time = None;
+    }
+
+    /// Whether a lease is held at `now`: the lease is `Leased` and, for a
+    /// fixed-duration lease, not yet past its expiry.
+    pub fn is_leased(&self, now: DateTime<Utc>) -> bool {
+        self.lease_state == LeaseState::Leased && self.lease_expiry.is_none_or(|expiry| expiry > now)
+    }
+
+    /// The duration of the lease held on the blob, reported as
+    /// `x-ms-lease-duration` while the lease state is `leased`.
+    pub fn active_lease_duration(&self) -> Option<LeaseDuration> {
+        match self.lease_state {
+            LeaseState::Leased => self.lease_duration,
+            _ => None,
+        }
+    }
+
+    /// Applies lease transitions that are due at `now`: a fixed-duration
+    /// lease past its expiry becomes `Expired` and a breaking lease past its
+    /// break time becomes `Broken`.
+    pub fn refresh_lease(&mut self, now: DateTime<Utc>) {
+        match self.lease_state {
+            LeaseState::Leased if !self.is_leased(now) => {
+                self.lease_state = LeaseState::Expired;
+                self.lease_status = LeaseStatus::Unlocked;
+            }
+            LeaseState::Breaking if self.lease_break_time.is_some_and(|time| time <= now) => {
+                self.lease_state = LeaseState::Broken;
+                self.lease_status = LeaseStatus::Unlocked;
+                self.lease_id = None;
+            }
+            _ => {}
+        }
     }
 }
 
@@ -283,11 +446,15 @@ pub struct BlobModel {
     /// Blob properties.
     pub properties: BlobProperties,
     /// User-defined metadata.
-    pub metadata: HashMap<String, String>,
+    pub metadata: Metadata,
     /// Tags for blob indexing.
     pub tags: HashMap<String, String>,
     /// References to extent data chunks.
     pub extent_chunks: Vec<ExtentChunk>,
+    /// Written page ranges of a page blob, sorted by offset. Pages outside
+    /// these ranges read as zeros.
+    #[serde(default)]
+    pub page_ranges: Vec<PersistencyPageRange>,
     /// Whether the blob is soft-deleted.
     pub deleted: bool,
     /// Soft-delete expiry time.
@@ -297,23 +464,25 @@ pub struct BlobModel {
 }
 
 impl BlobModel {
-    /// Creates a new blob model.
+    /// Creates a new blob model, created at `now`.
     pub fn new(
         account: String,
         container: String,
         name: String,
         blob_type: BlobType,
         content_length: u64,
+        now: DateTime<Utc>,
     ) -> Self {
         Self {
             account,
             container,
             name,
             snapshot: String::new(),
-            properties: BlobProperties::new(blob_type, content_length),
-            metadata: HashMap::new(),
+            properties: BlobProperties::new(blob_type, content_length, now),
+            metadata: Metadata::new(),
             tags: HashMap::new(),
             extent_chunks: Vec::new(),
+            page_ranges: Vec::new(),
             deleted: false,
             deleted_time: None,
             remaining_retention_days: None,
@@ -330,11 +499,26 @@ impl BlobModel {
         )
     }
 
-    /// Creates a snapshot of this blob.
-    pub fn create_snapshot(&self) -> Self {
+    /// Returns the committed block count of an append blob, or None for
+    /// other blob types. Every appended block is one extent chunk, so records
+    /// without a stored count report the number of chunks.
+    pub fn committed_block_count(&self) -> Option<u32> {
+        if self.properties.blob_type != BlobType::AppendBlob {
+            return None;
+        }
+        Some(
+            self.properties
+                .committed_block_count
+                .unwrap_or(self.extent_chunks.len() as u32),
+        )
+    }
+
+    /// Creates a snapshot of this blob taken at `now`.
+    pub fn create_snapshot(&self, now: DateTime<Utc>) -> Self {
         let mut snapshot = self.clone();
+        // Snapshots never carry a lease
+        snapshot.properties.clear_lease();
         // Azure snapshot format: 2024-01-27T12:34:56.1234567Z (7 decimal places)
-        let now = Utc::now();
         snapshot.snapshot = format!(
             "{}.{:07}Z",
             now.format("%Y-%m-%dT%H:%M:%S"),
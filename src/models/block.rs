@@ -36,11 +36,13 @@ pub enum BlockListType {
 }
 
 impl BlockListType {
-    pub fn from_str(s: &str) -> Self {
+    /// Parses a `blocklisttype` value; returns None for unknown values.
+    pub fn parse(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
-            "committed" => BlockListType::Committed,
-            "uncommitted" => BlockListType::Uncommitted,
-            _ => BlockListType::All,
+            "committed" => Some(BlockListType::Committed),
+            "uncommitted" => Some(BlockListType::Uncommitted),
+            "all" => Some(BlockListType::All),
+            _ => None,
         }
     }
 }
@@ -62,10 +64,14 @@ pub struct BlockModel {
     pub extent_chunk: ExtentChunk,
     /// When the block was staged.
     pub staged_time: DateTime<Utc>,
+    /// Position of the block in the store's staging order, assigned when the
+    /// block is staged. Uncommitted block lists are returned in this order.
+    #[serde(default)]
+    pub sequence: u64,
 }
 
 impl BlockModel {
-    /// Creates a new block model.
+    /// Creates a new block model, staged at `staged_time`.
     pub fn new(
         account: String,
         container: String,
@@ -73,6 +79,7 @@ impl BlockModel {
         block_id: String,
         size: u64,
         extent_chunk: ExtentChunk,
+        staged_time: DateTime<Utc>,
     ) -> Self {
         Self {
             account,
@@ -81,7 +88,8 @@ impl BlockModel {
             block_id,
             size,
             extent_chunk,
-            staged_time: Utc::now(),
+            staged_time,
+            sequence: 0,
         }
     }
 
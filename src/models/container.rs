@@ -1,10 +1,13 @@
 //! Container data models.
 
 use chrono::{DateTime, Utc};
+use md5::{Digest, Md5};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use uuid::Uuid;
 
 use super::blob::{LeaseDuration, LeaseState, LeaseStatus};
+use super::etag::{generate_etag, EtagClock};
+use super::metadata::Metadata;
 
 /// Public access level for a container.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -53,10 +56,18 @@ pub struct ContainerProperties {
 }
 
 impl Default for ContainerProperties {
+    /// Properties of a container created now by the wall clock.
     fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl ContainerProperties {
+    /// Creates the properties of a private container, last modified at `now`.
+    pub fn new(now: DateTime<Utc>) -> Self {
         Self {
-            etag: format!("\"0x{}\"", uuid::Uuid::new_v4().simple()),
-            last_modified: Utc::now(),
+            etag: generate_etag(now),
+            last_modified: now,
             lease_state: LeaseState::Available,
             lease_status: LeaseStatus::Unlocked,
             lease_duration: None,
@@ -70,13 +81,61 @@ impl Default for ContainerProperties {
             deny_encryption_scope_override: false,
         }
     }
-}
 
-impl ContainerProperties {
-    /// Updates the ETag and last modified time.
-    pub fn update_etag(&mut self) {
-        self.etag = format!("\"0x{}\"", uuid::Uuid::new_v4().simple());
-        self.last_modified = Utc::now();
+    /// Takes a new ETag from `etags` and sets the last modified time to
+    /// `now`.
+    pub fn update_etag(&mut self, etags: &EtagClock, now: DateTime<Utc>) {
+        self.etag = etags.next(now);
+        self.last_modified = now;
+    }
+
+    /// Takes a new ETag from `etags` for a container created at its last
+    /// modified time.
+    pub fn renew_etag(&mut self, etags: &EtagClock) {
+        self.etag = etags.next(self.last_modified);
+    }
+
+    /// Resets the lease to available/unlocked, as on a newly created container.
+    pub fn clear_lease(&mut self) {
+        self.lease_state = LeaseState::Available;
+        self.lease_status = LeaseStatus::Unlocked;
+        self.lease_duration = None;
+        self.lease_id = None;
+        self.lease_expiry = None;
+        self.lease_break_time = None;
+    }
+
+    /// Whether a lease is held at `now`: the lease is `Leased` and, for a
+    /// fixed-duration lease, not yet past its expiry.
+    pub fn is_leased(&self, now: DateTime<Utc>) -> bool {
+        self.lease_state == LeaseState::Leased && self.lease_expiry.is_none_or(|expiry| expiry > now)
+    }
+
+    /// The duration of the lease held on the container, reported as
+    /// `x-ms-lease-duration` while the lease state is `leased`.
+    pub fn active_lease_duration(&self) -> Option<LeaseDuration> {
+        match self.lease_state {
+            LeaseState::Leased => self.lease_duration,
+            _ => None,
+        }
+    }
+
+    /// Applies lease transitions that are due at `now`: a fixed-duration
+    /// lease past its expiry becomes `Expired` and a breaking lease past its
+    /// break time becomes `Broken`.
+    pub fn refresh_lease(&mut self, now: DateTime<Utc>) {
+        match self.lease_state {
+            LeaseState::Leased if !self.is_leased(now) => {
+                self.lease_state = LeaseState::Expired;
+                self.lease_status = LeaseStatus::Unlocked;
+            }
+            LeaseState::Breaking if self.lease_break_time.is_some_and(|time| time <= now) => {
+                self.lease_state = LeaseState::Broken;
+                self.lease_status = LeaseStatus::Unlocked;
+                self.lease_id = None;
+            }
+            _ => {}
+        }
     }
 }
 
@@ -105,7 +164,7 @@ pub struct ContainerModel {
     /// Container properties.
     pub properties: ContainerProperties,
     /// User-defined metadata.
-    pub metadata: HashMap<String, String>,
+    pub metadata: Metadata,
     /// Signed identifiers for stored access policies.
     pub signed_identifiers: Vec<SignedIdentifier>,
     /// Whether the container is soft-deleted.
@@ -116,21 +175,27 @@ pub struct ContainerModel {
     pub deleted_time: Option<DateTime<Utc>>,
     /// Remaining retention days after soft-delete.
     pub remaining_retention_days: Option<u32>,
+    /// Set on the record left behind by a delete while the name stays
+    /// reserved (see `Config::container_delete_linger`). Such a container
+    /// has no blobs and is neither listed nor addressable.
+    #[serde(default)]
+    pub deleting_until: Option<DateTime<Utc>>,
 }
 
 impl ContainerModel {
-    /// Creates a new container model.
-    pub fn new(account: String, name: String) -> Self {
+    /// Creates a new container model, created at `now`.
+    pub fn new(account: String, name: String, now: DateTime<Utc>) -> Self {
         Self {
             account,
             name,
-            properties: ContainerProperties::default(),
-            metadata: HashMap::new(),
+            properties: ContainerProperties::new(now),
+            metadata: Metadata::new(),
             signed_identifiers: Vec::new(),
             deleted: false,
             deleted_version: None,
             deleted_time: None,
             remaining_retention_days: None,
+            deleting_until: None,
         }
     }
 
@@ -139,3 +204,36 @@ impl ContainerModel {
         (self.account.clone(), self.name.clone())
     }
 }
+
+/// Status reported for simulated object replication, which never lags.
+pub const OBJECT_REPLICATION_STATUS: &str = "complete";
+
+/// The object replication rule simulated for a container's blobs when
+/// `Config::object_replication` is set. Blobs report it as a source would,
+/// with every replication complete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectReplicationRule {
+    pub policy_id: String,
+    pub rule_id: String,
+}
+
+impl ObjectReplicationRule {
+    /// Returns the rule of a container. The IDs are GUIDs derived from the
+    /// account and container names, so they are stable across restarts.
+    pub fn for_container(account: &str, container: &str) -> Self {
+        let guid = |kind: &str| {
+            let digest = Md5::digest(format!("{}/{}/{}", kind, account, container));
+            Uuid::from_bytes(digest.into()).to_string()
+        };
+        Self {
+            policy_id: guid("policy"),
+            rule_id: guid("rule"),
+        }
+    }
+
+    /// Returns `{policy}_{rule}`, which follows `x-ms-or-` in the status
+    /// header and `Or-` in list results.
+    pub fn id(&self) -> String {
+        format!("{}_{}", self.policy_id, self.rule_id)
+    }
+}
@@ -0,0 +1,138 @@
+//! ETag generation and comparison.
+
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 100-nanosecond intervals between 1601-01-01 and the Unix epoch.
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+/// Generates a quoted ETag in the service's `"0x8D..."` format from the
+/// modification time as hex FILETIME ticks. Writes take theirs from an
+/// [`EtagClock`] instead, so that no two share one.
+pub fn generate_etag(now: DateTime<Utc>) -> String {
+    format_etag(filetime_ticks(now))
+}
+
+fn filetime_ticks(now: DateTime<Utc>) -> u64 {
+    now.timestamp_nanos_opt()
+        .map(|nanos| (nanos.max(0) as u64) / 100 + FILETIME_UNIX_EPOCH)
+        .unwrap_or(FILETIME_UNIX_EPOCH)
+}
+
+fn format_etag(ticks: u64) -> String {
+    format!("\"0x{:X}\"", ticks)
+}
+
+/// Hands out the ETags of one store's writes. Each is the write time in
+/// FILETIME ticks, or one tick past the last ETag handed out if the clock
+/// has not moved beyond it, so concurrent writes and a delete followed by a
+/// recreate never get the same ETag. Nothing else goes into it: the same
+/// requests at the same clock times get the same ETags.
+#[derive(Debug, Default)]
+pub struct EtagClock {
+    last: AtomicU64,
+}
+
+impl EtagClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the ETag of a write at `now`.
+    pub fn next(&self, now: DateTime<Utc>) -> String {
+        let ticks = filetime_ticks(now);
+        let previous = self
+            .last
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| Some(ticks.max(last + 1)))
+            .unwrap_or_else(|last| last);
+        format_etag(ticks.max(previous + 1))
+    }
+}
+
+/// Compares a client-supplied ETag with a stored one, ignoring surrounding
+/// quotes and weak (`W/`) markers so `"0x8D..."`, `0x8D...` and
+/// `W/"0x8D..."` all match.
+pub fn etag_matches(given: &str, etag: &str) -> bool {
+    normalize(given) == normalize(etag)
+}
+
+/// Evaluates an `If-Match` / `If-None-Match` header against a stored ETag:
+/// true if the header is `*` or any listed ETag matches.
+pub fn etag_list_matches(header: &str, etag: &str) -> bool {
+    parse_etag_list(header)
+        .into_iter()
+        .any(|candidate| candidate == "*" || etag_matches(candidate, etag))
+}
+
+/// Splits a comma-separated ETag list, keeping commas inside quotes.
+pub fn parse_etag_list(header: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, c) in header.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                items.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&header[start..]);
+
+    items.into_iter().map(str::trim).filter(|item| !item.is_empty()).collect()
+}
+
+fn normalize(etag: &str) -> &str {
+    let etag = etag.trim();
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+    etag.strip_prefix('"')
+        .and_then(|e| e.strip_suffix('"'))
+        .unwrap_or(etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"0x8DBF1A2B3C4D5E6\"";
+
+    #[test]
+    fn test_parse_etag_list() {
+        assert_eq!(parse_etag_list("*"), vec!["*"]);
+        assert_eq!(parse_etag_list("\"a\""), vec!["\"a\""]);
+        assert_eq!(parse_etag_list("\"a\", W/\"b\",c"), vec!["\"a\"", "W/\"b\"", "c"]);
+        assert_eq!(parse_etag_list("\"a,b\", \"c\""), vec!["\"a,b\"", "\"c\""]);
+        assert!(parse_etag_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_etag_list_matches() {
+        assert!(etag_list_matches(ETAG, ETAG));
+        assert!(etag_list_matches("0x8DBF1A2B3C4D5E6", ETAG));
+        assert!(etag_list_matches("W/\"0x8DBF1A2B3C4D5E6\"", ETAG));
+        assert!(etag_list_matches("\"0x1\", \"0x8DBF1A2B3C4D5E6\"", ETAG));
+        assert!(etag_list_matches("*", ETAG));
+        assert!(!etag_list_matches("\"0x1\", \"0x2\"", ETAG));
+        assert!(!etag_list_matches("", ETAG));
+    }
+
+    #[test]
+    fn test_etag_clock_never_repeats() {
+        let now = Utc::now();
+        let first = generate_etag(now);
+        assert!(first.starts_with("\"0x"));
+        assert_eq!(generate_etag(now), first);
+
+        let etags = EtagClock::new();
+        assert_eq!(etags.next(now), first);
+        let second = etags.next(now);
+        assert!(second > first);
+        assert!(etags.next(now - chrono::Duration::seconds(1)) > second);
+
+        // Another store starts over from its clock
+        assert_eq!(EtagClock::new().next(now), first);
+    }
+}
@@ -0,0 +1,155 @@
+//! User-defined metadata of containers and blobs.
+
+use std::fmt;
+
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Name-value pairs set with `x-ms-meta-*` headers. Names are looked up
+/// case-insensitively, as Azure does, but keep the spelling they were first
+/// stored with; pairs are kept in the order they were set. Serializes as a
+/// map, so stores written with a plain `HashMap` load unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of `key`. A key already present under another casing
+    /// keeps its original spelling and position. Returns the old value.
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        match self.position(&key) {
+            Some(index) => Some(std::mem::replace(&mut self.entries[index].1, value)),
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Returns the value of `key`, matched case-insensitively.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.position(key).map(|index| self.entries[index].1.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Removes `key`, matched case-insensitively, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.position(key).map(|index| self.entries.remove(index).1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Iterates the pairs in the order they were set, with keys spelled as
+    /// first stored.
+    pub fn iter(&self) -> MetadataIter<'_> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.entries.iter().position(|(existing, _)| existing.eq_ignore_ascii_case(key))
+    }
+}
+
+/// Iterator over the pairs of a [`Metadata`].
+pub type MetadataIter<'a> = std::iter::Map<std::slice::Iter<'a, (String, String)>, fn(&'a (String, String)) -> (&'a str, &'a str)>;
+
+impl<'a> IntoIterator for &'a Metadata {
+    type Item = (&'a str, &'a str);
+    type IntoIter = MetadataIter<'a>;
+
+    fn into_iter(self) -> MetadataIter<'a> {
+        self.iter()
+    }
+}
+
+impl FromIterator<(String, String)> for Metadata {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        let mut metadata = Self::new();
+        for (key, value) in iter {
+            metadata.insert(key, value);
+        }
+        metadata
+    }
+}
+
+impl Serialize for Metadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (key, value) in &self.entries {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Metadata {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MetadataVisitor;
+
+        impl<'de> Visitor<'de> for MetadataVisitor {
+            type Value = Metadata;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of metadata names to values")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Metadata, A::Error> {
+                let mut metadata = Metadata::new();
+                while let Some((key, value)) = access.next_entry()? {
+                    metadata.insert(key, value);
+                }
+                Ok(metadata)
+            }
+        }
+
+        deserializer.deserialize_map(MetadataVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_match_case_insensitively_and_keep_first_spelling() {
+        let mut metadata = Metadata::new();
+        metadata.insert("MyKey".to_string(), "1".to_string());
+        metadata.insert("other".to_string(), "2".to_string());
+        assert_eq!(metadata.insert("MYKEY".to_string(), "3".to_string()).as_deref(), Some("1"));
+
+        assert_eq!(metadata.get("mykey"), Some("3"));
+        assert_eq!(metadata.iter().collect::<Vec<_>>(), [("MyKey", "3"), ("other", "2")]);
+        assert_eq!(metadata.remove("OTHER").as_deref(), Some("2"));
+        assert_eq!(metadata.len(), 1);
+    }
+
+    #[test]
+    fn test_serializes_as_an_ordered_map() {
+        let metadata: Metadata = [("b", "1"), ("A", "2")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert_eq!(json, r#"{"b":"1","A":"2"}"#);
+        assert_eq!(serde_json::from_str::<Metadata>(&json).unwrap(), metadata);
+    }
+}
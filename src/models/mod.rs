@@ -3,11 +3,15 @@
 mod blob;
 mod block;
 mod container;
+mod etag;
+mod metadata;
 mod page;
 mod service;
 
 pub use blob::*;
 pub use block::*;
 pub use container::*;
+pub use etag::*;
+pub use metadata::*;
 pub use page::*;
 pub use service::*;
@@ -60,6 +60,107 @@ impl PersistencyPageRange {
             extent_chunk,
         }
     }
+
+    /// Returns the part of this range covering `start..=end`, with the
+    /// extent reference narrowed to match.
+    fn slice(&self, start: u64, end: u64) -> Self {
+        let extent_chunk = self.extent_chunk.as_ref().map(|chunk| ExtentChunk {
+            id: chunk.id.clone(),
+            offset: chunk.offset + (start - self.start),
+            count: end - start + 1,
+        });
+        Self::new(start, end, extent_chunk)
+    }
+}
+
+/// Records `extent_chunk` as the content of `start..=end` in a sorted,
+/// non-overlapping list of written ranges. Overlapped ranges are split at
+/// the boundaries; `None` clears the range.
+pub fn update_page_ranges(
+    ranges: &mut Vec<PersistencyPageRange>,
+    start: u64,
+    end: u64,
+    extent_chunk: Option<ExtentChunk>,
+) {
+    let mut updated = Vec::with_capacity(ranges.len() + 2);
+    for range in ranges.drain(..) {
+        if range.end < start || range.start > end {
+            updated.push(range);
+            continue;
+        }
+        if range.start < start {
+            updated.push(range.slice(range.start, start - 1));
+        }
+        if range.end > end {
+            updated.push(range.slice(end + 1, range.end));
+        }
+    }
+    if extent_chunk.is_some() {
+        updated.push(PersistencyPageRange::new(start, end, extent_chunk));
+    }
+    updated.sort_by_key(|range| range.start);
+    *ranges = updated;
+}
+
+/// Drops everything at or beyond `size`, splitting a range that straddles it.
+pub fn truncate_page_ranges(ranges: &mut Vec<PersistencyPageRange>, size: u64) {
+    update_page_ranges(ranges, size, u64::MAX, None);
+}
+
+/// Compares the page maps of a snapshot (`previous`) and a later version
+/// (`current`): pages whose content changed are reported as page ranges,
+/// pages written before but cleared since as clear ranges.
+pub fn diff_page_ranges(
+    previous: &[PersistencyPageRange],
+    current: &[PersistencyPageRange],
+) -> Vec<PageRangeDiff> {
+    let mut boundaries: Vec<u64> = previous
+        .iter()
+        .chain(current)
+        .flat_map(|range| [range.start, range.end + 1])
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut diff: Vec<PageRangeDiff> = Vec::new();
+    for window in boundaries.windows(2) {
+        let (start, end) = (window[0], window[1] - 1);
+        let before = content_at(previous, start);
+        let after = content_at(current, start);
+
+        let is_clear = match (before, after) {
+            (_, Some(after)) if before != Some(after) => false,
+            (Some(_), None) => true,
+            _ => continue,
+        };
+
+        match diff.last_mut() {
+            Some(last) if last.end + 1 == start && last.is_clear == is_clear => last.end = end,
+            _ => diff.push(PageRangeDiff::new(start, end, is_clear)),
+        }
+    }
+    diff
+}
+
+/// Returns the extent and offset holding the byte at `position`, if written.
+fn content_at(ranges: &[PersistencyPageRange], position: u64) -> Option<(&str, u64)> {
+    let index = ranges.partition_point(|range| range.end < position);
+    let range = ranges.get(index).filter(|range| range.start <= position)?;
+    let chunk = range.extent_chunk.as_ref()?;
+    Some((chunk.id.as_str(), chunk.offset + (position - range.start)))
+}
+
+/// Returns the written ranges with adjacent ranges coalesced, as reported
+/// by Get Page Ranges.
+pub fn merge_page_ranges(ranges: &[PersistencyPageRange]) -> Vec<PageRange> {
+    let mut merged: Vec<PageRange> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.end + 1 == range.start => last.end = range.end,
+            _ => merged.push(PageRange::new(range.start, range.end)),
+        }
+    }
+    merged
 }
 
 /// Page blob constants.
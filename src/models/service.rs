@@ -89,6 +89,10 @@ impl Default for DeleteRetentionPolicy {
 }
 
 /// Service properties for blob storage.
+///
+/// The default is what an account reports before its properties are first
+/// set: version 1.0 logging and metrics, all disabled, no CORS rules and
+/// delete retention and static websites off.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ServiceProperties {
     pub logging: LoggingConfig,
@@ -0,0 +1,17 @@
+//! Hooks for observing handled requests, e.g. from embedding tests.
+
+use axum::http::StatusCode;
+
+use crate::context::RequestContext;
+use crate::operation::Operation;
+
+/// Receives every request the server handles.
+///
+/// Registered with [`crate::BlobServerBuilder::observer`]. Requests whose
+/// URL or headers cannot be parsed into a [`RequestContext`] are not
+/// reported.
+pub trait RequestObserver: Send + Sync {
+    /// Called after a request is handled with the operation it resolved to
+    /// (`Operation::Unknown` if none matched) and the response status.
+    fn on_operation(&self, operation: Operation, ctx: &RequestContext, status: StatusCode);
+}
@@ -0,0 +1,296 @@
+//! Classification of requests into Blob service operations.
+//!
+//! The router dispatches on [`Operation`], so the name reported to
+//! observers is always the handler that ran. Handlers find it in
+//! [`RequestContext::operation`].
+
+use crate::context::RequestContext;
+
+/// A Blob service REST operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    // Service
+    ListContainers,
+    GetServiceProperties,
+    SetServiceProperties,
+    GetServiceStats,
+    GetAccountInfo,
+    GetUserDelegationKey,
+    FilterBlobs,
+    SubmitBatch,
+    // Container
+    CreateContainer,
+    DeleteContainer,
+    GetContainerProperties,
+    SetContainerMetadata,
+    GetContainerAcl,
+    SetContainerAcl,
+    ListBlobs,
+    LeaseContainer,
+    RestoreContainer,
+    // Blob
+    GetBlob,
+    GetBlobProperties,
+    DeleteBlob,
+    PutBlob,
+    PutBlobFromUrl,
+    CopyBlob,
+    PutBlock,
+    PutBlockFromUrl,
+    PutBlockList,
+    GetBlockList,
+    PutPage,
+    ClearPage,
+    GetPageRanges,
+    GetPageRangesDiff,
+    AppendBlock,
+    AppendBlockFromUrl,
+    SealBlob,
+    SetBlobProperties,
+    ResizeBlob,
+    UpdateSequenceNumber,
+    SetBlobMetadata,
+    LeaseBlob,
+    SnapshotBlob,
+    AbortCopyBlob,
+    SetBlobTier,
+    GetBlobTags,
+    SetBlobTags,
+    UndeleteBlob,
+    IncrementalCopyBlob,
+    QueryBlob,
+    /// No operation matches the verb and query parameters.
+    Unknown,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::ListContainers => "ListContainers",
+            Operation::GetServiceProperties => "GetServiceProperties",
+            Operation::SetServiceProperties => "SetServiceProperties",
+            Operation::GetServiceStats => "GetServiceStats",
+            Operation::GetAccountInfo => "GetAccountInfo",
+            Operation::GetUserDelegationKey => "GetUserDelegationKey",
+            Operation::FilterBlobs => "FilterBlobs",
+            Operation::SubmitBatch => "SubmitBatch",
+            Operation::CreateContainer => "CreateContainer",
+            Operation::DeleteContainer => "DeleteContainer",
+            Operation::GetContainerProperties => "GetContainerProperties",
+            Operation::SetContainerMetadata => "SetContainerMetadata",
+            Operation::GetContainerAcl => "GetContainerAcl",
+            Operation::SetContainerAcl => "SetContainerAcl",
+            Operation::ListBlobs => "ListBlobs",
+            Operation::LeaseContainer => "LeaseContainer",
+            Operation::RestoreContainer => "RestoreContainer",
+            Operation::GetBlob => "GetBlob",
+            Operation::GetBlobProperties => "GetBlobProperties",
+            Operation::DeleteBlob => "DeleteBlob",
+            Operation::PutBlob => "PutBlob",
+            Operation::PutBlobFromUrl => "PutBlobFromUrl",
+            Operation::CopyBlob => "CopyBlob",
+            Operation::PutBlock => "PutBlock",
+            Operation::PutBlockFromUrl => "PutBlockFromUrl",
+            Operation::PutBlockList => "PutBlockList",
+            Operation::GetBlockList => "GetBlockList",
+            Operation::PutPage => "PutPage",
+            Operation::ClearPage => "ClearPage",
+            Operation::GetPageRanges => "GetPageRanges",
+            Operation::GetPageRangesDiff => "GetPageRangesDiff",
+            Operation::AppendBlock => "AppendBlock",
+            Operation::AppendBlockFromUrl => "AppendBlockFromUrl",
+            Operation::SealBlob => "SealBlob",
+            Operation::SetBlobProperties => "SetBlobProperties",
+            Operation::ResizeBlob => "ResizeBlob",
+            Operation::UpdateSequenceNumber => "UpdateSequenceNumber",
+            Operation::SetBlobMetadata => "SetBlobMetadata",
+            Operation::LeaseBlob => "LeaseBlob",
+            Operation::SnapshotBlob => "SnapshotBlob",
+            Operation::AbortCopyBlob => "AbortCopyBlob",
+            Operation::SetBlobTier => "SetBlobTier",
+            Operation::GetBlobTags => "GetBlobTags",
+            Operation::SetBlobTags => "SetBlobTags",
+            Operation::UndeleteBlob => "UndeleteBlob",
+            Operation::IncrementalCopyBlob => "IncrementalCopyBlob",
+            Operation::QueryBlob => "QueryBlob",
+            Operation::Unknown => "Unknown",
+        }
+    }
+
+    /// Classifies a request addressed to the service (`/{account}`).
+    pub fn service(ctx: &RequestContext) -> Self {
+        match (ctx.method.as_str(), ctx.restype(), ctx.comp()) {
+            ("GET", None, Some("list")) => Operation::ListContainers,
+            ("GET", Some("service"), Some("properties")) => Operation::GetServiceProperties,
+            ("PUT", Some("service"), Some("properties")) => Operation::SetServiceProperties,
+            ("GET", Some("service"), Some("stats")) => Operation::GetServiceStats,
+            ("GET" | "HEAD", Some("account"), Some("properties")) => Operation::GetAccountInfo,
+            ("POST", Some("service"), Some("userdelegationkey")) => Operation::GetUserDelegationKey,
+            ("GET", None, Some("blobs")) => Operation::FilterBlobs,
+            ("POST", None, Some("batch")) => Operation::SubmitBatch,
+            _ => Operation::Unknown,
+        }
+    }
+
+    /// Classifies a request addressed to a container.
+    pub fn container(ctx: &RequestContext) -> Self {
+        match (ctx.method.as_str(), ctx.restype(), ctx.comp()) {
+            ("PUT", Some("container"), None) => Operation::CreateContainer,
+            ("DELETE", Some("container"), None) => Operation::DeleteContainer,
+            ("GET" | "HEAD", Some("container"), None) => Operation::GetContainerProperties,
+            ("PUT", Some("container"), Some("metadata")) => Operation::SetContainerMetadata,
+            ("GET", Some("container"), Some("acl")) => Operation::GetContainerAcl,
+            ("PUT", Some("container"), Some("acl")) => Operation::SetContainerAcl,
+            ("GET", Some("container"), Some("list")) => Operation::ListBlobs,
+            ("PUT", Some("container"), Some("lease")) => Operation::LeaseContainer,
+            ("PUT", Some("container"), Some("undelete")) => Operation::RestoreContainer,
+            ("GET", Some("container"), Some("blobs")) => Operation::FilterBlobs,
+            ("POST", Some("container"), Some("batch")) => Operation::SubmitBatch,
+            _ => Operation::Unknown,
+        }
+    }
+
+    /// Classifies a request addressed to a blob.
+    pub fn blob(ctx: &RequestContext) -> Self {
+        match (ctx.method.as_str(), ctx.comp()) {
+            ("GET", None) => Operation::GetBlob,
+            ("HEAD", None) => Operation::GetBlobProperties,
+            ("DELETE", None) => Operation::DeleteBlob,
+            // Put Blob From URL names the blob type, Copy Blob does not
+            ("PUT", None) if ctx.copy_source().is_some() && ctx.blob_type().is_some() => {
+                Operation::PutBlobFromUrl
+            }
+            ("PUT", None) if ctx.copy_source().is_some() => Operation::CopyBlob,
+            ("PUT", None) => Operation::PutBlob,
+            ("PUT", Some("block")) if ctx.copy_source().is_some() || ctx.query_param("fromurl").is_some() => {
+                Operation::PutBlockFromUrl
+            }
+            ("PUT", Some("block")) => Operation::PutBlock,
+            ("PUT", Some("blocklist")) => Operation::PutBlockList,
+            ("GET", Some("blocklist")) => Operation::GetBlockList,
+            ("PUT", Some("page")) if ctx.header("x-ms-page-write") == Some("clear") => Operation::ClearPage,
+            ("PUT", Some("page")) => Operation::PutPage,
+            ("GET", Some("pagelist")) if ctx.query_param("prevsnapshot").is_some() => {
+                Operation::GetPageRangesDiff
+            }
+            ("GET", Some("pagelist")) => Operation::GetPageRanges,
+            ("PUT", Some("appendblock"))
+                if ctx.copy_source().is_some() || ctx.query_param("fromurl").is_some() =>
+            {
+                Operation::AppendBlockFromUrl
+            }
+            ("PUT", Some("appendblock")) => Operation::AppendBlock,
+            ("PUT", Some("seal")) => Operation::SealBlob,
+            // Page blob resize and sequence number updates share comp=properties
+            ("PUT", Some("properties")) if ctx.header("x-ms-blob-content-length").is_some() => {
+                Operation::ResizeBlob
+            }
+            ("PUT", Some("properties")) if ctx.header("x-ms-sequence-number-action").is_some() => {
+                Operation::UpdateSequenceNumber
+            }
+            ("PUT", Some("properties")) => Operation::SetBlobProperties,
+            ("PUT", Some("metadata")) => Operation::SetBlobMetadata,
+            ("PUT", Some("lease")) => Operation::LeaseBlob,
+            ("PUT", Some("snapshot")) => Operation::SnapshotBlob,
+            ("PUT", Some("copy")) => Operation::AbortCopyBlob,
+            ("PUT", Some("tier")) => Operation::SetBlobTier,
+            ("GET", Some("tags")) => Operation::GetBlobTags,
+            ("PUT", Some("tags")) => Operation::SetBlobTags,
+            ("PUT", Some("undelete")) => Operation::UndeleteBlob,
+            ("PUT", Some("incrementalcopy")) => Operation::IncrementalCopyBlob,
+            ("POST", Some("query")) => Operation::QueryBlob,
+            _ => Operation::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Operation;
+    use crate::context::RequestContext;
+
+    use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+    use std::collections::HashMap;
+
+    fn context(method: Method, uri: &'static str, headers: &[(&str, &str)]) -> RequestContext {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        let uri = Uri::from_static(uri);
+        let query = uri
+            .query()
+            .map(|query| url::form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+        let mut path_params = HashMap::new();
+        for (name, segment) in ["account", "container", "blob"].into_iter().zip(uri.path()[1..].splitn(3, '/')) {
+            path_params.insert(name.to_string(), segment.to_string());
+        }
+        RequestContext::new(method, uri, header_map, path_params, query).unwrap()
+    }
+
+    #[test]
+    fn test_service_operations() {
+        let cases = [
+            (Method::GET, "/acct?comp=list", Operation::ListContainers),
+            (Method::GET, "/acct?restype=service&comp=properties", Operation::GetServiceProperties),
+            (Method::PUT, "/acct?restype=service&comp=properties", Operation::SetServiceProperties),
+            (Method::HEAD, "/acct?restype=account&comp=properties", Operation::GetAccountInfo),
+            (Method::POST, "/acct?comp=batch", Operation::SubmitBatch),
+            (Method::DELETE, "/acct?comp=list", Operation::Unknown),
+        ];
+        for (method, uri, expected) in cases {
+            assert_eq!(Operation::service(&context(method, uri, &[])), expected, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_container_operations() {
+        let cases = [
+            (Method::PUT, "/acct/data?restype=container", Operation::CreateContainer),
+            (Method::HEAD, "/acct/data?restype=container", Operation::GetContainerProperties),
+            (Method::GET, "/acct/data?restype=container&comp=list", Operation::ListBlobs),
+            (Method::PUT, "/acct/data?restype=container&comp=lease", Operation::LeaseContainer),
+            (Method::PUT, "/acct/data?restype=container&comp=acl", Operation::SetContainerAcl),
+            (Method::POST, "/acct/data?restype=container", Operation::Unknown),
+        ];
+        for (method, uri, expected) in cases {
+            assert_eq!(Operation::container(&context(method, uri, &[])), expected, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_blob_operations() {
+        let blob = |method: Method, uri: &'static str, headers: &[(&str, &str)]| Operation::blob(&context(method, uri, headers));
+        let source = ("x-ms-copy-source", "http://127.0.0.1/acct/data/source");
+
+        assert_eq!(blob(Method::GET, "/acct/data/b", &[]), Operation::GetBlob);
+        assert_eq!(blob(Method::HEAD, "/acct/data/b", &[]), Operation::GetBlobProperties);
+        assert_eq!(blob(Method::PUT, "/acct/data/b", &[]), Operation::PutBlob);
+        assert_eq!(blob(Method::PUT, "/acct/data/b", &[source]), Operation::CopyBlob);
+        assert_eq!(
+            blob(Method::PUT, "/acct/data/b", &[source, ("x-ms-blob-type", "BlockBlob")]),
+            Operation::PutBlobFromUrl
+        );
+        assert_eq!(blob(Method::PUT, "/acct/data/b?comp=block&blockid=YQ==", &[]), Operation::PutBlock);
+        assert_eq!(blob(Method::PUT, "/acct/data/b?comp=block&blockid=YQ==", &[source]), Operation::PutBlockFromUrl);
+        assert_eq!(blob(Method::PUT, "/acct/data/b?comp=blocklist", &[]), Operation::PutBlockList);
+        assert_eq!(
+            blob(Method::PUT, "/acct/data/b?comp=page", &[("x-ms-page-write", "clear")]),
+            Operation::ClearPage
+        );
+        assert_eq!(
+            blob(Method::GET, "/acct/data/b?comp=pagelist&prevsnapshot=2024-01-01T00:00:00.0000000Z", &[]),
+            Operation::GetPageRangesDiff
+        );
+        assert_eq!(
+            blob(Method::PUT, "/acct/data/b?comp=properties", &[("x-ms-blob-content-length", "512")]),
+            Operation::ResizeBlob
+        );
+        assert_eq!(blob(Method::PUT, "/acct/data/b?comp=lease", &[]), Operation::LeaseBlob);
+        assert_eq!(blob(Method::PATCH, "/acct/data/b", &[]), Operation::Unknown);
+    }
+}
@@ -2,33 +2,49 @@
 
 use axum::{
     body::Body,
-    extract::{Path, Query, State},
-    http::{header, HeaderMap, Method, Response, StatusCode, Uri},
+    async_trait,
+    extract::{FromRequestParts, OriginalUri, Path, Query, State},
+    http::{header, request::Parts, HeaderMap, Method, Response, StatusCode, Uri},
     response::IntoResponse,
     routing::{delete, get, head, post, put},
     Router,
 };
 use bytes::Bytes;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::auth::authenticate;
+use crate::auth::{authenticate, public_access_allows, AuthResult, Authenticator, UserDelegationKeyRegistry};
+use crate::clock::{Clock, SystemClock};
 use crate::config::Config;
-use crate::context::RequestContext;
+use crate::context::{format_http_date, unsupported_header, RequestContext, ResponseHeaderOverrides, ROOT_CONTAINER};
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::handlers;
+use crate::header_case::HeaderCase;
+use crate::models::{EtagClock, PublicAccessLevel};
+use crate::observer::RequestObserver;
+use crate::operation::Operation;
 use crate::storage::{ExtentStore, MetadataStore};
+use crate::testing::RequestIdSequence;
 
 /// Converts an error response for HEAD requests by removing the body.
-/// HEAD responses must not have a body, so we keep headers but set empty body.
-fn error_response_for_method(error: StorageError, method: &Method, request_id: &str) -> Response<Body> {
-    let response = error.with_request_id(request_id).into_response();
+/// HEAD responses must not have a body, so we keep headers (including
+/// `x-ms-error-code`) but send an empty body with `Content-Length: 0`.
+/// Errors of a parsed request carry its request ID and timestamp.
+fn error_response_for_method(error: StorageError, method: &Method, ctx: Option<&RequestContext>) -> Response<Body> {
+    let error = match ctx {
+        Some(ctx) => error.with_request_id(&ctx.request_id).with_time(ctx.timestamp),
+        None => error,
+    };
+    let response = error.into_response();
 
     if method == Method::HEAD {
         // For HEAD requests, remove the body but keep headers
         let (mut parts, _) = response.into_parts();
-        // Remove content-length header since there's no body
-        parts.headers.remove(header::CONTENT_LENGTH);
+        // Declare the empty body so clients and proxies don't wait for the XML length
+        parts.headers.insert(header::CONTENT_LENGTH, header::HeaderValue::from_static("0"));
         // Remove content-type header to prevent Azure SDK from trying to parse empty XML body
         // Azure SDK tries to parse XML if Content-Type contains "xml", which fails on empty body
         parts.headers.remove(header::CONTENT_TYPE);
@@ -38,17 +54,217 @@ fn error_response_for_method(error: StorageError, method: &Method, request_id: &
     }
 }
 
+/// Runs a routed operation, bounded by the request's `timeout` query parameter
+/// when one was given. Elapsed timeouts are reported as `OperationTimedOut`,
+/// as are operations finishing after the timeout has passed on the server
+/// clock, so a mock clock can expire them too.
+async fn run_with_timeout<F>(ctx: &RequestContext, clock: &dyn Clock, operation: F) -> StorageResult<Response<Body>>
+where
+    F: Future<Output = StorageResult<Response<Body>>>,
+{
+    let Some(secs) = ctx.timeout() else {
+        return operation.await;
+    };
+    let result = tokio::time::timeout(Duration::from_secs(u64::from(secs)), operation)
+        .await
+        .unwrap_or_else(|_| Err(StorageError::new(ErrorCode::OperationTimedOut)))?;
+    if clock.now() - ctx.timestamp > chrono::Duration::seconds(i64::from(secs)) {
+        return Err(StorageError::new(ErrorCode::OperationTimedOut));
+    }
+    Ok(result)
+}
+
+/// Reports a handled request to the registered observer, if any, and with
+/// reproducible request IDs configured stamps the response with the
+/// request's ID and timestamp.
+fn observed(
+    state: &AppState,
+    operation: Operation,
+    ctx: &RequestContext,
+    response: Response<Body>,
+) -> Response<Body> {
+    if let Some(observer) = &state.observer {
+        observer.on_operation(operation, ctx, response.status());
+    }
+    if state.request_ids.is_none() {
+        return response;
+    }
+
+    // Replace the IDs and dates handlers stamp on their own
+    let mut response = response;
+    let headers = response.headers_mut();
+    headers.insert("x-ms-request-id", header::HeaderValue::from_str(&ctx.request_id).unwrap());
+    headers.insert(header::DATE, header::HeaderValue::from_str(&format_http_date(&ctx.timestamp)).unwrap());
+    response
+}
+
+/// The request URI as routed, with the path prefix `Router::nest` stripped
+/// from it when the router is mounted inside a larger application.
+struct RoutedUri {
+    uri: Uri,
+    mount_path: String,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RoutedUri {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let original = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map(|original| original.0.path())
+            .unwrap_or_else(|| parts.uri.path());
+        let mount_path = match parts.uri.path() {
+            "/" => original.trim_end_matches('/'),
+            path => original.strip_suffix(path).unwrap_or(""),
+        };
+        Ok(Self {
+            mount_path: mount_path.to_string(),
+            uri: parts.uri.clone(),
+        })
+    }
+}
+
+/// The request headers, and how the client spelled their names when the
+/// connection followed it (see [`crate::header_case`]).
+struct RequestHeaders {
+    headers: HeaderMap,
+    case: HeaderCase,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestHeaders {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            headers: parts.headers.clone(),
+            case: parts.extensions.get::<HeaderCase>().cloned().unwrap_or_default(),
+        })
+    }
+}
+
+/// Authenticates a request with the state's custom authenticator, or with
+/// [`authenticate`] if it has none.
+pub(crate) fn authenticate_request(state: &AppState, ctx: &RequestContext) -> StorageResult<AuthResult> {
+    let Some(authenticator) = &state.authenticator else {
+        return authenticate(ctx, &state.config, &state.delegation_keys);
+    };
+    authenticator(ctx, &state.config)?;
+    Ok(AuthResult {
+        account: ctx.account.clone(),
+        is_anonymous: false,
+        sas_permissions: None,
+        response_overrides: ResponseHeaderOverrides::default(),
+    })
+}
+
+/// Records what authentication granted on the request context.
+fn apply_auth_result(ctx: &mut RequestContext, auth: AuthResult) {
+    ctx.sas_permissions = auth.sas_permissions;
+    ctx.response_overrides = auth.response_overrides;
+}
+
+/// Applies the container's public access level to an anonymous request.
+///
+/// Operations the level does not grant fail with `ResourceNotFound`, as in
+/// Azure, so anonymous callers cannot tell whether the resource exists. A
+/// private container grants nothing, unless loose mode or
+/// [`Config::anonymous_private_access`] lets clients without credentials
+/// use it.
+async fn check_public_access(state: &AppState, ctx: &RequestContext, operation: Operation) -> StorageResult<()> {
+    let Some(container) = ctx.container.as_deref() else {
+        return Ok(());
+    };
+    let level = match state.metadata.get_container(&ctx.account, container).await {
+        Ok(container) => container.properties.public_access,
+        Err(_) => return Ok(()),
+    };
+    if level == PublicAccessLevel::None && (state.config.loose || state.config.anonymous_private_access) {
+        return Ok(());
+    }
+    if public_access_allows(level, operation) {
+        return Ok(());
+    }
+    Err(StorageError::new(ErrorCode::ResourceNotFound))
+}
+
 /// Application state shared between handlers.
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Config>,
     pub metadata: Arc<dyn MetadataStore>,
     pub extents: Arc<dyn ExtentStore>,
+    pub delegation_keys: Arc<UserDelegationKeyRegistry>,
+    /// Notified after every handled request.
+    pub observer: Option<Arc<dyn RequestObserver>>,
+    /// Source of the current time; stamped on each request context.
+    pub clock: Arc<dyn Clock>,
+    /// Source of the ETags of every write.
+    pub etags: Arc<EtagClock>,
+    /// Source of request IDs when they must be reproducible. Responses then
+    /// also carry the request timestamp as their Date. Random IDs if unset.
+    pub request_ids: Option<Arc<RequestIdSequence>>,
+    /// Replaces the built-in authentication when set.
+    pub authenticator: Option<Authenticator>,
+    /// Reads the sources of from-URL operations; shared so connections to
+    /// a source are reused.
+    pub copy_source_client: reqwest::Client,
+}
+
+impl AppState {
+    /// Creates state over the given stores with the system clock, random
+    /// request IDs and no observer.
+    pub fn new(config: Config, metadata: Arc<dyn MetadataStore>, extents: Arc<dyn ExtentStore>) -> Self {
+        Self {
+            config: Arc::new(config),
+            metadata,
+            extents,
+            delegation_keys: Arc::new(UserDelegationKeyRegistry::new()),
+            observer: None,
+            clock: Arc::new(SystemClock),
+            etags: Arc::new(EtagClock::new()),
+            request_ids: None,
+            authenticator: None,
+            copy_source_client: handlers::copy_source_client(),
+        }
+    }
 }
 
 /// Creates the main router for the blob service.
+///
+/// Routes are relative to wherever the router is mounted, so it can be
+/// nested under a prefix of a larger application. Clients then address
+/// accounts below that prefix, and SharedKey signatures cover the full
+/// path including it.
+///
+/// ```
+/// use std::sync::Arc;
+/// use axum::{routing::get, Router};
+/// use azurite_rs::router::{create_router, AppState};
+/// use azurite_rs::{Config, MemoryExtentStore, MemoryMetadataStore};
+///
+/// let state = AppState::new(
+///     Config::default(),
+///     Arc::new(MemoryMetadataStore::new()),
+///     Arc::new(MemoryExtentStore::new()),
+/// );
+///
+/// // Blob endpoint: http://<host>/azure/devstoreaccount1
+/// let app: Router = Router::new()
+///     .nest("/azure", create_router(state))
+///     .route("/health", get(|| async { "ok" }));
+/// ```
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
+    let router = if state.config.admin {
+        Router::new()
+            .route("/__admin/stats", get(admin_stats_handler))
+            .route("/__admin/verify", get(admin_verify_handler))
+    } else {
+        Router::new()
+    };
+    router
         // Service-level routes (no container/blob)
         .route("/", get(service_handler).put(service_handler).post(service_handler).head(service_handler))
         .route("/:account", get(service_handler).put(service_handler).post(service_handler).head(service_handler))
@@ -60,43 +276,112 @@ pub fn create_router(state: AppState) -> Router {
         .with_state(state)
 }
 
+/// Handler for the admin statistics endpoint. Not authenticated.
+async fn admin_stats_handler(State(state): State<AppState>) -> Response<Body> {
+    match handlers::storage_stats(state.metadata.clone(), state.extents.clone()).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Handler for the admin consistency check endpoint. Not authenticated.
+async fn admin_verify_handler(State(state): State<AppState>) -> Response<Body> {
+    match handlers::verify_storage(state.metadata.clone(), state.extents.clone()).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
 /// Handler for service-level operations.
 async fn service_handler(
     State(state): State<AppState>,
     method: Method,
-    uri: Uri,
+    RoutedUri { uri, mount_path }: RoutedUri,
     headers: HeaderMap,
     Path(params): Path<HashMap<String, String>>,
-    Query(query): Query<HashMap<String, String>>,
+    Query(query): Query<Vec<(String, String)>>,
     body: Bytes,
 ) -> Response<Body> {
-    let ctx = match RequestContext::new(method.clone(), uri, headers.clone(), params, query) {
+    let at_server_root = !params.contains_key("account");
+    let mut ctx = match RequestContext::new(method.clone(), uri, headers.clone(), params, query) {
         Ok(ctx) => ctx,
-        Err(e) => return error_response_for_method(e, &method, ""),
+        Err(e) => return error_response_for_method(e, &method, None),
     };
+    ctx.timestamp = state.clock.now();
+    ctx.etags = state.etags.clone();
+    if let Some(request_ids) = &state.request_ids {
+        ctx.request_id = request_ids.next_id();
+    }
+    ctx.mount_path = mount_path;
+    ctx.configured_base_url = state.config.blob_base_url();
+    ctx.loose = state.config.loose;
+    ctx.max_metadata_count = state.config.max_metadata_count;
+    ctx.object_replication = state.config.object_replication;
+
+    let operation = Operation::service(&ctx);
+    ctx.operation = operation;
 
     // Authenticate
-    if let Err(e) = authenticate(&ctx, &state.config) {
-        return error_response_for_method(e, &method, &ctx.request_id);
+    let is_anonymous = match authenticate_request(&state, &ctx) {
+        Ok(auth) => {
+            let is_anonymous = auth.is_anonymous;
+            apply_auth_result(&mut ctx, auth);
+            is_anonymous
+        }
+        Err(e) => {
+            let response = error_response_for_method(e, &method, Some(&ctx));
+            return observed(&state, operation, &ctx, response);
+        }
+    };
+    if at_server_root && is_anonymous && method == Method::GET && is_bare_read(&ctx) {
+        let response = landing_page(&ctx, &state.config);
+        return observed(&state, operation, &ctx, response);
     }
 
-    let result = route_service_request(&ctx, &state, body).await;
-    match result {
+    let result = run_with_timeout(&ctx, &*state.clock, route_service_request(&ctx, &state, operation, body)).await;
+    let response = match result {
         Ok(response) => response,
-        Err(e) => error_response_for_method(e, &method, &ctx.request_id),
-    }
+        Err(e) => error_response_for_method(e, &method, Some(&ctx)),
+    };
+    observed(&state, operation, &ctx, response)
 }
 
 /// Handler for container-level operations.
+///
+/// Without `restype=container` a single path segment names a blob in the
+/// `$root` container, as long as that container exists.
 async fn container_handler(
     State(state): State<AppState>,
     method: Method,
-    uri: Uri,
-    headers: HeaderMap,
-    Path(params): Path<HashMap<String, String>>,
-    Query(query): Query<HashMap<String, String>>,
+    RoutedUri { uri, mount_path }: RoutedUri,
+    RequestHeaders { headers, case: header_case }: RequestHeaders,
+    Path(mut params): Path<HashMap<String, String>>,
+    Query(query): Query<Vec<(String, String)>>,
     body: Bytes,
 ) -> Response<Body> {
+    let is_container_op = query
+        .iter()
+        .any(|(k, v)| k.eq_ignore_ascii_case("restype") && v.eq_ignore_ascii_case("container"));
+    if !is_container_op {
+        let account = params.get("account").cloned().unwrap_or_default();
+        if state.metadata.container_exists(&account, ROOT_CONTAINER).await {
+            if let Some(blob) = params.remove("container") {
+                params.insert("container".to_string(), ROOT_CONTAINER.to_string());
+                params.insert("blob".to_string(), blob);
+                return blob_handler(
+                    State(state),
+                    method,
+                    RoutedUri { uri, mount_path },
+                    RequestHeaders { headers, case: header_case },
+                    Path(params),
+                    Query(query),
+                    body,
+                )
+                .await;
+            }
+        }
+    }
+
     // Debug logging for incoming container requests
     tracing::debug!(
         "CONTAINER REQUEST: method={} uri={} path_params={:?}",
@@ -106,32 +391,61 @@ async fn container_handler(
     );
     tracing::debug!("CONTAINER REQUEST: query_params={:?}", query);
 
-    let ctx = match RequestContext::new(method.clone(), uri, headers.clone(), params, query) {
+    let mut ctx = match RequestContext::new(method.clone(), uri, headers.clone(), params, query) {
         Ok(ctx) => ctx,
-        Err(e) => return error_response_for_method(e, &method, ""),
+        Err(e) => return error_response_for_method(e, &method, None),
     };
+    ctx.timestamp = state.clock.now();
+    ctx.etags = state.etags.clone();
+    if let Some(request_ids) = &state.request_ids {
+        ctx.request_id = request_ids.next_id();
+    }
+    ctx.mount_path = mount_path;
+    ctx.configured_base_url = state.config.blob_base_url();
+    ctx.header_case = header_case;
+    ctx.loose = state.config.loose;
+    ctx.max_metadata_count = state.config.max_metadata_count;
+    ctx.object_replication = state.config.object_replication;
+
+    let operation = Operation::container(&ctx);
+    ctx.operation = operation;
 
     // Authenticate
-    if let Err(e) = authenticate(&ctx, &state.config) {
-        tracing::debug!("CONTAINER REQUEST: Authentication failed - {:?}", e);
-        return error_response_for_method(e, &method, &ctx.request_id);
+    let is_anonymous = match authenticate_request(&state, &ctx) {
+        Ok(auth) => {
+            let is_anonymous = auth.is_anonymous;
+            apply_auth_result(&mut ctx, auth);
+            is_anonymous
+        }
+        Err(e) => {
+            tracing::debug!("CONTAINER REQUEST: Authentication failed - {:?}", e);
+            let response = error_response_for_method(e, &method, Some(&ctx));
+            return observed(&state, operation, &ctx, response);
+        }
+    };
+    if is_anonymous {
+        if let Err(e) = check_public_access(&state, &ctx, operation).await {
+            let response = error_response_for_method(e, &method, Some(&ctx));
+            return observed(&state, operation, &ctx, response);
+        }
     }
 
-    let result = route_container_request(&ctx, &state, body).await;
-    match result {
+    let result = run_with_timeout(&ctx, &*state.clock, route_container_request(&ctx, &state, operation, body)).await;
+    let response = match result {
         Ok(response) => response,
-        Err(e) => error_response_for_method(e, &method, &ctx.request_id),
-    }
+        Err(e) => error_response_for_method(e, &method, Some(&ctx)),
+    };
+    observed(&state, operation, &ctx, response)
 }
 
 /// Handler for blob-level operations.
 async fn blob_handler(
     State(state): State<AppState>,
     method: Method,
-    uri: Uri,
-    headers: HeaderMap,
+    RoutedUri { uri, mount_path }: RoutedUri,
+    RequestHeaders { headers, case: header_case }: RequestHeaders,
     Path(params): Path<HashMap<String, String>>,
-    Query(query): Query<HashMap<String, String>>,
+    Query(query): Query<Vec<(String, String)>>,
     body: Bytes,
 ) -> Response<Body> {
     // Debug logging for incoming blob requests
@@ -143,10 +457,21 @@ async fn blob_handler(
     );
     tracing::debug!("BLOB REQUEST: query_params={:?}", query);
 
-    let ctx = match RequestContext::new(method.clone(), uri, headers.clone(), params, query) {
+    let mut ctx = match RequestContext::new(method.clone(), uri, headers.clone(), params, query) {
         Ok(ctx) => ctx,
-        Err(e) => return error_response_for_method(e, &method, ""),
+        Err(e) => return error_response_for_method(e, &method, None),
     };
+    ctx.timestamp = state.clock.now();
+    ctx.etags = state.etags.clone();
+    if let Some(request_ids) = &state.request_ids {
+        ctx.request_id = request_ids.next_id();
+    }
+    ctx.mount_path = mount_path;
+    ctx.configured_base_url = state.config.blob_base_url();
+    ctx.header_case = header_case;
+    ctx.loose = state.config.loose;
+    ctx.max_metadata_count = state.config.max_metadata_count;
+    ctx.object_replication = state.config.object_replication;
 
     tracing::debug!(
         "BLOB REQUEST CTX: account={} container={:?} blob={:?}",
@@ -155,16 +480,92 @@ async fn blob_handler(
         ctx.blob
     );
 
+    let operation = Operation::blob(&ctx);
+    ctx.operation = operation;
+
     // Authenticate
-    if let Err(e) = authenticate(&ctx, &state.config) {
-        tracing::debug!("BLOB REQUEST: Authentication failed - {:?}", e);
-        return error_response_for_method(e, &method, &ctx.request_id);
+    let is_anonymous = match authenticate_request(&state, &ctx) {
+        Ok(auth) => {
+            let is_anonymous = auth.is_anonymous;
+            apply_auth_result(&mut ctx, auth);
+            is_anonymous
+        }
+        Err(e) => {
+            tracing::debug!("BLOB REQUEST: Authentication failed - {:?}", e);
+            let response = error_response_for_method(e, &method, Some(&ctx));
+            return observed(&state, operation, &ctx, response);
+        }
+    };
+    if is_anonymous {
+        if let Err(e) = check_public_access(&state, &ctx, operation).await {
+            let response = error_response_for_method(e, &method, Some(&ctx));
+            return observed(&state, operation, &ctx, response);
+        }
     }
 
-    let result = route_blob_request(&ctx, &state, body).await;
-    match result {
+    let result = run_with_timeout(&ctx, &*state.clock, route_blob_request(&ctx, &state, operation, body)).await;
+    let response = match result {
         Ok(response) => response,
-        Err(e) => error_response_for_method(e, &method, &ctx.request_id),
+        Err(e) => error_response_for_method(e, &method, Some(&ctx)),
+    };
+    observed(&state, operation, &ctx, response)
+}
+
+/// `restype` and `comp` values routed at the service level.
+const SERVICE_RESTYPES: &[&str] = &["service", "account"];
+const SERVICE_COMPS: &[&str] = &["list", "properties", "stats", "userdelegationkey", "blobs", "batch"];
+
+/// `restype` and `comp` values routed at the container level.
+const CONTAINER_RESTYPES: &[&str] = &["container"];
+const CONTAINER_COMPS: &[&str] = &[
+    "metadata", "acl", "list", "lease", "undelete", "blobs", "batch",
+];
+
+/// `comp` values routed at the blob level.
+const BLOB_COMPS: &[&str] = &[
+    "block", "blocklist", "page", "pagelist", "appendblock", "seal", "properties", "metadata",
+    "lease", "snapshot", "copy", "tier", "tags", "undelete", "incrementalcopy", "query",
+];
+
+/// Classifies a request that matched no route. An unknown `comp` or
+/// `restype` is reported by name; otherwise the verb is wrong for the
+/// resource. Verbs the service never accepts are rejected by the router.
+fn unmatched_route(ctx: &RequestContext, restypes: Option<&[&str]>, comps: &[&str]) -> StorageError {
+    if let Some(comp) = ctx.comp().filter(|comp| !comps.contains(comp)) {
+        return StorageError::invalid_query_parameter(
+            "comp",
+            comp,
+            "The value is not supported for this resource.",
+        );
+    }
+
+    if let (Some(restypes), Some(restype)) = (restypes, ctx.restype()) {
+        if !restypes.contains(&restype) {
+            return StorageError::invalid_query_parameter(
+                "restype",
+                restype,
+                "The value is not supported for this resource.",
+            );
+        }
+    }
+
+    StorageError::new(ErrorCode::UnsupportedHttpVerb)
+}
+
+/// Fails requests carrying an `x-ms-` header the Blob service does not
+/// accept, such as the Data Lake `x-ms-owner`. Loose mode logs and ignores
+/// them instead.
+fn check_request_headers(ctx: &RequestContext) -> StorageResult<()> {
+    let unsupported = ctx.unsupported_headers();
+    if ctx.loose {
+        for name in unsupported {
+            tracing::warn!("Ignoring unsupported header {} on {} {}", name, ctx.method, ctx.uri.path());
+        }
+        return Ok(());
+    }
+    match unsupported.first() {
+        Some(name) => Err(unsupported_header(name)),
+        None => Ok(()),
     }
 }
 
@@ -172,45 +573,94 @@ async fn blob_handler(
 async fn route_service_request(
     ctx: &RequestContext,
     state: &AppState,
+    operation: Operation,
     body: Bytes,
 ) -> StorageResult<Response<Body>> {
-    let restype = ctx.restype();
-    let comp = ctx.comp();
-
-    match (ctx.method.as_str(), restype, comp) {
-        // List containers
-        ("GET", None, Some("list")) => {
+    check_request_headers(ctx)?;
+    match operation {
+        Operation::ListContainers => {
             handlers::list_containers(ctx, state.metadata.clone()).await
         }
-        // Get service properties
-        ("GET", Some("service"), Some("properties")) => {
+        Operation::GetServiceProperties => {
             handlers::get_service_properties(ctx, state.metadata.clone()).await
         }
-        // Set service properties
-        ("PUT", Some("service"), Some("properties")) => {
+        Operation::SetServiceProperties => {
             handlers::set_service_properties(ctx, state.metadata.clone(), body).await
         }
-        // Get service stats
-        ("GET", Some("service"), Some("stats")) => {
+        Operation::GetServiceStats => {
             handlers::get_service_stats(ctx).await
         }
-        // Get account info
-        ("GET" | "HEAD", Some("account"), Some("properties")) => {
+        Operation::GetAccountInfo => {
             handlers::get_account_info(ctx).await
         }
-        // Get user delegation key
-        ("POST", Some("service"), Some("userdelegationkey")) => {
-            handlers::get_user_delegation_key(ctx, body).await
+        Operation::GetUserDelegationKey => {
+            handlers::get_user_delegation_key(ctx, state.delegation_keys.clone(), body).await
         }
         // Filter blobs (service level)
-        ("GET", None, Some("blobs")) => {
+        Operation::FilterBlobs => {
             handlers::filter_blobs_service(ctx, state.metadata.clone()).await
         }
-        // Submit batch
-        ("POST", None, Some("batch")) => {
+        Operation::SubmitBatch => {
             handlers::submit_batch(ctx, state.metadata.clone(), state.extents.clone(), body).await
         }
-        _ => Err(StorageError::new(ErrorCode::UnsupportedHttpVerb)),
+        _ if is_bare_read(ctx) => Err(StorageError::missing_required_query_parameter("comp")),
+        _ => Err(unmatched_route(ctx, Some(SERVICE_RESTYPES), SERVICE_COMPS)),
+    }
+}
+
+/// Whether a request is a GET or HEAD without `comp` or `restype`, which
+/// addresses nothing at the service level.
+fn is_bare_read(ctx: &RequestContext) -> bool {
+    matches!(ctx.method, Method::GET | Method::HEAD) && ctx.comp().is_none() && ctx.restype().is_none()
+}
+
+/// The response to an anonymous `GET /`, most likely a browser opened at
+/// the emulator's address: the `MissingRequiredQueryParameter` error an
+/// account root gets, with a short plain-text page in place of the XML
+/// naming the account endpoints to use.
+fn landing_page(ctx: &RequestContext, config: &Config) -> Response<Body> {
+    let base_url = ctx.base_url();
+    let mut page = String::from(
+        "Azurite-rs Blob service\n\n\
+         This is an Azure Blob Storage emulator. Point a storage client or SDK at the\n\
+         blob endpoint of an account:\n\n",
+    );
+    for account in &config.accounts {
+        page.push_str(&format!("    {}/{}\n", base_url, account.name));
+    }
+    page.push_str("\nGET <endpoint>?comp=list lists the containers of an account.\n");
+
+    let error = StorageError::missing_required_query_parameter("comp")
+        .with_request_id(&ctx.request_id)
+        .with_time(ctx.timestamp);
+    let (mut parts, _) = error.into_response().into_parts();
+    parts.headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("text/plain; charset=utf-8"));
+    parts.headers.insert(header::CONTENT_LENGTH, header::HeaderValue::from(page.len()));
+    Response::from_parts(parts, Body::from(page))
+}
+
+/// Fails requests for a deleted container whose name is still reserved by
+/// [`Config::container_delete_linger`](crate::Config::container_delete_linger),
+/// and drops the reservation once it has passed.
+async fn check_container_not_deleting(ctx: &RequestContext, state: &AppState) -> StorageResult<()> {
+    // Without a linger deletes leave nothing behind, apart from records
+    // restored from an archive, which garbage collection purges
+    if state.config.container_delete_linger.is_zero() {
+        return Ok(());
+    }
+    let Some(container) = &ctx.container else {
+        return Ok(());
+    };
+    let Ok(existing) = state.metadata.get_container(&ctx.account, container).await else {
+        return Ok(());
+    };
+    match existing.deleting_until {
+        Some(until) if until > ctx.timestamp => Err(StorageError::new(ErrorCode::ContainerBeingDeleted)),
+        Some(_) => {
+            let _ = state.metadata.delete_container(&ctx.account, container, None).await;
+            Ok(())
+        }
+        None => Ok(()),
     }
 }
 
@@ -218,58 +668,54 @@ async fn route_service_request(
 async fn route_container_request(
     ctx: &RequestContext,
     state: &AppState,
+    operation: Operation,
     body: Bytes,
 ) -> StorageResult<Response<Body>> {
-    let restype = ctx.restype();
-    let comp = ctx.comp();
-
-    match (ctx.method.as_str(), restype, comp) {
-        // Create container
-        ("PUT", Some("container"), None) => {
+    check_request_headers(ctx)?;
+    check_container_not_deleting(ctx, state).await?;
+    match operation {
+        Operation::CreateContainer => {
             handlers::create_container(ctx, state.metadata.clone()).await
         }
-        // Delete container
-        ("DELETE", Some("container"), None) => {
-            handlers::delete_container(ctx, state.metadata.clone()).await
+        Operation::DeleteContainer => {
+            handlers::delete_container(
+                ctx,
+                state.metadata.clone(),
+                state.extents.clone(),
+                state.config.container_delete_linger,
+            )
+            .await
         }
-        // Get container properties
-        ("GET" | "HEAD", Some("container"), None) => {
-            handlers::get_container_properties(ctx, state.metadata.clone()).await
+        Operation::GetContainerProperties => {
+            handlers::get_container_properties(ctx, state.metadata.clone(), state.config.container_stats_headers).await
         }
-        // Set container metadata
-        ("PUT", Some("container"), Some("metadata")) => {
+        Operation::SetContainerMetadata => {
             handlers::set_container_metadata(ctx, state.metadata.clone()).await
         }
-        // Get container ACL
-        ("GET", Some("container"), Some("acl")) => {
+        Operation::GetContainerAcl => {
             handlers::get_container_acl(ctx, state.metadata.clone()).await
         }
-        // Set container ACL
-        ("PUT", Some("container"), Some("acl")) => {
+        Operation::SetContainerAcl => {
             handlers::set_container_acl(ctx, state.metadata.clone(), body).await
         }
-        // List blobs
-        ("GET", Some("container"), Some("list")) => {
+        Operation::ListBlobs => {
             handlers::list_blobs(ctx, state.metadata.clone()).await
         }
-        // Container lease
-        ("PUT", Some("container"), Some("lease")) => {
+        Operation::LeaseContainer => {
             handlers::container_lease(ctx, state.metadata.clone()).await
         }
-        // Restore container
-        ("PUT", Some("container"), Some("undelete")) => {
+        Operation::RestoreContainer => {
             handlers::restore_container(ctx, state.metadata.clone()).await
         }
         // Filter blobs (container level)
-        ("GET", Some("container"), Some("blobs")) => {
+        Operation::FilterBlobs => {
             // Similar to list blobs but with tag filtering
             handlers::list_blobs(ctx, state.metadata.clone()).await
         }
-        // Submit batch (container level)
-        ("POST", Some("container"), Some("batch")) => {
+        Operation::SubmitBatch => {
             handlers::submit_batch(ctx, state.metadata.clone(), state.extents.clone(), body).await
         }
-        _ => Err(StorageError::new(ErrorCode::UnsupportedHttpVerb)),
+        _ => Err(unmatched_route(ctx, Some(CONTAINER_RESTYPES), CONTAINER_COMPS)),
     }
 }
 
@@ -277,139 +723,119 @@ async fn route_container_request(
 async fn route_blob_request(
     ctx: &RequestContext,
     state: &AppState,
+    operation: Operation,
     body: Bytes,
 ) -> StorageResult<Response<Body>> {
-    let comp = ctx.comp();
-    let blob_type = ctx.blob_type();
-
-    match (ctx.method.as_str(), comp) {
-        // Download blob
-        ("GET", None) => {
+    check_request_headers(ctx)?;
+    check_container_not_deleting(ctx, state).await?;
+    match operation {
+        Operation::GetBlob => {
             handlers::download_blob(ctx, state.metadata.clone(), state.extents.clone()).await
         }
-        // Get blob properties
-        ("HEAD", None) => {
+        Operation::GetBlobProperties => {
             handlers::get_blob_properties(ctx, state.metadata.clone()).await
         }
-        // Delete blob
-        ("DELETE", None) => {
+        Operation::DeleteBlob => {
             handlers::delete_blob(ctx, state.metadata.clone(), state.extents.clone()).await
         }
-        // Upload blob or copy
-        ("PUT", None) => {
-            if ctx.copy_source().is_some() {
-                handlers::copy_blob(ctx, state.metadata.clone(), state.extents.clone()).await
-            } else {
-                match blob_type {
-                    Some("PageBlob") => {
-                        handlers::create_page_blob(ctx, state.metadata.clone()).await
-                    }
-                    Some("AppendBlob") => {
-                        handlers::create_append_blob(ctx, state.metadata.clone()).await
-                    }
-                    _ => {
-                        handlers::upload_block_blob(ctx, state.metadata.clone(), state.extents.clone(), body).await
-                    }
-                }
-            }
+        Operation::CopyBlob if ctx.header("x-ms-requires-sync") == Some("true") => {
+            handlers::copy_blob_from_url(ctx, state).await
         }
-        // Stage block
-        ("PUT", Some("block")) => {
-            if ctx.query_param("fromURL").is_some() {
-                handlers::stage_block_from_url(ctx, state.metadata.clone(), state.extents.clone()).await
-            } else {
-                handlers::stage_block(ctx, state.metadata.clone(), state.extents.clone(), body).await
+        Operation::CopyBlob => {
+            handlers::copy_blob(ctx, state).await
+        }
+        // Only block blobs can be created from a URL
+        Operation::PutBlobFromUrl => match ctx.blob_type() {
+            Some("BlockBlob") => {
+                handlers::put_blob_from_url(ctx, state).await
+            }
+            blob_type => Err(StorageError::invalid_header_value("x-ms-blob-type", blob_type.unwrap_or_default())),
+        },
+        Operation::PutBlob => match ctx.blob_type() {
+            Some("PageBlob") => {
+                handlers::create_page_blob(ctx, state.metadata.clone(), body).await
             }
+            Some("AppendBlob") => {
+                handlers::create_append_blob(ctx, state.metadata.clone(), body).await
+            }
+            Some("BlockBlob") | None => {
+                handlers::upload_block_blob(ctx, state.metadata.clone(), state.extents.clone(), body).await
+            }
+            Some(blob_type) => Err(StorageError::invalid_header_value("x-ms-blob-type", blob_type)),
+        },
+        Operation::PutBlockFromUrl => {
+            handlers::stage_block_from_url(ctx, state).await
         }
-        // Commit block list
-        ("PUT", Some("blocklist")) => {
+        Operation::PutBlock => {
+            handlers::stage_block(ctx, state.metadata.clone(), state.extents.clone(), body).await
+        }
+        Operation::PutBlockList => {
             handlers::commit_block_list(ctx, state.metadata.clone(), state.extents.clone(), body).await
         }
-        // Get block list
-        ("GET", Some("blocklist")) => {
+        Operation::GetBlockList => {
             handlers::get_block_list(ctx, state.metadata.clone()).await
         }
-        // Page operations
-        ("PUT", Some("page")) => {
-            let page_write = ctx.header("x-ms-page-write").unwrap_or("update");
-            if page_write == "clear" {
-                handlers::clear_pages(ctx, state.metadata.clone()).await
-            } else {
-                handlers::upload_pages(ctx, state.metadata.clone(), state.extents.clone(), body).await
-            }
+        Operation::ClearPage => {
+            handlers::clear_pages(ctx, state.metadata.clone(), state.extents.clone()).await
         }
-        // Get page ranges
-        ("GET", Some("pagelist")) => {
-            if ctx.query_param("prevsnapshot").is_some() {
-                handlers::get_page_ranges_diff(ctx, state.metadata.clone()).await
-            } else {
-                handlers::get_page_ranges(ctx, state.metadata.clone()).await
-            }
+        Operation::PutPage => {
+            handlers::upload_pages(ctx, state.metadata.clone(), state.extents.clone(), body).await
         }
-        // Append block
-        ("PUT", Some("appendblock")) => {
-            if ctx.query_param("fromUrl").is_some() || ctx.query_param("fromURL").is_some() {
-                handlers::append_block_from_url(ctx, state.metadata.clone(), state.extents.clone()).await
-            } else {
-                handlers::append_block(ctx, state.metadata.clone(), state.extents.clone(), body).await
-            }
+        Operation::GetPageRangesDiff => {
+            handlers::get_page_ranges_diff(ctx, state.metadata.clone()).await
+        }
+        Operation::GetPageRanges => {
+            handlers::get_page_ranges(ctx, state.metadata.clone()).await
+        }
+        Operation::AppendBlockFromUrl => {
+            handlers::append_block_from_url(ctx, state).await
         }
-        // Seal append blob
-        ("PUT", Some("seal")) => {
+        Operation::AppendBlock => {
+            handlers::append_block(ctx, state.metadata.clone(), state.extents.clone(), body).await
+        }
+        Operation::SealBlob => {
             handlers::seal_append_blob(ctx, state.metadata.clone()).await
         }
-        // Set blob properties
-        ("PUT", Some("properties")) => {
-            // Check if this is a page blob resize or sequence number update
-            if ctx.header("x-ms-blob-content-length").is_some() {
-                handlers::resize_page_blob(ctx, state.metadata.clone()).await
-            } else if ctx.header("x-ms-sequence-number-action").is_some() {
-                handlers::update_sequence_number(ctx, state.metadata.clone()).await
-            } else {
-                handlers::set_blob_properties(ctx, state.metadata.clone()).await
-            }
+        Operation::ResizeBlob => {
+            handlers::resize_page_blob(ctx, state.metadata.clone(), state.extents.clone()).await
+        }
+        Operation::UpdateSequenceNumber => {
+            handlers::update_sequence_number(ctx, state.metadata.clone()).await
+        }
+        Operation::SetBlobProperties => {
+            handlers::set_blob_properties(ctx, state.metadata.clone()).await
         }
-        // Set blob metadata
-        ("PUT", Some("metadata")) => {
+        Operation::SetBlobMetadata => {
             handlers::set_blob_metadata(ctx, state.metadata.clone()).await
         }
-        // Blob lease
-        ("PUT", Some("lease")) => {
+        Operation::LeaseBlob => {
             handlers::blob_lease(ctx, state.metadata.clone()).await
         }
-        // Create snapshot
-        ("PUT", Some("snapshot")) => {
+        Operation::SnapshotBlob => {
             handlers::create_snapshot(ctx, state.metadata.clone()).await
         }
-        // Abort copy
-        ("PUT", Some("copy")) => {
+        Operation::AbortCopyBlob => {
             handlers::abort_copy(ctx, state.metadata.clone()).await
         }
-        // Set tier
-        ("PUT", Some("tier")) => {
+        Operation::SetBlobTier => {
             handlers::set_blob_tier(ctx, state.metadata.clone()).await
         }
-        // Get tags
-        ("GET", Some("tags")) => {
+        Operation::GetBlobTags => {
             handlers::get_blob_tags(ctx, state.metadata.clone()).await
         }
-        // Set tags
-        ("PUT", Some("tags")) => {
+        Operation::SetBlobTags => {
             handlers::set_blob_tags(ctx, state.metadata.clone(), body).await
         }
-        // Undelete blob
-        ("PUT", Some("undelete")) => {
+        Operation::UndeleteBlob => {
             handlers::undelete_blob(ctx, state.metadata.clone()).await
         }
-        // Incremental copy (page blob)
-        ("PUT", Some("incrementalcopy")) => {
+        Operation::IncrementalCopyBlob => {
             handlers::copy_incremental(ctx, state.metadata.clone()).await
         }
-        // Query blob
-        ("POST", Some("query")) => {
+        Operation::QueryBlob => {
             // Simplified - return the blob content as-is
             handlers::download_blob(ctx, state.metadata.clone(), state.extents.clone()).await
         }
-        _ => Err(StorageError::new(ErrorCode::UnsupportedHttpVerb)),
+        _ => Err(unmatched_route(ctx, None, BLOB_COMPS)),
     }
 }
@@ -1,33 +1,81 @@
 //! HTTP server for Azure Blob Storage emulator.
 
+use axum::http::header::CONTENT_TYPE;
+use axum::extract::Request;
+use axum::http::{Extensions, HeaderMap, StatusCode, Version};
+use axum::routing::Route;
+use axum::Router;
+use axum::response::IntoResponse;
+use futures::future::join_all;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::convert::Infallible;
+use std::io::IoSlice;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{lookup_host, TcpListener};
+use tokio::sync::Semaphore;
+use tower::{Layer, Service};
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
-use tracing::{info, Level};
+use tracing::{debug, error, info, warn, Level};
 
+use crate::auth::Authenticator;
+use crate::clock::{Clock, SystemClock};
 use crate::config::Config;
+use crate::context::RequestContext;
+use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::header_case;
+use crate::models::EtagClock;
+use crate::observer::RequestObserver;
 use crate::router::{create_router, AppState};
-use crate::storage::{ExtentStore, MemoryExtentStore, MemoryMetadataStore, MetadataStore};
+use crate::storage::{ExtentStore, GarbageCollector, MemoryExtentStore, MemoryMetadataStore, MetadataStore};
+use crate::testing::{Fixtures, RequestIdSequence};
 
 /// Blob storage server.
 pub struct BlobServer {
     config: Arc<Config>,
     metadata: Arc<dyn MetadataStore>,
     extents: Arc<dyn ExtentStore>,
+    observer: Option<Arc<dyn RequestObserver>>,
+    clock: Arc<dyn Clock>,
+    etags: Arc<EtagClock>,
+    request_id_seed: Option<u64>,
+    authenticator: Option<Authenticator>,
+    layers: Vec<RouterLayer>,
 }
 
+/// Wraps the service router in a layer given to
+/// [`BlobServerBuilder::layer`].
+type RouterLayer = Arc<dyn Fn(Router) -> Router + Send + Sync>;
+
 impl BlobServer {
     /// Creates a new blob server with in-memory storage.
     pub fn new(config: Config) -> Self {
         let metadata: Arc<dyn MetadataStore> = Arc::new(MemoryMetadataStore::new());
-        let extents: Arc<dyn ExtentStore> = Arc::new(MemoryExtentStore::new());
+        let extents = default_extent_store(&config);
 
         Self {
             config: Arc::new(config),
             metadata,
             extents,
+            observer: None,
+            clock: Arc::new(SystemClock),
+            etags: Arc::new(EtagClock::new()),
+            request_id_seed: None,
+            authenticator: None,
+            layers: Vec::new(),
         }
     }
 
@@ -41,21 +89,61 @@ impl BlobServer {
             config: Arc::new(config),
             metadata,
             extents,
+            observer: None,
+            clock: Arc::new(SystemClock),
+            etags: Arc::new(EtagClock::new()),
+            request_id_seed: None,
+            authenticator: None,
+            layers: Vec::new(),
         }
     }
 
     /// Runs the server.
+    ///
+    /// With a Unix socket configured the server listens only on that socket.
+    /// Otherwise every configured host is resolved and each resolved address
+    /// gets its own listener serving the same router. Garbage collection
+    /// runs in the background every [`Config::gc_interval`].
     pub async fn run(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let addr: SocketAddr = self.config.blob_bind_address().parse()?;
+        let app = self.router();
 
-        let state = AppState {
-            config: self.config.clone(),
-            metadata: self.metadata.clone(),
-            extents: self.extents.clone(),
-        };
+        if !self.config.gc_interval.is_zero() {
+            let gc = self.garbage_collector();
+            tokio::spawn(async move { gc.run().await });
+        }
+
+        info!(
+            "Default account: {}, key: {}...",
+            self.config.accounts.first().map(|a| a.name.as_str()).unwrap_or("unknown"),
+            self.config.accounts.first()
+                .map(|a| &a.key[..20])
+                .unwrap_or("unknown")
+        );
+
+        match &self.config.socket {
+            Some(path) => serve_unix(&self.config, path, app).await,
+            None => serve_tcp(&self.config, app).await,
+        }
+    }
+
+    /// Returns the service router with this server's storage, observer,
+    /// clock and custom layers, for mounting in another axum application
+    /// instead of calling [`BlobServer::run`]. See [`create_router`].
+    pub fn router(&self) -> Router {
+        let mut state = AppState::new((*self.config).clone(), self.metadata.clone(), self.extents.clone());
+        state.observer = self.observer.clone();
+        state.clock = self.clock.clone();
+        state.etags = self.etags.clone();
+        state.request_ids = self.request_id_seed.map(|seed| Arc::new(RequestIdSequence::new(seed)));
+        state.authenticator = self.authenticator.clone();
 
-        // Create router with middleware
-        let app = create_router(state)
+        // Custom layers see requests and responses as the service handles
+        // them, inside CORS, compression and tracing
+        let router = self
+            .layers
+            .iter()
+            .fold(create_router(state), |router, layer| layer(router));
+        router
             .layer(
                 CorsLayer::new()
                     .allow_origin(Any)
@@ -63,39 +151,352 @@ impl BlobServer {
                     .allow_headers(Any)
                     .expose_headers(Any),
             )
-            .layer(TraceLayer::new_for_http());
+            .layer(
+                CompressionLayer::new()
+                    .no_br()
+                    .no_zstd()
+                    .compress_when(SizeAbove::new(MIN_COMPRESSED_SIZE).and(is_service_xml)),
+            )
+            .layer(TraceLayer::new_for_http())
+    }
 
-        info!("Azurite Blob service is starting at http://{}", addr);
-        info!(
-            "Default account: {}, key: {}...",
-            self.config.accounts.first().map(|a| a.name.as_str()).unwrap_or("unknown"),
-            self.config.accounts.first()
-                .map(|a| &a.key[..20])
-                .unwrap_or("unknown")
-        );
+    /// Returns a handle for seeding and inspecting this server's storage.
+    /// It stays valid after the server is started.
+    pub fn fixtures(&self) -> Fixtures {
+        Fixtures::new(self.metadata.clone(), self.extents.clone())
+            .with_clock(self.clock.clone())
+            .with_etags(self.etags.clone())
+    }
 
-        let listener = TcpListener::bind(addr).await?;
-        axum::serve(listener, app).await?;
+    /// Returns a garbage collector over this server's storage and clock, for
+    /// running passes on demand with [`GarbageCollector::run_once`].
+    pub fn garbage_collector(&self) -> GarbageCollector {
+        GarbageCollector::new(
+            self.metadata.clone(),
+            self.extents.clone(),
+            self.clock.clone(),
+            self.config.gc_interval,
+        )
+    }
+
+    /// Writes the server's storage to a state archive at `path`.
+    pub async fn export_state(&self, path: impl AsRef<Path>) -> StorageResult<()> {
+        self.fixtures().export_state(path).await
+    }
 
-        Ok(())
+    /// Loads a state archive into the server's storage.
+    pub async fn import_state(&self, path: impl AsRef<Path>) -> StorageResult<()> {
+        self.fixtures().import_state(path).await
     }
 
-    /// Returns the bind address.
+    /// Returns the primary bind address.
     pub fn bind_address(&self) -> String {
         self.config.blob_bind_address()
     }
 
-    /// Returns the base URL for the blob service.
+    /// Returns the base URL for the blob service on the primary address,
+    /// with an unspecified bind address shown as loopback. See
+    /// [`Config::blob_display_address`].
     pub fn base_url(&self) -> String {
-        format!("http://{}", self.bind_address())
+        self.config.blob_base_url()
+    }
+}
+
+/// Responses smaller than this many bytes are sent uncompressed.
+const MIN_COMPRESSED_SIZE: u16 = 256;
+
+/// Whether a response is an XML document produced by the service (listings,
+/// properties, errors) and may be compressed. Blob content is never
+/// compressed, whatever its content type, so its bytes and Content-MD5 reach
+/// the client untouched.
+fn is_service_xml(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    let is_xml = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/xml"));
+    is_xml && !headers.contains_key("x-ms-blob-type")
+}
+
+/// Serves `app` on every address the configured hosts resolve to.
+async fn serve_tcp(config: &Config, app: Router) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for address in config.blob_bind_addresses() {
+        for addr in lookup_host(address.as_str()).await? {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    if addrs.is_empty() {
+        return Err("no host addresses to bind to".into());
+    }
+
+    let connections = ConnectionOptions::new(config);
+    let mut servers = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Azurite Blob service is listening at http://{}", listener.local_addr()?);
+        let (app, connections) = (app.clone(), connections.clone());
+        servers.push(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        handle_accept_error(e).await;
+                        continue;
+                    }
+                };
+                if connections.tcp_nodelay {
+                    if let Err(e) = stream.set_nodelay(true) {
+                        debug!("Failed to set TCP_NODELAY: {}", e);
+                    }
+                }
+                connections.serve(stream, app.clone());
+            }
+        });
+    }
+    join_all(servers).await;
+
+    Ok(())
+}
+
+/// Logs a failed accept and keeps the listener running. Errors other than a
+/// peer dropping the connection mid-handshake (notably `EMFILE` and `ENFILE`
+/// when file descriptors run out) are followed by a one second pause, as in
+/// `axum::serve`, so the loop does not spin until descriptors are released.
+async fn handle_accept_error(e: std::io::Error) {
+    use std::io::ErrorKind;
+
+    if matches!(
+        e.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+    ) {
+        debug!("Connection dropped before accept: {}", e);
+        return;
+    }
+    error!("Failed to accept connection: {}", e);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
+
+/// Connection handling shared by every listener, from the keep-alive and
+/// connection limit settings of [`Config`].
+#[derive(Clone)]
+struct ConnectionOptions {
+    keep_alive_timeout: Duration,
+    tcp_nodelay: bool,
+    /// One permit per connection served, when the count is limited.
+    slots: Option<Arc<Semaphore>>,
+}
+
+impl ConnectionOptions {
+    fn new(config: &Config) -> Self {
+        Self {
+            keep_alive_timeout: config.keep_alive_timeout,
+            tcp_nodelay: config.tcp_nodelay,
+            slots: (config.max_connections > 0).then(|| Arc::new(Semaphore::new(config.max_connections))),
+        }
+    }
+
+    /// Serves an accepted connection in the background. Once the connection
+    /// limit is reached, each request on a new connection is answered with
+    /// 503 `ServerBusy` and the connection is closed.
+    fn serve<I>(&self, io: I, app: Router)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let permit = match &self.slots {
+            Some(slots) => match slots.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    tokio::spawn(async move {
+                        let mut builder = auto::Builder::new(TokioExecutor::new());
+                        builder.http1().keep_alive(false);
+                        let busy = service_fn(|_| async {
+                            warn!("Connection limit reached, rejecting request");
+                            Ok::<_, Infallible>(StorageError::new(ErrorCode::ServerBusy).into_response())
+                        });
+                        if let Err(e) = builder.serve_connection(TokioIo::new(io), busy).await {
+                            debug!("Connection failed: {}", e);
+                        }
+                    });
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        let keep_alive_timeout = self.keep_alive_timeout;
+        tokio::spawn(async move {
+            let _permit = permit;
+            let idle = Arc::new(Idle::new());
+            let io = IdleIo { io, idle: idle.clone() };
+            // Requests learn, and responses keep, the spelling of metadata
+            // header names, which hyper would lowercase
+            let (io, tracker) = header_case::track(io);
+            let app = TowerToHyperService::new(app);
+            let requests = idle.clone();
+            let service = service_fn(move |mut request: hyper::Request<Incoming>| {
+                requests.busy.fetch_add(1, Ordering::Relaxed);
+                tracker.on_request(&mut request);
+                let (method, version) = (request.method().clone(), request.version());
+                let response = hyper::service::Service::call(&app, request);
+                let (tracker, requests) = (tracker.clone(), requests.clone());
+                async move {
+                    let mut response = response.await?;
+                    tracker.on_response(&method, version, &mut response);
+                    requests.busy.fetch_sub(1, Ordering::Relaxed);
+                    requests.touch();
+                    Ok::<_, Infallible>(response)
+                }
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+            tokio::pin!(connection);
+            let result = if keep_alive_timeout.is_zero() {
+                connection.await
+            } else {
+                tokio::select! {
+                    result = connection.as_mut() => result,
+                    _ = idle.elapsed(keep_alive_timeout) => {
+                        // Finishes a response still being written, then closes
+                        connection.as_mut().graceful_shutdown();
+                        connection.await
+                    }
+                }
+            };
+            if let Err(e) = result {
+                debug!("Connection failed: {}", e);
+            }
+        });
     }
 }
 
+/// When a connection last did anything, for the keep-alive timeout.
+struct Idle {
+    /// Requests whose response has not been produced yet.
+    busy: AtomicUsize,
+    /// Last time bytes moved or a request was answered.
+    since: parking_lot::Mutex<Instant>,
+}
+
+impl Idle {
+    fn new() -> Self {
+        Self {
+            busy: AtomicUsize::new(0),
+            since: parking_lot::Mutex::new(Instant::now()),
+        }
+    }
+
+    fn touch(&self) {
+        *self.since.lock() = Instant::now();
+    }
+
+    /// Resolves once the connection has had no request in progress and
+    /// moved no bytes for `timeout`.
+    async fn elapsed(&self, timeout: Duration) {
+        loop {
+            let since = *self.since.lock();
+            tokio::time::sleep_until((since + timeout).into()).await;
+            if self.busy.load(Ordering::Relaxed) > 0 {
+                self.touch();
+            } else if *self.since.lock() == since {
+                return;
+            }
+        }
+    }
+}
+
+/// A connection's I/O, stamping [`Idle`] whenever bytes move.
+struct IdleIo<I> {
+    io: I,
+    idle: Arc<Idle>,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for IdleIo<I> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.io).poll_read(cx, buf);
+        if buf.filled().len() > before {
+            self.idle.touch();
+        }
+        result
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for IdleIo<I> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.io).poll_write(cx, buf);
+        if matches!(result, Poll::Ready(Ok(n)) if n > 0) {
+            self.idle.touch();
+        }
+        result
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        if matches!(result, Poll::Ready(Ok(n)) if n > 0) {
+            self.idle.touch();
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+/// Serves `app` on a Unix domain socket at `path`, replacing a stale socket
+/// left by a previous run.
+#[cfg(unix)]
+async fn serve_unix(config: &Config, path: &Path, app: Router) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::net::UnixListener;
+
+    if let Ok(existing) = std::fs::symlink_metadata(path) {
+        if existing.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+    info!("Azurite Blob service is listening at unix:{}", path.display());
+
+    let connections = ConnectionOptions::new(config);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => connections.serve(stream, app.clone()),
+            Err(e) => handle_accept_error(e).await,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_config: &Config, _path: &Path, _app: Router) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    Err("Unix domain sockets are not supported on this platform".into())
+}
+
 /// Builder for creating a blob server.
 pub struct BlobServerBuilder {
     config: Config,
     metadata: Option<Arc<dyn MetadataStore>>,
     extents: Option<Arc<dyn ExtentStore>>,
+    observer: Option<Arc<dyn RequestObserver>>,
+    clock: Option<Arc<dyn Clock>>,
+    request_id_seed: Option<u64>,
+    authenticator: Option<Authenticator>,
+    layers: Vec<RouterLayer>,
 }
 
 impl BlobServerBuilder {
@@ -105,6 +506,11 @@ impl BlobServerBuilder {
             config: Config::default(),
             metadata: None,
             extents: None,
+            observer: None,
+            clock: None,
+            request_id_seed: None,
+            authenticator: None,
+            layers: Vec::new(),
         }
     }
 
@@ -114,9 +520,25 @@ impl BlobServerBuilder {
         self
     }
 
-    /// Sets the host address.
+    /// Sets a single host address.
     pub fn host(mut self, host: impl Into<String>) -> Self {
-        self.config.host = host.into();
+        self.config.hosts = vec![host.into()];
+        self
+    }
+
+    /// Sets the host addresses to bind to; the first one is the primary.
+    pub fn hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Serves on a Unix domain socket at `path` instead of TCP.
+    pub fn unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.socket = Some(path.into());
         self
     }
 
@@ -132,6 +554,74 @@ impl BlobServerBuilder {
         self
     }
 
+    /// Serves storage statistics at `/__admin/stats`.
+    pub fn admin(mut self, admin: bool) -> Self {
+        self.config.admin = admin;
+        self
+    }
+
+    /// Reports container blob counts in Get Container Properties. See
+    /// [`Config::container_stats_headers`].
+    pub fn container_stats_headers(mut self, enabled: bool) -> Self {
+        self.config.container_stats_headers = enabled;
+        self
+    }
+
+    /// Keeps a deleted container's name reserved for `linger`. See
+    /// [`Config::container_delete_linger`].
+    pub fn container_delete_linger(mut self, linger: Duration) -> Self {
+        self.config.container_delete_linger = linger;
+        self
+    }
+
+    /// Closes keep-alive connections idle for longer than `timeout`. See
+    /// [`Config::keep_alive_timeout`].
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.config.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Limits the number of connections served at once. See
+    /// [`Config::max_connections`].
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = max_connections;
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on accepted connections.
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.config.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Limits the number of metadata pairs a request may set. See
+    /// [`Config::max_metadata_count`].
+    pub fn max_metadata_count(mut self, count: usize) -> Self {
+        self.config.max_metadata_count = count;
+        self
+    }
+
+    /// Allows Copy Blob from other accounts. See
+    /// [`Config::cross_account_copy`].
+    pub fn cross_account_copy(mut self, enabled: bool) -> Self {
+        self.config.cross_account_copy = enabled;
+        self
+    }
+
+    /// Simulates completed object replication on every container. See
+    /// [`Config::object_replication`].
+    pub fn object_replication(mut self, enabled: bool) -> Self {
+        self.config.object_replication = enabled;
+        self
+    }
+
+    /// Lets anonymous requests use private containers. See
+    /// [`Config::anonymous_private_access`].
+    pub fn anonymous_private_access(mut self, enabled: bool) -> Self {
+        self.config.anonymous_private_access = enabled;
+        self
+    }
+
     /// Sets the metadata store.
     pub fn metadata(mut self, metadata: Arc<dyn MetadataStore>) -> Self {
         self.metadata = Some(metadata);
@@ -144,6 +634,79 @@ impl BlobServerBuilder {
         self
     }
 
+    /// Sets an observer notified after every handled request.
+    pub fn observer(mut self, observer: Arc<dyn RequestObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Sets the clock used for lease expiry, SAS validity and stored
+    /// timestamps such as Last-Modified. Defaults to [`SystemClock`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Derives request IDs from `seed` and a counter instead of generating
+    /// them at random, and sets every response's Date to the request time
+    /// read from the clock. With a [`crate::testing::MockClock`] the same
+    /// requests then get byte-identical responses, ETags and Last-Modified
+    /// included, for golden-file tests. Not available from the command line.
+    pub fn deterministic_request_ids(mut self, seed: u64) -> Self {
+        self.request_id_seed = Some(seed);
+        self
+    }
+
+    /// Wraps the service router in a tower layer, for request capture,
+    /// injected delays or failures and similar test middleware. Layers
+    /// apply in the order added, each around the previous ones, and run
+    /// inside the CORS, compression and tracing layers of the server.
+    ///
+    /// Delaying every PUT by half a second:
+    ///
+    /// ```
+    /// use axum::extract::Request;
+    /// use axum::http::Method;
+    /// use axum::middleware::{self, Next};
+    /// use axum::response::Response;
+    /// use azurite_rs::BlobServerBuilder;
+    /// use std::time::Duration;
+    ///
+    /// async fn slow_puts(request: Request, next: Next) -> Response {
+    ///     if request.method() == Method::PUT {
+    ///         tokio::time::sleep(Duration::from_millis(500)).await;
+    ///     }
+    ///     next.run(request).await
+    /// }
+    ///
+    /// let server = BlobServerBuilder::new()
+    ///     .layer(middleware::from_fn(slow_puts))
+    ///     .build();
+    /// ```
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers.push(Arc::new(move |router: Router| router.layer(layer.clone())));
+        self
+    }
+
+    /// Replaces the built-in SharedKey, SAS and anonymous authentication
+    /// with `authenticator`. Requests it accepts get full access to the
+    /// account they address; the error of those it rejects is the response.
+    /// Copy sources in other accounts are still verified the built-in way.
+    pub fn with_authenticator<F>(mut self, authenticator: F) -> Self
+    where
+        F: Fn(&RequestContext, &Config) -> StorageResult<()> + Send + Sync + 'static,
+    {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
     /// Builds the server.
     pub fn build(self) -> BlobServer {
         let metadata = self
@@ -151,9 +714,31 @@ impl BlobServerBuilder {
             .unwrap_or_else(|| Arc::new(MemoryMetadataStore::new()));
         let extents = self
             .extents
-            .unwrap_or_else(|| Arc::new(MemoryExtentStore::new()));
+            .unwrap_or_else(|| default_extent_store(&self.config));
 
-        BlobServer::with_storage(self.config, metadata, extents)
+        BlobServer {
+            observer: self.observer,
+            clock: self.clock.unwrap_or_else(|| Arc::new(SystemClock)),
+            request_id_seed: self.request_id_seed,
+            authenticator: self.authenticator,
+            layers: self.layers,
+            ..BlobServer::with_storage(self.config, metadata, extents)
+        }
+    }
+}
+
+/// Creates the in-memory extent store described by the configured memory
+/// budget and spill directory.
+fn default_extent_store(config: &Config) -> Arc<dyn ExtentStore> {
+    let Some(dir) = config.extent_spill_dir.clone().filter(|_| config.extent_memory_limit > 0) else {
+        return Arc::new(MemoryExtentStore::with_limit(config.extent_memory_limit));
+    };
+    match MemoryExtentStore::with_spill(config.extent_memory_limit, dir) {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            warn!("{}; rejecting writes beyond the memory limit instead", e.message);
+            Arc::new(MemoryExtentStore::with_limit(config.extent_memory_limit))
+        }
     }
 }
 
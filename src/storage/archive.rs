@@ -0,0 +1,254 @@
+//! Portable archives of emulator state.
+//!
+//! An archive is a zstd-compressed tar holding:
+//! - `manifest.json`: the archive format and version
+//! - `metadata.json`: a [`MetadataState`] whose extent chunks name payload entries
+//! - `extents/<n>`: the bytes of each distinct extent chunk
+//!
+//! Leases are released on export, so imported objects start unlocked.
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::models::ExtentChunk;
+
+use super::gc::release_extents;
+use super::{ExtentStore, MetadataState, MetadataStore};
+
+/// Identifies state archives written by this emulator.
+const ARCHIVE_FORMAT: &str = "azurite-rs-state";
+
+/// Current archive version. Bump it when the archived models change and
+/// teach [`decode_metadata`] to migrate the older layout.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const METADATA_ENTRY: &str = "metadata.json";
+const EXTENTS_DIR: &str = "extents/";
+
+/// zstd compression level used for archives.
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    exported_at: DateTime<Utc>,
+}
+
+/// Writes the full contents of the given stores to `writer` as an archive.
+pub async fn export_archive<W: Write>(
+    metadata: &dyn MetadataStore,
+    extents: &dyn ExtentStore,
+    writer: W,
+) -> StorageResult<W> {
+    let mut state = metadata.export_state().await?;
+
+    for container in &mut state.containers {
+        container.properties.clear_lease();
+    }
+    for blob in &mut state.blobs {
+        blob.properties.clear_lease();
+    }
+
+    // Store each distinct chunk once and point references at its entry
+    let mut entries: HashMap<(String, u64, u64), usize> = HashMap::new();
+    let mut payloads: Vec<Bytes> = Vec::new();
+    let mut chunks = Vec::new();
+    for_each_chunk(&mut state, |chunk| chunks.push(chunk.clone()));
+    for chunk in chunks {
+        if let Entry::Vacant(entry) = entries.entry((chunk.id.clone(), chunk.offset, chunk.count)) {
+            payloads.push(extents.read(&chunk).await?);
+            entry.insert(payloads.len() - 1);
+        }
+    }
+    for_each_chunk(&mut state, |chunk| {
+        let index = entries[&(chunk.id.clone(), chunk.offset, chunk.count)];
+        *chunk = ExtentChunk::new(index.to_string(), 0, chunk.count);
+    });
+
+    let manifest = Manifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+    };
+
+    let encoder = zstd::Encoder::new(writer, COMPRESSION_LEVEL).map_err(archive_io_error)?;
+    let mut builder = tar::Builder::new(encoder);
+    append_entry(&mut builder, MANIFEST_ENTRY, &to_json(&manifest)?)?;
+    append_entry(&mut builder, METADATA_ENTRY, &to_json(&state)?)?;
+    for (index, payload) in payloads.iter().enumerate() {
+        append_entry(&mut builder, &format!("{}{}", EXTENTS_DIR, index), payload)?;
+    }
+
+    let encoder = builder.into_inner().map_err(archive_io_error)?;
+    encoder.finish().map_err(archive_io_error)
+}
+
+/// Restores an archive read from `reader` into the given stores.
+///
+/// The manifest, the first entry, is checked before anything else is read.
+/// Payloads are written as new extents; records replace existing ones with
+/// the same key, and the extents only the replaced records used are freed.
+/// When the import fails, the extents it wrote are freed again unless a
+/// record imported before the failure uses them.
+pub async fn import_archive<R: Read>(
+    metadata: &dyn MetadataStore,
+    extents: &dyn ExtentStore,
+    reader: R,
+) -> StorageResult<()> {
+    let decoder = zstd::Decoder::new(reader).map_err(archive_io_error)?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = archive.entries().map_err(archive_io_error)?;
+
+    let manifest = match entries.next() {
+        Some(entry) => {
+            let (path, data) = read_entry(entry)?;
+            if path != MANIFEST_ENTRY {
+                return Err(invalid_archive("missing manifest"));
+            }
+            serde_json::from_slice::<Manifest>(&data)
+                .map_err(|e| invalid_archive(format!("unreadable manifest: {}", e)))?
+        }
+        None => return Err(invalid_archive("missing manifest")),
+    };
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(invalid_archive(format!("unknown format '{}'", manifest.format)));
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(unsupported_version(manifest.version));
+    }
+
+    let mut metadata_json = None;
+    let mut payloads: HashMap<String, Vec<u8>> = HashMap::new();
+    for entry in entries {
+        let (path, data) = read_entry(entry)?;
+        if path == METADATA_ENTRY {
+            metadata_json = Some(data);
+        } else if let Some(name) = path.strip_prefix(EXTENTS_DIR) {
+            payloads.insert(name.to_string(), data);
+        }
+    }
+    let metadata_json = metadata_json.ok_or_else(|| invalid_archive("missing metadata"))?;
+    let state = decode_metadata(manifest.version, &metadata_json)?;
+
+    let mut written: HashMap<String, ExtentChunk> = HashMap::new();
+    let result = import_payloads(metadata, extents, state, payloads, &mut written).await;
+    if result.is_err() {
+        let chunks: Vec<ExtentChunk> = written.into_values().collect();
+        release_extents(metadata, extents, &chunks).await;
+    }
+    result
+}
+
+/// Writes the payloads of an archive, recording each in `written` so a
+/// failed import can free them, then imports the records pointing at them.
+async fn import_payloads(
+    metadata: &dyn MetadataStore,
+    extents: &dyn ExtentStore,
+    mut state: MetadataState,
+    payloads: HashMap<String, Vec<u8>>,
+    written: &mut HashMap<String, ExtentChunk>,
+) -> StorageResult<()> {
+    for (name, data) in payloads {
+        let chunk = extents.write(Bytes::from(data)).await?;
+        written.insert(name, chunk);
+    }
+
+    let mut missing = None;
+    for_each_chunk(&mut state, |chunk| match written.get(&chunk.id) {
+        Some(stored) => *chunk = stored.clone(),
+        None => missing = Some(chunk.id.clone()),
+    });
+    if let Some(name) = missing {
+        return Err(invalid_archive(format!("missing extent payload '{}'", name)));
+    }
+
+    // The chunks of the records the import replaces, freed once it is done
+    let mut replaced = Vec::new();
+    for blob in &state.blobs {
+        if let Ok(existing) = metadata.get_blob(&blob.account, &blob.container, &blob.name, &blob.snapshot).await {
+            replaced.extend(existing.extent_chunks);
+        }
+    }
+    for block in &state.blocks {
+        if let Ok(existing) = metadata
+            .get_staged_block(&block.account, &block.container, &block.blob, &block.block_id)
+            .await
+        {
+            replaced.push(existing.extent_chunk);
+        }
+    }
+
+    metadata.import_state(state).await?;
+    release_extents(metadata, extents, &replaced).await;
+    Ok(())
+}
+
+fn read_entry<R: Read>(entry: std::io::Result<tar::Entry<R>>) -> StorageResult<(String, Vec<u8>)> {
+    let mut entry = entry.map_err(archive_io_error)?;
+    let path = entry.path().map_err(archive_io_error)?.to_string_lossy().into_owned();
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).map_err(archive_io_error)?;
+    Ok((path, data))
+}
+
+/// Parses the metadata entry of an archive with the given version,
+/// migrating older layouts to the current models.
+fn decode_metadata(version: u32, data: &[u8]) -> StorageResult<MetadataState> {
+    match version {
+        1 => serde_json::from_slice(data)
+            .map_err(|e| invalid_archive(format!("unreadable metadata: {}", e))),
+        _ => Err(unsupported_version(version)),
+    }
+}
+
+fn unsupported_version(version: u32) -> StorageError {
+    invalid_archive(format!(
+        "unsupported version {} (this build reads up to {})",
+        version, ARCHIVE_VERSION
+    ))
+}
+
+/// Calls `f` on every extent chunk referenced by blobs, page ranges and
+/// staged blocks.
+fn for_each_chunk(state: &mut MetadataState, mut f: impl FnMut(&mut ExtentChunk)) {
+    for blob in &mut state.blobs {
+        blob.extent_chunks.iter_mut().for_each(&mut f);
+        blob.page_ranges
+            .iter_mut()
+            .filter_map(|range| range.extent_chunk.as_mut())
+            .for_each(&mut f);
+    }
+    for block in &mut state.blocks {
+        f(&mut block.extent_chunk);
+    }
+}
+
+fn append_entry<W: Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) -> StorageResult<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data).map_err(archive_io_error)
+}
+
+fn to_json<T: Serialize>(value: &T) -> StorageResult<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|e| {
+        StorageError::with_message(ErrorCode::InternalError, format!("Failed to encode state: {}", e))
+    })
+}
+
+fn archive_io_error(e: std::io::Error) -> StorageError {
+    StorageError::with_message(ErrorCode::InternalError, format!("State archive I/O failed: {}", e))
+}
+
+fn invalid_archive(reason: impl std::fmt::Display) -> StorageError {
+    StorageError::with_message(ErrorCode::InvalidInput, format!("Invalid state archive: {}", reason))
+}
@@ -0,0 +1,1096 @@
+//! Conformance suites for [`MetadataStore`] and [`ExtentStore`]
+//! implementations.
+//!
+//! A backend plugged in with [`BlobServerBuilder::metadata`] or
+//! [`BlobServerBuilder::extents`] must behave like the memory stores the
+//! handlers are written against. These suites check the contracts documented
+//! on the traits and panic, naming the broken case, on the first deviation:
+//!
+//! ```no_run
+//! use azurite_rs::storage::conformance;
+//! use azurite_rs::{MemoryExtentStore, MemoryMetadataStore};
+//!
+//! # async fn example() {
+//! conformance::run_metadata_store_tests(&MemoryMetadataStore::new()).await;
+//! conformance::run_extent_store_tests(&MemoryExtentStore::new()).await;
+//! # }
+//! ```
+//!
+//! The metadata suite keeps to accounts named `conformance-*`. The extent
+//! suite checks [`ExtentStore::stats`] by difference, so the store must not
+//! be written to by anything else while it runs.
+//!
+//! [`BlobServerBuilder::metadata`]: crate::BlobServerBuilder::metadata
+//! [`BlobServerBuilder::extents`]: crate::BlobServerBuilder::extents
+
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::models::{BlobModel, BlobType, BlockModel, ContainerModel, ExtentChunk, LeaseState};
+use crate::storage::{AccountStats, ContainerStats, ExtentStore, MetadataStats, MetadataStore};
+
+/// Runs every metadata store case against `store`.
+pub async fn run_metadata_store_tests(store: &dyn MetadataStore) {
+    containers(store).await;
+    container_listing(store).await;
+    container_deletion(store).await;
+    blobs(store).await;
+    modify_blob(store).await;
+    blob_listing(store).await;
+    staged_blocks(store).await;
+    extent_references(store).await;
+    stats(store).await;
+    service_properties(store).await;
+    maintenance_state(store).await;
+    state_round_trip(store).await;
+}
+
+/// Runs every extent store case against `store`.
+pub async fn run_extent_store_tests(store: &dyn ExtentStore) {
+    extent_round_trip(store).await;
+    extent_sub_chunks(store).await;
+    extent_boundary_reads(store).await;
+    extent_small_writes(store).await;
+    extent_out_of_range(store).await;
+    extent_deletion(store).await;
+}
+
+/// Returns the error code of a result that must have failed.
+fn error_code<T>(result: StorageResult<T>, case: &str) -> ErrorCode {
+    match result {
+        Ok(_) => panic!("{}: expected an error, the call succeeded", case),
+        Err(e) => e.code,
+    }
+}
+
+fn timestamp(second: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap()
+}
+
+fn container(account: &str, name: &str) -> ContainerModel {
+    ContainerModel::new(account.to_string(), name.to_string(), timestamp(0))
+}
+
+fn blob(account: &str, container: &str, name: &str, len: u64) -> BlobModel {
+    BlobModel::new(
+        account.to_string(),
+        container.to_string(),
+        name.to_string(),
+        BlobType::BlockBlob,
+        len,
+        timestamp(0),
+    )
+}
+
+fn block(account: &str, container: &str, blob: &str, block_id: &str, extent_id: &str) -> BlockModel {
+    let chunk = ExtentChunk::new(extent_id.to_string(), 0, 4);
+    BlockModel::new(
+        account.to_string(),
+        container.to_string(),
+        blob.to_string(),
+        block_id.to_string(),
+        4,
+        chunk,
+        timestamp(0),
+    )
+}
+
+async fn create_container(store: &dyn MetadataStore, account: &str, name: &str) {
+    store
+        .create_container(container(account, name))
+        .await
+        .unwrap_or_else(|e| panic!("creating container {}/{} failed: {}", account, name, e));
+}
+
+async fn container_names(
+    store: &dyn MetadataStore,
+    account: &str,
+    prefix: Option<&str>,
+    marker: Option<&str>,
+    maxresults: Option<u32>,
+    include_deleted: bool,
+    include_system: bool,
+) -> (Vec<String>, Option<String>) {
+    let (containers, next_marker) = store
+        .list_containers(account, prefix, marker, maxresults, include_deleted, include_system)
+        .await
+        .expect("list_containers failed");
+    (containers.into_iter().map(|c| c.name).collect(), next_marker)
+}
+
+async fn containers(store: &dyn MetadataStore) {
+    let account = "conformance-containers";
+    create_container(store, account, "alpha").await;
+    assert_eq!(
+        error_code(store.create_container(container(account, "alpha")).await, "create_container twice"),
+        ErrorCode::ContainerAlreadyExists,
+        "create_container: a second create of a name must fail with ContainerAlreadyExists"
+    );
+
+    let stored = store.get_container(account, "alpha").await.expect("get_container failed");
+    assert_eq!((stored.account.as_str(), stored.name.as_str()), (account, "alpha"));
+    assert!(store.container_exists(account, "alpha").await, "container_exists: a created container exists");
+    assert!(!store.container_exists(account, "missing").await, "container_exists: an unknown container does not exist");
+    assert!(
+        !store.container_exists("conformance-other", "alpha").await,
+        "container_exists: containers belong to one account"
+    );
+
+    let mut updated = stored;
+    updated.metadata.insert("key".to_string(), "value".to_string());
+    store.update_container(updated).await.expect("update_container failed");
+    let stored = store.get_container(account, "alpha").await.expect("get_container failed");
+    assert_eq!(
+        stored.metadata.get("key"),
+        Some("value"),
+        "update_container: the update must be stored"
+    );
+
+    assert_eq!(
+        error_code(store.get_container(account, "missing").await, "get_container"),
+        ErrorCode::ContainerNotFound,
+        "get_container: an unknown container fails with ContainerNotFound"
+    );
+    assert_eq!(
+        error_code(store.update_container(container(account, "missing")).await, "update_container"),
+        ErrorCode::ContainerNotFound,
+        "update_container: an unknown container fails with ContainerNotFound"
+    );
+    assert_eq!(
+        error_code(store.delete_container(account, "missing", None).await, "delete_container"),
+        ErrorCode::ContainerNotFound,
+        "delete_container: an unknown container fails with ContainerNotFound"
+    );
+}
+
+async fn container_listing(store: &dyn MetadataStore) {
+    let account = "conformance-container-listing";
+    for name in ["delta", "alpha", "gamma", "beta", "$logs"] {
+        create_container(store, account, name).await;
+    }
+    let mut deleted = container(account, "epsilon");
+    store.create_container(deleted.clone()).await.expect("create_container failed");
+    deleted.deleted = true;
+    store.update_container(deleted).await.expect("update_container failed");
+
+    let (names, next_marker) = container_names(store, account, None, None, None, false, false).await;
+    assert_eq!(
+        names,
+        ["alpha", "beta", "delta", "gamma"],
+        "list_containers: containers are listed in name order without system or soft-deleted ones"
+    );
+    assert_eq!(next_marker, None, "list_containers: a complete listing has no marker");
+
+    let (names, _) = container_names(store, account, None, None, None, true, true).await;
+    assert_eq!(
+        names,
+        ["$logs", "alpha", "beta", "delta", "epsilon", "gamma"],
+        "list_containers: system and soft-deleted containers are listed when asked for"
+    );
+    assert!(
+        !store.container_exists(account, "epsilon").await,
+        "container_exists: a soft-deleted container does not exist"
+    );
+
+    let (names, next_marker) = container_names(store, account, None, None, Some(2), false, false).await;
+    assert_eq!(names, ["alpha", "beta"], "list_containers: maxresults limits the page");
+    assert_eq!(
+        next_marker.as_deref(),
+        Some("beta"),
+        "list_containers: the marker of a partial page is the last name listed"
+    );
+    let (names, next_marker) = container_names(store, account, None, next_marker.as_deref(), Some(2), false, false).await;
+    assert_eq!(names, ["delta", "gamma"], "list_containers: the marker is exclusive");
+    assert_eq!(next_marker, None, "list_containers: the last page has no marker");
+
+    let (names, _) = container_names(store, account, None, Some("b"), None, false, false).await;
+    assert_eq!(names, ["beta", "delta", "gamma"], "list_containers: a marker need not be a listed name");
+    let (names, _) = container_names(store, account, Some("g"), None, None, false, false).await;
+    assert_eq!(names, ["gamma"], "list_containers: the prefix filters names");
+}
+
+async fn container_deletion(store: &dyn MetadataStore) {
+    let account = "conformance-container-deletion";
+    create_container(store, account, "doomed").await;
+    create_container(store, account, "neighbour").await;
+
+    let mut doomed_blob = blob(account, "doomed", "blob", 4);
+    doomed_blob.extent_chunks = vec![
+        ExtentChunk::new("conformance-deletion-private".to_string(), 0, 2),
+        ExtentChunk::new("conformance-deletion-shared".to_string(), 0, 2),
+    ];
+    store.create_blob(doomed_blob).await.expect("create_blob failed");
+    store
+        .stage_block(block(account, "doomed", "blob", "block", "conformance-deletion-block"))
+        .await
+        .expect("stage_block failed");
+    let mut neighbour_blob = blob(account, "neighbour", "blob", 2);
+    neighbour_blob.extent_chunks = vec![ExtentChunk::new("conformance-deletion-shared".to_string(), 0, 2)];
+    store.create_blob(neighbour_blob).await.expect("create_blob failed");
+
+    let mut freed = store.delete_container(account, "doomed", None).await.expect("delete_container failed");
+    freed.sort();
+    assert_eq!(
+        freed,
+        ["conformance-deletion-block", "conformance-deletion-private"],
+        "delete_container: returns the extents of its blobs and blocks that nothing else references"
+    );
+    assert_eq!(
+        error_code(store.get_container(account, "doomed").await, "get_container after delete"),
+        ErrorCode::ContainerNotFound,
+        "delete_container: the container is gone"
+    );
+    assert!(
+        store.blob_exists(account, "neighbour", "blob", "").await,
+        "delete_container: blobs of other containers are kept"
+    );
+
+    create_container(store, account, "doomed").await;
+    let (blobs, _, _) = store
+        .list_blobs(account, "doomed", None, None, None, None, true, true)
+        .await
+        .expect("list_blobs failed");
+    assert!(blobs.is_empty(), "create_container: a recreated container starts empty");
+    assert_eq!(
+        error_code(store.get_staged_block(account, "doomed", "blob", "block").await, "get_staged_block"),
+        ErrorCode::InvalidBlockId,
+        "create_container: a recreated container has no staged blocks"
+    );
+
+    // A container still being deleted reserves its name and takes no writes
+    create_container(store, account, "deleting").await;
+    store.create_blob(blob(account, "deleting", "blob", 0)).await.expect("create_blob failed");
+    let until = Utc::now() + chrono::Duration::hours(1);
+    store
+        .delete_container(account, "deleting", Some(until))
+        .await
+        .expect("delete_container failed");
+    let reserved = store.get_container(account, "deleting").await.expect("get_container failed");
+    assert_eq!(
+        reserved.deleting_until,
+        Some(until),
+        "delete_container: with reserve_until the record is left being deleted"
+    );
+    assert!(
+        !store.blob_exists(account, "deleting", "blob", "").await,
+        "delete_container: a reserved container's blobs are deleted"
+    );
+    assert_eq!(
+        error_code(store.create_container(container(account, "deleting")).await, "create_container"),
+        ErrorCode::ContainerBeingDeleted,
+        "create_container: a name being deleted fails with ContainerBeingDeleted"
+    );
+    assert!(
+        !store.container_exists(account, "deleting").await,
+        "container_exists: a container being deleted does not exist"
+    );
+    let (names, _) = container_names(store, account, None, None, None, true, true).await;
+    assert!(
+        !names.iter().any(|name| name == "deleting"),
+        "list_containers: containers being deleted are never listed"
+    );
+    assert_eq!(
+        error_code(store.create_blob(blob(account, "deleting", "blob", 0)).await, "create_blob"),
+        ErrorCode::ContainerNotFound,
+        "create_blob: a container being deleted fails with ContainerNotFound"
+    );
+    assert_eq!(
+        error_code(store.stage_block(block(account, "deleting", "blob", "block", "x")).await, "stage_block"),
+        ErrorCode::ContainerNotFound,
+        "stage_block: a container being deleted fails with ContainerNotFound"
+    );
+    store.delete_container(account, "deleting", None).await.expect("delete_container failed");
+}
+
+async fn blobs(store: &dyn MetadataStore) {
+    let account = "conformance-blobs";
+    assert_eq!(
+        error_code(store.create_blob(blob(account, "missing", "blob", 0)).await, "create_blob"),
+        ErrorCode::ContainerNotFound,
+        "create_blob: an unknown container fails with ContainerNotFound"
+    );
+    assert_eq!(
+        error_code(store.get_blob(account, "missing", "blob", "").await, "get_blob"),
+        ErrorCode::ContainerNotFound,
+        "get_blob: an unknown container fails with ContainerNotFound"
+    );
+    assert_eq!(
+        error_code(store.update_blob(blob(account, "missing", "blob", 0)).await, "update_blob"),
+        ErrorCode::ContainerNotFound,
+        "update_blob: an unknown container fails with ContainerNotFound"
+    );
+
+    create_container(store, account, "blobs").await;
+    let base = blob(account, "blobs", "dir/blob", 3);
+    store.create_blob(base.clone()).await.expect("create_blob failed");
+    let stored = store.get_blob(account, "blobs", "dir/blob", "").await.expect("get_blob failed");
+    assert_eq!(stored.name, "dir/blob", "get_blob: returns the stored blob");
+    assert_eq!(stored.properties.content_length, 3, "get_blob: returns the stored properties");
+    assert!(store.blob_exists(account, "blobs", "dir/blob", "").await, "blob_exists: a created blob exists");
+    assert_eq!(
+        error_code(store.get_blob(account, "blobs", "missing", "").await, "get_blob"),
+        ErrorCode::BlobNotFound,
+        "get_blob: an unknown blob fails with BlobNotFound"
+    );
+
+    // Snapshots are keyed by their timestamp next to the base blob
+    let snapshot = base.create_snapshot(timestamp(1));
+    store.create_blob(snapshot.clone()).await.expect("create_blob of a snapshot failed");
+    let stored = store
+        .get_blob(account, "blobs", "dir/blob", &snapshot.snapshot)
+        .await
+        .expect("get_blob of a snapshot failed");
+    assert_eq!(stored.snapshot, snapshot.snapshot, "get_blob: a snapshot is addressed by its timestamp");
+    assert!(
+        store.blob_exists(account, "blobs", "dir/blob", &snapshot.snapshot).await,
+        "blob_exists: a created snapshot exists"
+    );
+    let other = base.create_snapshot(timestamp(2)).snapshot;
+    assert_eq!(
+        error_code(store.get_blob(account, "blobs", "dir/blob", &other).await, "get_blob"),
+        ErrorCode::BlobNotFound,
+        "get_blob: an unknown snapshot fails with BlobNotFound"
+    );
+
+    let mut updated = base.clone();
+    updated.metadata.insert("key".to_string(), "value".to_string());
+    store.update_blob(updated).await.expect("update_blob failed");
+    let stored = store.get_blob(account, "blobs", "dir/blob", "").await.expect("get_blob failed");
+    assert_eq!(stored.metadata.get("key"), Some("value"), "update_blob: the update is stored");
+    let stored = store
+        .get_blob(account, "blobs", "dir/blob", &snapshot.snapshot)
+        .await
+        .expect("get_blob of a snapshot failed");
+    assert!(stored.metadata.is_empty(), "update_blob: updating a blob leaves its snapshots alone");
+
+    // Soft-deleted blobs are kept but cannot be read
+    let mut deleted = blob(account, "blobs", "deleted", 0);
+    deleted.deleted = true;
+    store.create_blob(deleted).await.expect("create_blob failed");
+    assert_eq!(
+        error_code(store.get_blob(account, "blobs", "deleted", "").await, "get_blob"),
+        ErrorCode::BlobNotFound,
+        "get_blob: a soft-deleted blob fails with BlobNotFound"
+    );
+    assert!(
+        !store.blob_exists(account, "blobs", "deleted", "").await,
+        "blob_exists: a soft-deleted blob does not exist"
+    );
+
+    store
+        .delete_blob(account, "blobs", "dir/blob", &snapshot.snapshot)
+        .await
+        .expect("delete_blob of a snapshot failed");
+    assert!(
+        !store.blob_exists(account, "blobs", "dir/blob", &snapshot.snapshot).await,
+        "delete_blob: the snapshot is gone"
+    );
+    assert!(
+        store.blob_exists(account, "blobs", "dir/blob", "").await,
+        "delete_blob: deleting a snapshot keeps the base blob"
+    );
+    store.delete_blob(account, "blobs", "dir/blob", "").await.expect("delete_blob failed");
+    assert_eq!(
+        error_code(store.get_blob(account, "blobs", "dir/blob", "").await, "get_blob after delete"),
+        ErrorCode::BlobNotFound,
+        "delete_blob: the blob is gone"
+    );
+    assert_eq!(
+        error_code(store.delete_blob(account, "blobs", "dir/blob", "").await, "delete_blob twice"),
+        ErrorCode::BlobNotFound,
+        "delete_blob: an unknown blob fails with BlobNotFound"
+    );
+    let (blobs, _, _) = store
+        .list_blobs(account, "blobs", None, None, None, None, true, false)
+        .await
+        .expect("list_blobs failed");
+    assert!(blobs.is_empty(), "delete_blob: a deleted blob is no longer listed");
+}
+
+async fn modify_blob(store: &dyn MetadataStore) {
+    let account = "conformance-modify";
+    create_container(store, account, "blobs").await;
+    let base = blob(account, "blobs", "blob", 1);
+    let snapshot = base.create_snapshot(timestamp(1));
+    store.create_blob(base).await.expect("create_blob failed");
+    store.create_blob(snapshot.clone()).await.expect("create_blob failed");
+
+    let returned = store
+        .modify_blob(account, "blobs", "blob", "", &mut |blob| {
+            blob.metadata.insert("key".to_string(), "value".to_string());
+            blob.properties.content_length = 5;
+            Ok(())
+        })
+        .await
+        .expect("modify_blob failed");
+    assert_eq!(returned.properties.content_length, 5, "modify_blob: returns the updated blob");
+    let stored = store.get_blob(account, "blobs", "blob", "").await.expect("get_blob failed");
+    assert_eq!(stored.metadata.get("key"), Some("value"), "modify_blob: stores the update");
+    assert_eq!(stored.properties.content_length, 5, "modify_blob: stores the update");
+
+    let result = store
+        .modify_blob(account, "blobs", "blob", "", &mut |blob| {
+            blob.metadata.clear();
+            Err(StorageError::new(ErrorCode::LeaseIdMismatchWithBlobOperation))
+        })
+        .await;
+    assert_eq!(
+        error_code(result, "modify_blob"),
+        ErrorCode::LeaseIdMismatchWithBlobOperation,
+        "modify_blob: returns the error of a failed update"
+    );
+    let stored = store.get_blob(account, "blobs", "blob", "").await.expect("get_blob failed");
+    assert!(!stored.metadata.is_empty(), "modify_blob: a failed update is not stored");
+
+    store
+        .modify_blob(account, "blobs", "blob", &snapshot.snapshot, &mut |blob| {
+            blob.metadata.insert("snapshot".to_string(), "true".to_string());
+            Ok(())
+        })
+        .await
+        .expect("modify_blob of a snapshot failed");
+    let stored = store.get_blob(account, "blobs", "blob", "").await.expect("get_blob failed");
+    assert!(!stored.metadata.contains_key("snapshot"), "modify_blob: a snapshot is modified on its own");
+
+    let result = store.modify_blob(account, "blobs", "missing", "", &mut |_| Ok(())).await;
+    assert_eq!(
+        error_code(result, "modify_blob"),
+        ErrorCode::BlobNotFound,
+        "modify_blob: an unknown blob fails with BlobNotFound"
+    );
+    let result = store.modify_blob(account, "missing", "blob", "", &mut |_| Ok(())).await;
+    assert_eq!(
+        error_code(result, "modify_blob"),
+        ErrorCode::ContainerNotFound,
+        "modify_blob: an unknown container fails with ContainerNotFound"
+    );
+}
+
+/// Lists a container page by page and returns every entry, blobs as
+/// `name` or `name@snapshot` and virtual directories by their prefix.
+async fn list_all_blobs(
+    store: &dyn MetadataStore,
+    account: &str,
+    prefix: Option<&str>,
+    delimiter: Option<&str>,
+    maxresults: Option<u32>,
+    include_snapshots: bool,
+) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut marker: Option<String> = None;
+    for _ in 0..100 {
+        let (blobs, prefixes, next_marker) = store
+            .list_blobs(account, "listing", prefix, delimiter, marker.as_deref(), maxresults, include_snapshots, false)
+            .await
+            .expect("list_blobs failed");
+        let page = blobs.len() + prefixes.len();
+        if let Some(max) = maxresults {
+            assert!(page <= max as usize, "list_blobs: a page holds at most maxresults entries");
+            if next_marker.is_some() {
+                assert_eq!(page, max as usize, "list_blobs: only the last page may be short");
+            }
+        }
+
+        let mut page_entries: Vec<String> = blobs
+            .into_iter()
+            .map(|blob| match blob.snapshot.as_str() {
+                "" => blob.name,
+                snapshot => format!("{}@{}", blob.name, snapshot),
+            })
+            .chain(prefixes)
+            .collect();
+        // Blobs and prefixes come back apart; a page of one needs no merging
+        if maxresults != Some(1) {
+            page_entries.sort_by(|a, b| list_order(a).cmp(&list_order(b)));
+        }
+        entries.extend(page_entries);
+
+        match next_marker {
+            Some(next) => {
+                assert!(!next.is_empty(), "list_blobs: a continuation marker is not empty");
+                marker = Some(next);
+            }
+            None => return entries,
+        }
+    }
+    panic!("list_blobs: the listing did not end after 100 pages");
+}
+
+/// Sort key of a [`list_all_blobs`] entry: by name, a blob's snapshots
+/// before the blob itself.
+fn list_order(entry: &str) -> (&str, bool, &str) {
+    match entry.split_once('@') {
+        Some((name, snapshot)) => (name, false, snapshot),
+        None => (entry, true, ""),
+    }
+}
+
+async fn blob_listing(store: &dyn MetadataStore) {
+    let account = "conformance-blob-listing";
+    assert_eq!(
+        error_code(store.list_blobs(account, "listing", None, None, None, None, false, false).await, "list_blobs"),
+        ErrorCode::ContainerNotFound,
+        "list_blobs: an unknown container fails with ContainerNotFound"
+    );
+    create_container(store, account, "listing").await;
+    for name in ["e", "dir/y", "b", "a", "dir/x", "c"] {
+        store.create_blob(blob(account, "listing", name, 1)).await.expect("create_blob failed");
+    }
+    let base = blob(account, "listing", "b", 1);
+    // Stored newest first, listed oldest first
+    let newer = base.create_snapshot(timestamp(2));
+    let older = base.create_snapshot(timestamp(1));
+    store.create_blob(newer.clone()).await.expect("create_blob failed");
+    store.create_blob(older.clone()).await.expect("create_blob failed");
+    let older_entry = format!("b@{}", older.snapshot);
+    let newer_entry = format!("b@{}", newer.snapshot);
+
+    let expected = vec![
+        "a".to_string(),
+        older_entry.clone(),
+        newer_entry.clone(),
+        "b".to_string(),
+        "c".to_string(),
+        "dir/x".to_string(),
+        "dir/y".to_string(),
+        "e".to_string(),
+    ];
+    assert_eq!(
+        list_all_blobs(store, account, None, None, None, true).await,
+        expected,
+        "list_blobs: blobs are listed by name, each blob's snapshots oldest first before it"
+    );
+    for maxresults in [1, 2, 3] {
+        assert_eq!(
+            list_all_blobs(store, account, None, None, Some(maxresults), true).await,
+            expected,
+            "list_blobs: paging by {} with markers lists every entry once, snapshots included",
+            maxresults
+        );
+    }
+
+    // A marker resuming inside a blob's snapshots
+    let (blobs, _, next_marker) = store
+        .list_blobs(account, "listing", None, None, None, Some(2), true, false)
+        .await
+        .expect("list_blobs failed");
+    assert_eq!(blobs.len(), 2);
+    let next_marker = next_marker.expect("list_blobs: a partial page has a marker");
+    let (blobs, _, _) = store
+        .list_blobs(account, "listing", None, None, Some(&next_marker), Some(1), true, false)
+        .await
+        .expect("list_blobs failed");
+    assert_eq!(
+        (blobs[0].name.as_str(), blobs[0].snapshot.as_str()),
+        ("b", newer.snapshot.as_str()),
+        "list_blobs: the marker after a snapshot resumes at the next snapshot of the blob"
+    );
+
+    assert_eq!(
+        list_all_blobs(store, account, None, None, Some(1), false).await,
+        ["a", "b", "c", "dir/x", "dir/y", "e"],
+        "list_blobs: snapshots are only listed when asked for"
+    );
+    assert_eq!(
+        list_all_blobs(store, account, Some("d"), None, None, false).await,
+        ["dir/x", "dir/y"],
+        "list_blobs: the prefix filters names"
+    );
+
+    let hierarchy = ["a", "b", "c", "dir/", "e"];
+    assert_eq!(
+        list_all_blobs(store, account, None, Some("/"), None, false).await,
+        hierarchy,
+        "list_blobs: blobs under the delimiter collapse into one virtual directory"
+    );
+    assert_eq!(
+        list_all_blobs(store, account, None, Some("/"), Some(1), false).await,
+        hierarchy,
+        "list_blobs: a virtual directory counts against maxresults and is listed once across pages"
+    );
+    assert_eq!(
+        list_all_blobs(store, account, Some("dir/"), Some("/"), Some(1), false).await,
+        ["dir/x", "dir/y"],
+        "list_blobs: the delimiter is searched for after the prefix"
+    );
+}
+
+async fn staged_blocks(store: &dyn MetadataStore) {
+    let account = "conformance-blocks";
+    assert_eq!(
+        error_code(store.stage_block(block(account, "missing", "blob", "b1", "x")).await, "stage_block"),
+        ErrorCode::ContainerNotFound,
+        "stage_block: an unknown container fails with ContainerNotFound"
+    );
+    create_container(store, account, "blocks").await;
+
+    let block_ids = |blocks: Vec<BlockModel>| blocks.into_iter().map(|b| b.block_id).collect::<Vec<_>>();
+    for id in ["b1", "b2", "b3"] {
+        store
+            .stage_block(block(account, "blocks", "blob", id, &format!("conformance-block-{}", id)))
+            .await
+            .expect("stage_block failed");
+    }
+    store
+        .stage_block(block(account, "blocks", "other", "b1", "conformance-block-other"))
+        .await
+        .expect("stage_block failed");
+
+    let staged = store.get_staged_blocks(account, "blocks", "blob").await.expect("get_staged_blocks failed");
+    assert!(
+        staged.windows(2).all(|pair| pair[0].sequence < pair[1].sequence),
+        "stage_block: the store numbers blocks in staging order"
+    );
+    assert_eq!(block_ids(staged), ["b1", "b2", "b3"], "get_staged_blocks: blocks come back in staging order");
+
+    // Restaging replaces the block and moves it to the end
+    store
+        .stage_block(block(account, "blocks", "blob", "b1", "conformance-block-b1-again"))
+        .await
+        .expect("stage_block failed");
+    let staged = store.get_staged_blocks(account, "blocks", "blob").await.expect("get_staged_blocks failed");
+    assert_eq!(block_ids(staged), ["b2", "b3", "b1"], "get_staged_blocks: a restaged block moves to the end");
+    let restaged = store.get_staged_block(account, "blocks", "blob", "b1").await.expect("get_staged_block failed");
+    assert_eq!(
+        restaged.extent_chunk.id, "conformance-block-b1-again",
+        "stage_block: restaging a block ID replaces the block"
+    );
+    assert_eq!(
+        error_code(store.get_staged_block(account, "blocks", "blob", "b9").await, "get_staged_block"),
+        ErrorCode::InvalidBlockId,
+        "get_staged_block: an unknown block fails with InvalidBlockId"
+    );
+
+    let mut freed: Vec<String> = store
+        .delete_staged_blocks(account, "blocks", "blob")
+        .await
+        .expect("delete_staged_blocks failed")
+        .into_iter()
+        .map(|chunk| chunk.id)
+        .collect();
+    freed.sort();
+    assert_eq!(
+        freed,
+        ["conformance-block-b1-again", "conformance-block-b2", "conformance-block-b3"],
+        "delete_staged_blocks: returns the chunks of the discarded blocks"
+    );
+    assert!(
+        store.get_staged_blocks(account, "blocks", "blob").await.expect("get_staged_blocks failed").is_empty(),
+        "delete_staged_blocks: no blocks remain"
+    );
+    assert_eq!(
+        error_code(store.get_staged_block(account, "blocks", "blob", "b2").await, "get_staged_block"),
+        ErrorCode::InvalidBlockId,
+        "delete_staged_blocks: a discarded block is gone"
+    );
+    assert_eq!(
+        block_ids(store.get_staged_blocks(account, "blocks", "other").await.expect("get_staged_blocks failed")),
+        ["b1"],
+        "delete_staged_blocks: blocks of other blobs are kept"
+    );
+    assert!(
+        store.delete_staged_blocks(account, "blocks", "blob").await.expect("delete_staged_blocks failed").is_empty(),
+        "delete_staged_blocks: a blob without blocks frees nothing"
+    );
+
+    store
+        .stage_block(block(account, "blocks", "blob", "b2", "conformance-block-b2"))
+        .await
+        .expect("stage_block failed");
+    assert_eq!(
+        block_ids(store.get_staged_blocks(account, "blocks", "blob").await.expect("get_staged_blocks failed")),
+        ["b2"],
+        "stage_block: a blob's blocks can be staged again after they were discarded"
+    );
+}
+
+async fn extent_references(store: &dyn MetadataStore) {
+    let account = "conformance-extent-references";
+    create_container(store, account, "refs").await;
+    let shared = "conformance-ref-shared";
+    let staged = "conformance-ref-staged";
+    assert!(!store.extent_in_use(shared).await, "extent_in_use: an unreferenced extent is not in use");
+
+    let mut base = blob(account, "refs", "blob", 4);
+    base.extent_chunks = vec![ExtentChunk::new(shared.to_string(), 0, 4)];
+    let snapshot = base.create_snapshot(timestamp(1));
+    store.create_blob(base).await.expect("create_blob failed");
+    store.create_blob(snapshot.clone()).await.expect("create_blob failed");
+    store.stage_block(block(account, "refs", "blob", "block", staged)).await.expect("stage_block failed");
+    assert!(store.extent_in_use(shared).await, "extent_in_use: a blob's extent is in use");
+    assert!(store.extent_in_use(staged).await, "extent_in_use: a staged block's extent is in use");
+
+    store.delete_blob(account, "refs", "blob", "").await.expect("delete_blob failed");
+    assert!(
+        store.extent_in_use(shared).await,
+        "extent_in_use: an extent shared with a snapshot stays in use after the blob is deleted"
+    );
+    store.delete_blob(account, "refs", "blob", &snapshot.snapshot).await.expect("delete_blob failed");
+    assert!(!store.extent_in_use(shared).await, "extent_in_use: an extent is free once its last reference is gone");
+
+    store.delete_staged_blocks(account, "refs", "blob").await.expect("delete_staged_blocks failed");
+    assert!(!store.extent_in_use(staged).await, "extent_in_use: a discarded block's extent is free");
+
+    // Replacing a blob's chunks moves its references
+    let replaced = "conformance-ref-replaced";
+    let replacement = "conformance-ref-replacement";
+    let mut blob_model = blob(account, "refs", "replaced", 4);
+    blob_model.extent_chunks = vec![ExtentChunk::new(replaced.to_string(), 0, 4)];
+    store.create_blob(blob_model.clone()).await.expect("create_blob failed");
+    blob_model.extent_chunks = vec![ExtentChunk::new(replacement.to_string(), 0, 4)];
+    store.update_blob(blob_model).await.expect("update_blob failed");
+    assert!(!store.extent_in_use(replaced).await, "update_blob: the replaced chunks are no longer referenced");
+    assert!(store.extent_in_use(replacement).await, "update_blob: the new chunks are referenced");
+}
+
+async fn stats(store: &dyn MetadataStore) {
+    let account = "conformance-stats";
+    assert_eq!(
+        store.container_stats(account, "stats").await,
+        ContainerStats::default(),
+        "container_stats: an unknown container has no blobs"
+    );
+    create_container(store, account, "stats").await;
+    let first = blob(account, "stats", "first", 10);
+    store.create_blob(first.clone()).await.expect("create_blob failed");
+    store.create_blob(blob(account, "stats", "second", 20)).await.expect("create_blob failed");
+    store.create_blob(first.create_snapshot(timestamp(1))).await.expect("create_blob failed");
+    store.stage_block(block(account, "stats", "third", "block", "conformance-stats-block")).await.expect("stage_block failed");
+
+    let account_stats = |stats: MetadataStats| stats.accounts.get(account).copied().unwrap_or_default();
+    assert_eq!(
+        account_stats(store.stats().await),
+        AccountStats { containers: 1, blobs: 2, snapshots: 1, staged_blocks: 1, bytes: 40 },
+        "stats: counts the account's containers, blobs, snapshots, blocks and content bytes"
+    );
+    let expected = ContainerStats { blobs: 2, bytes: 30 };
+    assert_eq!(
+        store.container_stats(account, "stats").await,
+        expected,
+        "container_stats: counts the container's base blobs and their bytes"
+    );
+    assert_eq!(
+        store.stats().await.containers.get(account).and_then(|containers| containers.get("stats")).copied(),
+        Some(expected),
+        "stats: reports the same container counts as container_stats"
+    );
+
+    store
+        .modify_blob(account, "stats", "second", "", &mut |blob| {
+            blob.properties.content_length = 25;
+            Ok(())
+        })
+        .await
+        .expect("modify_blob failed");
+    assert_eq!(
+        store.container_stats(account, "stats").await,
+        ContainerStats { blobs: 2, bytes: 35 },
+        "container_stats: follows a blob's length"
+    );
+    store.delete_blob(account, "stats", "first", "").await.expect("delete_blob failed");
+    assert_eq!(
+        store.container_stats(account, "stats").await,
+        ContainerStats { blobs: 1, bytes: 25 },
+        "container_stats: a deleted blob is no longer counted"
+    );
+
+    store.delete_container(account, "stats", None).await.expect("delete_container failed");
+    assert_eq!(
+        account_stats(store.stats().await),
+        AccountStats::default(),
+        "stats: deleting a container uncounts everything in it"
+    );
+    assert_eq!(
+        store.container_stats(account, "stats").await,
+        ContainerStats::default(),
+        "container_stats: a deleted container has no blobs"
+    );
+}
+
+async fn service_properties(store: &dyn MetadataStore) {
+    let account = "conformance-service";
+    let properties = store.get_service_properties(account).await.expect("get_service_properties failed");
+    assert_eq!(
+        properties.default_service_version, None,
+        "get_service_properties: an account reports the defaults before its properties are set"
+    );
+    assert!(properties.cors.is_empty(), "get_service_properties: the defaults have no CORS rules");
+
+    let mut properties = properties;
+    properties.default_service_version = Some("2021-08-06".to_string());
+    store.set_service_properties(account, properties).await.expect("set_service_properties failed");
+    let properties = store.get_service_properties(account).await.expect("get_service_properties failed");
+    assert_eq!(
+        properties.default_service_version.as_deref(),
+        Some("2021-08-06"),
+        "set_service_properties: the properties are stored"
+    );
+    let other = store.get_service_properties("conformance-service-other").await.expect("get_service_properties failed");
+    assert_eq!(other.default_service_version, None, "set_service_properties: properties belong to one account");
+}
+
+async fn maintenance_state(store: &dyn MetadataStore) {
+    let account = "conformance-maintenance";
+    create_container(store, account, "live").await;
+    let mut trash = container(account, "trash");
+    trash.deleted = true;
+    store.create_container(trash).await.expect("create_container failed");
+    store.create_blob(blob(account, "live", "kept", 4)).await.expect("create_blob failed");
+    let mut deleted = blob(account, "live", "deleted", 4);
+    deleted.deleted = true;
+    store.create_blob(deleted).await.expect("create_blob failed");
+    let mut leased = blob(account, "live", "leased", 4);
+    leased.properties.lease_state = LeaseState::Breaking;
+    store.create_blob(leased).await.expect("create_blob failed");
+    store.stage_block(block(account, "live", "next", "b1", "conformance-maintenance")).await.expect("stage_block failed");
+
+    let mut state = store.maintenance_state().await.expect("maintenance_state failed");
+    state.containers.retain(|c| c.account == account);
+    state.blobs.retain(|b| b.account == account);
+    state.blocks.retain(|b| b.account == account);
+    assert_eq!(
+        state.containers.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        ["trash"],
+        "maintenance_state: includes soft-deleted containers only"
+    );
+    let mut names: Vec<&str> = state.blobs.iter().map(|b| b.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["deleted", "leased"], "maintenance_state: includes soft-deleted and breaking blobs only");
+    assert_eq!(state.blocks.len(), 1, "maintenance_state: includes staged blocks");
+}
+
+async fn state_round_trip(store: &dyn MetadataStore) {
+    let account = "conformance-state";
+    create_container(store, account, "state").await;
+    let base = blob(account, "state", "blob", 4);
+    let snapshot = base.create_snapshot(timestamp(1));
+    store.create_blob(base).await.expect("create_blob failed");
+    store.create_blob(snapshot.clone()).await.expect("create_blob failed");
+    for id in ["b1", "b2", "b1"] {
+        store
+            .stage_block(block(account, "state", "next", id, &format!("conformance-state-{}", id)))
+            .await
+            .expect("stage_block failed");
+    }
+    let mut properties = store.get_service_properties(account).await.expect("get_service_properties failed");
+    properties.default_service_version = Some("2021-08-06".to_string());
+    store.set_service_properties(account, properties).await.expect("set_service_properties failed");
+
+    let mut state = store.export_state().await.expect("export_state failed");
+    state.containers.retain(|c| c.account == account);
+    state.blobs.retain(|b| b.account == account);
+    state.blocks.retain(|b| b.account == account);
+    state.service_properties.retain(|(a, _)| a == account);
+    assert_eq!(state.containers.len(), 1, "export_state: includes every container");
+    let mut snapshots: Vec<&str> = state.blobs.iter().map(|b| b.snapshot.as_str()).collect();
+    snapshots.sort();
+    assert_eq!(snapshots, ["", snapshot.snapshot.as_str()], "export_state: includes blobs and their snapshots");
+    assert_eq!(
+        state.blocks.iter().map(|b| b.block_id.as_str()).collect::<Vec<_>>(),
+        ["b2", "b1"],
+        "export_state: includes staged blocks in staging order"
+    );
+    assert_eq!(state.service_properties.len(), 1, "export_state: includes service properties");
+
+    store.delete_container(account, "state", None).await.expect("delete_container failed");
+    store.import_state(state).await.expect("import_state failed");
+    assert!(store.container_exists(account, "state").await, "import_state: restores containers");
+    assert!(
+        store.blob_exists(account, "state", "blob", &snapshot.snapshot).await,
+        "import_state: restores snapshots"
+    );
+    let blocks = store.get_staged_blocks(account, "state", "next").await.expect("get_staged_blocks failed");
+    assert_eq!(
+        blocks.iter().map(|b| b.block_id.as_str()).collect::<Vec<_>>(),
+        ["b2", "b1"],
+        "import_state: restores staged blocks in their order"
+    );
+}
+
+/// Bytes whose value at each offset differs from its neighbours', so a read
+/// from the wrong place cannot match.
+fn pattern(len: usize) -> Bytes {
+    (0..len).map(|i| (i % 251) as u8).collect::<Vec<u8>>().into()
+}
+
+async fn write(store: &dyn ExtentStore, data: Bytes) -> ExtentChunk {
+    store.write(data).await.unwrap_or_else(|e| panic!("write failed: {}", e))
+}
+
+async fn extent_round_trip(store: &dyn ExtentStore) {
+    let before = store.stats().await;
+    let total_before = store.total_size().await;
+    let chunk = write(store, Bytes::from_static(b"hello, extent")).await;
+    assert_eq!((chunk.offset, chunk.count), (0, 13), "write: the chunk covers the whole write");
+    assert_eq!(store.read(&chunk).await.expect("read failed"), "hello, extent", "read: returns the written bytes");
+    assert!(store.contains(&chunk.id).await, "contains: a written extent is held");
+    assert!(!store.contains("conformance-missing-extent").await, "contains: an unknown extent is not held");
+
+    let after = store.stats().await;
+    assert_eq!(after.extents, before.extents + 1, "stats: counts written extents");
+    assert_eq!(after.bytes, before.bytes + 13, "stats: counts written bytes");
+    assert_eq!(store.total_size().await, total_before + 13, "total_size: counts written bytes");
+
+    let second = write(store, Bytes::from_static(b"hello, extent")).await;
+    assert_ne!(second.id, chunk.id, "write: every write gets a new extent");
+
+    let empty = write(store, Bytes::new()).await;
+    assert_eq!(empty.count, 0, "write: an empty write gives an empty chunk");
+    assert!(store.read(&empty).await.expect("read of an empty extent failed").is_empty());
+
+    for id in [chunk.id, second.id, empty.id] {
+        store.delete(&id).await.expect("delete failed");
+    }
+}
+
+async fn extent_sub_chunks(store: &dyn ExtentStore) {
+    let data = pattern(10_000);
+    let chunk = write(store, data.clone()).await;
+
+    // Blobs reference parts of extents by offset and count
+    let part = ExtentChunk::new(chunk.id.clone(), 1_000, 5_000);
+    assert_eq!(store.read(&part).await.expect("read failed"), data.slice(1_000..6_000), "read: honours the chunk offset");
+    assert_eq!(
+        store.read_range(&part, 10, 20).await.expect("read_range failed"),
+        data.slice(1_010..1_030),
+        "read_range: the offset is relative to the chunk"
+    );
+    assert_eq!(
+        store.read_range(&part, 4_999, 1).await.expect("read_range failed"),
+        data.slice(5_999..6_000),
+        "read_range: reads the last byte of a chunk"
+    );
+    assert!(
+        store.read_range(&part, 0, 0).await.expect("read_range failed").is_empty(),
+        "read_range: an empty range reads nothing"
+    );
+    store.delete(&chunk.id).await.expect("delete failed");
+}
+
+async fn extent_boundary_reads(store: &dyn ExtentStore) {
+    // Spans several 64 KiB blocks and ends inside one
+    let len = 3 * 64 * 1024 + 1_234;
+    let data = pattern(len);
+    let chunk = write(store, data.clone()).await;
+    assert_eq!(store.read(&chunk).await.expect("read failed"), data, "read: returns every byte of a large write");
+
+    let block = 64 * 1024;
+    let ranges = [
+        (0, 1),
+        (block - 1, 1),
+        (block, 1),
+        (block - 1, 2),
+        (block - 10, block + 20),
+        (2 * block - 1, 2),
+        (1, 3 * block),
+        (3 * block, len - 3 * block),
+        (len - 1, 1),
+        (len, 0),
+    ];
+    for (offset, count) in ranges {
+        assert_eq!(
+            store.read_range(&chunk, offset as u64, count as u64).await.unwrap_or_else(|e| panic!(
+                "read_range({}, {}) failed: {}",
+                offset, count, e
+            )),
+            data.slice(offset..offset + count),
+            "read_range: returns the bytes at {}..{}",
+            offset,
+            offset + count
+        );
+    }
+
+    let part = ExtentChunk::new(chunk.id.clone(), block as u64 - 3, block as u64);
+    assert_eq!(
+        store.read_range(&part, 0, 6).await.expect("read_range failed"),
+        data.slice(block - 3..block + 3),
+        "read_range: a chunk starting before a block boundary reads across it"
+    );
+    store.delete(&chunk.id).await.expect("delete failed");
+}
+
+async fn extent_small_writes(store: &dyn ExtentStore) {
+    // Stores may pack small extents together; none may read another's bytes
+    let chunks = {
+        let mut chunks = Vec::new();
+        for i in 0..64u8 {
+            let data = Bytes::from(vec![i; i as usize + 1]);
+            chunks.push((write(store, data.clone()).await, data));
+        }
+        chunks
+    };
+    for (chunk, data) in &chunks {
+        assert_eq!(&store.read(chunk).await.expect("read failed"), data, "read: small extents are kept apart");
+        assert!(
+            store.read_range(chunk, 0, chunk.count + 1).await.is_err(),
+            "read_range: a range past a small extent fails instead of reading its neighbour"
+        );
+    }
+    for (chunk, _) in chunks {
+        store.delete(&chunk.id).await.expect("delete failed");
+    }
+}
+
+async fn extent_out_of_range(store: &dyn ExtentStore) {
+    let chunk = write(store, pattern(100)).await;
+    for (offset, count) in [(99, 2), (100, 1), (0, 101), (200, 10)] {
+        assert_eq!(
+            error_code(store.read_range(&chunk, offset, count).await, "read_range"),
+            ErrorCode::InternalError,
+            "read_range: {}..{} past the end of the extent fails with InternalError",
+            offset,
+            offset + count
+        );
+    }
+    let overlong = ExtentChunk::new(chunk.id.clone(), 50, 60);
+    assert_eq!(
+        error_code(store.read(&overlong).await, "read"),
+        ErrorCode::InternalError,
+        "read: a chunk reaching past the end of its extent fails with InternalError"
+    );
+
+    let missing_reads = store.stats().await.missing_reads;
+    let missing = ExtentChunk::new("conformance-missing-extent".to_string(), 0, 1);
+    assert_eq!(
+        error_code(store.read(&missing).await, "read"),
+        ErrorCode::InternalError,
+        "read: an unknown extent fails with InternalError"
+    );
+    assert_eq!(
+        store.stats().await.missing_reads,
+        missing_reads + 1,
+        "stats: counts reads of extents the store does not hold"
+    );
+    store.delete(&chunk.id).await.expect("delete failed");
+}
+
+async fn extent_deletion(store: &dyn ExtentStore) {
+    let before = store.stats().await;
+    let chunk = write(store, pattern(1_000)).await;
+    let kept = write(store, pattern(10)).await;
+
+    store.delete(&chunk.id).await.expect("delete failed");
+    assert!(!store.contains(&chunk.id).await, "delete: the extent is no longer held");
+    assert!(store.read(&chunk).await.is_err(), "delete: a deleted extent cannot be read");
+    assert_eq!(store.read(&kept).await.expect("read failed"), pattern(10), "delete: other extents are kept");
+    let after = store.stats().await;
+    assert_eq!(
+        (after.extents, after.bytes),
+        (before.extents + 1, before.bytes + 10),
+        "stats: a deleted extent is no longer counted"
+    );
+
+    store.delete(&chunk.id).await.expect("delete: deleting a deleted extent succeeds");
+    store
+        .delete("conformance-missing-extent")
+        .await
+        .expect("delete: deleting an unknown extent succeeds");
+    let again = store.stats().await;
+    assert_eq!(
+        (again.extents, again.bytes),
+        (after.extents, after.bytes),
+        "delete: deleting an extent twice changes nothing"
+    );
+    store.delete(&kept.id).await.expect("delete failed");
+}
@@ -1,28 +1,42 @@
 //! Extent store for blob data.
 
 use async_trait::async_trait;
-use bytes::Bytes;
+use axum::http::StatusCode;
+use bytes::{Bytes, BytesMut};
 use dashmap::DashMap;
+use parking_lot::Mutex;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use serde::Serialize;
 use uuid::Uuid;
 
 use crate::error::{ErrorCode, StorageError, StorageResult};
 use crate::models::ExtentChunk;
 
 /// Trait for extent (blob data) storage operations.
+///
+/// Extents are immutable once written. Blobs reference the whole or a part
+/// of an extent through an [`ExtentChunk`], so any byte range of an extent
+/// must be readable. [`conformance::run_extent_store_tests`] checks an
+/// implementation against these contracts.
+///
+/// [`conformance::run_extent_store_tests`]: crate::storage::conformance::run_extent_store_tests
 #[async_trait]
 pub trait ExtentStore: Send + Sync {
-    /// Writes data to the extent store and returns an ExtentChunk reference.
+    /// Writes data to a new extent and returns a chunk covering all of it,
+    /// at offset 0.
     async fn write(&self, data: Bytes) -> StorageResult<ExtentChunk>;
 
-    /// Reads data from the extent store.
+    /// Reads the bytes of a chunk, like `read_range(chunk, 0, chunk.count)`.
     async fn read(&self, chunk: &ExtentChunk) -> StorageResult<Bytes>;
 
-    /// Reads a range of data from the extent store.
+    /// Reads `count` bytes at `offset` within a chunk, that is at
+    /// `chunk.offset + offset` within its extent. Fails with `InternalError`
+    /// if the extent is unknown or the range reaches past its end; reads
+    /// never return fewer bytes than asked for.
     async fn read_range(
         &self,
         chunk: &ExtentChunk,
@@ -30,49 +44,197 @@ pub trait ExtentStore: Send + Sync {
         count: u64,
     ) -> StorageResult<Bytes>;
 
-    /// Deletes an extent from the store.
+    /// Deletes an extent. Deleting an unknown extent succeeds.
     async fn delete(&self, extent_id: &str) -> StorageResult<()>;
 
+    /// Returns whether the store holds the extent.
+    async fn contains(&self, extent_id: &str) -> bool;
+
     /// Returns the total size of all extents.
     async fn total_size(&self) -> u64;
+
+    /// Returns the number and total size of the stored extents.
+    async fn stats(&self) -> ExtentStats;
+}
+
+/// Extents held by an extent store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtentStats {
+    pub extents: u64,
+    pub bytes: u64,
+    /// Reads of extents the store does not hold, which means a blob or
+    /// block references data that is gone.
+    pub missing_reads: u64,
+}
+
+/// Error for a read of an extent the store does not hold.
+fn missing_extent(extent_id: &str, missing_reads: &AtomicU64) -> StorageError {
+    missing_reads.fetch_add(1, Ordering::Relaxed);
+    StorageError::with_message(ErrorCode::InternalError, format!("Extent {} does not exist.", extent_id))
 }
 
 /// Number of shards for the extent store (must be power of 2).
 const NUM_SHARDS: usize = 64;
 
+/// Bytes held by a [`MemoryExtentStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExtentUsage {
+    /// Bytes of extents held in memory.
+    pub memory: u64,
+    /// Bytes of extents spilled to disk.
+    pub spilled: u64,
+    /// The memory limit (0 = unlimited).
+    pub limit: u64,
+}
+
+/// Size of the segments a [`MemoryExtentStore`] packs small extents into.
+/// A store with a limit below four segments uses a quarter of its limit.
+pub const EXTENT_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// An extent held in memory, stamped with its last access for spilling.
+struct MemoryExtent {
+    data: Bytes,
+    last_used: AtomicU64,
+    /// The memory holding `data`: its own buffer or the segment it shares.
+    _charge: Arc<Charge>,
+}
+
+/// Memory counted against a [`MemoryExtentStore`]'s limit, given back when
+/// the last extent holding it is dropped.
+struct Charge {
+    bytes: u64,
+    resident: Arc<AtomicU64>,
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.resident.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Extents moved out of memory by a store in spill mode.
+struct SpillArea {
+    dir: PathBuf,
+    /// Sizes and CRC32s of the spilled extents.
+    extents: DashMap<Arc<str>, (u64, u32)>,
+    size: AtomicU64,
+    /// Serializes moving extents between memory and disk.
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl SpillArea {
+    fn path(&self, extent_id: &str) -> PathBuf {
+        self.dir.join(extent_id)
+    }
+}
+
+impl Drop for SpillArea {
+    fn drop(&mut self) {
+        for entry in self.extents.iter() {
+            std::fs::remove_file(self.path(entry.key())).ok();
+        }
+    }
+}
+
 /// Sharded in-memory implementation of the extent store.
 /// Uses multiple DashMaps to reduce lock contention.
+///
+/// Small writes, such as the 64 KiB blocks ClickHouse stages, are copied
+/// back to back into shared [`EXTENT_SEGMENT_SIZE`] segments instead of each
+/// keeping its own request buffer alive. Every extent keeps its own ID, so
+/// deletes stay exact; a segment is reference counted by the extents in it
+/// and freed with the last of them. Usage counts extent bytes, but the
+/// memory limit counts a segment in full for as long as any extent in it is
+/// held. A store in spill mode does not pool: each small extent gets a
+/// buffer of its own, so spilling it frees its memory.
+///
+/// A store created with [`MemoryExtentStore::with_limit`] rejects writes
+/// beyond its limit with `ServerBusy` and status 507; one created with
+/// [`MemoryExtentStore::with_spill`] moves the least recently used extents
+/// to disk instead and loads them back when read.
 pub struct MemoryExtentStore {
     /// Sharded extents - each shard handles a subset of extent IDs.
-    shards: Vec<DashMap<Arc<str>, Bytes>>,
-    /// Current total size in bytes.
+    shards: Vec<DashMap<Arc<str>, MemoryExtent>>,
+    /// Current size of the extents in memory, in bytes.
     current_size: AtomicU64,
+    /// Memory held by the extents in memory, segment capacity included.
+    resident: Arc<AtomicU64>,
     /// Maximum size limit (0 = unlimited).
     size_limit: u64,
+    /// Set in spill mode.
+    spill: Option<SpillArea>,
+    /// Access counter stamped on extents as they are used.
+    access_clock: AtomicU64,
+    /// Number of extents, in memory or spilled.
+    extent_count: AtomicU64,
+    /// Reads of extents the store does not hold.
+    missing_reads: AtomicU64,
+    /// Size of the segments small extents are packed into; a quarter of it
+    /// is the largest extent packed.
+    segment_size: usize,
+    /// Unused tail of the segment small extents are appended to.
+    segment: Mutex<Option<(BytesMut, Arc<Charge>)>>,
 }
 
 impl MemoryExtentStore {
     pub fn new() -> Self {
-        let shards = (0..NUM_SHARDS).map(|_| DashMap::new()).collect();
-        Self {
-            shards,
-            current_size: AtomicU64::new(0),
-            size_limit: 0,
-        }
+        Self::with_limit(0)
     }
 
+    /// Creates a store that rejects writes once `limit` bytes are held.
     pub fn with_limit(limit: u64) -> Self {
         let shards = (0..NUM_SHARDS).map(|_| DashMap::new()).collect();
         Self {
             shards,
             current_size: AtomicU64::new(0),
+            resident: Arc::new(AtomicU64::new(0)),
             size_limit: limit,
+            spill: None,
+            access_clock: AtomicU64::new(0),
+            extent_count: AtomicU64::new(0),
+            missing_reads: AtomicU64::new(0),
+            segment_size: match limit {
+                0 => EXTENT_SEGMENT_SIZE,
+                limit => EXTENT_SEGMENT_SIZE.min((limit / 4) as usize),
+            },
+            segment: Mutex::new(None),
+        }
+    }
+
+    /// Creates a store that keeps at most `limit` bytes in memory, spilling
+    /// the least recently used extents to files in `dir`.
+    pub fn with_spill(limit: u64, dir: impl Into<PathBuf>) -> StorageResult<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::InternalError,
+                format!("Failed to create extent spill directory: {}", e),
+            )
+        })?;
+        Ok(Self {
+            spill: Some(SpillArea {
+                dir,
+                extents: DashMap::new(),
+                size: AtomicU64::new(0),
+                lock: tokio::sync::Mutex::new(()),
+            }),
+            ..Self::with_limit(limit)
+        })
+    }
+
+    /// Returns how many bytes are held in memory and on disk.
+    pub fn usage(&self) -> ExtentUsage {
+        ExtentUsage {
+            memory: self.current_size.load(Ordering::Relaxed),
+            spilled: self.spill.as_ref().map_or(0, |spill| spill.size.load(Ordering::Relaxed)),
+            limit: self.size_limit,
         }
     }
 
     /// Get the shard for a given extent ID.
     #[inline]
-    fn get_shard(&self, extent_id: &str) -> &DashMap<Arc<str>, Bytes> {
+    fn get_shard(&self, extent_id: &str) -> &DashMap<Arc<str>, MemoryExtent> {
         // Use a simple hash of the first few bytes of the UUID
         let hash = extent_id
             .bytes()
@@ -80,6 +242,169 @@ impl MemoryExtentStore {
             .fold(0usize, |acc, b| acc.wrapping_mul(31).wrapping_add(b as usize));
         &self.shards[hash % NUM_SHARDS]
     }
+
+    fn tick(&self) -> u64 {
+        self.access_clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Counts `bytes` of memory as held. A store with a limit and no spill
+    /// area fails with `ServerBusy` and status 507 rather than go over it.
+    fn charge(&self, bytes: u64) -> StorageResult<Arc<Charge>> {
+        let enforced = self.size_limit > 0 && self.spill.is_none();
+        self.resident
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |resident| {
+                let total = resident + bytes;
+                (!enforced || total <= self.size_limit).then_some(total)
+            })
+            .map_err(|_| {
+                StorageError::with_message(
+                    ErrorCode::ServerBusy,
+                    format!(
+                        "The in-memory extent store is over its limit of {} bytes.",
+                        self.size_limit
+                    ),
+                )
+                .with_status(StatusCode::INSUFFICIENT_STORAGE)
+            })?;
+        Ok(Arc::new(Charge {
+            bytes,
+            resident: self.resident.clone(),
+        }))
+    }
+
+    /// Copies a small extent into the current segment, starting a new one
+    /// when it does not fit. The returned buffer shares the segment. In spill
+    /// mode, or when a new segment would go over the limit, the extent is
+    /// copied into a buffer of its own instead.
+    fn pooled(&self, data: Bytes) -> StorageResult<(Bytes, Arc<Charge>)> {
+        if data.is_empty() || data.len() > self.segment_size / 4 {
+            let charge = self.charge(data.len() as u64)?;
+            return Ok((data, charge));
+        }
+        if self.spill.is_none() {
+            let mut segment = self.segment.lock();
+            if !matches!(&*segment, Some((tail, _)) if tail.capacity() >= data.len()) {
+                // A segment counts in full while any extent in it is held
+                *segment = self
+                    .charge(self.segment_size as u64)
+                    .ok()
+                    .map(|charge| (BytesMut::with_capacity(self.segment_size), charge));
+            }
+            if let Some((tail, charge)) = segment.as_mut() {
+                tail.extend_from_slice(&data);
+                return Ok((tail.split().freeze(), charge.clone()));
+            }
+        }
+        let charge = self.charge(data.len() as u64)?;
+        Ok((Bytes::copy_from_slice(&data), charge))
+    }
+
+    fn insert(&self, extent_id: Arc<str>, data: Bytes) -> StorageResult<()> {
+        let size = data.len() as u64;
+        let (data, charge) = self.pooled(data)?;
+        let extent = MemoryExtent {
+            data,
+            last_used: AtomicU64::new(self.tick()),
+            _charge: charge,
+        };
+        self.get_shard(&extent_id).insert(extent_id, extent);
+        self.current_size.fetch_add(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the data of an extent held in memory, marking it as used.
+    fn get_in_memory(&self, extent_id: &str) -> Option<Bytes> {
+        self.get_shard(extent_id).get(extent_id).map(|extent| {
+            extent.last_used.store(self.tick(), Ordering::Relaxed);
+            extent.data.clone()
+        })
+    }
+
+    /// Returns the data of an extent, loading it back into memory if it was
+    /// spilled.
+    async fn extent_data(&self, extent_id: &str) -> StorageResult<Bytes> {
+        if let Some(data) = self.get_in_memory(extent_id) {
+            return Ok(data);
+        }
+        let Some(spill) = &self.spill else {
+            return Err(missing_extent(extent_id, &self.missing_reads));
+        };
+
+        let _guard = spill.lock.lock().await;
+        // Loaded by a concurrent read while waiting for the lock
+        if let Some(data) = self.get_in_memory(extent_id) {
+            return Ok(data);
+        }
+        let Some(checksum) = spill.extents.get(extent_id).map(|entry| entry.1) else {
+            return Err(missing_extent(extent_id, &self.missing_reads));
+        };
+        let data = Bytes::from(fs::read(spill.path(extent_id)).await.map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::InternalError,
+                format!("Failed to read spilled extent: {}", e),
+            )
+        })?);
+        if crc32fast::hash(&data) != checksum {
+            return Err(StorageError::with_message(
+                ErrorCode::InternalError,
+                format!("Checksum mismatch in spilled extent {}; the spill file is corrupted.", extent_id),
+            ));
+        }
+
+        // An extent larger than the whole budget is served from disk
+        if data.len() as u64 <= self.size_limit {
+            if let Some((id, (size, _))) = spill.extents.remove(extent_id) {
+                spill.size.fetch_sub(size, Ordering::Relaxed);
+                fs::remove_file(spill.path(extent_id)).await.ok();
+                self.insert(id, data.clone())?;
+                self.spill_over_limit(spill).await?;
+            }
+        }
+        Ok(data)
+    }
+
+    /// Spills least recently used extents until the memory limit is met.
+    /// Callers hold the spill lock.
+    async fn spill_over_limit(&self, spill: &SpillArea) -> StorageResult<()> {
+        if self.current_size.load(Ordering::Relaxed) <= self.size_limit {
+            return Ok(());
+        }
+
+        let mut candidates: Vec<(u64, Arc<str>)> = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .iter()
+                    .map(|entry| (entry.last_used.load(Ordering::Relaxed), entry.key().clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        candidates.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        for (_, extent_id) in candidates {
+            if self.current_size.load(Ordering::Relaxed) <= self.size_limit {
+                break;
+            }
+            let Some(data) = self.get_shard(&extent_id).get(&extent_id).map(|extent| extent.data.clone()) else {
+                continue;
+            };
+            fs::write(spill.path(&extent_id), &data).await.map_err(|e| {
+                StorageError::with_message(
+                    ErrorCode::InternalError,
+                    format!("Failed to spill extent: {}", e),
+                )
+            })?;
+            let size = data.len() as u64;
+            spill.extents.insert(extent_id.clone(), (size, crc32fast::hash(&data)));
+            spill.size.fetch_add(size, Ordering::Relaxed);
+            if self.get_shard(&extent_id).remove(&extent_id).is_some() {
+                self.current_size.fetch_sub(size, Ordering::Relaxed);
+            }
+        }
+        tracing::debug!("Spilled extents to disk, usage now {:?}", self.usage());
+        Ok(())
+    }
 }
 
 impl Default for MemoryExtentStore {
@@ -92,42 +417,20 @@ impl Default for MemoryExtentStore {
 impl ExtentStore for MemoryExtentStore {
     async fn write(&self, data: Bytes) -> StorageResult<ExtentChunk> {
         let size = data.len() as u64;
-
-        // Check size limit
-        if self.size_limit > 0 {
-            let current = self.current_size.load(Ordering::Relaxed);
-            if current + size > self.size_limit {
-                return Err(StorageError::with_message(
-                    ErrorCode::RequestBodyTooLarge,
-                    "Storage limit exceeded",
-                ));
-            }
-        }
-
         let extent_id = Uuid::new_v4().to_string();
-        let extent_id_arc: Arc<str> = Arc::from(extent_id.as_str());
+        self.insert(Arc::from(extent_id.as_str()), data)?;
+        self.extent_count.fetch_add(1, Ordering::Relaxed);
 
-        let shard = self.get_shard(&extent_id);
-        shard.insert(extent_id_arc, data);
-        self.current_size.fetch_add(size, Ordering::Relaxed);
+        if let Some(spill) = &self.spill {
+            let _guard = spill.lock.lock().await;
+            self.spill_over_limit(spill).await?;
+        }
 
         Ok(ExtentChunk::new(extent_id, 0, size))
     }
 
     async fn read(&self, chunk: &ExtentChunk) -> StorageResult<Bytes> {
-        let shard = self.get_shard(&chunk.id);
-        let extent = shard
-            .get(chunk.id.as_str())
-            .ok_or_else(|| StorageError::new(ErrorCode::InternalError))?;
-
-        let start = chunk.offset as usize;
-        let end = start + chunk.count as usize;
-
-        if end > extent.len() {
-            return Err(StorageError::new(ErrorCode::InternalError));
-        }
-
-        Ok(extent.slice(start..end))
+        self.read_range(chunk, 0, chunk.count).await
     }
 
     async fn read_range(
@@ -136,10 +439,7 @@ impl ExtentStore for MemoryExtentStore {
         offset: u64,
         count: u64,
     ) -> StorageResult<Bytes> {
-        let shard = self.get_shard(&chunk.id);
-        let extent = shard
-            .get(chunk.id.as_str())
-            .ok_or_else(|| StorageError::new(ErrorCode::InternalError))?;
+        let extent = self.extent_data(&chunk.id).await?;
 
         let start = (chunk.offset + offset) as usize;
         let end = start + count as usize;
@@ -152,27 +452,80 @@ impl ExtentStore for MemoryExtentStore {
     }
 
     async fn delete(&self, extent_id: &str) -> StorageResult<()> {
+        // An extent being spilled is in neither place or both; wait for the
+        // move to finish so no orphaned spill file is left behind.
+        let _guard = match &self.spill {
+            Some(spill) => Some(spill.lock.lock().await),
+            None => None,
+        };
         let shard = self.get_shard(extent_id);
-        if let Some((_, data)) = shard.remove(extent_id) {
+        let mut removed = false;
+        if let Some((_, extent)) = shard.remove(extent_id) {
             self.current_size
-                .fetch_sub(data.len() as u64, Ordering::Relaxed);
+                .fetch_sub(extent.data.len() as u64, Ordering::Relaxed);
+            removed = true;
+        }
+        if let Some(spill) = &self.spill {
+            if let Some((_, (size, _))) = spill.extents.remove(extent_id) {
+                spill.size.fetch_sub(size, Ordering::Relaxed);
+                fs::remove_file(spill.path(extent_id)).await.ok();
+                removed = true;
+            }
+        }
+        if removed {
+            self.extent_count.fetch_sub(1, Ordering::Relaxed);
         }
         Ok(())
     }
 
+    async fn contains(&self, extent_id: &str) -> bool {
+        self.get_shard(extent_id).contains_key(extent_id)
+            || self.spill.as_ref().is_some_and(|spill| spill.extents.contains_key(extent_id))
+    }
+
     async fn total_size(&self) -> u64 {
-        self.current_size.load(Ordering::Relaxed)
+        let usage = self.usage();
+        usage.memory + usage.spilled
     }
+
+    async fn stats(&self) -> ExtentStats {
+        ExtentStats {
+            extents: self.extent_count.load(Ordering::Relaxed),
+            bytes: self.total_size().await,
+            missing_reads: self.missing_reads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Size of the blocks an extent file is checksummed in.
+const CHECKSUM_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Size and per-block checksums of an extent file.
+struct ExtentInfo {
+    size: u64,
+    /// CRC32 of each `CHECKSUM_BLOCK_SIZE` block of the extent, in order.
+    checksums: Vec<u32>,
+}
+
+/// Returns the CRC32 of each checksum block of `data`.
+fn block_checksums(data: &[u8]) -> Vec<u32> {
+    data.chunks(CHECKSUM_BLOCK_SIZE as usize).map(crc32fast::hash).collect()
 }
 
 /// File system implementation of the extent store.
+///
+/// A CRC32 of every 64 KiB block is kept at write time and checked on read;
+/// reads verify each block they touch, so corrupted files fail with
+/// `InternalError` instead of returning wrong bytes.
 pub struct FsExtentStore {
     /// Base directory for extent files.
     base_path: PathBuf,
-    /// Metadata for extents (size tracking).
-    extent_sizes: DashMap<Arc<str>, u64>,
+    /// Metadata for extents (size and checksums).
+    extents: DashMap<Arc<str>, ExtentInfo>,
     /// Current total size in bytes.
     current_size: AtomicU64,
+    /// Reads of extents the store does not hold.
+    missing_reads: AtomicU64,
 }
 
 impl FsExtentStore {
@@ -186,8 +539,9 @@ impl FsExtentStore {
 
         Ok(Self {
             base_path,
-            extent_sizes: DashMap::new(),
+            extents: DashMap::new(),
             current_size: AtomicU64::new(0),
+            missing_reads: AtomicU64::new(0),
         })
     }
 
@@ -210,15 +564,23 @@ impl ExtentStore for FsExtentStore {
             )
         })?;
 
+        // tokio finishes file writes in the background unless flushed
         file.write_all(&data).await.map_err(|e| {
             StorageError::with_message(
                 ErrorCode::InternalError,
                 format!("Failed to write extent data: {}", e),
             )
         })?;
+        file.flush().await.map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::InternalError,
+                format!("Failed to write extent data: {}", e),
+            )
+        })?;
 
         let extent_id_arc: Arc<str> = Arc::from(extent_id.as_str());
-        self.extent_sizes.insert(extent_id_arc, size);
+        let checksums = block_checksums(&data);
+        self.extents.insert(extent_id_arc, ExtentInfo { size, checksums });
         self.current_size.fetch_add(size, Ordering::Relaxed);
 
         Ok(ExtentChunk::new(extent_id, 0, size))
@@ -236,14 +598,20 @@ impl ExtentStore for FsExtentStore {
     ) -> StorageResult<Bytes> {
         let path = self.extent_path(&chunk.id);
 
-        let mut file = fs::File::open(&path).await.map_err(|e| {
-            StorageError::with_message(
+        let mut file = fs::File::open(&path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => missing_extent(&chunk.id, &self.missing_reads),
+            _ => StorageError::with_message(
                 ErrorCode::InternalError,
                 format!("Failed to open extent file: {}", e),
-            )
+            ),
         })?;
 
         let start = chunk.offset + offset;
+        let checksums = self.extents.get(chunk.id.as_str()).map(|info| info.checksums.clone());
+        if let Some(checksums) = checksums {
+            return read_verified(&mut file, &chunk.id, &checksums, start, count).await;
+        }
+
         file.seek(std::io::SeekFrom::Start(start))
             .await
             .map_err(|e| {
@@ -267,15 +635,92 @@ impl ExtentStore for FsExtentStore {
     async fn delete(&self, extent_id: &str) -> StorageResult<()> {
         let path = self.extent_path(extent_id);
 
-        if let Some((_, size)) = self.extent_sizes.remove(extent_id) {
-            self.current_size.fetch_sub(size, Ordering::Relaxed);
+        if let Some((_, info)) = self.extents.remove(extent_id) {
+            self.current_size.fetch_sub(info.size, Ordering::Relaxed);
         }
 
         fs::remove_file(&path).await.ok(); // Ignore errors if file doesn't exist
         Ok(())
     }
 
+    async fn contains(&self, extent_id: &str) -> bool {
+        // Files written before a restart have no entry
+        self.extents.contains_key(extent_id) || fs::try_exists(self.extent_path(extent_id)).await.unwrap_or(false)
+    }
+
     async fn total_size(&self) -> u64 {
         self.current_size.load(Ordering::Relaxed)
     }
+
+    async fn stats(&self) -> ExtentStats {
+        ExtentStats {
+            extents: self.extents.len() as u64,
+            bytes: self.current_size.load(Ordering::Relaxed),
+            missing_reads: self.missing_reads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Reads `count` bytes at `start` of an extent file, reading whole checksum
+/// blocks and verifying each against `checksums`.
+async fn read_verified(
+    file: &mut fs::File,
+    extent_id: &str,
+    checksums: &[u32],
+    start: u64,
+    count: u64,
+) -> StorageResult<Bytes> {
+    if count == 0 {
+        return Ok(Bytes::new());
+    }
+    let first_block = start / CHECKSUM_BLOCK_SIZE;
+    let last_block = (start + count - 1) / CHECKSUM_BLOCK_SIZE;
+    if last_block as usize >= checksums.len() {
+        return Err(StorageError::with_message(
+            ErrorCode::InternalError,
+            format!("Read past the end of extent {}", extent_id),
+        ));
+    }
+
+    let block_start = first_block * CHECKSUM_BLOCK_SIZE;
+    file.seek(std::io::SeekFrom::Start(block_start))
+        .await
+        .map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::InternalError,
+                format!("Failed to seek in extent file: {}", e),
+            )
+        })?;
+    let mut buffer = Vec::with_capacity(((last_block - first_block + 1) * CHECKSUM_BLOCK_SIZE) as usize);
+    file.take((last_block - first_block + 1) * CHECKSUM_BLOCK_SIZE)
+        .read_to_end(&mut buffer)
+        .await
+        .map_err(|e| {
+            StorageError::with_message(
+                ErrorCode::InternalError,
+                format!("Failed to read extent data: {}", e),
+            )
+        })?;
+
+    for (i, block) in buffer.chunks(CHECKSUM_BLOCK_SIZE as usize).enumerate() {
+        let index = first_block as usize + i;
+        if crc32fast::hash(block) != checksums[index] {
+            return Err(StorageError::with_message(
+                ErrorCode::InternalError,
+                format!(
+                    "Checksum mismatch in block {} of extent {}; the extent file is corrupted.",
+                    index, extent_id
+                ),
+            ));
+        }
+    }
+    let offset = (start - block_start) as usize;
+    let data = Bytes::from(buffer);
+    if offset + count as usize > data.len() {
+        return Err(StorageError::with_message(
+            ErrorCode::InternalError,
+            format!("Extent {} is shorter than when it was written.", extent_id),
+        ));
+    }
+    Ok(data.slice(offset..offset + count as usize))
 }
@@ -1,32 +1,125 @@
 //! Metadata store for containers, blobs, and blocks.
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 
+use crate::context::is_system_container;
 use crate::error::{ErrorCode, StorageError, StorageResult};
-use crate::models::{BlobModel, BlockModel, ContainerModel, ServiceProperties};
+use crate::models::{BlobModel, BlockModel, ContainerModel, ExtentChunk, LeaseState, ServiceProperties};
+
+/// Every record held by a metadata store, as exported to and imported from
+/// state archives.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataState {
+    pub containers: Vec<ContainerModel>,
+    /// Base blobs, snapshots and soft-deleted blobs.
+    pub blobs: Vec<BlobModel>,
+    /// Staged (uncommitted) blocks.
+    pub blocks: Vec<BlockModel>,
+    /// Service properties by account.
+    pub service_properties: Vec<(String, ServiceProperties)>,
+}
+
+/// Records a metadata store holds for one account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountStats {
+    /// Containers, including soft-deleted ones.
+    pub containers: u64,
+    /// Base blobs, including soft-deleted ones.
+    pub blobs: u64,
+    pub snapshots: u64,
+    /// Uncommitted blocks.
+    pub staged_blocks: u64,
+    /// Content length of all blobs and snapshots. Snapshots share data with
+    /// their base blob, so this can exceed the bytes held by the extent store.
+    pub bytes: u64,
+}
+
+/// Blobs held by one container.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ContainerStats {
+    /// Base blobs, not counting snapshots or soft-deleted blobs.
+    pub blobs: u64,
+    /// Content length of those blobs.
+    pub bytes: u64,
+}
+
+/// Record counts of a metadata store, by account.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MetadataStats {
+    pub accounts: BTreeMap<String, AccountStats>,
+    /// Blob counts by account, then container.
+    pub containers: BTreeMap<String, BTreeMap<String, ContainerStats>>,
+}
 
 /// Trait for metadata storage operations.
+///
+/// Records are keyed by account, container, blob name and snapshot; the
+/// base blob has the empty snapshot. Failures carry the error code the
+/// handlers pass on to the client, so implementations must use the codes
+/// documented here. [`conformance::run_metadata_store_tests`] checks an
+/// implementation against these contracts.
+///
+/// [`conformance::run_metadata_store_tests`]: crate::storage::conformance::run_metadata_store_tests
 #[async_trait]
 pub trait MetadataStore: Send + Sync {
     // Container operations
+    /// Stores a new container. Fails with `ContainerAlreadyExists` if the
+    /// name is taken, and with `ContainerBeingDeleted` while the record of a
+    /// deleted container still reserves the name. A container created under
+    /// the name of a deleted one starts empty.
     async fn create_container(&self, container: ContainerModel) -> StorageResult<()>;
+    /// Returns a container, soft-deleted or being deleted included. Fails
+    /// with `ContainerNotFound` if there is no record.
     async fn get_container(&self, account: &str, name: &str) -> StorageResult<ContainerModel>;
+    /// Replaces a container's record. Fails with `ContainerNotFound` if
+    /// there is none.
     async fn update_container(&self, container: ContainerModel) -> StorageResult<()>;
-    async fn delete_container(&self, account: &str, name: &str) -> StorageResult<()>;
+    /// Deletes a container together with its blobs, snapshots and staged
+    /// blocks. Returns the IDs of the extents they referenced that no other
+    /// record still references. With `reserve_until`, the record is replaced
+    /// in the same step by an empty one being deleted until then, so the
+    /// name is never free in between. Fails with `ContainerNotFound` if
+    /// there is no record.
+    async fn delete_container(
+        &self,
+        account: &str,
+        name: &str,
+        reserve_until: Option<DateTime<Utc>>,
+    ) -> StorageResult<Vec<String>>;
+    /// Lists containers in name order. Soft-deleted containers and system
+    /// containers (`$logs` and `$web`) are only listed when asked for,
+    /// containers being deleted never. Only names after `marker` are listed,
+    /// at most `maxresults` (5000 if unset); the returned marker is the last
+    /// name of a page that was cut short.
     async fn list_containers(
         &self,
         account: &str,
         prefix: Option<&str>,
         marker: Option<&str>,
         maxresults: Option<u32>,
+        include_deleted: bool,
+        include_system: bool,
     ) -> StorageResult<(Vec<ContainerModel>, Option<String>)>;
+    /// Returns whether a container exists and is neither soft-deleted nor
+    /// being deleted.
     async fn container_exists(&self, account: &str, name: &str) -> bool;
 
     // Blob operations
+    /// Stores a blob or snapshot, replacing any record with the same key.
+    /// Fails with `ContainerNotFound` if the container does not exist or is
+    /// being deleted; soft-deleted containers take writes.
     async fn create_blob(&self, blob: BlobModel) -> StorageResult<()>;
+    /// Returns a blob, or a snapshot by its timestamp. Fails with
+    /// `ContainerNotFound` if the container does not exist, and with
+    /// `BlobNotFound` if the blob does not or is soft-deleted.
     async fn get_blob(
         &self,
         account: &str,
@@ -34,7 +127,24 @@ pub trait MetadataStore: Send + Sync {
         name: &str,
         snapshot: &str,
     ) -> StorageResult<BlobModel>;
+    /// Replaces a blob or snapshot like [`MetadataStore::create_blob`]. The
+    /// extents of the chunks it replaces are released.
     async fn update_blob(&self, blob: BlobModel) -> StorageResult<()>;
+    /// Applies `update` to a stored blob or snapshot with no other update
+    /// interleaving, and stores the result only if `update` succeeds.
+    /// Returns the updated blob. `update` must not call back into the store.
+    /// Fails like [`MetadataStore::get_blob`] or with the error of `update`.
+    async fn modify_blob(
+        &self,
+        account: &str,
+        container: &str,
+        name: &str,
+        snapshot: &str,
+        update: &mut (dyn for<'b> FnMut(&'b mut BlobModel) -> StorageResult<()> + Send),
+    ) -> StorageResult<BlobModel>;
+    /// Removes a blob or one snapshot; the blob's other snapshots are kept.
+    /// Fails with `ContainerNotFound` if the container does not exist, and
+    /// with `BlobNotFound` if there is no record.
     async fn delete_blob(
         &self,
         account: &str,
@@ -42,6 +152,16 @@ pub trait MetadataStore: Send + Sync {
         name: &str,
         snapshot: &str,
     ) -> StorageResult<()>;
+    /// Lists blobs in name order, each blob's snapshots oldest first before
+    /// the blob itself. Returns the blobs, the virtual directories under
+    /// `delimiter` and the marker of the next page.
+    ///
+    /// A virtual directory is one entry: it counts against `maxresults`
+    /// (5000 if unset) and is listed once across pages. The marker is the
+    /// name of the last entry of a page that was cut short, with
+    /// `?snapshot=<timestamp>` appended for a snapshot; the next page starts
+    /// after that entry. Fails with `ContainerNotFound` if the container
+    /// does not exist.
     async fn list_blobs(
         &self,
         account: &str,
@@ -53,6 +173,7 @@ pub trait MetadataStore: Send + Sync {
         include_snapshots: bool,
         include_deleted: bool,
     ) -> StorageResult<(Vec<BlobModel>, Vec<String>, Option<String>)>;
+    /// Returns whether a blob or snapshot exists and is not soft-deleted.
     async fn blob_exists(
         &self,
         account: &str,
@@ -62,13 +183,21 @@ pub trait MetadataStore: Send + Sync {
     ) -> bool;
 
     // Block operations
+    /// Stores an uncommitted block, replacing one staged with the same ID.
+    /// The store sets `sequence` to number the block after every block
+    /// staged before it. Fails with `ContainerNotFound` like
+    /// [`MetadataStore::create_blob`].
     async fn stage_block(&self, block: BlockModel) -> StorageResult<()>;
+    /// Returns a blob's uncommitted blocks in the order they were staged.
+    /// Restaging a block ID moves it to the end.
     async fn get_staged_blocks(
         &self,
         account: &str,
         container: &str,
         blob: &str,
     ) -> StorageResult<Vec<BlockModel>>;
+    /// Returns one uncommitted block. Fails with `InvalidBlockId` if the
+    /// blob has no block staged under the ID.
     async fn get_staged_block(
         &self,
         account: &str,
@@ -76,20 +205,120 @@ pub trait MetadataStore: Send + Sync {
         blob: &str,
         block_id: &str,
     ) -> StorageResult<BlockModel>;
+    /// Discards a blob's uncommitted blocks and returns the extent chunks
+    /// they referenced, for the caller to free once nothing else uses them.
     async fn delete_staged_blocks(
         &self,
         account: &str,
         container: &str,
         blob: &str,
-    ) -> StorageResult<()>;
+    ) -> StorageResult<Vec<ExtentChunk>>;
 
     // Service properties
+    /// Returns an account's service properties, the defaults if they were
+    /// never set.
     async fn get_service_properties(&self, account: &str) -> StorageResult<ServiceProperties>;
+    /// Replaces an account's service properties.
     async fn set_service_properties(
         &self,
         account: &str,
         properties: ServiceProperties,
     ) -> StorageResult<()>;
+
+    /// Returns whether any blob, snapshot or staged block references the
+    /// extent. Snapshots and copies share extents with their source, so an
+    /// extent may only be freed once this is false.
+    async fn extent_in_use(&self, extent_id: &str) -> bool;
+
+    /// Returns per-account record counts. Stores keep these as counters, so
+    /// this does not scan the records.
+    async fn stats(&self) -> MetadataStats;
+
+    /// Returns the blob count and bytes of one container, from the same
+    /// counters as [`MetadataStore::stats`]. Zero for unknown containers.
+    async fn container_stats(&self, account: &str, container: &str) -> ContainerStats;
+
+    // Maintenance
+    /// Returns copies of the records garbage collection may act on:
+    /// soft-deleted containers and blobs, containers reserving the name of a
+    /// deleted one, containers and blobs whose lease can expire or is
+    /// breaking, and staged blocks in staging order. Service properties are
+    /// left out. The default filters [`MetadataStore::export_state`]; stores
+    /// should skip the other records without copying them.
+    async fn maintenance_state(&self) -> StorageResult<MetadataState> {
+        let state = self.export_state().await?;
+        Ok(MetadataState {
+            containers: state.containers.into_iter().filter(container_needs_maintenance).collect(),
+            blobs: state.blobs.into_iter().filter(blob_needs_maintenance).collect(),
+            blocks: state.blocks,
+            service_properties: Vec::new(),
+        })
+    }
+
+    // State export and import
+    /// Returns a copy of every record in the store, staged blocks in staging
+    /// order.
+    async fn export_state(&self) -> StorageResult<MetadataState>;
+    /// Adds the given records, replacing existing ones with the same key.
+    async fn import_state(&self, state: MetadataState) -> StorageResult<()> {
+        for container in state.containers {
+            if self.get_container(&container.account, &container.name).await.is_ok() {
+                self.update_container(container).await?;
+            } else {
+                self.create_container(container).await?;
+            }
+        }
+        for blob in state.blobs {
+            self.create_blob(blob).await?;
+        }
+        for block in state.blocks {
+            self.stage_block(block).await?;
+        }
+        for (account, properties) in state.service_properties {
+            self.set_service_properties(&account, properties).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether garbage collection may act on a container; see
+/// [`MetadataStore::maintenance_state`].
+fn container_needs_maintenance(container: &ContainerModel) -> bool {
+    container.deleted
+        || container.deleting_until.is_some()
+        || lease_transition_ahead(container.properties.lease_state, container.properties.lease_expiry.is_some())
+}
+
+/// Whether garbage collection may act on a blob or snapshot.
+fn blob_needs_maintenance(blob: &BlobModel) -> bool {
+    blob.deleted || lease_transition_ahead(blob.properties.lease_state, blob.properties.lease_expiry.is_some())
+}
+
+/// Whether a lease will expire or finish breaking by itself.
+fn lease_transition_ahead(state: LeaseState, expires: bool) -> bool {
+    match state {
+        LeaseState::Leased => expires,
+        LeaseState::Breaking => true,
+        _ => false,
+    }
+}
+
+/// Running record counts of an account. Signed so that concurrent updates
+/// may pass through zero in either order.
+#[derive(Default)]
+struct AccountCounters {
+    containers: AtomicI64,
+    blobs: AtomicI64,
+    snapshots: AtomicI64,
+    staged_blocks: AtomicI64,
+    bytes: AtomicI64,
+}
+
+/// Running blob counts of a container, signed like [`AccountCounters`].
+#[derive(Default)]
+struct ContainerCounters {
+    blobs: AtomicI64,
+    bytes: AtomicI64,
 }
 
 /// Key type for containers - uses Arc<str> to avoid allocations.
@@ -118,10 +347,50 @@ pub struct MemoryMetadataStore {
     /// Secondary index: account+container+blob -> set of block_ids.
     block_index: DashMap<(Arc<str>, Arc<str>, Arc<str>), HashSet<Arc<str>>>,
 
+    /// Sequence number given to the next staged block.
+    next_block_sequence: AtomicU64,
+
+    /// Number of blob chunks and staged blocks referencing each extent.
+    extent_refs: DashMap<Arc<str>, u64>,
+
+    /// Record counts by account, kept for [`MetadataStore::stats`].
+    counters: DashMap<Arc<str>, AccountCounters>,
+
+    /// Blob counts by container, kept for [`MetadataStore::container_stats`].
+    container_counters: DashMap<ContainerKey, ContainerCounters>,
+
     /// Service properties indexed by account.
     service_properties: DashMap<Arc<str>, ServiceProperties>,
 }
 
+/// Joins the blob name and snapshot of a List Blobs marker that stops
+/// between the snapshots of a blob.
+const SNAPSHOT_MARKER_SEPARATOR: &str = "?snapshot=";
+
+/// Orders List Blobs entries as Azure does: by name, and for each name the
+/// snapshots oldest first, then the base blob.
+fn blob_list_key(blob: &BlobModel) -> (&str, bool, &str) {
+    (&blob.name, blob.snapshot.is_empty(), &blob.snapshot)
+}
+
+/// Returns the NextMarker resuming a listing after `blob`.
+fn blob_marker(blob: &BlobModel) -> String {
+    if blob.snapshot.is_empty() {
+        blob.name.clone()
+    } else {
+        format!("{}{}{}", blob.name, SNAPSHOT_MARKER_SEPARATOR, blob.snapshot)
+    }
+}
+
+/// Parses a marker from [`blob_marker`] into the list key of the last entry
+/// it covers. Anything else is taken as a blob name.
+fn parse_blob_marker(marker: &str) -> (&str, bool, &str) {
+    match marker.rsplit_once(SNAPSHOT_MARKER_SEPARATOR) {
+        Some((name, snapshot)) if chrono::DateTime::parse_from_rfc3339(snapshot).is_ok() => (name, false, snapshot),
+        _ => (marker, true, ""),
+    }
+}
+
 impl MemoryMetadataStore {
     pub fn new() -> Self {
         Self {
@@ -130,10 +399,186 @@ impl MemoryMetadataStore {
             blob_index: DashMap::new(),
             blocks: DashMap::new(),
             block_index: DashMap::new(),
+            next_block_sequence: AtomicU64::new(0),
+            extent_refs: DashMap::new(),
+            counters: DashMap::new(),
+            container_counters: DashMap::new(),
             service_properties: DashMap::new(),
         }
     }
 
+    /// Creates a store holding the given records, without going through the
+    /// async API. Useful for pre-populating large datasets in tests and
+    /// benchmarks.
+    pub fn from_state(state: MetadataState) -> Self {
+        let store = Self::new();
+        for container in state.containers {
+            store.insert_container(container);
+        }
+        for blob in state.blobs {
+            store.insert_blob(blob);
+        }
+        for block in state.blocks {
+            store.insert_block(block);
+        }
+        for (account, properties) in state.service_properties {
+            store.service_properties.insert(Self::arc_str(&account), properties);
+        }
+        store
+    }
+
+    /// Applies `update` to the counters of `account`.
+    fn count(&self, account: &str, update: impl FnOnce(&AccountCounters)) {
+        match self.counters.get(account) {
+            Some(counters) => update(&counters),
+            None => update(&self.counters.entry(Self::arc_str(account)).or_default()),
+        }
+    }
+
+    /// Applies `update` to the counters of a container.
+    fn count_container(&self, account: &str, container: &str, update: impl FnOnce(&ContainerCounters)) {
+        let key = Self::container_key(account, container);
+        match self.container_counters.get(&key) {
+            Some(counters) => update(&counters),
+            None => update(&self.container_counters.entry(key).or_default()),
+        }
+    }
+
+    /// Counts a blob or snapshot being added (`sign` 1) or removed (-1).
+    fn count_blob(&self, blob: &BlobModel, sign: i64) {
+        let bytes = sign * blob.properties.content_length as i64;
+        self.count(&blob.account, |counters| {
+            let records = if blob.snapshot.is_empty() { &counters.blobs } else { &counters.snapshots };
+            records.fetch_add(sign, Ordering::Relaxed);
+            counters.bytes.fetch_add(bytes, Ordering::Relaxed);
+        });
+        if blob.snapshot.is_empty() && !blob.deleted {
+            self.count_container(&blob.account, &blob.container, |counters| {
+                counters.blobs.fetch_add(sign, Ordering::Relaxed);
+                counters.bytes.fetch_add(bytes, Ordering::Relaxed);
+            });
+        }
+    }
+
+    fn count_block(&self, account: &str, sign: i64) {
+        self.count(account, |counters| {
+            counters.staged_blocks.fetch_add(sign, Ordering::Relaxed);
+        });
+    }
+
+    /// Adds a reference to each chunk's extent.
+    fn reference_extents<'a>(&self, chunks: impl IntoIterator<Item = &'a ExtentChunk>) {
+        for chunk in chunks {
+            match self.extent_refs.get_mut(chunk.id.as_str()) {
+                Some(mut refs) => *refs += 1,
+                None => *self.extent_refs.entry(Self::arc_str(&chunk.id)).or_default() += 1,
+            }
+        }
+    }
+
+    /// Drops a reference to each chunk's extent and returns the IDs of the
+    /// extents left unreferenced.
+    fn release_extents<'a>(&self, chunks: impl IntoIterator<Item = &'a ExtentChunk>) -> Vec<String> {
+        let mut unreferenced = Vec::new();
+        for chunk in chunks {
+            let Some(mut refs) = self.extent_refs.get_mut(chunk.id.as_str()) else {
+                continue;
+            };
+            *refs -= 1;
+            let released = *refs == 0;
+            drop(refs);
+            if released && self.extent_refs.remove_if(chunk.id.as_str(), |_, refs| *refs == 0).is_some() {
+                unreferenced.push(chunk.id.clone());
+            }
+        }
+        unreferenced
+    }
+
+    /// Inserts or replaces a container.
+    fn insert_container(&self, container: ContainerModel) {
+        let key = Self::container_key(&container.account, &container.name);
+        let account = key.0.clone();
+        if self.containers.insert(key, container).is_none() {
+            self.count(&account, |counters| {
+                counters.containers.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+    }
+
+    /// Inserts or replaces a blob record, keeping the counters in step.
+    fn store_blob(&self, blob: BlobModel) {
+        let key = Self::blob_key(&blob.account, &blob.container, &blob.name, &blob.snapshot);
+        self.count_blob(&blob, 1);
+        self.reference_extents(&blob.extent_chunks);
+        if let Some(previous) = self.blobs.insert(key, blob) {
+            self.count_blob(&previous, -1);
+            self.release_extents(&previous.extent_chunks);
+        }
+    }
+
+    /// Inserts or replaces a blob and indexes its name.
+    fn insert_blob(&self, blob: BlobModel) {
+        let index_key = (Self::arc_str(&blob.account), Self::arc_str(&blob.container));
+        let blob_name = Self::arc_str(&blob.name);
+
+        // Update the secondary index
+        self.blob_index
+            .entry(index_key)
+            .or_default()
+            .insert(blob_name);
+
+        self.store_blob(blob);
+    }
+
+    /// Inserts or replaces a staged block and indexes its ID. The block keeps
+    /// its sequence number; later stages are numbered after it.
+    fn insert_block(&self, block: BlockModel) {
+        self.next_block_sequence.fetch_max(block.sequence + 1, Ordering::Relaxed);
+        let key = Self::block_key(
+            &block.account,
+            &block.container,
+            &block.blob,
+            &block.block_id,
+        );
+        let index_key = (
+            Self::arc_str(&block.account),
+            Self::arc_str(&block.container),
+            Self::arc_str(&block.blob),
+        );
+        let block_id = Self::arc_str(&block.block_id);
+
+        // Update the secondary index
+        self.block_index
+            .entry(index_key)
+            .or_default()
+            .insert(block_id);
+
+        let account = key.0.clone();
+        self.reference_extents([&block.extent_chunk]);
+        match self.blocks.insert(key, block) {
+            Some(previous) => {
+                self.release_extents([&previous.extent_chunk]);
+            }
+            None => self.count_block(&account, 1),
+        }
+    }
+
+    /// Runs `write` under a read lock of the container's record, so it is
+    /// ordered against [`MetadataStore::delete_container`]: a write that
+    /// gets in first is removed by the cascade, a later one fails with
+    /// `ContainerNotFound`. Soft-deleted containers still take writes, as
+    /// restores and imports need.
+    fn write_in_container(&self, account: &str, container: &str, write: impl FnOnce()) -> StorageResult<()> {
+        let record = self
+            .containers
+            .get(&Self::container_key(account, container))
+            .filter(|container| container.deleting_until.is_none())
+            .ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))?;
+        write();
+        drop(record);
+        Ok(())
+    }
+
     /// Create an Arc<str> key from a string slice.
     #[inline]
     fn arc_str(s: &str) -> Arc<str> {
@@ -179,10 +624,23 @@ impl Default for MemoryMetadataStore {
 impl MetadataStore for MemoryMetadataStore {
     async fn create_container(&self, container: ContainerModel) -> StorageResult<()> {
         let key = Self::container_key(&container.account, &container.name);
-        if self.containers.contains_key(&key) {
-            return Err(StorageError::new(ErrorCode::ContainerAlreadyExists));
-        }
-        self.containers.insert(key, container);
+        let entry = match self.containers.entry(key) {
+            Entry::Occupied(existing) if existing.get().deleting_until.is_some() => {
+                return Err(StorageError::new(ErrorCode::ContainerBeingDeleted));
+            }
+            Entry::Occupied(_) => return Err(StorageError::new(ErrorCode::ContainerAlreadyExists)),
+            Entry::Vacant(entry) => entry,
+        };
+
+        // A new container starts empty, whatever an earlier one of the same
+        // name left in the index and counters
+        self.blob_index.remove(entry.key());
+        self.container_counters.remove(entry.key());
+        let account = entry.key().0.clone();
+        entry.insert(container);
+        self.count(&account, |counters| {
+            counters.containers.fetch_add(1, Ordering::Relaxed);
+        });
         Ok(())
     }
 
@@ -199,16 +657,65 @@ impl MetadataStore for MemoryMetadataStore {
         if !self.containers.contains_key(&key) {
             return Err(StorageError::new(ErrorCode::ContainerNotFound));
         }
-        self.containers.insert(key, container);
+        self.insert_container(container);
         Ok(())
     }
 
-    async fn delete_container(&self, account: &str, name: &str) -> StorageResult<()> {
+    async fn delete_container(
+        &self,
+        account: &str,
+        name: &str,
+        reserve_until: Option<DateTime<Utc>>,
+    ) -> StorageResult<Vec<String>> {
         let key = Self::container_key(account, name);
-        self.containers
-            .remove(&key)
-            .map(|_| ())
-            .ok_or_else(|| StorageError::new(ErrorCode::ContainerNotFound))
+        // The record stays locked until its contents are gone: writes into
+        // the container wait and then fail, and a container created under
+        // the same name afterwards cannot lose blobs to this cascade
+        let Entry::Occupied(mut entry) = self.containers.entry(key.clone()) else {
+            return Err(StorageError::new(ErrorCode::ContainerNotFound));
+        };
+
+        let mut extent_ids = Vec::new();
+        let in_container =
+            |blob_account: &str, blob_container: &str| blob_account == account && blob_container == name;
+
+        // Blobs and snapshots
+        self.blobs.retain(|(blob_account, blob_container, _, _), blob| {
+            if !in_container(blob_account, blob_container) {
+                return true;
+            }
+            extent_ids.extend(self.release_extents(&blob.extent_chunks));
+            self.count_blob(blob, -1);
+            false
+        });
+        self.blob_index.remove(&key);
+
+        // Staged blocks
+        self.blocks.retain(|(block_account, block_container, _, _), block| {
+            if !in_container(block_account, block_container) {
+                return true;
+            }
+            extent_ids.extend(self.release_extents([&block.extent_chunk]));
+            self.count_block(account, -1);
+            false
+        });
+        self.block_index
+            .retain(|(block_account, block_container, _), _| !in_container(block_account, block_container));
+        self.container_counters.remove(&key);
+
+        if let Some(until) = reserve_until {
+            // The tombstone takes the record's place and count
+            let mut tombstone =
+                ContainerModel::new(account.to_string(), name.to_string(), entry.get().properties.last_modified);
+            tombstone.deleting_until = Some(until);
+            entry.insert(tombstone);
+        } else {
+            entry.remove();
+            self.count(account, |counters| {
+                counters.containers.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+        Ok(extent_ids)
     }
 
     async fn list_containers(
@@ -217,6 +724,8 @@ impl MetadataStore for MemoryMetadataStore {
         prefix: Option<&str>,
         marker: Option<&str>,
         maxresults: Option<u32>,
+        include_deleted: bool,
+        include_system: bool,
     ) -> StorageResult<(Vec<ContainerModel>, Option<String>)> {
         let maxresults = maxresults.unwrap_or(5000) as usize;
         let account_arc = Self::arc_str(account);
@@ -230,7 +739,13 @@ impl MetadataStore for MemoryMetadataStore {
                 if acct.as_ref() != account_arc.as_ref() {
                     return None;
                 }
-                if entry.value().deleted {
+                if entry.value().deleted && !include_deleted {
+                    return None;
+                }
+                if entry.value().deleting_until.is_some() {
+                    return None;
+                }
+                if is_system_container(name) && !include_system {
                     return None;
                 }
                 if let Some(p) = prefix {
@@ -280,23 +795,13 @@ impl MetadataStore for MemoryMetadataStore {
         let key = Self::container_key(account, name);
         self.containers
             .get(&key)
-            .map(|c| !c.deleted)
+            .map(|c| !c.deleted && c.deleting_until.is_none())
             .unwrap_or(false)
     }
 
     async fn create_blob(&self, blob: BlobModel) -> StorageResult<()> {
-        let key = Self::blob_key(&blob.account, &blob.container, &blob.name, &blob.snapshot);
-        let index_key = (Self::arc_str(&blob.account), Self::arc_str(&blob.container));
-        let blob_name = Self::arc_str(&blob.name);
-
-        // Update the secondary index
-        self.blob_index
-            .entry(index_key)
-            .or_default()
-            .insert(blob_name);
-
-        self.blobs.insert(key, blob);
-        Ok(())
+        let (account, container) = (blob.account.clone(), blob.container.clone());
+        self.write_in_container(&account, &container, || self.insert_blob(blob))
     }
 
     async fn get_blob(
@@ -320,9 +825,45 @@ impl MetadataStore for MemoryMetadataStore {
     }
 
     async fn update_blob(&self, blob: BlobModel) -> StorageResult<()> {
-        let key = Self::blob_key(&blob.account, &blob.container, &blob.name, &blob.snapshot);
-        self.blobs.insert(key, blob);
-        Ok(())
+        let (account, container) = (blob.account.clone(), blob.container.clone());
+        self.write_in_container(&account, &container, || self.store_blob(blob))
+    }
+
+    async fn modify_blob(
+        &self,
+        account: &str,
+        container: &str,
+        name: &str,
+        snapshot: &str,
+        update: &mut (dyn for<'b> FnMut(&'b mut BlobModel) -> StorageResult<()> + Send),
+    ) -> StorageResult<BlobModel> {
+        if !self.container_exists(account, container).await {
+            return Err(StorageError::new(ErrorCode::ContainerNotFound));
+        }
+
+        // The entry guard holds the shard lock until the update is stored
+        let key = Self::blob_key(account, container, name, snapshot);
+        let mut entry = self
+            .blobs
+            .get_mut(&key)
+            .filter(|b| !b.deleted)
+            .ok_or_else(|| StorageError::new(ErrorCode::BlobNotFound))?;
+        let mut blob = entry.value().clone();
+        update(&mut blob)?;
+        let growth = blob.properties.content_length as i64 - entry.properties.content_length as i64;
+        self.reference_extents(&blob.extent_chunks);
+        let previous = std::mem::replace(entry.value_mut(), blob.clone());
+        drop(entry);
+        self.release_extents(&previous.extent_chunks);
+        self.count(account, |counters| {
+            counters.bytes.fetch_add(growth, Ordering::Relaxed);
+        });
+        if snapshot.is_empty() {
+            self.count_container(account, container, |counters| {
+                counters.bytes.fetch_add(growth, Ordering::Relaxed);
+            });
+        }
+        Ok(blob)
     }
 
     async fn delete_blob(
@@ -341,6 +882,10 @@ impl MetadataStore for MemoryMetadataStore {
 
         // Remove from main store
         let removed = self.blobs.remove(&key);
+        if let Some((_, blob)) = &removed {
+            self.count_blob(blob, -1);
+            self.release_extents(&blob.extent_chunks);
+        }
 
         // Update secondary index if this was the base blob (not a snapshot)
         if snapshot.is_empty() {
@@ -377,6 +922,8 @@ impl MetadataStore for MemoryMetadataStore {
         let container_arc = Self::arc_str(container);
         let index_key = (account_arc.clone(), container_arc.clone());
 
+        let marker = marker.map(parse_blob_marker);
+
         // Use the secondary index to get blob names in this container
         let blob_names: Vec<Arc<str>> = self
             .blob_index
@@ -391,9 +938,10 @@ impl MetadataStore for MemoryMetadataStore {
                                 return false;
                             }
                         }
-                        // Filter by marker
-                        if let Some(m) = marker {
-                            if name.as_ref() <= m {
+                        // Filter by marker; entries of its own name are
+                        // filtered once snapshots are known
+                        if let Some((m, _, _)) = marker {
+                            if name.as_ref() < m {
                                 return false;
                             }
                         }
@@ -446,41 +994,57 @@ impl MetadataStore for MemoryMetadataStore {
             }
         }
 
-        // Sort by (name, snapshot)
-        blobs.sort_by(|a, b| (&a.name, &a.snapshot).cmp(&(&b.name, &b.snapshot)));
+        blobs.sort_by(|a, b| blob_list_key(a).cmp(&blob_list_key(b)));
+        if let Some(marker) = marker {
+            blobs.retain(|blob| blob_list_key(blob) > marker);
+        }
 
-        // Handle delimiter for hierarchical listing
+        // Handle delimiter for hierarchical listing. A virtual directory is a
+        // single entry: it counts against maxresults like a blob and can be
+        // the NextMarker, after which its blobs are not listed again.
+        let delimiter = delimiter.filter(|d| !d.is_empty());
+        let prefix_len = prefix.map_or(0, str::len);
+        let mut listed: Vec<BlobModel> = Vec::new();
         let mut prefixes: Vec<String> = Vec::new();
-        if let Some(delim) = delimiter {
-            let prefix_str = prefix.unwrap_or("");
-            let mut seen_prefixes = HashSet::new();
-
-            blobs.retain(|blob| {
-                let name_after_prefix = &blob.name[prefix_str.len()..];
-                if let Some(idx) = name_after_prefix.find(delim) {
-                    // This blob is under a virtual directory
-                    let virtual_prefix =
-                        format!("{}{}{}", prefix_str, &name_after_prefix[..idx], delim);
-                    if seen_prefixes.insert(virtual_prefix.clone()) {
-                        prefixes.push(virtual_prefix);
-                    }
-                    false
-                } else {
-                    true
-                }
+        let mut last_entry: Option<String> = None;
+        let mut next_marker = None;
+
+        for blob in blobs {
+            // The delimiter is searched for after the prefix, so an occurrence
+            // the prefix ends inside of does not start a virtual directory
+            let virtual_prefix = delimiter.and_then(|delim| {
+                blob.name[prefix_len..]
+                    .find(delim)
+                    .map(|idx| blob.name[..prefix_len + idx + delim.len()].to_string())
             });
-        }
 
-        prefixes.sort();
+            if let Some(virtual_prefix) = &virtual_prefix {
+                // Blobs under a directory sort together, so a repeat is the last one
+                if prefixes.last() == Some(virtual_prefix)
+                    || marker.is_some_and(|(m, _, _)| virtual_prefix.as_str() <= m)
+                {
+                    continue;
+                }
+            }
 
-        let next_marker = if blobs.len() > maxresults {
-            blobs.truncate(maxresults);
-            blobs.last().map(|b| b.name.clone())
-        } else {
-            None
-        };
+            if listed.len() + prefixes.len() == maxresults {
+                next_marker = last_entry;
+                break;
+            }
+
+            match virtual_prefix {
+                Some(virtual_prefix) => {
+                    last_entry = Some(virtual_prefix.clone());
+                    prefixes.push(virtual_prefix);
+                }
+                None => {
+                    last_entry = Some(blob_marker(&blob));
+                    listed.push(blob);
+                }
+            }
+        }
 
-        Ok((blobs, prefixes, next_marker))
+        Ok((listed, prefixes, next_marker))
     }
 
     async fn blob_exists(
@@ -494,28 +1058,10 @@ impl MetadataStore for MemoryMetadataStore {
         self.blobs.get(&key).map(|b| !b.deleted).unwrap_or(false)
     }
 
-    async fn stage_block(&self, block: BlockModel) -> StorageResult<()> {
-        let key = Self::block_key(
-            &block.account,
-            &block.container,
-            &block.blob,
-            &block.block_id,
-        );
-        let index_key = (
-            Self::arc_str(&block.account),
-            Self::arc_str(&block.container),
-            Self::arc_str(&block.blob),
-        );
-        let block_id = Self::arc_str(&block.block_id);
-
-        // Update the secondary index
-        self.block_index
-            .entry(index_key)
-            .or_default()
-            .insert(block_id);
-
-        self.blocks.insert(key, block);
-        Ok(())
+    async fn stage_block(&self, mut block: BlockModel) -> StorageResult<()> {
+        block.sequence = self.next_block_sequence.fetch_add(1, Ordering::Relaxed);
+        let (account, container) = (block.account.clone(), block.container.clone());
+        self.write_in_container(&account, &container, || self.insert_block(block))
     }
 
     async fn get_staged_blocks(
@@ -554,6 +1100,7 @@ impl MetadataStore for MemoryMetadataStore {
                 blocks.push(entry.value().clone());
             }
         }
+        blocks.sort_by_key(|block| block.sequence);
 
         Ok(blocks)
     }
@@ -577,7 +1124,7 @@ impl MetadataStore for MemoryMetadataStore {
         account: &str,
         container: &str,
         blob: &str,
-    ) -> StorageResult<()> {
+    ) -> StorageResult<Vec<ExtentChunk>> {
         let index_key = (
             Self::arc_str(account),
             Self::arc_str(container),
@@ -596,6 +1143,7 @@ impl MetadataStore for MemoryMetadataStore {
         let container_arc = Self::arc_str(container);
         let blob_arc = Self::arc_str(blob);
 
+        let mut chunks = Vec::with_capacity(block_ids.len());
         for block_id in block_ids {
             let key = (
                 account_arc.clone(),
@@ -603,10 +1151,14 @@ impl MetadataStore for MemoryMetadataStore {
                 blob_arc.clone(),
                 block_id,
             );
-            self.blocks.remove(&key);
+            if let Some((_, block)) = self.blocks.remove(&key) {
+                self.count_block(account, -1);
+                self.release_extents([&block.extent_chunk]);
+                chunks.push(block.extent_chunk);
+            }
         }
 
-        Ok(())
+        Ok(chunks)
     }
 
     async fn get_service_properties(&self, account: &str) -> StorageResult<ServiceProperties> {
@@ -627,4 +1179,92 @@ impl MetadataStore for MemoryMetadataStore {
         self.service_properties.insert(key, properties);
         Ok(())
     }
+
+    async fn extent_in_use(&self, extent_id: &str) -> bool {
+        self.extent_refs.contains_key(extent_id)
+    }
+
+    async fn stats(&self) -> MetadataStats {
+        let load = |counter: &AtomicI64| counter.load(Ordering::Relaxed).max(0) as u64;
+        MetadataStats {
+            accounts: self
+                .counters
+                .iter()
+                .map(|entry| {
+                    let counters = entry.value();
+                    let stats = AccountStats {
+                        containers: load(&counters.containers),
+                        blobs: load(&counters.blobs),
+                        snapshots: load(&counters.snapshots),
+                        staged_blocks: load(&counters.staged_blocks),
+                        bytes: load(&counters.bytes),
+                    };
+                    (entry.key().to_string(), stats)
+                })
+                .collect(),
+            containers: self.container_counters.iter().fold(BTreeMap::new(), |mut containers, entry| {
+                let (account, container) = entry.key();
+                let counters = entry.value();
+                let stats = ContainerStats {
+                    blobs: load(&counters.blobs),
+                    bytes: load(&counters.bytes),
+                };
+                containers
+                    .entry(account.to_string())
+                    .or_insert_with(BTreeMap::new)
+                    .insert(container.to_string(), stats);
+                containers
+            }),
+        }
+    }
+
+    async fn container_stats(&self, account: &str, container: &str) -> ContainerStats {
+        let load = |counter: &AtomicI64| counter.load(Ordering::Relaxed).max(0) as u64;
+        self.container_counters
+            .get(&Self::container_key(account, container))
+            .map(|counters| ContainerStats {
+                blobs: load(&counters.blobs),
+                bytes: load(&counters.bytes),
+            })
+            .unwrap_or_default()
+    }
+
+    async fn maintenance_state(&self) -> StorageResult<MetadataState> {
+        let mut blocks: Vec<BlockModel> = self.blocks.iter().map(|b| b.value().clone()).collect();
+        blocks.sort_by_key(|block| block.sequence);
+        Ok(MetadataState {
+            containers: self
+                .containers
+                .iter()
+                .filter(|c| container_needs_maintenance(c.value()))
+                .map(|c| c.value().clone())
+                .collect(),
+            blobs: self
+                .blobs
+                .iter()
+                .filter(|b| blob_needs_maintenance(b.value()))
+                .map(|b| b.value().clone())
+                .collect(),
+            blocks,
+            service_properties: Vec::new(),
+        })
+    }
+
+    async fn export_state(&self) -> StorageResult<MetadataState> {
+        Ok(MetadataState {
+            containers: self.containers.iter().map(|c| c.value().clone()).collect(),
+            blobs: self.blobs.iter().map(|b| b.value().clone()).collect(),
+            // In staging order, so importing restages blocks in the same order
+            blocks: {
+                let mut blocks: Vec<BlockModel> = self.blocks.iter().map(|b| b.value().clone()).collect();
+                blocks.sort_by_key(|block| block.sequence);
+                blocks
+            },
+            service_properties: self
+                .service_properties
+                .iter()
+                .map(|p| (p.key().to_string(), p.value().clone()))
+                .collect(),
+        })
+    }
 }
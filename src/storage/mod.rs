@@ -1,9 +1,15 @@
 //! Storage layer for persistence.
 
+mod archive;
+#[cfg(feature = "conformance")]
+pub mod conformance;
 mod extent;
 mod gc;
 mod metadata;
+mod verify;
 
+pub use archive::*;
 pub use extent::*;
 pub use gc::*;
 pub use metadata::*;
+pub use verify::*;
@@ -0,0 +1,78 @@
+//! Consistency checks between the metadata and extent stores.
+//!
+//! Records should only reference extents the extent store holds. A
+//! reference to a missing extent, left by a failed import, a collection bug
+//! or files removed by hand, makes every read of the data fail; a scan with
+//! [`verify_extents`] lists them all up front.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use super::{ExtentStore, MetadataStore};
+use crate::error::StorageResult;
+
+/// A record referencing an extent the extent store does not hold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DanglingExtent {
+    pub account: String,
+    pub container: String,
+    pub blob: String,
+    /// Empty for a base blob.
+    pub snapshot: String,
+    /// Set when the reference is a staged block's.
+    pub block_id: Option<String>,
+    pub extent_id: String,
+}
+
+/// Scans every blob, snapshot and staged block for references to extents
+/// missing from `extents`, in the order the records are exported. A blob
+/// is reported once per missing extent, however many of its chunks and
+/// page ranges reference it.
+pub async fn verify_extents(metadata: &dyn MetadataStore, extents: &dyn ExtentStore) -> StorageResult<Vec<DanglingExtent>> {
+    let state = metadata.export_state().await?;
+    let mut present: HashMap<String, bool> = HashMap::new();
+    let mut dangling = Vec::new();
+
+    for blob in &state.blobs {
+        let page_chunks = blob.page_ranges.iter().filter_map(|range| range.extent_chunk.as_ref());
+        let mut checked = HashSet::new();
+        for chunk in blob.extent_chunks.iter().chain(page_chunks) {
+            if checked.insert(chunk.id.as_str()) && !extent_present(extents, &mut present, &chunk.id).await {
+                dangling.push(DanglingExtent {
+                    account: blob.account.clone(),
+                    container: blob.container.clone(),
+                    blob: blob.name.clone(),
+                    snapshot: blob.snapshot.clone(),
+                    block_id: None,
+                    extent_id: chunk.id.clone(),
+                });
+            }
+        }
+    }
+
+    for block in &state.blocks {
+        if !extent_present(extents, &mut present, &block.extent_chunk.id).await {
+            dangling.push(DanglingExtent {
+                account: block.account.clone(),
+                container: block.container.clone(),
+                blob: block.blob.clone(),
+                snapshot: String::new(),
+                block_id: Some(block.block_id.clone()),
+                extent_id: block.extent_chunk.id.clone(),
+            });
+        }
+    }
+
+    Ok(dangling)
+}
+
+/// Whether `extents` holds `extent_id`, asking the store once per extent.
+async fn extent_present(extents: &dyn ExtentStore, present: &mut HashMap<String, bool>, extent_id: &str) -> bool {
+    if let Some(&exists) = present.get(extent_id) {
+        return exists;
+    }
+    let exists = extents.contains(extent_id).await;
+    present.insert(extent_id.to_string(), exists);
+    exists
+}
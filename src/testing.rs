@@ -0,0 +1,315 @@
+//! Helpers for embedding the emulator in tests: direct storage access,
+//! request recording and a controllable clock.
+//!
+//! Seeding through [`Fixtures`] writes straight into the configured stores,
+//! skipping HTTP, and produces the same models as the REST API would.
+//! With the `testing` feature, `Client` sends SharedKey-signed requests
+//! to a running server, signed by the same code the server verifies with.
+//!
+//! ```no_run
+//! use azurite_rs::{models::BlobProperties, BlobServer, Config, DEFAULT_ACCOUNT};
+//!
+//! # async fn example() -> azurite_rs::StorageResult<()> {
+//! let server = BlobServer::new(Config::default());
+//! let fixtures = server.fixtures();
+//! fixtures.seed_container(DEFAULT_ACCOUNT, "data").await?;
+//! fixtures
+//!     .seed_blob(DEFAULT_ACCOUNT, "data", "a.txt", "hello".into(), BlobProperties::default())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use axum::http::StatusCode;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::clock::{Clock, SystemClock};
+use crate::context::RequestContext;
+use crate::error::{ErrorCode, StorageError, StorageResult};
+use crate::models::{BlobModel, BlobProperties, BlobType, ContainerModel, EtagClock};
+use crate::observer::RequestObserver;
+use crate::operation::Operation;
+use crate::storage::{export_archive, import_archive, release_extents, verify_extents, DanglingExtent, ExtentStore, MetadataStore};
+
+#[cfg(feature = "testing")]
+mod client;
+#[cfg(feature = "testing")]
+pub use client::{Client, ClientError};
+
+/// Handle for seeding and inspecting a server's storage.
+#[derive(Clone)]
+pub struct Fixtures {
+    metadata: Arc<dyn MetadataStore>,
+    extents: Arc<dyn ExtentStore>,
+    clock: Arc<dyn Clock>,
+    etags: Arc<EtagClock>,
+}
+
+impl Fixtures {
+    /// Creates a fixtures handle over the given stores, stamping seeded
+    /// models with the wall clock.
+    pub fn new(metadata: Arc<dyn MetadataStore>, extents: Arc<dyn ExtentStore>) -> Self {
+        Self {
+            metadata,
+            extents,
+            clock: Arc::new(SystemClock),
+            etags: Arc::new(EtagClock::new()),
+        }
+    }
+
+    /// Stamps seeded models with the time read from `clock`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Takes the ETags of seeded models from `etags`, such as a server's.
+    pub fn with_etags(mut self, etags: Arc<EtagClock>) -> Self {
+        self.etags = etags;
+        self
+    }
+
+    /// Creates a private container, as Create Container without headers.
+    pub async fn seed_container(&self, account: &str, name: &str) -> StorageResult<ContainerModel> {
+        let mut container = ContainerModel::new(account.to_string(), name.to_string(), self.clock.now());
+        container.properties.renew_etag(&self.etags);
+        self.metadata.create_container(container.clone()).await?;
+        Ok(container)
+    }
+
+    /// Creates or replaces a block blob holding `data`, as a single Put Blob.
+    ///
+    /// Content headers, the access tier and other properties are taken from
+    /// `properties`, except the blob type, length, ETag and timestamps, which
+    /// are set as the REST path sets them.
+    pub async fn seed_blob(
+        &self,
+        account: &str,
+        container: &str,
+        name: &str,
+        data: Bytes,
+        properties: BlobProperties,
+    ) -> StorageResult<BlobModel> {
+        if !self.metadata.container_exists(account, container).await {
+            return Err(StorageError::new(ErrorCode::ContainerNotFound));
+        }
+
+        let content_length = data.len() as u64;
+        let now = self.clock.now();
+        let mut blob = BlobModel::new(
+            account.to_string(),
+            container.to_string(),
+            name.to_string(),
+            BlobType::BlockBlob,
+            content_length,
+            now,
+        );
+
+        blob.properties = BlobProperties {
+            content_length,
+            blob_type: BlobType::BlockBlob,
+            etag: blob.properties.etag,
+            last_modified: now,
+            created_on: now,
+            ..properties
+        };
+        let replaced = self.metadata.get_blob(account, container, name, "").await.ok();
+        blob.properties.renew_etag(&self.etags);
+
+        if content_length > 0 {
+            blob.extent_chunks = vec![self.extents.write(data).await?];
+        }
+
+        self.metadata.create_blob(blob.clone()).await?;
+        let mut released = self
+            .metadata
+            .delete_staged_blocks(account, container, name)
+            .await?;
+        if let Some(replaced) = replaced {
+            released.extend(replaced.extent_chunks);
+        }
+        release_extents(&*self.metadata, &*self.extents, &released).await;
+
+        Ok(blob)
+    }
+
+    /// Returns the stored container model.
+    pub async fn container(&self, account: &str, name: &str) -> StorageResult<ContainerModel> {
+        self.metadata.get_container(account, name).await
+    }
+
+    /// Returns the stored model of a base blob.
+    pub async fn blob(&self, account: &str, container: &str, name: &str) -> StorageResult<BlobModel> {
+        self.metadata.get_blob(account, container, name, "").await
+    }
+
+    /// Reads back the committed content of a block or append blob.
+    pub async fn blob_data(&self, account: &str, container: &str, name: &str) -> StorageResult<Bytes> {
+        let blob = self.blob(account, container, name).await?;
+        let mut data = BytesMut::with_capacity(blob.properties.content_length as usize);
+        for chunk in &blob.extent_chunks {
+            data.extend_from_slice(&self.extents.read(chunk).await?);
+        }
+        Ok(data.freeze())
+    }
+
+    /// Writes all containers, blobs, staged blocks and their data to a state
+    /// archive at `path`. See [`crate::storage::export_archive`].
+    pub async fn export_state(&self, path: impl AsRef<Path>) -> StorageResult<()> {
+        let file = File::create(path.as_ref()).map_err(|e| state_file_error(path.as_ref(), e))?;
+        export_archive(&*self.metadata, &*self.extents, BufWriter::new(file)).await?;
+        Ok(())
+    }
+
+    /// Restores a state archive written by [`Fixtures::export_state`].
+    pub async fn import_state(&self, path: impl AsRef<Path>) -> StorageResult<()> {
+        let file = File::open(path.as_ref()).map_err(|e| state_file_error(path.as_ref(), e))?;
+        import_archive(&*self.metadata, &*self.extents, BufReader::new(file)).await
+    }
+
+    /// Lists references to extents missing from the extent store. See
+    /// [`crate::storage::verify_extents`].
+    pub async fn verify(&self) -> StorageResult<Vec<DanglingExtent>> {
+        verify_extents(&*self.metadata, &*self.extents).await
+    }
+
+    /// Returns the metadata store.
+    pub fn metadata(&self) -> Arc<dyn MetadataStore> {
+        self.metadata.clone()
+    }
+
+    /// Returns the extent store.
+    pub fn extents(&self) -> Arc<dyn ExtentStore> {
+        self.extents.clone()
+    }
+}
+
+/// A request seen by a [`RecordingObserver`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedOperation {
+    pub operation: Operation,
+    pub account: String,
+    pub container: Option<String>,
+    pub blob: Option<String>,
+    pub status: StatusCode,
+}
+
+/// Observer that records every handled request for later assertions.
+#[derive(Debug, Default)]
+pub struct RecordingObserver {
+    records: Mutex<Vec<RecordedOperation>>,
+}
+
+impl RecordingObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the recorded requests in the order they completed.
+    pub fn operations(&self) -> Vec<RecordedOperation> {
+        self.records.lock().clone()
+    }
+
+    /// Returns how many requests resolved to `operation`.
+    pub fn count(&self, operation: Operation) -> usize {
+        self.records
+            .lock()
+            .iter()
+            .filter(|record| record.operation == operation)
+            .count()
+    }
+
+    /// Forgets everything recorded so far.
+    pub fn clear(&self) {
+        self.records.lock().clear();
+    }
+}
+
+impl RequestObserver for RecordingObserver {
+    fn on_operation(&self, operation: Operation, ctx: &RequestContext, status: StatusCode) {
+        self.records.lock().push(RecordedOperation {
+            operation,
+            account: ctx.account.clone(),
+            container: ctx.container.clone(),
+            blob: ctx.blob.clone(),
+            status,
+        });
+    }
+}
+
+/// Clock that only moves when told to, for tests of lease expiry, SAS
+/// validity and other time-dependent behavior.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    /// Creates a clock stopped at `now`.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+    }
+
+    /// Sets the clock to `now`.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock() = now;
+    }
+}
+
+impl Default for MockClock {
+    /// Creates a clock stopped at the current wall-clock time.
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock()
+    }
+}
+
+/// Request IDs derived from a seed and a counter, so a server built with
+/// [`crate::BlobServerBuilder::deterministic_request_ids`] answers the same
+/// requests with the same IDs on every run.
+#[derive(Debug)]
+pub struct RequestIdSequence {
+    seed: u64,
+    next: AtomicU64,
+}
+
+impl RequestIdSequence {
+    /// Creates a sequence whose first ID is derived from `seed` and 1.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            next: AtomicU64::new(1),
+        }
+    }
+
+    /// Returns the next ID, formatted as a UUID like random request IDs.
+    pub fn next_id(&self) -> String {
+        let counter = self.next.fetch_add(1, Ordering::Relaxed);
+        Uuid::from_u64_pair(self.seed, counter).to_string()
+    }
+}
+
+fn state_file_error(path: &Path, e: std::io::Error) -> StorageError {
+    StorageError::with_message(
+        ErrorCode::InternalError,
+        format!("Failed to open state archive {}: {}", path.display(), e),
+    )
+}
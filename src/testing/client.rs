@@ -0,0 +1,308 @@
+//! A SharedKey-signing HTTP client for tests against a running server.
+//!
+//! Requests are signed with the string-to-sign the server itself verifies
+//! ([`build_string_to_sign`]), so a request the client sends and the server
+//! rejects points at a signing mismatch on one side or the other.
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+use bytes::Bytes;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::auth::{build_string_to_sign, sign_string};
+use crate::config::{DEFAULT_ACCOUNT, DEFAULT_ACCOUNT_KEY, DEFAULT_API_VERSION};
+use crate::context::{format_http_date, RequestContext};
+use crate::error::{ErrorCode, StorageError};
+
+/// Error returned by [`Client`] requests.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The request could not be sent or its response read.
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The request could not be signed.
+    #[error("request could not be signed: {0}")]
+    Signing(#[from] StorageError),
+    /// The service answered with an error status.
+    #[error("{status} {code}: {message}")]
+    Service {
+        status: u16,
+        /// The `x-ms-error-code` header.
+        code: String,
+        /// The `Message` of the error body.
+        message: String,
+    },
+}
+
+impl ClientError {
+    /// Returns the service error code, if the service answered.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Service { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// Client for one account of a running server, signing every request with
+/// the account key.
+///
+/// ```no_run
+/// use azurite_rs::testing::Client;
+///
+/// # async fn example() -> Result<(), azurite_rs::testing::ClientError> {
+/// let client = Client::for_default_account("http://127.0.0.1:10000");
+/// client.create_container("data").await?;
+/// client.put_blob("data", "a.txt", "hello").await?;
+/// assert_eq!(client.get_blob("data", "a.txt").await?, "hello");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    account: String,
+    key: String,
+}
+
+impl Client {
+    /// Creates a client for `account` at `base_url`, the server address
+    /// including any prefix the router is mounted under.
+    pub fn new(base_url: impl Into<String>, account: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            account: account.into(),
+            key: key.into(),
+        }
+    }
+
+    /// Creates a client for the development storage account.
+    pub fn for_default_account(base_url: impl Into<String>) -> Self {
+        Self::new(base_url, DEFAULT_ACCOUNT, DEFAULT_ACCOUNT_KEY)
+    }
+
+    /// Returns the account requests are signed for.
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// Creates a private container.
+    pub async fn create_container(&self, container: &str) -> Result<(), ClientError> {
+        let response = self
+            .send_signed(reqwest::Method::PUT, Some(container), None, &[("restype", "container")], &[], Bytes::new())
+            .await?;
+        check(response).await.map(drop)
+    }
+
+    /// Creates or replaces a block blob with a single Put Blob.
+    pub async fn put_blob(&self, container: &str, blob: &str, data: impl Into<Bytes>) -> Result<(), ClientError> {
+        let headers = [("x-ms-blob-type", "BlockBlob")];
+        let response = self
+            .send_signed(reqwest::Method::PUT, Some(container), Some(blob), &[], &headers, data.into())
+            .await?;
+        check(response).await.map(drop)
+    }
+
+    /// Reads the content of a blob.
+    pub async fn get_blob(&self, container: &str, blob: &str) -> Result<Bytes, ClientError> {
+        let response = self
+            .send_signed(reqwest::Method::GET, Some(container), Some(blob), &[], &[], Bytes::new())
+            .await?;
+        Ok(check(response).await?.bytes().await?)
+    }
+
+    /// Lists the names of the base blobs of a container, following
+    /// continuation markers until the listing is complete.
+    pub async fn list_blobs(&self, container: &str) -> Result<Vec<String>, ClientError> {
+        let mut names = Vec::new();
+        let mut marker = String::new();
+        loop {
+            let mut query = vec![("restype", "container"), ("comp", "list")];
+            if !marker.is_empty() {
+                query.push(("marker", &marker));
+            }
+            let response = self
+                .send_signed(reqwest::Method::GET, Some(container), None, &query, &[], Bytes::new())
+                .await?;
+            let body = check(response).await?.text().await?;
+            let page: BlobListing = quick_xml::de::from_str(&body).map_err(|e| ClientError::Service {
+                status: 200,
+                code: "InvalidXml".to_string(),
+                message: e.to_string(),
+            })?;
+            names.extend(page.blobs.blobs.into_iter().map(|blob| blob.name));
+            match page.next_marker.filter(|next| !next.is_empty()) {
+                Some(next) => marker = next,
+                None => return Ok(names),
+            }
+        }
+    }
+
+    /// Acquires a lease on a blob for `duration` seconds (-1 for infinite)
+    /// and returns its ID.
+    pub async fn acquire_lease(&self, container: &str, blob: &str, duration: i32) -> Result<String, ClientError> {
+        let duration = duration.to_string();
+        let response = self
+            .lease(container, blob, &[("x-ms-lease-action", "acquire"), ("x-ms-lease-duration", &duration)])
+            .await?;
+        Ok(header(&response, "x-ms-lease-id"))
+    }
+
+    /// Renews a lease held on a blob.
+    pub async fn renew_lease(&self, container: &str, blob: &str, lease_id: &str) -> Result<(), ClientError> {
+        self.lease(container, blob, &[("x-ms-lease-action", "renew"), ("x-ms-lease-id", lease_id)])
+            .await
+            .map(drop)
+    }
+
+    /// Releases a lease held on a blob.
+    pub async fn release_lease(&self, container: &str, blob: &str, lease_id: &str) -> Result<(), ClientError> {
+        self.lease(container, blob, &[("x-ms-lease-action", "release"), ("x-ms-lease-id", lease_id)])
+            .await
+            .map(drop)
+    }
+
+    /// Breaks the lease on a blob at once and returns the seconds left
+    /// until it is broken, zero here.
+    pub async fn break_lease(&self, container: &str, blob: &str) -> Result<u32, ClientError> {
+        let response = self
+            .lease(container, blob, &[("x-ms-lease-action", "break"), ("x-ms-lease-break-period", "0")])
+            .await?;
+        Ok(header(&response, "x-ms-lease-time").parse().unwrap_or(0))
+    }
+
+    async fn lease(&self, container: &str, blob: &str, headers: &[(&str, &str)]) -> Result<reqwest::Response, ClientError> {
+        let response = self
+            .send_signed(reqwest::Method::PUT, Some(container), Some(blob), &[("comp", "lease")], headers, Bytes::new())
+            .await?;
+        check(response).await
+    }
+
+    /// Sends any request to the account, a container or a blob, signed with
+    /// SharedKey. `x-ms-date` and `x-ms-version` are added. The response is
+    /// returned whatever its status.
+    pub async fn send_signed(
+        &self,
+        method: reqwest::Method,
+        container: Option<&str>,
+        blob: Option<&str>,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: Bytes,
+    ) -> Result<reqwest::Response, ClientError> {
+        let mut url = Url::parse(&self.base_url).map_err(|e| StorageError::with_message(ErrorCode::InvalidUri, e.to_string()))?;
+        {
+            let mut segments = url.path_segments_mut().map_err(|_| {
+                StorageError::with_message(ErrorCode::InvalidUri, "The base URL cannot have a path")
+            })?;
+            segments.pop_if_empty().push(&self.account);
+            if let Some(container) = container {
+                segments.push(container);
+            }
+            if let Some(blob) = blob {
+                segments.extend(blob.split('/'));
+            }
+        }
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        let mut request = self
+            .http
+            .request(method, url)
+            .header("x-ms-date", format_http_date(&chrono::Utc::now()))
+            .header("x-ms-version", DEFAULT_API_VERSION);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        if !body.is_empty() {
+            request = request.header("content-length", body.len());
+        }
+        let mut request = request.body(body).build()?;
+
+        let authorization = format!("SharedKey {}:{}", self.account, self.signature(&request, container, blob)?);
+        request
+            .headers_mut()
+            .insert("authorization", authorization.parse().expect("signature is a valid header value"));
+        Ok(self.http.execute(request).await?)
+    }
+
+    /// Signs `request` as the server will verify it: from a request context
+    /// built over the same method, URI, headers and query.
+    fn signature(&self, request: &reqwest::Request, container: Option<&str>, blob: Option<&str>) -> Result<String, StorageError> {
+        let invalid = |what: &str| StorageError::with_message(ErrorCode::InvalidInput, format!("Invalid {} to sign", what));
+        let method = Method::from_bytes(request.method().as_str().as_bytes()).map_err(|_| invalid("method"))?;
+        let uri: Uri = request.url().as_str().parse().map_err(|_| invalid("URI"))?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in request.headers() {
+            let name = HeaderName::from_bytes(name.as_str().as_bytes()).map_err(|_| invalid("header"))?;
+            let value = HeaderValue::from_bytes(value.as_bytes()).map_err(|_| invalid("header"))?;
+            headers.append(name, value);
+        }
+        let mut params = HashMap::from([("account".to_string(), self.account.clone())]);
+        if let Some(container) = container {
+            params.insert("container".to_string(), container.to_string());
+        }
+        if let Some(blob) = blob {
+            params.insert("blob".to_string(), blob.to_string());
+        }
+        let query = request
+            .url()
+            .query_pairs()
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+
+        let ctx = RequestContext::new(method, uri, headers, params, query)?;
+        sign_string(&build_string_to_sign(&ctx)?, &self.key)
+    }
+}
+
+/// Turns an error status into [`ClientError::Service`].
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, ClientError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status().as_u16();
+    let code = header(&response, "x-ms-error-code");
+    let body = response.text().await?;
+    let message = body
+        .split_once("<Message>")
+        .and_then(|(_, rest)| rest.split_once("</Message>"))
+        .map(|(message, _)| message.to_string())
+        .unwrap_or(body);
+    Err(ClientError::Service { status, code, message })
+}
+
+fn header(response: &reqwest::Response, name: &str) -> String {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The parts of a List Blobs response the client reads.
+#[derive(Deserialize)]
+struct BlobListing {
+    #[serde(rename = "Blobs")]
+    blobs: ListedBlobs,
+    #[serde(rename = "NextMarker")]
+    next_marker: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListedBlobs {
+    #[serde(rename = "Blob", default)]
+    blobs: Vec<ListedBlob>,
+}
+
+#[derive(Deserialize)]
+struct ListedBlob {
+    #[serde(rename = "Name")]
+    name: String,
+}
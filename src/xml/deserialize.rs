@@ -23,48 +23,92 @@ pub struct BlockListRequest {
 }
 
 impl BlockListRequest {
+    /// Parses a `<BlockList>` document. Its children may only be
+    /// `Committed`, `Uncommitted` and `Latest` elements holding a block ID;
+    /// any other element fails with `UnsupportedXmlNode`, anything that is
+    /// not a well-formed document with a single root with
+    /// `InvalidXmlDocument`. An empty `<BlockList/>` lists no blocks.
     pub fn parse(xml: &str) -> StorageResult<Self> {
         let mut reader = Reader::from_str(xml);
         reader.trim_text(true);
 
         let mut result = Self::default();
         let mut buf = Vec::new();
-        let mut current_element: Option<String> = None;
+        let invalid = || StorageError::new(ErrorCode::InvalidXmlDocument);
+        // Where the reader is: before, inside or after the root, and in
+        // which block element within it
+        let mut root_seen = false;
+        let mut in_root = false;
+        let mut current_element: Option<BlockListType> = None;
 
         loop {
             match reader.read_event_into(&mut buf) {
                 Ok(Event::Start(e)) => {
-                    current_element = Some(String::from_utf8_lossy(e.name().as_ref()).to_string());
+                    let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                    if !in_root {
+                        // A second root is not a well-formed document
+                        if root_seen {
+                            return Err(invalid());
+                        }
+                        if name != "BlockList" {
+                            return Err(StorageError::unsupported_xml_node(&name));
+                        }
+                        root_seen = true;
+                        in_root = true;
+                    } else if current_element.is_some() {
+                        return Err(StorageError::unsupported_xml_node(&name));
+                    } else {
+                        current_element = Some(BlockListType::from_element(&name)?);
+                    }
+                },
+                Ok(Event::Empty(e)) => {
+                    let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                    if !in_root {
+                        if root_seen {
+                            return Err(invalid());
+                        }
+                        if name != "BlockList" {
+                            return Err(StorageError::unsupported_xml_node(&name));
+                        }
+                        root_seen = true;
+                    } else if current_element.is_some() {
+                        return Err(StorageError::unsupported_xml_node(&name));
+                    } else {
+                        // An empty block element names no block
+                        BlockListType::from_element(&name)?;
+                    }
                 },
                 Ok(Event::End(_)) => {
-                    current_element = None;
+                    // Closing a block element stays in the root
+                    in_root = current_element.take().is_some();
                 },
                 Ok(Event::Text(e)) => {
-                    if let Some(ref elem) = current_element {
-                        let block_id = e.unescape().map_err(|_| {
-                            StorageError::new(ErrorCode::InvalidXmlDocument)
-                        })?.to_string();
-
-                        // Skip empty strings (whitespace between elements)
-                        if block_id.trim().is_empty() {
-                            continue;
-                        }
+                    let block_id = e.unescape().map_err(|_| invalid())?.to_string();
 
-                        match elem.as_str() {
-                            "Committed" => result.committed.push(block_id),
-                            "Uncommitted" => result.uncommitted.push(block_id),
-                            "Latest" => result.latest.push(block_id),
-                            _ => {}
-                        }
+                    // Skip empty strings (whitespace between elements)
+                    if block_id.trim().is_empty() {
+                        continue;
+                    }
+
+                    match current_element {
+                        Some(BlockListType::Committed) => result.committed.push(block_id),
+                        Some(BlockListType::Uncommitted) => result.uncommitted.push(block_id),
+                        Some(BlockListType::Latest) => result.latest.push(block_id),
+                        // Text outside of a block element
+                        None => return Err(invalid()),
                     }
                 }
+                Ok(Event::CData(_)) => return Err(invalid()),
                 Ok(Event::Eof) => break,
-                Err(_) => return Err(StorageError::new(ErrorCode::InvalidXmlDocument)),
+                Err(_) => return Err(invalid()),
                 _ => {}
             }
             buf.clear();
         }
 
+        if !root_seen || in_root {
+            return Err(invalid());
+        }
         Ok(result)
     }
 
@@ -91,12 +135,66 @@ pub enum BlockListType {
     Latest,
 }
 
-/// Parses service properties XML.
-pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
+impl BlockListType {
+    /// Returns the block list section an element of a `<BlockList>` opens.
+    fn from_element(name: &str) -> StorageResult<Self> {
+        match name {
+            "Committed" => Ok(Self::Committed),
+            "Uncommitted" => Ok(Self::Uncommitted),
+            "Latest" => Ok(Self::Latest),
+            _ => Err(StorageError::unsupported_xml_node(name)),
+        }
+    }
+}
+
+/// The settings a Set Blob Service Properties request changes: one field
+/// per top-level element, None where the element is omitted.
+#[derive(Debug, Default)]
+pub struct ServicePropertiesUpdate {
+    pub logging: Option<LoggingConfig>,
+    pub hour_metrics: Option<MetricsConfig>,
+    pub minute_metrics: Option<MetricsConfig>,
+    /// Empty for an empty `Cors` element, which removes all rules.
+    pub cors: Option<Vec<CorsRule>>,
+    pub default_service_version: Option<String>,
+    pub delete_retention_policy: Option<DeleteRetentionPolicy>,
+    pub static_website: Option<StaticWebsite>,
+}
+
+impl ServicePropertiesUpdate {
+    /// Replaces each setting of `properties` the request gives, leaving the
+    /// omitted ones as they are.
+    pub fn apply(self, properties: &mut ServiceProperties) {
+        if let Some(logging) = self.logging {
+            properties.logging = logging;
+        }
+        if let Some(hour_metrics) = self.hour_metrics {
+            properties.hour_metrics = hour_metrics;
+        }
+        if let Some(minute_metrics) = self.minute_metrics {
+            properties.minute_metrics = minute_metrics;
+        }
+        if let Some(cors) = self.cors {
+            properties.cors = cors;
+        }
+        if let Some(version) = self.default_service_version {
+            properties.default_service_version = Some(version);
+        }
+        if let Some(policy) = self.delete_retention_policy {
+            properties.delete_retention_policy = policy;
+        }
+        if let Some(static_website) = self.static_website {
+            properties.static_website = static_website;
+        }
+    }
+}
+
+/// Parses service properties XML into the settings it changes.
+pub fn parse_service_properties(xml: &str) -> StorageResult<ServicePropertiesUpdate> {
     let mut reader = Reader::from_str(xml);
     reader.trim_text(true);
 
-    let mut props = ServiceProperties::default();
+    let mut props = ServicePropertiesUpdate::default();
     let mut buf = Vec::new();
     let mut path: Vec<String> = Vec::new();
     let mut current_text = String::new();
@@ -146,7 +244,7 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                         retention = RetentionPolicy::default();
                     }
                     [_, "Logging"] if name == "Logging" => {
-                        props.logging = logging.clone();
+                        props.logging = Some(logging.clone());
                     }
                     [_, "HourMetrics", "Version"] if name == "Version" => {
                         hour_metrics.version = current_text.clone();
@@ -168,7 +266,7 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                         retention = RetentionPolicy::default();
                     }
                     [_, "HourMetrics"] if name == "HourMetrics" => {
-                        props.hour_metrics = hour_metrics.clone();
+                        props.hour_metrics = Some(hour_metrics.clone());
                     }
                     [_, "MinuteMetrics", "Version"] if name == "Version" => {
                         minute_metrics.version = current_text.clone();
@@ -194,7 +292,7 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                         retention = RetentionPolicy::default();
                     }
                     [_, "MinuteMetrics"] if name == "MinuteMetrics" => {
-                        props.minute_metrics = minute_metrics.clone();
+                        props.minute_metrics = Some(minute_metrics.clone());
                     }
                     [_, "Cors", "CorsRule", "AllowedOrigins"]
                         if name == "AllowedOrigins" =>
@@ -223,15 +321,20 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                     [_, "Cors", "CorsRule", "MaxAgeInSeconds"]
                         if name == "MaxAgeInSeconds" =>
                     {
-                        current_cors_rule.max_age_in_seconds =
-                            current_text.parse().unwrap_or(0);
+                        current_cors_rule.max_age_in_seconds = current_text.parse().map_err(|_| {
+                            StorageError::invalid_xml_node_value(
+                                "MaxAgeInSeconds",
+                                &current_text,
+                                "The value must be a non-negative integer.",
+                            )
+                        })?;
                     }
                     [_, "Cors", "CorsRule"] if name == "CorsRule" => {
                         cors_rules.push(current_cors_rule.clone());
                         current_cors_rule = CorsRule::default();
                     }
                     [_, "Cors"] if name == "Cors" => {
-                        props.cors = cors_rules.clone();
+                        props.cors = Some(cors_rules.clone());
                     }
                     [_, "DefaultServiceVersion"] if name == "DefaultServiceVersion" => {
                         props.default_service_version = Some(current_text.clone());
@@ -240,10 +343,16 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                         delete_retention.enabled = current_text == "true";
                     }
                     [_, "DeleteRetentionPolicy", "Days"] if name == "Days" => {
-                        delete_retention.days = current_text.parse().ok();
+                        delete_retention.days = Some(current_text.parse().map_err(|_| {
+                            StorageError::invalid_xml_node_value(
+                                "Days",
+                                &current_text,
+                                "The value must be an integer.",
+                            )
+                        })?);
                     }
                     [_, "DeleteRetentionPolicy"] if name == "DeleteRetentionPolicy" => {
-                        props.delete_retention_policy = delete_retention.clone();
+                        props.delete_retention_policy = Some(delete_retention.clone());
                     }
                     [_, "StaticWebsite", "Enabled"] if name == "Enabled" => {
                         static_website.enabled = current_text == "true";
@@ -257,7 +366,7 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                         static_website.error_document_404_path = Some(current_text.clone());
                     }
                     [_, "StaticWebsite"] if name == "StaticWebsite" => {
-                        props.static_website = static_website.clone();
+                        props.static_website = Some(static_website.clone());
                     }
                     _ => {}
                 }
@@ -265,6 +374,9 @@ pub fn parse_service_properties(xml: &str) -> StorageResult<ServiceProperties> {
                 path.pop();
                 current_text.clear();
             }
+            Ok(Event::Empty(e)) if path.len() == 1 && e.name().as_ref() == b"Cors" => {
+                props.cors = Some(Vec::new());
+            }
             Ok(Event::Text(e)) => {
                 current_text = e.unescape().map_err(|_| {
                     StorageError::new(ErrorCode::InvalidXmlDocument)
@@ -443,3 +555,73 @@ pub fn parse_user_delegation_key_request(xml: &str) -> StorageResult<(String, St
 
     Ok((start, expiry))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::BlockListRequest;
+    use crate::error::ErrorCode;
+
+    fn parse_error(xml: &str) -> ErrorCode {
+        BlockListRequest::parse(xml).expect_err(xml).code
+    }
+
+    #[test]
+    fn test_block_list_sections() {
+        let list = BlockListRequest::parse(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <BlockList>
+                <Committed>YQ==</Committed>
+                <Uncommitted>Yg==</Uncommitted>
+                <Latest>Yw==</Latest>
+                <Latest>ZA==</Latest>
+            </BlockList>"#,
+        )
+        .unwrap();
+        assert_eq!(list.committed, ["YQ=="]);
+        assert_eq!(list.uncommitted, ["Yg=="]);
+        assert_eq!(list.latest, ["Yw==", "ZA=="]);
+    }
+
+    #[test]
+    fn test_empty_block_list() {
+        for xml in ["<BlockList/>", "<BlockList></BlockList>", r#"<?xml version="1.0"?><BlockList>  </BlockList>"#] {
+            let list = BlockListRequest::parse(xml).unwrap();
+            assert!(list.all_blocks().is_empty(), "{}", xml);
+        }
+    }
+
+    #[test]
+    fn test_malformed_block_list() {
+        for xml in [
+            "",
+            "not xml",
+            "<BlockList><Latest>YQ==</Latest>",
+            "<BlockList><Latest>YQ==</Committed></BlockList>",
+            "<BlockList></BlockList><BlockList></BlockList>",
+            "<BlockList>YQ==</BlockList>",
+        ] {
+            assert_eq!(parse_error(xml), ErrorCode::InvalidXmlDocument, "{}", xml);
+        }
+    }
+
+    #[test]
+    fn test_block_list_wrong_root() {
+        let error = BlockListRequest::parse("<BlockLists><Latest>YQ==</Latest></BlockLists>").unwrap_err();
+        assert_eq!(error.code, ErrorCode::UnsupportedXmlNode);
+        assert_eq!(error.details, [("XmlNodeName".to_string(), "BlockLists".to_string())]);
+        assert_eq!(parse_error("<Latest>YQ==</Latest>"), ErrorCode::UnsupportedXmlNode);
+    }
+
+    #[test]
+    fn test_block_list_unknown_child() {
+        let error = BlockListRequest::parse("<BlockList><Latest>YQ==</Latest><Lastest>Yg==</Lastest></BlockList>")
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::UnsupportedXmlNode);
+        assert_eq!(error.details, [("XmlNodeName".to_string(), "Lastest".to_string())]);
+        assert_eq!(parse_error("<BlockList><Block/></BlockList>"), ErrorCode::UnsupportedXmlNode);
+        assert_eq!(
+            parse_error("<BlockList><Latest><Id>YQ==</Id></Latest></BlockList>"),
+            ErrorCode::UnsupportedXmlNode
+        );
+    }
+}
@@ -1,12 +1,12 @@
 //! XML response serialization for Azure Blob Storage API.
 
-use crate::context::format_http_date;
+use crate::context::{format_http_date, ListParams};
 use crate::models::{
     AccessTier, BlobModel, BlobType, BlockModel, BlockState, ContainerModel,
     CorsRule, DeleteRetentionPolicy, GeoReplicationStatus, LeaseState, LeaseStatus,
-    LoggingConfig, MetricsConfig, PageRange, PageRangeDiff, PublicAccessLevel,
-    RetentionPolicy, ServiceProperties, ServiceStats, SignedIdentifier, StaticWebsite,
-    UserDelegationKey,
+    LoggingConfig, MetricsConfig, ObjectReplicationRule, PageRange, PageRangeDiff,
+    PublicAccessLevel, RetentionPolicy, ServiceProperties, ServiceStats, SignedIdentifier,
+    StaticWebsite, UserDelegationKey, OBJECT_REPLICATION_STATUS,
 };
 
 /// Escapes special XML characters.
@@ -25,13 +25,14 @@ pub fn serialize_container_list(
     marker: Option<&str>,
     maxresults: u32,
     next_marker: Option<&str>,
-    account: &str,
+    service_endpoint: &str,
+    include_metadata: bool,
 ) -> String {
     let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
     xml.push_str("<EnumerationResults");
     xml.push_str(&format!(
-        r#" ServiceEndpoint="http://127.0.0.1:10000/{}/""#,
-        xml_escape(account)
+        r#" ServiceEndpoint="{}""#,
+        xml_escape(service_endpoint)
     ));
     xml.push('>');
 
@@ -45,7 +46,7 @@ pub fn serialize_container_list(
 
     xml.push_str("<Containers>");
     for container in containers {
-        xml.push_str(&serialize_container(container));
+        xml.push_str(&serialize_container(container, include_metadata));
     }
     xml.push_str("</Containers>");
 
@@ -58,9 +59,15 @@ pub fn serialize_container_list(
 }
 
 /// Serializes a single container for list results.
-fn serialize_container(container: &ContainerModel) -> String {
+fn serialize_container(container: &ContainerModel, include_metadata: bool) -> String {
     let mut xml = String::from("<Container>");
     xml.push_str(&format!("<Name>{}</Name>", xml_escape(&container.name)));
+    if container.deleted {
+        xml.push_str("<Deleted>true</Deleted>");
+        if let Some(ref version) = container.deleted_version {
+            xml.push_str(&format!("<Version>{}</Version>", xml_escape(version)));
+        }
+    }
     xml.push_str("<Properties>");
     xml.push_str(&format!(
         "<Last-Modified>{}</Last-Modified>",
@@ -78,6 +85,9 @@ fn serialize_container(container: &ContainerModel) -> String {
         "<LeaseState>{}</LeaseState>",
         container.properties.lease_state.as_str()
     ));
+    if let Some(duration) = container.properties.active_lease_duration() {
+        xml.push_str(&format!("<LeaseDuration>{}</LeaseDuration>", duration.as_str()));
+    }
     if container.properties.public_access != PublicAccessLevel::None {
         xml.push_str(&format!(
             "<PublicAccess>{}</PublicAccess>",
@@ -92,9 +102,15 @@ fn serialize_container(container: &ContainerModel) -> String {
         "<HasLegalHold>{}</HasLegalHold>",
         container.properties.has_legal_hold
     ));
+    if let Some(deleted_time) = container.deleted_time {
+        xml.push_str(&format!("<DeletedTime>{}</DeletedTime>", format_http_date(&deleted_time)));
+    }
+    if let Some(days) = container.remaining_retention_days {
+        xml.push_str(&format!("<RemainingRetentionDays>{}</RemainingRetentionDays>", days));
+    }
     xml.push_str("</Properties>");
 
-    if !container.metadata.is_empty() {
+    if include_metadata && !container.metadata.is_empty() {
         xml.push_str("<Metadata>");
         for (key, value) in &container.metadata {
             xml.push_str(&format!(
@@ -111,23 +127,31 @@ fn serialize_container(container: &ContainerModel) -> String {
     xml
 }
 
+/// Where a page of List Blobs results sits: the listing parameters it
+/// echoes, the marker of the next page and the endpoint it was listed at.
+#[derive(Debug, Clone, Copy)]
+pub struct BlobListPage<'a> {
+    pub prefix: Option<&'a str>,
+    pub delimiter: Option<&'a str>,
+    pub marker: Option<&'a str>,
+    pub maxresults: u32,
+    pub next_marker: Option<&'a str>,
+    pub service_endpoint: &'a str,
+}
+
 /// Serializes a list of blobs to XML.
 pub fn serialize_blob_list(
     blobs: &[BlobModel],
     blob_prefixes: &[String],
-    prefix: Option<&str>,
-    delimiter: Option<&str>,
-    marker: Option<&str>,
-    maxresults: u32,
-    next_marker: Option<&str>,
-    account: &str,
+    page: &BlobListPage,
     container: &str,
+    includes: &BlobListIncludes,
 ) -> String {
     let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
     xml.push_str("<EnumerationResults");
     xml.push_str(&format!(
-        r#" ServiceEndpoint="http://127.0.0.1:10000/{}/""#,
-        xml_escape(account)
+        r#" ServiceEndpoint="{}""#,
+        xml_escape(page.service_endpoint)
     ));
     xml.push_str(&format!(
         r#" ContainerName="{}""#,
@@ -135,20 +159,20 @@ pub fn serialize_blob_list(
     ));
     xml.push('>');
 
-    if let Some(p) = prefix {
+    if let Some(p) = page.prefix {
         xml.push_str(&format!("<Prefix>{}</Prefix>", xml_escape(p)));
     }
-    if let Some(m) = marker {
+    if let Some(m) = page.marker {
         xml.push_str(&format!("<Marker>{}</Marker>", xml_escape(m)));
     }
-    xml.push_str(&format!("<MaxResults>{}</MaxResults>", maxresults));
-    if let Some(d) = delimiter {
+    xml.push_str(&format!("<MaxResults>{}</MaxResults>", page.maxresults));
+    if let Some(d) = page.delimiter {
         xml.push_str(&format!("<Delimiter>{}</Delimiter>", xml_escape(d)));
     }
 
     xml.push_str("<Blobs>");
     for blob in blobs {
-        xml.push_str(&serialize_blob(blob));
+        xml.push_str(&serialize_blob(blob, includes));
     }
     for prefix in blob_prefixes {
         xml.push_str(&format!(
@@ -158,7 +182,7 @@ pub fn serialize_blob_list(
     }
     xml.push_str("</Blobs>");
 
-    if let Some(nm) = next_marker {
+    if let Some(nm) = page.next_marker {
         xml.push_str(&format!("<NextMarker>{}</NextMarker>", xml_escape(nm)));
     }
 
@@ -166,8 +190,44 @@ pub fn serialize_blob_list(
     xml
 }
 
+/// Datasets a List Blobs request asked for with `include`, deciding which
+/// optional elements [`serialize_blob_list`] writes for each blob.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlobListIncludes {
+    pub copy: bool,
+    pub metadata: bool,
+    pub tags: bool,
+    pub deleted: bool,
+    pub versions: bool,
+    pub immutability_policy: bool,
+    pub legal_hold: bool,
+    /// Only honored by servers simulating object replication.
+    pub object_replication: bool,
+}
+
+impl BlobListIncludes {
+    /// Reads the datasets from the `include` values of a listing.
+    pub fn from_params(params: &ListParams) -> Self {
+        Self {
+            copy: params.includes("copy"),
+            metadata: params.includes("metadata"),
+            tags: params.includes("tags"),
+            deleted: params.includes("deleted"),
+            versions: params.includes("versions"),
+            immutability_policy: params.includes("immutabilitypolicy"),
+            legal_hold: params.includes("legalhold"),
+            object_replication: params.includes("objectreplicationmetadata"),
+        }
+    }
+}
+
 /// Serializes a single blob for list results.
-fn serialize_blob(blob: &BlobModel) -> String {
+///
+/// Every element a listing can carry is written here. Properties with no
+/// value are left out, and those tied to an `include` dataset are only
+/// written when `includes` asks for it.
+fn serialize_blob(blob: &BlobModel, includes: &BlobListIncludes) -> String {
+    let props = &blob.properties;
     let mut xml = String::from("<Blob>");
     xml.push_str(&format!("<Name>{}</Name>", xml_escape(&blob.name)));
 
@@ -177,94 +237,137 @@ fn serialize_blob(blob: &BlobModel) -> String {
             xml_escape(&blob.snapshot)
         ));
     }
+    if includes.versions {
+        if let Some(ref version_id) = props.version_id {
+            xml.push_str(&format!("<VersionId>{}</VersionId>", xml_escape(version_id)));
+        }
+        if let Some(current) = props.is_current_version {
+            xml.push_str(&format!("<IsCurrentVersion>{}</IsCurrentVersion>", current));
+        }
+    }
+    if includes.deleted && blob.deleted {
+        xml.push_str("<Deleted>true</Deleted>");
+    }
 
     xml.push_str("<Properties>");
     xml.push_str(&format!(
         "<Creation-Time>{}</Creation-Time>",
-        format_http_date(&blob.properties.created_on)
+        format_http_date(&props.created_on)
     ));
     xml.push_str(&format!(
         "<Last-Modified>{}</Last-Modified>",
-        format_http_date(&blob.properties.last_modified)
+        format_http_date(&props.last_modified)
     ));
     xml.push_str(&format!(
         "<Etag>{}</Etag>",
-        xml_escape(&blob.properties.etag)
+        xml_escape(&props.etag)
     ));
     xml.push_str(&format!(
         "<Content-Length>{}</Content-Length>",
-        blob.properties.content_length
+        props.content_length
     ));
-    if let Some(ref ct) = blob.properties.content_type {
+    if let Some(ref ct) = props.content_type {
         xml.push_str(&format!("<Content-Type>{}</Content-Type>", xml_escape(ct)));
     }
-    if let Some(ref ce) = blob.properties.content_encoding {
+    if let Some(ref ce) = props.content_encoding {
         xml.push_str(&format!(
             "<Content-Encoding>{}</Content-Encoding>",
             xml_escape(ce)
         ));
     }
-    if let Some(ref cl) = blob.properties.content_language {
+    if let Some(ref cl) = props.content_language {
         xml.push_str(&format!(
             "<Content-Language>{}</Content-Language>",
             xml_escape(cl)
         ));
     }
-    if let Some(ref md5) = blob.properties.content_md5 {
+    if let Some(ref md5) = props.content_md5 {
         xml.push_str(&format!("<Content-MD5>{}</Content-MD5>", xml_escape(md5)));
     }
-    if let Some(ref cd) = blob.properties.content_disposition {
+    if let Some(ref cd) = props.content_disposition {
         xml.push_str(&format!(
             "<Content-Disposition>{}</Content-Disposition>",
             xml_escape(cd)
         ));
     }
-    if let Some(ref cc) = blob.properties.cache_control {
+    if let Some(ref cc) = props.cache_control {
         xml.push_str(&format!(
             "<Cache-Control>{}</Cache-Control>",
             xml_escape(cc)
         ));
     }
+    if props.blob_type == BlobType::PageBlob {
+        if let Some(seq) = props.sequence_number {
+            xml.push_str(&format!(
+                "<x-ms-blob-sequence-number>{}</x-ms-blob-sequence-number>",
+                seq
+            ));
+        }
+    }
     xml.push_str(&format!(
         "<BlobType>{}</BlobType>",
-        blob.properties.blob_type.as_str()
+        props.blob_type.as_str()
     ));
-    xml.push_str(&format!(
-        "<AccessTier>{}</AccessTier>",
-        blob.properties.access_tier.as_str()
-    ));
-    xml.push_str("<AccessTierInferred>true</AccessTierInferred>");
+    if props.access_tier.is_reported_for(props.blob_type) {
+        xml.push_str(&format!(
+            "<AccessTier>{}</AccessTier>",
+            props.access_tier.as_str()
+        ));
+        xml.push_str("<AccessTierInferred>true</AccessTierInferred>");
+    }
     xml.push_str(&format!(
         "<LeaseStatus>{}</LeaseStatus>",
-        blob.properties.lease_status.as_str()
+        props.lease_status.as_str()
     ));
     xml.push_str(&format!(
         "<LeaseState>{}</LeaseState>",
-        blob.properties.lease_state.as_str()
+        props.lease_state.as_str()
     ));
+    if let Some(duration) = props.active_lease_duration() {
+        xml.push_str(&format!("<LeaseDuration>{}</LeaseDuration>", duration.as_str()));
+    }
+    if includes.copy {
+        serialize_copy_properties(&mut xml, blob);
+    }
     xml.push_str(&format!(
         "<ServerEncrypted>{}</ServerEncrypted>",
-        blob.properties.server_encrypted
+        props.server_encrypted
     ));
-
-    if blob.properties.blob_type == BlobType::PageBlob {
-        if let Some(seq) = blob.properties.sequence_number {
+    if blob.deleted {
+        if let Some(ref deleted_time) = blob.deleted_time {
+            xml.push_str(&format!("<DeletedTime>{}</DeletedTime>", format_http_date(deleted_time)));
+        }
+        if let Some(days) = blob.remaining_retention_days {
+            xml.push_str(&format!("<RemainingRetentionDays>{}</RemainingRetentionDays>", days));
+        }
+    }
+    if !blob.tags.is_empty() {
+        xml.push_str(&format!("<TagCount>{}</TagCount>", blob.tags.len()));
+    }
+    if let Some(priority) = props.rehydrate_priority {
+        xml.push_str(&format!("<RehydratePriority>{}</RehydratePriority>", priority.as_str()));
+    }
+    if let Some(ref accessed_on) = props.last_accessed_on {
+        xml.push_str(&format!("<LastAccessTime>{}</LastAccessTime>", format_http_date(accessed_on)));
+    }
+    if includes.immutability_policy {
+        if let Some(ref until) = props.immutability_policy_until {
             xml.push_str(&format!(
-                "<x-ms-blob-sequence-number>{}</x-ms-blob-sequence-number>",
-                seq
+                "<ImmutabilityPolicyUntilDate>{}</ImmutabilityPolicyUntilDate>",
+                format_http_date(until)
             ));
         }
     }
-
-    if blob.properties.blob_type == BlobType::AppendBlob {
-        if let Some(sealed) = blob.properties.is_sealed {
-            xml.push_str(&format!("<Sealed>{}</Sealed>", sealed));
-        }
+    if includes.legal_hold {
+        xml.push_str(&format!("<LegalHold>{}</LegalHold>", props.legal_hold));
+    }
+    if let Some(count) = blob.committed_block_count() {
+        xml.push_str(&format!("<CommittedBlockCount>{}</CommittedBlockCount>", count));
+        xml.push_str(&format!("<Sealed>{}</Sealed>", props.is_sealed.unwrap_or(false)));
     }
-
     xml.push_str("</Properties>");
 
-    if !blob.metadata.is_empty() {
+    if includes.metadata && !blob.metadata.is_empty() {
         xml.push_str("<Metadata>");
         for (key, value) in &blob.metadata {
             xml.push_str(&format!(
@@ -277,7 +380,7 @@ fn serialize_blob(blob: &BlobModel) -> String {
         xml.push_str("</Metadata>");
     }
 
-    if !blob.tags.is_empty() {
+    if includes.tags && !blob.tags.is_empty() {
         xml.push_str("<Tags><TagSet>");
         for (key, value) in &blob.tags {
             xml.push_str(&format!(
@@ -289,10 +392,50 @@ fn serialize_blob(blob: &BlobModel) -> String {
         xml.push_str("</TagSet></Tags>");
     }
 
+    if includes.object_replication {
+        let rule = ObjectReplicationRule::for_container(&blob.account, &blob.container);
+        xml.push_str(&format!(
+            "<OrMetadata><Or-{id}>{status}</Or-{id}></OrMetadata>",
+            id = rule.id(),
+            status = OBJECT_REPLICATION_STATUS
+        ));
+    }
+
     xml.push_str("</Blob>");
     xml
 }
 
+/// Writes the copy properties of a blob created by Copy Blob.
+fn serialize_copy_properties(xml: &mut String, blob: &BlobModel) {
+    let props = &blob.properties;
+    let Some(ref copy_id) = props.copy_id else {
+        return;
+    };
+
+    xml.push_str(&format!("<CopyId>{}</CopyId>", xml_escape(copy_id)));
+    if let Some(ref status) = props.copy_status {
+        xml.push_str(&format!("<CopyStatus>{}</CopyStatus>", status.as_str()));
+    }
+    if let Some(ref source) = props.copy_source {
+        xml.push_str(&format!("<CopySource>{}</CopySource>", xml_escape(source)));
+    }
+    if let Some(ref progress) = props.copy_progress {
+        xml.push_str(&format!("<CopyProgress>{}</CopyProgress>", xml_escape(progress)));
+    }
+    if let Some(ref completion_time) = props.copy_completion_time {
+        xml.push_str(&format!(
+            "<CopyCompletionTime>{}</CopyCompletionTime>",
+            format_http_date(completion_time)
+        ));
+    }
+    if let Some(ref description) = props.copy_status_description {
+        xml.push_str(&format!(
+            "<CopyStatusDescription>{}</CopyStatusDescription>",
+            xml_escape(description)
+        ));
+    }
+}
+
 /// Serializes a block list to XML.
 pub fn serialize_block_list(
     committed: &[BlockModel],
@@ -324,7 +467,7 @@ pub fn serialize_block_list(
 }
 
 /// Serializes page ranges to XML.
-pub fn serialize_page_ranges(ranges: &[PageRange]) -> String {
+pub fn serialize_page_ranges(ranges: &[PageRange], next_marker: Option<&str>) -> String {
     let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
     xml.push_str("<PageList>");
     for range in ranges {
@@ -333,12 +476,13 @@ pub fn serialize_page_ranges(ranges: &[PageRange]) -> String {
         xml.push_str(&format!("<End>{}</End>", range.end));
         xml.push_str("</PageRange>");
     }
+    push_page_list_marker(&mut xml, next_marker);
     xml.push_str("</PageList>");
     xml
 }
 
 /// Serializes page range diff to XML.
-pub fn serialize_page_ranges_diff(ranges: &[PageRangeDiff]) -> String {
+pub fn serialize_page_ranges_diff(ranges: &[PageRangeDiff], next_marker: Option<&str>) -> String {
     let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
     xml.push_str("<PageList>");
     for range in ranges {
@@ -354,10 +498,17 @@ pub fn serialize_page_ranges_diff(ranges: &[PageRangeDiff]) -> String {
             xml.push_str("</PageRange>");
         }
     }
+    push_page_list_marker(&mut xml, next_marker);
     xml.push_str("</PageList>");
     xml
 }
 
+fn push_page_list_marker(xml: &mut String, next_marker: Option<&str>) {
+    if let Some(marker) = next_marker {
+        xml.push_str(&format!("<NextMarker>{}</NextMarker>", xml_escape(marker)));
+    }
+}
+
 /// Serializes service properties to XML.
 pub fn serialize_service_properties(props: &ServiceProperties) -> String {
     let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
@@ -0,0 +1,306 @@
+//! Attempts to reach one account's data through another account's requests.
+
+mod common;
+
+use azurite_rs::config::AccountConfig;
+use azurite_rs::models::{BlobProperties, PublicAccessLevel};
+use azurite_rs::{BlobServerBuilder, Config};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use common::TestServer;
+
+const OTHER_ACCOUNT: &str = "tenantb";
+/// An account configured with the same key as the default one.
+const SHARED_KEY_ACCOUNT: &str = "tenantc";
+
+fn date() -> String {
+    chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Starts a server with the default account and two more, seeding private
+/// and public containers in `tenantb` and a private one in `tenantc`.
+async fn start(cross_account_copy: bool) -> (TestServer, String) {
+    start_with(|builder| builder.cross_account_copy(cross_account_copy)).await
+}
+
+/// Like [`start`], with further settings made by `configure`.
+async fn start_with(configure: impl FnOnce(BlobServerBuilder) -> BlobServerBuilder) -> (TestServer, String) {
+    let mut config = Config::default();
+    let other_key = BASE64.encode([0x5b; 64]);
+    config.accounts.push(AccountConfig {
+        name: OTHER_ACCOUNT.to_string(),
+        key: other_key.clone(),
+    });
+    config.accounts.push(AccountConfig {
+        name: SHARED_KEY_ACCOUNT.to_string(),
+        key: config.accounts[0].key.clone(),
+    });
+    let server = TestServer::start_with(configure(BlobServerBuilder::new().config(config))).await;
+
+    let fixtures = &server.fixtures;
+    fixtures.seed_container(&server.account, "mine").await.unwrap();
+    for account in [OTHER_ACCOUNT, SHARED_KEY_ACCOUNT] {
+        fixtures.seed_container(account, "secret").await.unwrap();
+        fixtures
+            .seed_blob(account, "secret", "data.txt", "secret data".into(), BlobProperties::default())
+            .await
+            .unwrap();
+    }
+    let mut public = fixtures.seed_container(OTHER_ACCOUNT, "public").await.unwrap();
+    public.properties.public_access = PublicAccessLevel::Blob;
+    fixtures.metadata().update_container(public).await.unwrap();
+    fixtures
+        .seed_blob(OTHER_ACCOUNT, "public", "open.txt", "public data".into(), BlobProperties::default())
+        .await
+        .unwrap();
+
+    (server, other_key)
+}
+
+/// Starts an async Copy Blob into `mine/{name}` of the default account.
+async fn copy(server: &TestServer, name: &str, source: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(server.blob_url("mine", name))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-copy-source", source)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn query_string(params: &[(String, String)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new()).extend_pairs(params).finish()
+}
+
+#[tokio::test]
+async fn test_shared_key_cannot_address_another_account() {
+    let (server, _) = start(false).await;
+    let client = reqwest::Client::new();
+
+    // Signed with the default account's key over the other account's path
+    for account in [OTHER_ACCOUNT, SHARED_KEY_ACCOUNT] {
+        let path = format!("/{}/secret/data.txt", account);
+        let date = date();
+        let auth = common::create_auth_header("GET", &server.account, &server.key, &path, &[], None, None, &date, &[]);
+        let response = client
+            .get(format!("{}{}", server.base_url, path))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", &date)
+            .header("Authorization", auth)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403, "account={}", account);
+        assert_ne!(response.text().await.unwrap(), "secret data");
+    }
+}
+
+#[tokio::test]
+async fn test_sas_cannot_address_another_account() {
+    let (server, _) = start(false).await;
+    let client = reqwest::Client::new();
+
+    // A SAS for the default account's container, replayed against the same
+    // container name of an account sharing its key
+    server.fixtures.seed_container(&server.account, "secret").await.unwrap();
+    let sas = common::create_blob_sas(&server.account, &server.key, "secret", None, "rl", &[]);
+    let response = client
+        .get(format!("{}/{}/secret/data.txt", server.base_url, SHARED_KEY_ACCOUNT))
+        .query(&sas)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["x-ms-error-code"], "AuthenticationFailed");
+
+    let response = client
+        .get(format!("{}/{}/secret", server.base_url, SHARED_KEY_ACCOUNT))
+        .query(&[("restype", "container"), ("comp", "list")])
+        .query(&sas)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert!(!response.text().await.unwrap().contains("data.txt"));
+}
+
+#[tokio::test]
+async fn test_copy_across_accounts_disabled() {
+    let (server, _) = start(false).await;
+
+    for source in [
+        format!("{}/{}/secret/data.txt", server.base_url, OTHER_ACCOUNT),
+        format!("/{}/public/open.txt", OTHER_ACCOUNT),
+    ] {
+        let response = copy(&server, "stolen.txt", &source).await;
+        assert_eq!(response.status(), 400, "source={}", source);
+        assert_eq!(response.headers()["x-ms-error-code"], "CopyAcrossAccountsNotSupported");
+    }
+    assert!(server.fixtures.blob(&server.account, "mine", "stolen.txt").await.is_err());
+
+    // Copies within the account are unaffected
+    server
+        .fixtures
+        .seed_blob(&server.account, "mine", "own.txt", "own data".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let response = copy(&server, "own-copy.txt", &server.blob_url("mine", "own.txt")).await;
+    assert_eq!(response.status(), 202);
+}
+
+#[tokio::test]
+async fn test_copy_across_accounts_needs_source_authorization() {
+    let (server, other_key) = start(true).await;
+    let fixtures = &server.fixtures;
+    let source_url = format!("{}/{}/secret/data.txt", server.base_url, OTHER_ACCOUNT);
+
+    // No SAS on a private source, or a SAS signed with another account's key
+    let forged = common::create_blob_sas(OTHER_ACCOUNT, &server.key, "secret", Some("data.txt"), "r", &[]);
+    let without_read = common::create_blob_sas(OTHER_ACCOUNT, &other_key, "secret", Some("data.txt"), "w", &[]);
+    for source in [
+        source_url.clone(),
+        format!("{}?{}", source_url, query_string(&forged)),
+        format!("{}?{}", source_url, query_string(&without_read)),
+    ] {
+        let response = copy(&server, "stolen.txt", &source).await;
+        assert_eq!(response.status(), 403, "source={}", source);
+        assert_eq!(response.headers()["x-ms-error-code"], "CannotVerifyCopySource");
+    }
+    assert!(fixtures.blob(&server.account, "mine", "stolen.txt").await.is_err());
+
+    // A SAS of the source account authorizes the copy, which gets its own data
+    let sas = common::create_blob_sas(OTHER_ACCOUNT, &other_key, "secret", Some("data.txt"), "r", &[]);
+    let response = copy(&server, "copied.txt", &format!("{}?{}", source_url, query_string(&sas))).await;
+    assert_eq!(response.status(), 202);
+    let copied = fixtures.blob(&server.account, "mine", "copied.txt").await.unwrap();
+    let source = fixtures.blob(OTHER_ACCOUNT, "secret", "data.txt").await.unwrap();
+    assert_ne!(copied.extent_chunks[0].id, source.extent_chunks[0].id);
+
+    // Deleting the source leaves the copy intact
+    let sas = common::create_blob_sas(OTHER_ACCOUNT, &other_key, "secret", Some("data.txt"), "d", &[]);
+    let response = reqwest::Client::new()
+        .delete(format!("{}?{}", source_url, query_string(&sas)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(fixtures.blob_data(&server.account, "mine", "copied.txt").await.unwrap(), "secret data");
+
+    // Public sources need no SAS
+    let response = copy(&server, "public.txt", &format!("{}/{}/public/open.txt", server.base_url, OTHER_ACCOUNT)).await;
+    assert_eq!(response.status(), 202);
+    assert_eq!(fixtures.blob_data(&server.account, "mine", "public.txt").await.unwrap(), "public data");
+}
+
+#[tokio::test]
+async fn test_copy_across_accounts_copies_shared_page_extents_once() {
+    use azurite_rs::models::{BlobModel, BlobType, ExtentChunk, PersistencyPageRange};
+    use azurite_rs::ExtentStore;
+
+    let (server, _) = start(true).await;
+    let data: Vec<u8> = (0..1024).map(|i| (i / 4) as u8).collect();
+    let extent = server.extents.write(data.clone().into()).await.unwrap();
+
+    // Two page ranges with a cleared range between them, in one extent
+    let mut disk = BlobModel::new(
+        OTHER_ACCOUNT.to_string(),
+        "public".to_string(),
+        "disk.vhd".to_string(),
+        BlobType::PageBlob,
+        1024,
+        chrono::Utc::now(),
+    );
+    disk.extent_chunks = vec![extent.clone()];
+    disk.page_ranges = vec![
+        PersistencyPageRange::new(0, 255, Some(ExtentChunk::new(extent.id.clone(), 0, 256))),
+        PersistencyPageRange::new(512, 1023, Some(ExtentChunk::new(extent.id.clone(), 512, 512))),
+    ];
+    server.fixtures.metadata().create_blob(disk).await.unwrap();
+
+    let extents_before = server.extents.stats().await.extents;
+    let response = copy(&server, "disk.vhd", &format!("{}/{}/public/disk.vhd", server.base_url, OTHER_ACCOUNT)).await;
+    assert_eq!(response.status(), 202);
+    assert_eq!(server.extents.stats().await.extents, extents_before + 1);
+
+    let copied = server.fixtures.blob(&server.account, "mine", "disk.vhd").await.unwrap();
+    let copy_id = &copied.extent_chunks[0].id;
+    assert_ne!(copy_id, &extent.id);
+    for range in &copied.page_ranges {
+        assert_eq!(&range.extent_chunk.as_ref().unwrap().id, copy_id);
+    }
+
+    let response = reqwest::Client::new()
+        .get(server.blob_url("mine", "disk.vhd"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    let mut expected = data;
+    expected[256..512].fill(0);
+    assert_eq!(response.bytes().await.unwrap().to_vec(), expected);
+}
+
+#[tokio::test]
+async fn test_copy_across_accounts_authorized_by_custom_authenticator() {
+    let (server, _) = start_with(|builder| {
+        builder.cross_account_copy(true).with_authenticator(|ctx, _| {
+            if ctx.account != OTHER_ACCOUNT || ctx.query_params.get("token").is_some_and(|token| token == "letmein") {
+                Ok(())
+            } else {
+                Err(azurite_rs::StorageError::authentication_failed("No token"))
+            }
+        })
+    })
+    .await;
+    let source_url = format!("{}/{}/secret/data.txt", server.base_url, OTHER_ACCOUNT);
+
+    let response = copy(&server, "stolen.txt", &format!("{}?token=guess", source_url)).await;
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.headers()["x-ms-error-code"], "CannotVerifyCopySource");
+
+    let response = copy(&server, "copied.txt", &format!("{}?token=letmein", source_url)).await;
+    assert_eq!(response.status(), 202);
+    assert_eq!(server.fixtures.blob_data(&server.account, "mine", "copied.txt").await.unwrap(), "secret data");
+}
+
+#[tokio::test]
+async fn test_batch_cannot_reach_another_account() {
+    let (server, _) = start(false).await;
+    server
+        .fixtures
+        .seed_blob(&server.account, "mine", "own.txt", "own data".into(), BlobProperties::default())
+        .await
+        .unwrap();
+
+    let boundary = "batch_isolation";
+    let mut body = String::new();
+    for (i, path) in [format!("/{}/secret/data.txt", OTHER_ACCOUNT), format!("/{}/mine/own.txt", server.account)]
+        .iter()
+        .enumerate()
+    {
+        body.push_str(&format!(
+            "--{}\r\nContent-Type: application/http\r\nContent-Transfer-Encoding: binary\r\nContent-ID: {}\r\n\r\n\
+             DELETE {} HTTP/1.1\r\nx-ms-version: 2021-10-04\r\n\r\n",
+            boundary, i, path
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/{}?comp=batch", server.base_url, server.account))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("Content-Type", format!("multipart/mixed; boundary={}", boundary))
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("HTTP/1.1 403"), "{}", body);
+    assert!(body.contains("x-ms-error-code: AuthorizationFailure"), "{}", body);
+    assert!(body.contains("HTTP/1.1 202"), "{}", body);
+
+    assert!(server.fixtures.blob(OTHER_ACCOUNT, "secret", "data.txt").await.is_ok());
+    assert!(server.fixtures.blob(&server.account, "mine", "own.txt").await.is_err());
+}
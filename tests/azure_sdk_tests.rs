@@ -470,6 +470,73 @@ async fn test_sdk_copy_blob() {
     assert_eq!(String::from_utf8(data).unwrap(), content);
 }
 
+/// A blob name the SDK has to percent-encode, and whose `+` and `%25`
+/// must not be decoded a second time.
+const ENCODED_BLOB_NAME: &str = "folder/file with spaces+plus%25.txt";
+
+#[tokio::test]
+async fn test_sdk_blob_name_needing_encoding() {
+    let server = TestServer::start().await;
+    let builder = create_builder(&server);
+    let container_client = builder.container_client("sdk-encoding");
+    container_client.create().await.unwrap();
+
+    let blob_client = container_client.blob_client(ENCODED_BLOB_NAME);
+    blob_client.put_block_blob("encoded name").await.unwrap();
+    let data = blob_client.get_content().await.unwrap();
+    assert_eq!(data, b"encoded name");
+
+    // Stored once, under the decoded name
+    let mut stream = container_client.list_blobs().into_stream();
+    let page = stream.next().await.unwrap().unwrap();
+    let names: Vec<_> = page.blobs.blobs().map(|blob| blob.name.clone()).collect();
+    assert_eq!(names, [ENCODED_BLOB_NAME]);
+    let direct = reqwest::Client::new()
+        .get(format!("{}/folder/file%20with%20spaces%2Bplus%2525.txt", server.container_url("sdk-encoding")))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(direct.status(), 200, "an equivalent spelling of the path addresses the same blob");
+
+    // Query values are signed decoded once, so a `%25` in the prefix stays
+    let mut stream = container_client.list_blobs().prefix("folder/file with spaces+plus%25").into_stream();
+    let page = stream.next().await.unwrap().unwrap();
+    assert_eq!(page.blobs.blobs().count(), 1);
+
+    // Copy sources are percent-encoded URLs too
+    let copy_client = container_client.blob_client("copy with spaces.txt");
+    copy_client.copy(blob_client.url().unwrap()).await.unwrap();
+    assert_eq!(copy_client.get_content().await.unwrap(), b"encoded name");
+}
+
+#[tokio::test]
+async fn test_sdk_sas_read_of_blob_name_needing_encoding() {
+    let server = TestServer::start().await;
+    let builder = create_builder(&server);
+    let container_client = builder.container_client("sdk-encoding-sas");
+    container_client.create().await.unwrap();
+    let blob_client = container_client.blob_client(ENCODED_BLOB_NAME);
+    blob_client.put_block_blob("signed read").await.unwrap();
+
+    let permissions = BlobSasPermissions { read: true, ..Default::default() };
+    let expiry = time::OffsetDateTime::now_utc() + time::Duration::hours(1);
+    let sas = blob_client.shared_access_signature(permissions, expiry).await.unwrap();
+    let url = blob_client.generate_signed_blob_url(&sas).unwrap();
+    assert!(url.path().ends_with("/folder/file%20with%20spaces+plus%2525.txt"), "{}", url);
+
+    let response = reqwest::get(url.as_str()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "signed read");
+
+    // The signature covers the name, so it does not read another blob
+    let other = container_client.blob_client("folder/file with spaces plus%25.txt");
+    other.put_block_blob("other").await.unwrap();
+    let mut other_url = other.url().unwrap();
+    other_url.set_query(url.query());
+    assert_eq!(reqwest::get(other_url.as_str()).await.unwrap().status(), 401);
+}
+
 // ============================================================================
 // Error handling tests
 // ============================================================================
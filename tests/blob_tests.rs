@@ -2,7 +2,12 @@
 
 mod common;
 
+use azurite_rs::models::BlobProperties;
+use azurite_rs::testing::{Client, ClientError, MockClock};
+use azurite_rs::storage::FsExtentStore;
+use azurite_rs::{BlobServer, BlobServerBuilder, Config, ErrorCode, ExtentUsage, MemoryExtentStore, MemoryMetadataStore};
 use common::TestServer;
+use std::sync::Arc;
 
 async fn create_container(server: &TestServer, name: &str) {
     let client = reqwest::Client::new();
@@ -19,38 +24,40 @@ async fn create_container(server: &TestServer, name: &str) {
 #[tokio::test]
 async fn test_upload_and_download_blob() {
     let server = TestServer::start().await;
-    create_container(&server, "blobcontainer").await;
-
-    let client = reqwest::Client::new();
-    let blob_url = server.blob_url("blobcontainer", "testblob.txt");
+    let client = server.client();
+    client.create_container("blobcontainer").await.unwrap();
     let content = "Hello, Azure Blob Storage!";
 
-    // Upload
-    let response = client
-        .put(&blob_url)
-        .header("x-ms-version", "2021-10-04")
-        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
-        .header("x-ms-blob-type", "BlockBlob")
-        .header("Content-Type", "text/plain")
-        .body(content)
-        .send()
-        .await
-        .unwrap();
+    client.put_blob("blobcontainer", "testblob.txt", content).await.unwrap();
+    assert_eq!(client.get_blob("blobcontainer", "testblob.txt").await.unwrap(), content);
+    assert_eq!(client.list_blobs("blobcontainer").await.unwrap(), ["testblob.txt"]);
+}
 
-    assert_eq!(response.status(), 201);
+#[tokio::test]
+async fn test_client_signing_and_leases() {
+    let server = TestServer::start().await;
+    let client = server.client();
+    client.create_container("leased").await.unwrap();
+    client.put_blob("leased", "dir/a b.txt", "leased data").await.unwrap();
 
-    // Download
-    let response = client
-        .get(&blob_url)
-        .header("x-ms-version", "2021-10-04")
-        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
-        .send()
-        .await
-        .unwrap();
+    let lease_id = client.acquire_lease("leased", "dir/a b.txt", 15).await.unwrap();
+    assert!(!lease_id.is_empty());
+    client.renew_lease("leased", "dir/a b.txt", &lease_id).await.unwrap();
 
-    assert_eq!(response.status(), 200);
-    let body = response.text().await.unwrap();
-    assert_eq!(body, content);
+    // Writes without the lease ID are refused while it is held
+    let err = client.put_blob("leased", "dir/a b.txt", "other").await.unwrap_err();
+    assert_eq!(err.code(), Some("LeaseIdMissing"));
+    client.release_lease("leased", "dir/a b.txt", &lease_id).await.unwrap();
+
+    client.acquire_lease("leased", "dir/a b.txt", -1).await.unwrap();
+    assert_eq!(client.break_lease("leased", "dir/a b.txt").await.unwrap(), 0);
+    client.put_blob("leased", "dir/a b.txt", "other").await.unwrap();
+
+    // A wrong key fails the signature check
+    let wrong_key = Client::new(&server.base_url, &server.account, "d3Jvbmcga2V5");
+    let err = wrong_key.get_blob("leased", "dir/a b.txt").await.unwrap_err();
+    assert!(matches!(err, ClientError::Service { status: 401, .. }), "{}", err);
+    assert_eq!(err.code(), Some("AuthenticationFailed"));
 }
 
 #[tokio::test]
@@ -251,6 +258,138 @@ async fn test_list_blobs_with_prefix() {
     assert!(!body.contains("dir2/"));
 }
 
+/// Lists `container` with extra query parameters, returning the blob names,
+/// the BlobPrefix names and the NextMarker.
+async fn list_entries(server: &TestServer, container: &str, query: &str) -> (Vec<String>, Vec<String>, Option<String>) {
+    let response = reqwest::Client::new()
+        .get(format!("{}?restype=container&comp=list{}", server.container_url(container), query))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+
+    let names = |element: &str| -> Vec<String> {
+        body.split(&format!("<{}><Name>", element))
+            .skip(1)
+            .map(|rest| rest.split("</Name>").next().unwrap().to_string())
+            .collect()
+    };
+    let next_marker = body
+        .split_once("<NextMarker>")
+        .map(|(_, rest)| rest.split("</NextMarker>").next().unwrap().to_string());
+    (names("Blob"), names("BlobPrefix"), next_marker)
+}
+
+#[tokio::test]
+async fn test_list_blobs_with_multi_character_delimiter() {
+    let server = TestServer::start().await;
+    create_container(&server, "delimiters").await;
+    for name in ["a-", "a--b", "a---c", "a--d--e", "x"] {
+        put_blob(&server, "delimiters", name, b"data".to_vec()).await;
+    }
+
+    let (blobs, prefixes, next_marker) = list_entries(&server, "delimiters", "&delimiter=--").await;
+    assert_eq!(blobs, ["a-", "x"]);
+    assert_eq!(prefixes, ["a--"]);
+    assert_eq!(next_marker, None);
+
+    // The prefix ends inside the "--" of a--b, which is therefore a blob
+    let (blobs, prefixes, _) = list_entries(&server, "delimiters", "&delimiter=--&prefix=a-").await;
+    assert_eq!(blobs, ["a-", "a--b"]);
+    assert_eq!(prefixes, ["a---", "a--d--"]);
+
+    // Virtual directories are paged like blobs and not repeated
+    let mut pages = Vec::new();
+    let mut marker = String::new();
+    loop {
+        let query = format!("&delimiter=--&maxresults=1&marker={}", marker);
+        let (blobs, prefixes, next_marker) = list_entries(&server, "delimiters", &query).await;
+        pages.push((blobs, prefixes));
+        match next_marker {
+            Some(next) => marker = next,
+            None => break,
+        }
+    }
+    assert_eq!(
+        pages,
+        [
+            (vec!["a-".to_string()], vec![]),
+            (vec![], vec!["a--".to_string()]),
+            (vec!["x".to_string()], vec![]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_list_blobs_orders_snapshots_before_base() {
+    let server = TestServer::start().await;
+    create_container(&server, "snaporder").await;
+    for name in ["a.txt", "b.txt"] {
+        put_blob(&server, "snaporder", name, b"data".to_vec()).await;
+    }
+
+    let client = reqwest::Client::new();
+    let mut snapshots = Vec::new();
+    for _ in 0..2 {
+        let response = client
+            .put(format!("{}?comp=snapshot", server.blob_url("snaporder", "a.txt")))
+            .header("x-ms-version", "2021-10-04")
+            .send()
+            .await
+            .unwrap();
+        snapshots.push(response.headers()["x-ms-snapshot"].to_str().unwrap().to_string());
+    }
+
+    // Each <Blob> element as "name" or "name@snapshot", in document order
+    let list = |query: String| {
+        let request = client
+            .get(format!("{}?restype=container&comp=list&include=snapshots{}", server.container_url("snaporder"), query))
+            .header("x-ms-version", "2021-10-04");
+        async move {
+            let body = request.send().await.unwrap().text().await.unwrap();
+            let entries: Vec<String> = body
+                .split("<Blob>")
+                .skip(1)
+                .map(|blob| {
+                    let name = blob.split_once("<Name>").unwrap().1.split_once("</Name>").unwrap().0;
+                    match blob.split_once("<Snapshot>") {
+                        Some((_, rest)) => format!("{}@{}", name, rest.split_once("</Snapshot>").unwrap().0),
+                        None => name.to_string(),
+                    }
+                })
+                .collect();
+            let next_marker = body
+                .split_once("<NextMarker>")
+                .map(|(_, rest)| rest.split_once("</NextMarker>").unwrap().0.to_string());
+            (entries, next_marker)
+        }
+    };
+
+    let expected = [
+        format!("a.txt@{}", snapshots[0]),
+        format!("a.txt@{}", snapshots[1]),
+        "a.txt".to_string(),
+        "b.txt".to_string(),
+    ];
+    assert_eq!(list(String::new()).await, (expected.to_vec(), None));
+
+    // Pages may end between the snapshots of a blob
+    let mut paged = Vec::new();
+    let mut marker = String::new();
+    loop {
+        let query = format!("&maxresults=1&marker={}", url::form_urlencoded::byte_serialize(marker.as_bytes()).collect::<String>());
+        let (entries, next_marker) = list(query).await;
+        paged.extend(entries);
+        match next_marker {
+            Some(next) => marker = next,
+            None => break,
+        }
+    }
+    assert_eq!(paged, expected);
+}
+
 #[tokio::test]
 async fn test_blob_metadata() {
     let server = TestServer::start().await;
@@ -292,6 +431,267 @@ async fn test_blob_metadata() {
     );
 }
 
+#[tokio::test]
+async fn test_metadata_key_casing() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::start().await;
+    create_container(&server, "metacasing").await;
+
+    // Raw HTTP/1 requests, as HTTP clients lowercase header names
+    let address = server.base_url.trim_start_matches("http://").to_string();
+    let exchange = |requests: String| {
+        let address = address.clone();
+        async move {
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.unwrap();
+            String::from_utf8(response).unwrap()
+        }
+    };
+    let path = format!("/{}/metacasing/blob.txt", server.account);
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let headers = format!("Host: localhost\r\nx-ms-version: 2021-10-04\r\nx-ms-date: {}\r\n", date);
+
+    let response = exchange(format!(
+        "PUT {} HTTP/1.1\r\n{}x-ms-blob-type: BlockBlob\r\nx-ms-meta-MyKey: value\r\n\
+         X-Ms-Meta-mykey: again\r\nx-ms-meta-lower: 1\r\nContent-Length: 7\r\nConnection: close\r\n\r\ncontent",
+        path, headers
+    ))
+    .await;
+    assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
+
+    // Keys come back spelled as first sent, after a HEAD and its
+    // pipelined GET alike
+    let response = exchange(format!(
+        "HEAD {path} HTTP/1.1\r\n{headers}\r\nGET {path} HTTP/1.1\r\n{headers}Connection: close\r\n\r\n",
+        path = path,
+        headers = headers
+    ))
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let (head, get) = response.split_at(response[1..].find("HTTP/1.1 200").unwrap() + 1);
+    for response in [head, get] {
+        assert!(response.contains("\r\nx-ms-meta-MyKey: value,again\r\n"), "{}", response);
+        assert!(response.contains("\r\nx-ms-meta-lower: 1\r\n"), "{}", response);
+    }
+    assert!(get.ends_with("\r\n\r\ncontent"), "{}", get);
+
+    // A malformed request pipelined after a HEAD stops the spelling of later
+    // requests, not of the response to the HEAD already read
+    let response = exchange(format!("HEAD {} HTTP/1.1\r\n{}\r\nBAD\r\n\r\n", path, headers)).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\r\nx-ms-meta-MyKey: value,again\r\n"), "{}", response);
+    assert!(response.contains("HTTP/1.1 400"), "{}", response);
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}?restype=container&comp=list&include=metadata", server.container_url("metacasing")))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Metadata><MyKey>value,again</MyKey><lower>1</lower></Metadata>"), "{}", body);
+
+    // Lookups ignore case: a lowercase request still reads the key
+    let response = client
+        .head(server.blob_url("metacasing", "blob.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-ms-meta-mykey"], "value,again");
+}
+
+#[tokio::test]
+async fn test_metadata_validation() {
+    let server = TestServer::start_with(BlobServerBuilder::new().max_metadata_count(2)).await;
+    create_container(&server, "metalimits").await;
+
+    let client = reqwest::Client::new();
+    let put = |name: &str, metadata: &[(&'static str, &str)]| {
+        let mut request = client
+            .put(server.blob_url("metalimits", name))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-blob-type", "BlockBlob")
+            .body("content");
+        for (key, value) in metadata {
+            request = request.header(*key, *value);
+        }
+        request.send()
+    };
+
+    let response = put("empty-key.txt", &[("x-ms-meta-", "value")]).await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "EmptyMetadataKey");
+
+    let response = put("too-many.txt", &[("x-ms-meta-a", "1"), ("x-ms-meta-b", "2"), ("x-ms-meta-c", "3")])
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "MetadataTooLarge");
+
+    // Keys differing only in case are one pair with joined values
+    let response = put("merged.txt", &[("x-ms-meta-a", "1"), ("X-Ms-Meta-A", "2"), ("x-ms-meta-b", "3")])
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client
+        .head(server.blob_url("metalimits", "merged.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-ms-meta-a"], "1,2");
+
+    // Neither rejected upload left a blob behind
+    for name in ["empty-key.txt", "too-many.txt"] {
+        assert!(server.fixtures.blob(&server.account, "metalimits", name).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_unsupported_headers() {
+    let strict = TestServer::start().await;
+    let loose = TestServer::start_with(BlobServerBuilder::new().loose(true)).await;
+    let client = reqwest::Client::new();
+
+    for server in [&strict, &loose] {
+        create_container(server, "dfsheaders").await;
+        for (header, value) in [("x-ms-owner", "$superuser"), ("x-ms-metadata-key", "value")] {
+            let response = client
+                .put(server.blob_url("dfsheaders", "file.txt"))
+                .header("x-ms-version", "2021-10-04")
+                .header("x-ms-blob-type", "BlockBlob")
+                .header(header, value)
+                .body("content")
+                .send()
+                .await
+                .unwrap();
+            if std::ptr::eq(server, &loose) {
+                assert_eq!(response.status(), 201);
+                continue;
+            }
+            assert_eq!(response.status(), 400);
+            assert_eq!(response.headers()["x-ms-error-code"], "UnsupportedHeader");
+            let body = response.text().await.unwrap();
+            assert!(body.contains(&format!("<HeaderName>{}</HeaderName>", header)), "{}", body);
+        }
+    }
+    assert!(strict.fixtures.blob(&strict.account, "dfsheaders", "file.txt").await.is_err());
+}
+
+#[tokio::test]
+async fn test_chunked_uploads() {
+    let server = TestServer::start().await;
+    create_container(&server, "chunked").await;
+
+    let client = reqwest::Client::new();
+    // A streamed body has no known length, so it is sent chunked
+    let chunked = |data: &'static [u8]| {
+        reqwest::Body::wrap_stream(futures_util::stream::iter([Ok::<_, std::io::Error>(data)]))
+    };
+    let put = |name: &str, query: &str| {
+        client
+            .put(format!("{}{}", server.blob_url("chunked", name), query))
+            .header("x-ms-version", "2021-10-04")
+    };
+
+    // Block blobs and blocks accept chunked bodies
+    let response = put("block.txt", "")
+        .header("x-ms-blob-type", "BlockBlob")
+        .body(chunked(b"hello"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(get_blob(&server, "chunked", "block.txt").await, b"hello");
+
+    let response = put("staged.txt", "?comp=block&blockid=YmxvY2s=")
+        .body(chunked(b"block"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // Creating page and append blobs and writing pages need a declared length
+    let response = put("page.bin", "")
+        .header("x-ms-blob-type", "PageBlob")
+        .header("x-ms-blob-content-length", "512")
+        .body(chunked(b""))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 411);
+    assert_eq!(response.headers()["x-ms-error-code"], "MissingContentLengthHeader");
+
+    let response = put("append.txt", "")
+        .header("x-ms-blob-type", "AppendBlob")
+        .body(chunked(b""))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 411);
+
+    let response = put("page.bin", "")
+        .header("x-ms-blob-type", "PageBlob")
+        .header("x-ms-blob-content-length", "512")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = put("page.bin", "?comp=page")
+        .header("x-ms-page-write", "update")
+        .header("x-ms-range", "bytes=0-511")
+        .body(chunked(&[1; 512]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 411);
+}
+
+#[tokio::test]
+async fn test_blob_type_validation() {
+    let server = TestServer::start().await;
+    create_container(&server, "blobtypes").await;
+
+    let client = reqwest::Client::new();
+    let put = |name: &str, query: &str| {
+        client
+            .put(format!("{}{}", server.blob_url("blobtypes", name), query))
+            .header("x-ms-version", "2021-10-04")
+    };
+
+    let response = put("typo.txt", "")
+        .header("x-ms-blob-type", "Blockblob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidHeaderValue");
+    let response = client
+        .head(server.blob_url("blobtypes", "typo.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = put("log.txt", "")
+        .header("x-ms-blob-type", "AppendBlob")
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = put("log.txt", "?comp=block&blockid=YmxvY2s=").body("block").send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidBlobType");
+}
+
 #[tokio::test]
 async fn test_copy_blob() {
     let server = TestServer::start().await;
@@ -343,3 +743,2606 @@ async fn test_copy_blob() {
     let body = response.text().await.unwrap();
     assert_eq!(body, content);
 }
+
+#[tokio::test]
+async fn test_list_blobs_repeated_include_signed() {
+    let server = TestServer::start().await;
+    create_container(&server, "includecontainer").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("includecontainer", "blob.txt");
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-meta-color", "blue")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .put(format!("{}?comp=snapshot", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // List with include given twice, signed with SharedKey
+    let path = format!("/{}/includecontainer", server.account);
+    let query = [
+        ("restype", "container"),
+        ("comp", "list"),
+        ("include", "snapshots"),
+        ("include", "metadata"),
+    ];
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let auth = common::create_auth_header(
+        "GET",
+        &server.account,
+        &server.key,
+        &path,
+        &query,
+        None,
+        None,
+        &date,
+        &[],
+    );
+
+    let response = client
+        .get(format!("{}{}", server.base_url, path))
+        .query(&query)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", &date)
+        .header("Authorization", auth)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Snapshot>"));
+    assert!(body.contains("<color>blue</color>"));
+}
+
+#[tokio::test]
+async fn test_sas_response_header_overrides() {
+    let server = TestServer::start().await;
+    create_container(&server, "sascontainer").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("sascontainer", "report.bin");
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("Content-Type", "application/octet-stream")
+        .body("pdf bytes")
+        .send()
+        .await
+        .unwrap();
+
+    let overrides = [
+        ("rscc", "no-cache"),
+        ("rscd", "attachment; filename=report.pdf"),
+        ("rsce", "identity"),
+        ("rscl", "en-US"),
+        ("rsct", "application/pdf"),
+    ];
+    let sas = common::create_blob_sas(
+        &server.account,
+        &server.key,
+        "sascontainer",
+        Some("report.bin"),
+        "r",
+        &overrides,
+    );
+
+    for response in [
+        client.get(&blob_url).query(&sas).send().await.unwrap(),
+        client.head(&blob_url).query(&sas).send().await.unwrap(),
+    ] {
+        assert_eq!(response.status(), 200);
+        let header = |name: &str| response.headers().get(name).map(|v| v.to_str().unwrap().to_string());
+        assert_eq!(header("cache-control").as_deref(), Some("no-cache"));
+        assert_eq!(
+            header("content-disposition").as_deref(),
+            Some("attachment; filename=report.pdf")
+        );
+        assert_eq!(header("content-encoding").as_deref(), Some("identity"));
+        assert_eq!(header("content-language").as_deref(), Some("en-US"));
+        assert_eq!(header("content-type").as_deref(), Some("application/pdf"));
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_comp_value() {
+    let server = TestServer::start().await;
+    create_container(&server, "unknowncomp").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("unknowncomp", "blob.txt");
+
+    // Typo in comp names the offending value
+    let response = client
+        .put(format!("{}?comp=blocklistt", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("InvalidQueryParameterValue")
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<QueryParameterName>comp</QueryParameterName>"));
+    assert!(body.contains("<QueryParameterValue>blocklistt</QueryParameterValue>"));
+
+    // Unknown restype on a container
+    let response = client
+        .get(format!("{}?restype=containr", server.container_url("unknowncomp")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<QueryParameterName>restype</QueryParameterName>"));
+
+    // Known comp with the wrong verb keeps UnsupportedHttpVerb
+    let response = client
+        .post(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("UnsupportedHttpVerb")
+    );
+}
+
+#[tokio::test]
+async fn test_comp_and_restype_are_case_insensitive() {
+    let server = TestServer::start().await;
+    create_container(&server, "casing").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("casing", "blob.txt");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let response = client
+        .put(format!("{}?Comp=Block&BlockId=YmxvY2sx", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .put(format!("{}?comp=BLOCKLIST", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body(r#"<?xml version="1.0" encoding="utf-8"?><BlockList><Latest>YmxvY2sx</Latest></BlockList>"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(format!("{}?COMP=BlockList&BlockListType=all", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-ms-blob-content-length"], "5");
+
+    let response = client
+        .get(format!("{}?Restype=Container&Comp=List", server.container_url("casing")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("<Name>blob.txt</Name>"));
+
+    let response = client
+        .put(format!("{}?comp=LEASE", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-lease-action", "acquire")
+        .header("x-ms-lease-duration", "-1")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert!(response.headers().contains_key("x-ms-lease-id"));
+
+    // An empty comp is the same as none
+    let response = client
+        .get(format!("{}?comp=", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn test_list_blobs_parameter_names_are_case_insensitive() {
+    let server = TestServer::start().await;
+    create_container(&server, "listcasing").await;
+    for name in ["b.txt", "dir/c.txt"] {
+        assert_eq!(put_blob(&server, "listcasing", name, b"data".to_vec()).await.status(), 201);
+    }
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put(server.blob_url("listcasing", "a.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-meta-author", "test")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let list = |query: &str| {
+        client
+            .get(format!("{}?restype=container&comp=list&{}", server.container_url("listcasing"), query))
+            .header("x-ms-version", "2021-10-04")
+            .send()
+    };
+
+    let body = list("MaxResults=1").await.unwrap().text().await.unwrap();
+    assert!(body.contains("<Name>a.txt</Name>") && !body.contains("<Name>b.txt</Name>"), "{}", body);
+    assert!(body.contains("<NextMarker>a.txt</NextMarker>"), "{}", body);
+
+    let body = list("Marker=a.txt&Prefix=b").await.unwrap().text().await.unwrap();
+    assert!(body.contains("<Name>b.txt</Name>") && !body.contains("<Name>dir/c.txt</Name>"), "{}", body);
+
+    let body = list("Delimiter=/&Include=Metadata").await.unwrap().text().await.unwrap();
+    assert!(body.contains("<BlobPrefix><Name>dir/</Name></BlobPrefix>"), "{}", body);
+    assert!(body.contains("<Metadata"), "{}", body);
+
+    let response = list("MaxResults=0").await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "OutOfRangeQueryParameterValue");
+}
+
+#[tokio::test]
+async fn test_lease_duration_reported() {
+    let server = TestServer::start().await;
+    create_container(&server, "leaseduration").await;
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("leaseduration", "blob.txt");
+    let container_url = server.container_url("leaseduration");
+    let list_blobs = format!("{}?restype=container&comp=list", container_url);
+    let list_containers = format!("{}/{}?comp=list", server.base_url, server.account);
+
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let lease = |url: String, action: &'static str, extra: Option<(&'static str, String)>| {
+        let mut request = client
+            .put(url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-lease-action", action);
+        if let Some((name, value)) = extra {
+            request = request.header(name, value);
+        }
+        request.send()
+    };
+    let head = |url: String| client.head(url).header("x-ms-version", "2021-10-04").send();
+    let get_text = |url: String| async {
+        client.get(url).header("x-ms-version", "2021-10-04").send().await.unwrap().text().await.unwrap()
+    };
+
+    let response = lease(format!("{}?comp=lease", blob_url), "acquire", Some(("x-ms-lease-duration", "-1".into())))
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let lease_id = response.headers()["x-ms-lease-id"].to_str().unwrap().to_string();
+    let response = lease(
+        format!("{}?restype=container&comp=lease", container_url),
+        "acquire",
+        Some(("x-ms-lease-duration", "15".into())),
+    )
+    .await
+    .unwrap();
+    assert!(response.status().is_success());
+
+    let response = head(blob_url.clone()).await.unwrap();
+    assert_eq!(response.headers()["x-ms-lease-duration"], "infinite");
+    let response = client.get(&blob_url).header("x-ms-version", "2021-10-04").send().await.unwrap();
+    assert_eq!(response.headers()["x-ms-lease-duration"], "infinite");
+    assert!(get_text(list_blobs.clone()).await.contains("<LeaseDuration>infinite</LeaseDuration>"));
+    let response = head(format!("{}?restype=container", container_url)).await.unwrap();
+    assert_eq!(response.headers()["x-ms-lease-duration"], "fixed");
+    assert!(get_text(list_containers.clone()).await.contains("<LeaseDuration>fixed</LeaseDuration>"));
+
+    // Released and broken leases report no duration
+    let response = lease(format!("{}?comp=lease", blob_url), "release", Some(("x-ms-lease-id", lease_id)))
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let response = lease(
+        format!("{}?restype=container&comp=lease", container_url),
+        "break",
+        Some(("x-ms-lease-break-period", "0".into())),
+    )
+    .await
+    .unwrap();
+    assert!(response.status().is_success());
+
+    let response = head(blob_url.clone()).await.unwrap();
+    assert!(!response.headers().contains_key("x-ms-lease-duration"));
+    assert!(!get_text(list_blobs).await.contains("<LeaseDuration>"));
+    let response = head(format!("{}?restype=container", container_url)).await.unwrap();
+    assert_eq!(response.headers()["x-ms-lease-state"], "broken");
+    assert!(!response.headers().contains_key("x-ms-lease-duration"));
+    assert!(!get_text(list_containers).await.contains("<LeaseDuration>"));
+}
+
+fn assert_azure_etag(etag: &str) {
+    let hex = etag
+        .strip_prefix("\"0x")
+        .and_then(|e| e.strip_suffix('"'))
+        .unwrap_or_else(|| panic!("unexpected ETag format: {}", etag));
+    assert!(hex.len() >= 15, "unexpected ETag format: {}", etag);
+    assert!(
+        hex.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c)),
+        "unexpected ETag format: {}",
+        etag
+    );
+}
+
+#[tokio::test]
+async fn test_etag_format_and_metadata_update() {
+    let server = TestServer::start().await;
+    create_container(&server, "etagcontainer").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("etagcontainer", "blob.txt");
+
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let etag = response.headers().get("etag").unwrap().to_str().unwrap().to_string();
+    let last_modified = response.headers().get("last-modified").unwrap().to_str().unwrap().to_string();
+    assert_azure_etag(&etag);
+
+    // Last-Modified has one-second resolution
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    let response = client
+        .put(format!("{}?comp=metadata", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-meta-key", "value")
+        .header("If-Match", etag.trim_matches('"'))
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let new_etag = response.headers().get("etag").unwrap().to_str().unwrap().to_string();
+    assert_azure_etag(&new_etag);
+    assert_ne!(new_etag, etag);
+    assert!(new_etag > etag, "ETags should increase: {} -> {}", etag, new_etag);
+
+    let response = client
+        .head(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("etag").unwrap().to_str().unwrap(), new_etag);
+    assert_ne!(
+        response.headers().get("last-modified").unwrap().to_str().unwrap(),
+        last_modified
+    );
+}
+
+#[tokio::test]
+async fn test_set_blob_properties_clears_omitted_headers() {
+    let server = TestServer::start().await;
+    create_container(&server, "propscontainer").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("propscontainer", "blob.txt");
+
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("{}?comp=properties", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-cache-control", "max-age=3600")
+        .header("x-ms-blob-content-language", "en-US")
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .put(format!("{}?comp=properties", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-content-type", "text/plain")
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .head(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/plain");
+    assert!(response.headers().get("cache-control").is_none());
+    assert!(response.headers().get("content-language").is_none());
+
+    // Omitting the content type falls back to the default
+    client
+        .put(format!("{}?comp=properties", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .head(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("content-type").unwrap(), "application/octet-stream");
+}
+
+#[tokio::test]
+async fn test_snapshot_parameter_validation() {
+    let server = TestServer::start().await;
+    create_container(&server, "snapparam").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("snapparam", "blob.txt");
+
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("{}?comp=snapshot", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let snapshot = response.headers().get("x-ms-snapshot").unwrap().to_str().unwrap().to_string();
+
+    // Garbage value is rejected rather than reported as a missing blob
+    let response = client
+        .get(format!("{}?snapshot=not-a-date", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("InvalidQueryParameterValue")
+    );
+
+    // Same instant with more fractional digits and an explicit offset
+    let parsed = chrono::DateTime::parse_from_rfc3339(&snapshot).unwrap();
+    let rewritten = parsed.format("%Y-%m-%dT%H:%M:%S%.9f+00:00").to_string();
+    let response = client
+        .get(&blob_url)
+        .query(&[("snapshot", rewritten.as_str())])
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200, "snapshot={}", rewritten);
+    assert_eq!(response.text().await.unwrap(), "data");
+}
+
+#[tokio::test]
+async fn test_create_append_blob_rejects_body() {
+    let server = TestServer::start().await;
+    create_container(&server, "appendcreate").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("appendcreate", "log.txt");
+
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "AppendBlob")
+        .body("lost data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("InvalidOperation")
+    );
+
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "AppendBlob")
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+}
+
+async fn acquire_lease(client: &reqwest::Client, blob_url: &str) -> String {
+    let response = client
+        .put(format!("{}?comp=lease", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-lease-action", "acquire")
+        .header("x-ms-lease-duration", "-1")
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    response.headers().get("x-ms-lease-id").unwrap().to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_conditional_lease_acquire() {
+    let server = TestServer::start().await;
+    create_container(&server, "condlease").await;
+    let response = put_blob(&server, "condlease", "blob.txt", b"v1".to_vec()).await;
+    let stale_etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let response = put_blob(&server, "condlease", "blob.txt", b"v2".to_vec()).await;
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+    let client = reqwest::Client::new();
+    let lease = |url: String, condition: &'static str, value: String| {
+        client
+            .put(url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-lease-action", "acquire")
+            .header("x-ms-lease-duration", "-1")
+            .header(condition, value)
+            .send()
+    };
+    let blob_url = format!("{}?comp=lease", server.blob_url("condlease", "blob.txt"));
+
+    // A stale ETag fails before the lease is taken
+    let response = lease(blob_url.clone(), "If-Match", stale_etag).await.unwrap();
+    assert_eq!(response.status(), 412);
+    assert_eq!(response.headers()["x-ms-error-code"], "ConditionNotMet");
+    let response = client
+        .head(server.blob_url("condlease", "blob.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-ms-lease-state"], "available");
+
+    let response = lease(blob_url, "If-Match", etag).await.unwrap();
+    assert!(response.status().is_success());
+
+    // Container leases take the date conditions
+    let container_url = format!("{}?restype=container&comp=lease", server.container_url("condlease"));
+    let before = (chrono::Utc::now() - chrono::Duration::hours(1))
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let response = lease(container_url.clone(), "If-Unmodified-Since", before.clone()).await.unwrap();
+    assert_eq!(response.status(), 412);
+    let response = lease(container_url, "If-Modified-Since", before).await.unwrap();
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn test_copy_blob_destination_lease_and_tier() {
+    let server = TestServer::start().await;
+    create_container(&server, "copydest").await;
+
+    let client = reqwest::Client::new();
+    let source_url = server.blob_url("copydest", "source.txt");
+    let dest_url = server.blob_url("copydest", "dest.txt");
+
+    for url in [&source_url, &dest_url] {
+        client
+            .put(url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-blob-type", "BlockBlob")
+            .body("data")
+            .send()
+            .await
+            .unwrap();
+    }
+    client
+        .put(format!("{}?comp=tags", source_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .body("<Tags><TagSet><Tag><Key>origin</Key><Value>source</Value></Tag></TagSet></Tags>")
+        .send()
+        .await
+        .unwrap();
+    let lease_id = acquire_lease(&client, &dest_url).await;
+
+    let copy = |lease: Option<&str>| {
+        let mut request = client
+            .put(&dest_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-copy-source", source_url.as_str())
+            .header("x-ms-access-tier", "Cool")
+            .header("x-ms-tags", "origin=request")
+            .body("");
+        if let Some(lease) = lease {
+            request = request.header("x-ms-lease-id", lease);
+        }
+        request.send()
+    };
+
+    // Leased destination requires the lease ID
+    let response = copy(None).await.unwrap();
+    assert_eq!(response.status(), 412);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("LeaseIdMissing")
+    );
+
+    let response = copy(Some(&lease_id)).await.unwrap();
+    assert_eq!(response.status(), 202);
+
+    let response = client
+        .head(&dest_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("x-ms-access-tier").unwrap(), "Cool");
+    assert_eq!(response.headers().get("x-ms-lease-state").unwrap(), "leased");
+
+    let response = client
+        .get(format!("{}?comp=tags", dest_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Value>request</Value>"), "{}", body);
+
+    // x-ms-copy-source-tag-option: COPY carries the source tags instead,
+    // leaving any x-ms-tags unread
+    let response = client
+        .put(&dest_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-copy-source", source_url.as_str())
+        .header("x-ms-copy-source-tag-option", "COPY")
+        .header("x-ms-tags", "ignored=a%21")
+        .header("x-ms-lease-id", lease_id.as_str())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let response = client
+        .get(format!("{}?comp=tags", dest_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Value>source</Value>"), "{}", body);
+
+    // A tier that is not one, or not one a block blob takes, fails the copy
+    for (tier, code) in [("Lukewarm", "InvalidHeaderValue"), ("P10", "InvalidBlobTier")] {
+        let response = client
+            .put(&dest_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-copy-source", source_url.as_str())
+            .header("x-ms-access-tier", tier)
+            .header("x-ms-lease-id", lease_id.as_str())
+            .body("")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", tier);
+        assert_eq!(response.headers().get("x-ms-error-code").unwrap(), code);
+    }
+}
+
+#[tokio::test]
+async fn test_list_blobs_include_copy() {
+    let server = TestServer::start().await;
+    create_container(&server, "listcopy").await;
+
+    let client = reqwest::Client::new();
+    let source_url = server.blob_url("listcopy", "source.txt");
+
+    client
+        .put(&source_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .put(server.blob_url("listcopy", "target.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-copy-source", source_url.as_str())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let copy_id = response.headers().get("x-ms-copy-id").unwrap().to_str().unwrap().to_string();
+
+    let list = |include: &'static str| {
+        client
+            .get(format!("{}?restype=container&comp=list{}", server.container_url("listcopy"), include))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+    };
+
+    let body = list("").await.unwrap().text().await.unwrap();
+    assert!(!body.contains("<CopyId>"), "{}", body);
+
+    let body = list("&include=copy,metadata").await.unwrap().text().await.unwrap();
+    assert_eq!(body.matches("<CopyId>").count(), 1, "{}", body);
+    assert!(body.contains(&format!("<CopyId>{}</CopyId>", copy_id)), "{}", body);
+    assert!(body.contains("<CopyStatus>success</CopyStatus>"), "{}", body);
+    assert!(body.contains(&format!("<CopySource>{}</CopySource>", source_url)), "{}", body);
+    assert!(body.contains("<CopyProgress>4/4</CopyProgress>"), "{}", body);
+    assert!(body.contains("<CopyCompletionTime>"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_list_blobs_every_optional_property() {
+    use azurite_rs::models::{CopyStatus, ObjectReplicationRule, RehydratePriority};
+
+    // Without garbage collection, which would purge the long-deleted blob
+    let config = azurite_rs::Config { gc_interval: std::time::Duration::ZERO, ..Default::default() };
+    let server = TestServer::start_with(BlobServerBuilder::new().config(config).object_replication(true)).await;
+    let fixtures = &server.fixtures;
+    fixtures.seed_container(&server.account, "golden").await.unwrap();
+    let mut blob = fixtures
+        .seed_blob(&server.account, "golden", "every.txt", "data".into(), BlobProperties::default())
+        .await
+        .unwrap();
+
+    let at = chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z").unwrap().to_utc();
+    let props = &mut blob.properties;
+    props.created_on = at;
+    props.last_modified = at;
+    props.etag = "0x8DC0B5E9A7C3F10".to_string();
+    props.content_type = Some("text/plain".to_string());
+    props.content_encoding = Some("gzip".to_string());
+    props.content_language = Some("en-US".to_string());
+    props.content_md5 = Some("jQnpmcNwAJVFGS8Hb3fBKQ==".to_string());
+    props.content_disposition = Some("attachment".to_string());
+    props.cache_control = Some("no-cache".to_string());
+    props.copy_id = Some("copy-1".to_string());
+    props.copy_status = Some(CopyStatus::Success);
+    props.copy_source = Some("https://example.com/src/every.txt".to_string());
+    props.copy_progress = Some("4/4".to_string());
+    props.copy_completion_time = Some(at);
+    props.version_id = Some("2024-01-02T03:04:05.0000000Z".to_string());
+    props.is_current_version = Some(true);
+    props.rehydrate_priority = Some(RehydratePriority::High);
+    props.last_accessed_on = Some(at);
+    props.immutability_policy_until = Some(at);
+    props.legal_hold = true;
+    blob.metadata.insert("owner".to_string(), "ci".to_string());
+    blob.tags.insert("stage".to_string(), "done".to_string());
+    blob.deleted = true;
+    blob.deleted_time = Some(at);
+    blob.remaining_retention_days = Some(7);
+    fixtures.metadata().update_blob(blob).await.unwrap();
+
+    let list = |include: &'static str| {
+        reqwest::Client::new()
+            .get(format!("{}?restype=container&comp=list&include={}", server.container_url("golden"), include))
+            .header("x-ms-version", "2021-10-04")
+            .send()
+    };
+    let blob_xml = |body: &str| {
+        let start = body.find("<Blob>").expect(body);
+        let end = body.find("</Blob>").expect(body) + "</Blob>".len();
+        body[start..end].to_string()
+    };
+
+    let date = "Tue, 02 Jan 2024 03:04:05 GMT";
+    let rule = ObjectReplicationRule::for_container(&server.account, "golden").id();
+    let body = list("copy,metadata,tags,deleted,versions,immutabilitypolicy,legalhold,objectreplicationmetadata")
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let expected = [
+        "<Blob><Name>every.txt</Name>".to_string(),
+        "<VersionId>2024-01-02T03:04:05.0000000Z</VersionId><IsCurrentVersion>true</IsCurrentVersion>".to_string(),
+        "<Deleted>true</Deleted><Properties>".to_string(),
+        format!("<Creation-Time>{date}</Creation-Time><Last-Modified>{date}</Last-Modified>"),
+        "<Etag>0x8DC0B5E9A7C3F10</Etag><Content-Length>4</Content-Length>".to_string(),
+        "<Content-Type>text/plain</Content-Type><Content-Encoding>gzip</Content-Encoding>".to_string(),
+        "<Content-Language>en-US</Content-Language><Content-MD5>jQnpmcNwAJVFGS8Hb3fBKQ==</Content-MD5>".to_string(),
+        "<Content-Disposition>attachment</Content-Disposition><Cache-Control>no-cache</Cache-Control>".to_string(),
+        "<BlobType>BlockBlob</BlobType><AccessTier>Hot</AccessTier><AccessTierInferred>true</AccessTierInferred>".to_string(),
+        "<LeaseStatus>unlocked</LeaseStatus><LeaseState>available</LeaseState>".to_string(),
+        "<CopyId>copy-1</CopyId><CopyStatus>success</CopyStatus>".to_string(),
+        "<CopySource>https://example.com/src/every.txt</CopySource><CopyProgress>4/4</CopyProgress>".to_string(),
+        format!("<CopyCompletionTime>{date}</CopyCompletionTime><ServerEncrypted>true</ServerEncrypted>"),
+        format!("<DeletedTime>{date}</DeletedTime><RemainingRetentionDays>7</RemainingRetentionDays>"),
+        "<TagCount>1</TagCount><RehydratePriority>High</RehydratePriority>".to_string(),
+        format!("<LastAccessTime>{date}</LastAccessTime>"),
+        format!("<ImmutabilityPolicyUntilDate>{date}</ImmutabilityPolicyUntilDate><LegalHold>true</LegalHold>"),
+        "</Properties><Metadata><owner>ci</owner></Metadata>".to_string(),
+        "<Tags><TagSet><Tag><Key>stage</Key><Value>done</Value></Tag></TagSet></Tags>".to_string(),
+        format!("<OrMetadata><Or-{rule}>complete</Or-{rule}></OrMetadata></Blob>"),
+    ]
+    .concat();
+    assert_eq!(blob_xml(&body), expected);
+
+    // Without the include datasets only the ungated properties remain
+    let body = list("deleted").await.unwrap().text().await.unwrap();
+    let xml = blob_xml(&body);
+    for element in ["<VersionId>", "<CopyId>", "<ImmutabilityPolicyUntilDate>", "<LegalHold>", "<Metadata>", "<Tags>", "<OrMetadata>"] {
+        assert!(!xml.contains(element), "{} in {}", element, xml);
+    }
+    for element in ["<Deleted>true</Deleted>", "<DeletedTime>", "<TagCount>1</TagCount>", "<RehydratePriority>", "<LastAccessTime>"] {
+        assert!(xml.contains(element), "{} not in {}", element, xml);
+    }
+}
+
+#[tokio::test]
+async fn test_simulated_copy_failure() {
+    let server = TestServer::start_with(BlobServerBuilder::new().loose(true)).await;
+    create_container(&server, "copyfail").await;
+    assert_eq!(put_blob(&server, "copyfail", "source.txt", b"12345678".to_vec()).await.status(), 201);
+
+    let client = reqwest::Client::new();
+    let source_url = server.blob_url("copyfail", "source.txt");
+    let dest_url = server.blob_url("copyfail", "dest.txt");
+    let copy = |source: String| {
+        client
+            .put(&dest_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-copy-source", source)
+            .body("")
+            .send()
+    };
+
+    let response = copy(format!("{}?simulate=copy-failure", source_url)).await.unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.headers().get("x-ms-copy-status").unwrap(), "pending");
+
+    let response = client
+        .head(&dest_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("x-ms-copy-status").unwrap(), "failed");
+    assert_eq!(response.headers().get("x-ms-copy-progress").unwrap(), "4/8");
+    assert!(response.headers().get("x-ms-copy-status-description").is_some());
+
+    let response = client
+        .get(format!("{}?restype=container&comp=list&include=copy", server.container_url("copyfail")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<CopyStatus>failed</CopyStatus>"), "{}", body);
+    assert!(body.contains("<CopyProgress>4/8</CopyProgress>"), "{}", body);
+    assert!(body.contains("<CopyStatusDescription>"), "{}", body);
+
+    // Copying again over the failed destination succeeds
+    let response = copy(source_url.clone()).await.unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.headers().get("x-ms-copy-status").unwrap(), "success");
+    assert_eq!(get_blob(&server, "copyfail", "dest.txt").await, b"12345678");
+}
+
+#[tokio::test]
+async fn test_copy_and_snapshot_do_not_inherit_lease() {
+    let server = TestServer::start().await;
+    create_container(&server, "leasecopy").await;
+
+    let client = reqwest::Client::new();
+    let source_url = server.blob_url("leasecopy", "source.txt");
+    let dest_url = server.blob_url("leasecopy", "dest.txt");
+
+    client
+        .put(&source_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    let lease_id = acquire_lease(&client, &source_url).await;
+
+    let response = client
+        .put(&dest_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-copy-source", source_url.as_str())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+
+    let response = client
+        .put(format!("{}?comp=snapshot", source_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-lease-id", lease_id.as_str())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let snapshot = response.headers().get("x-ms-snapshot").unwrap().to_str().unwrap().to_string();
+
+    let lease_headers = |url: String| {
+        let request = client
+            .head(url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            (
+                response.headers().get("x-ms-lease-state").unwrap().to_str().unwrap().to_string(),
+                response.headers().get("x-ms-lease-status").unwrap().to_str().unwrap().to_string(),
+            )
+        }
+    };
+
+    let unleased = ("available".to_string(), "unlocked".to_string());
+    assert_eq!(lease_headers(dest_url.clone()).await, unleased);
+    let snapshot_url = format!("{}?snapshot={}", source_url, snapshot);
+    assert_eq!(lease_headers(snapshot_url).await, unleased);
+    assert_eq!(
+        lease_headers(source_url.clone()).await,
+        ("leased".to_string(), "locked".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_head_missing_blob_has_no_body() {
+    let server = TestServer::start().await;
+    create_container(&server, "headerrors").await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .head(server.blob_url("headerrors", "missing.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+    let headers = response.headers();
+    assert_eq!(headers.get("x-ms-error-code").unwrap(), "BlobNotFound");
+    assert_eq!(headers.get("content-length").unwrap(), "0");
+    assert!(headers.get("content-type").is_none());
+    assert!(headers.get("x-ms-request-id").is_some());
+    assert!(response.bytes().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_error_body_detail_elements() {
+    let server = TestServer::start().await;
+    create_container(&server, "errordetails").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("errordetails", "blob.txt");
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("content")
+        .send()
+        .await
+        .unwrap();
+
+    // Invalid header values name the header and echo its value
+    let response = client
+        .put(format!("{}?comp=lease", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-lease-action", "bogus")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers().get("x-ms-error-code").unwrap(), "InvalidHeaderValue");
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<HeaderName>x-ms-lease-action</HeaderName>"));
+    assert!(body.contains("<HeaderValue>bogus</HeaderValue>"));
+
+    // SharedKey failures echo the server's string-to-sign
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("Authorization", format!("SharedKey {}:bogus", server.account))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let body = response.text().await.unwrap();
+    let detail = body
+        .split("<AuthenticationErrorDetail>")
+        .nth(1)
+        .and_then(|rest| rest.split("</AuthenticationErrorDetail>").next())
+        .unwrap()
+        .replace("&apos;", "'");
+    assert!(detail.contains("'bogus'"));
+    assert!(detail.contains("Server used following string to sign: 'GET\n"));
+    assert!(detail.contains("/errordetails/blob.txt"));
+}
+
+#[tokio::test]
+async fn test_seeded_blob_served_over_http() {
+    let server = TestServer::start().await;
+    let fixtures = &server.fixtures;
+    fixtures.seed_container(&server.account, "seeded").await.unwrap();
+
+    let properties = BlobProperties {
+        content_type: Some("text/plain".to_string()),
+        ..BlobProperties::default()
+    };
+    let seeded = fixtures
+        .seed_blob(&server.account, "seeded", "dir/seeded.txt", "seeded content".into(), properties)
+        .await
+        .unwrap();
+    assert_eq!(seeded.extent_chunks.len(), 1);
+    assert_eq!(seeded.properties.created_on, seeded.properties.last_modified);
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(server.blob_url("seeded", "dir/seeded.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let headers = response.headers();
+    assert_eq!(headers.get("etag").unwrap().to_str().unwrap(), seeded.properties.etag);
+    assert_eq!(headers.get("content-type").unwrap(), "text/plain");
+    assert_eq!(headers.get("x-ms-blob-type").unwrap(), "BlockBlob");
+    assert_eq!(response.text().await.unwrap(), "seeded content");
+
+    // A blob uploaded over HTTP reads back through the same accessors
+    client
+        .put(server.blob_url("seeded", "uploaded.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("uploaded content")
+        .send()
+        .await
+        .unwrap();
+    let uploaded = fixtures.blob(&server.account, "seeded", "uploaded.txt").await.unwrap();
+    assert_eq!(uploaded.extent_chunks.len(), 1);
+    assert_eq!(
+        fixtures.blob_data(&server.account, "seeded", "uploaded.txt").await.unwrap(),
+        "uploaded content"
+    );
+    assert!(fixtures.container(&server.account, "seeded").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_fixed_lease_expires_with_mock_clock() {
+    let clock = Arc::new(MockClock::default());
+    let server = TestServer::start_with(BlobServerBuilder::new().clock(clock.clone())).await;
+    create_container(&server, "leaseclock").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("leaseclock", "leased.txt");
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+
+    let acquire = || {
+        client
+            .put(format!("{}?comp=lease", blob_url))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-lease-action", "acquire")
+            .header("x-ms-lease-duration", "15")
+            .send()
+    };
+
+    let response = acquire().await.unwrap();
+    assert!(response.status().is_success());
+    let first_lease = response.headers().get("x-ms-lease-id").unwrap().to_str().unwrap().to_string();
+
+    clock.advance(chrono::Duration::seconds(10));
+    let response = acquire().await.unwrap();
+    assert_eq!(response.status(), 409);
+    assert_eq!(response.headers().get("x-ms-error-code").unwrap(), "LeaseAlreadyPresent");
+
+    // Writes without the lease ID are allowed once the lease has expired
+    clock.advance(chrono::Duration::seconds(6));
+    let response = client
+        .put(format!("{}?comp=metadata", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-meta-state", "expired")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = acquire().await.unwrap();
+    assert!(response.status().is_success());
+    let second_lease = response.headers().get("x-ms-lease-id").unwrap().to_str().unwrap();
+    assert_ne!(second_lease, first_lease);
+}
+
+#[tokio::test]
+async fn test_operation_times_out_with_mock_clock() {
+    use tokio::sync::Notify;
+
+    let clock = Arc::new(MockClock::default());
+    let server = TestServer::start_with(BlobServerBuilder::new().clock(clock.clone())).await;
+    create_container(&server, "timeoutclock").await;
+
+    // A copy source that answers only once released
+    let (entered, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let app = axum::Router::new().route(
+        "/slow.bin",
+        axum::routing::get({
+            let (entered, release) = (entered.clone(), release.clone());
+            move || async move {
+                entered.notify_one();
+                release.notified().await;
+                "slow source"
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let source = format!("http://{}/slow.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let put = |name: &str| {
+        reqwest::Client::new()
+            .put(format!("{}?timeout=5", server.blob_url("timeoutclock", name)))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-blob-type", "BlockBlob")
+            .header("x-ms-copy-source", source.as_str())
+            .header("content-length", "0")
+            .send()
+    };
+
+    // Within the timeout on the server clock
+    let request = tokio::spawn(put("quick.bin"));
+    entered.notified().await;
+    clock.advance(chrono::Duration::seconds(4));
+    release.notify_one();
+    assert_eq!(request.await.unwrap().unwrap().status(), 201);
+
+    // Past it
+    let request = tokio::spawn(put("slow.bin"));
+    entered.notified().await;
+    clock.advance(chrono::Duration::seconds(6));
+    release.notify_one();
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.headers()["x-ms-error-code"], "OperationTimedOut");
+}
+
+#[tokio::test]
+async fn test_last_modified_follows_mock_clock() {
+    use chrono::TimeZone;
+
+    let start = chrono::Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+    let clock = Arc::new(MockClock::new(start));
+    let server = TestServer::start_with(BlobServerBuilder::new().clock(clock.clone())).await;
+    create_container(&server, "clocked").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("clocked", "a.txt");
+    let response = put_blob(&server, "clocked", "a.txt", b"data".to_vec()).await;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["last-modified"], "Wed, 01 Jan 2020 00:00:00 GMT");
+
+    clock.advance(chrono::Duration::hours(1));
+    let response = client
+        .put(format!("{}?comp=metadata", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-meta-state", "updated")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["last-modified"], "Wed, 01 Jan 2020 01:00:00 GMT");
+
+    let get_if_modified_since = |since: &'static str| {
+        client
+            .get(&blob_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("If-Modified-Since", since)
+            .send()
+    };
+    let response = get_if_modified_since("Wed, 01 Jan 2020 00:30:00 GMT").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-ms-creation-time"], "Wed, 01 Jan 2020 00:00:00 GMT");
+    let response = get_if_modified_since("Wed, 01 Jan 2020 01:30:00 GMT").await.unwrap();
+    assert_eq!(response.status(), 412);
+
+    let container = server.fixtures.container(&server.account, "clocked").await.unwrap();
+    assert_eq!(container.properties.last_modified, start);
+}
+
+#[tokio::test]
+async fn test_etag_changes_on_every_write_with_frozen_clock() {
+    let server = TestServer::start_with(BlobServerBuilder::new().clock(Arc::new(MockClock::default()))).await;
+    create_container(&server, "frozen").await;
+
+    let etag = |response: reqwest::Response| response.headers()["etag"].to_str().unwrap().to_string();
+    let first = etag(put_blob(&server, "frozen", "a.txt", b"one".to_vec()).await);
+    let second = etag(put_blob(&server, "frozen", "a.txt", b"two".to_vec()).await);
+    assert_ne!(first, second);
+
+    let response = reqwest::Client::new()
+        .put(format!("{}?comp=metadata", server.blob_url("frozen", "a.txt")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-meta-state", "updated")
+        .send()
+        .await
+        .unwrap();
+    let third = etag(response);
+    assert_ne!(third, second);
+    assert_ne!(third, first);
+
+    // A blob recreated under a deleted one's name gets a new ETag too
+    let response = reqwest::Client::new()
+        .delete(server.blob_url("frozen", "a.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let fourth = etag(put_blob(&server, "frozen", "a.txt", b"one".to_vec()).await);
+    assert!(![&first, &second, &third].contains(&&fourth), "{} reused", fourth);
+}
+
+#[tokio::test]
+async fn test_gzip_applies_to_listings_not_blob_content() {
+    use std::io::Read;
+
+    let server = TestServer::start().await;
+    create_container(&server, "compressed").await;
+
+    let client = reqwest::Client::new();
+    let document = format!("<?xml version=\"1.0\"?><Items>{}</Items>", "<Item>value</Item>".repeat(100));
+    for i in 0..20 {
+        client
+            .put(server.blob_url("compressed", &format!("doc{:02}.xml", i)))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-blob-type", "BlockBlob")
+            .header("Content-Type", "application/xml")
+            .body(document.clone())
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let list_url = format!("{}?restype=container&comp=list", server.container_url("compressed"));
+    let plain = client
+        .get(&list_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert!(plain.headers().get("content-encoding").is_none());
+    let plain = plain.text().await.unwrap();
+
+    let response = client
+        .get(&list_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+    let compressed = response.bytes().await.unwrap();
+    let mut decompressed = String::new();
+    flate2::read::GzDecoder::new(&compressed[..])
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert!(compressed.len() < decompressed.len());
+    assert_eq!(decompressed, plain);
+
+    // Blob content is sent as stored even when it is XML and gzip is accepted
+    let response = client
+        .get(server.blob_url("compressed", "doc00.xml"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("Accept-Encoding", "gzip, deflate")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("content-encoding").is_none());
+    assert_eq!(response.text().await.unwrap(), document);
+}
+
+#[tokio::test]
+async fn test_get_blob_multiple_ranges() {
+    let server = TestServer::start().await;
+    create_container(&server, "multirange").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("multirange", "data.bin");
+    let content: Vec<u8> = (0..2000u32).map(|i| (i % 251) as u8).collect();
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("Content-Type", "application/octet-stream")
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+
+    let get_range = |range: &'static str| {
+        client
+            .get(&blob_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("Range", range)
+            .send()
+    };
+
+    let response = get_range("bytes=0-99,1000-1099").await.unwrap();
+    assert_eq!(response.status(), 206);
+    let content_type = response.headers().get("content-type").unwrap().to_str().unwrap().to_string();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .expect("multipart content type");
+    let body = response.bytes().await.unwrap();
+
+    let mut expected = Vec::new();
+    for (start, end) in [(0usize, 99usize), (1000, 1099)] {
+        expected.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/2000\r\n\r\n",
+                boundary, start, end
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&content[start..=end]);
+        expected.extend_from_slice(b"\r\n");
+    }
+    expected.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    assert_eq!(&body[..], &expected[..]);
+
+    // Unsatisfiable ranges are dropped; a single remaining range is served plainly
+    let response = get_range("bytes=10-19,5000-6000").await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.headers().get("content-range").unwrap(), "bytes 10-19/2000");
+    assert_eq!(&response.bytes().await.unwrap()[..], &content[10..20]);
+
+    let response = get_range("bytes=5000-5999,7000-7999").await.unwrap();
+    assert_eq!(response.status(), 416);
+}
+
+#[tokio::test]
+async fn test_get_blob_if_range() {
+    let server = TestServer::start().await;
+    create_container(&server, "ifrange").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("ifrange", "resumable.txt");
+    let upload = |body: &'static str| {
+        client
+            .put(&blob_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-blob-type", "BlockBlob")
+            .body(body)
+            .send()
+    };
+    let ranged_get = |if_range: String| {
+        client
+            .get(&blob_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("Range", "bytes=0-4")
+            .header("If-Range", if_range)
+            .send()
+    };
+
+    let response = upload("original content").await.unwrap();
+    let old_etag = response.headers().get("etag").unwrap().to_str().unwrap().to_string();
+    let last_modified = response.headers().get("last-modified").unwrap().to_str().unwrap().to_string();
+
+    // Matching ETag or date: the range is served
+    let response = ranged_get(old_etag.clone()).await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.text().await.unwrap(), "origi");
+    let response = ranged_get(last_modified).await.unwrap();
+    assert_eq!(response.status(), 206);
+
+    // After an overwrite the old ETag no longer matches: full new content
+    upload("replacement content").await.unwrap();
+    let response = ranged_get(old_etag).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("content-range").is_none());
+    assert_eq!(response.text().await.unwrap(), "replacement content");
+}
+
+#[tokio::test]
+async fn test_set_tags_and_tier_on_snapshot() {
+    use azurite_rs::models::AccessTier;
+
+    let server = TestServer::start().await;
+    create_container(&server, "snapshots").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("snapshots", "tiered.txt");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .put(format!("{}?comp=tags", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("<Tags><TagSet><Tag><Key>stage</Key><Value>base</Value></Tag></TagSet></Tags>")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    let response = client
+        .put(format!("{}?comp=snapshot", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let snapshot = response.headers()["x-ms-snapshot"].to_str().unwrap().to_string();
+    let snapshot_url = format!("{}?snapshot={}", blob_url, snapshot);
+
+    let response = client
+        .put(format!("{}&comp=tags", snapshot_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("<Tags><TagSet><Tag><Key>stage</Key><Value>snapshot</Value></Tag></TagSet></Tags>")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidQueryParameterValue");
+
+    let response = client
+        .put(format!("{}&comp=tier", snapshot_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-access-tier", "Cool")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let metadata = server.fixtures.metadata();
+    let snapshot_blob = metadata.get_blob(&server.account, "snapshots", "tiered.txt", &snapshot).await.unwrap();
+    assert_eq!(snapshot_blob.properties.access_tier, AccessTier::Cool);
+
+    // The base blob keeps its tier and tags
+    let base = server.fixtures.blob(&server.account, "snapshots", "tiered.txt").await.unwrap();
+    assert_eq!(base.properties.access_tier, AccessTier::Hot);
+    assert_eq!(base.tags.get("stage").map(String::as_str), Some("base"));
+}
+
+/// Seeds `snaps/base.txt` with metadata and takes a snapshot of it,
+/// returning the snapshot's URL.
+async fn seed_snapshot(server: &TestServer) -> String {
+    server.fixtures.seed_container(&server.account, "snaps").await.unwrap();
+    let mut blob = server
+        .fixtures
+        .seed_blob(&server.account, "snaps", "base.txt", "base".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    blob.metadata.insert("origin".to_string(), "base".to_string());
+    server.fixtures.metadata().update_blob(blob).await.unwrap();
+
+    let blob_url = server.blob_url("snaps", "base.txt");
+    let response = reqwest::Client::new()
+        .put(format!("{}?comp=snapshot", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    format!("{}?snapshot={}", blob_url, response.headers()["x-ms-snapshot"].to_str().unwrap())
+}
+
+/// Sends `request` against a snapshot and checks it is refused without
+/// touching the base blob.
+async fn assert_snapshot_write_rejected(server: &TestServer, request: reqwest::RequestBuilder) {
+    let before = server.fixtures.blob(&server.account, "snaps", "base.txt").await.unwrap();
+    let response = request.header("x-ms-version", "2021-10-04").send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidQueryParameterValue");
+
+    let after = server.fixtures.blob(&server.account, "snaps", "base.txt").await.unwrap();
+    assert_eq!(after.properties.etag, before.properties.etag);
+    assert_eq!(after.properties.content_type, before.properties.content_type);
+    assert_eq!(after.properties.lease_state, before.properties.lease_state);
+    assert_eq!(after.metadata, before.metadata);
+}
+
+#[tokio::test]
+async fn test_set_blob_properties_on_snapshot_rejected() {
+    let server = TestServer::start().await;
+    let snapshot_url = seed_snapshot(&server).await;
+    let request = reqwest::Client::new()
+        .put(format!("{}&comp=properties", snapshot_url))
+        .header("x-ms-blob-content-type", "text/html");
+    assert_snapshot_write_rejected(&server, request).await;
+}
+
+#[tokio::test]
+async fn test_set_blob_metadata_on_snapshot_rejected() {
+    let server = TestServer::start().await;
+    let snapshot_url = seed_snapshot(&server).await;
+    let request = reqwest::Client::new()
+        .put(format!("{}&comp=metadata", snapshot_url))
+        .header("x-ms-meta-origin", "snapshot");
+    assert_snapshot_write_rejected(&server, request).await;
+}
+
+#[tokio::test]
+async fn test_lease_on_snapshot_rejected() {
+    let server = TestServer::start().await;
+    let snapshot_url = seed_snapshot(&server).await;
+    let request = reqwest::Client::new()
+        .put(format!("{}&comp=lease", snapshot_url))
+        .header("x-ms-lease-action", "acquire")
+        .header("x-ms-lease-duration", "-1");
+    assert_snapshot_write_rejected(&server, request).await;
+}
+
+#[tokio::test]
+async fn test_snapshot_of_snapshot_rejected() {
+    let server = TestServer::start().await;
+    let snapshot_url = seed_snapshot(&server).await;
+    let request = reqwest::Client::new()
+        .put(format!("{}&comp=snapshot", snapshot_url))
+        .header("x-ms-meta-origin", "snapshot");
+    assert_snapshot_write_rejected(&server, request).await;
+}
+
+#[tokio::test]
+async fn test_put_blob_with_tags_header() {
+    let server = TestServer::start().await;
+    create_container(&server, "tagged").await;
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("tagged", "upload.txt");
+    let get_tags = |url: String| {
+        reqwest::Client::new()
+            .get(format!("{}?comp=tags", url))
+            .header("x-ms-version", "2021-10-04")
+            .send()
+    };
+
+    // Encoded as the Python SDK's upload_blob(tags=...) sends them
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-tags", "project=azurite&build%20id=42&path=a%2Fb")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let body = get_tags(blob_url.clone()).await.unwrap().text().await.unwrap();
+    for tag in [
+        "<Tag><Key>project</Key><Value>azurite</Value></Tag>",
+        "<Tag><Key>build id</Key><Value>42</Value></Tag>",
+        "<Tag><Key>path</Key><Value>a/b</Value></Tag>",
+    ] {
+        assert!(body.contains(tag), "{} not in {}", tag, body);
+    }
+
+    // Put Block List takes them along with the commit
+    let block_url = server.blob_url("tagged", "blocks.txt");
+    let response = client
+        .put(format!("{}?comp=block&blockid=YmxvY2sx", block_url))
+        .header("x-ms-version", "2021-10-04")
+        .body("block")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client
+        .put(format!("{}?comp=blocklist", block_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-tags", "stage=committed")
+        .body("<BlockList><Latest>YmxvY2sx</Latest></BlockList>")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let body = get_tags(block_url).await.unwrap().text().await.unwrap();
+    assert!(body.contains("<Tag><Key>stage</Key><Value>committed</Value></Tag>"), "{}", body);
+
+    // Tags breaking the service's rules fail the write
+    for tags in ["bad=a%21", "caf%C3%A9=v", "=empty", "a=1&a=2", &(0..11).map(|i| format!("k{}=v", i)).collect::<Vec<_>>().join("&")] {
+        let response = client
+            .put(server.blob_url("tagged", "invalid.txt"))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-blob-type", "BlockBlob")
+            .header("x-ms-tags", tags)
+            .body("data")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400, "tags={}", tags);
+        assert_eq!(response.headers()["x-ms-error-code"], "InvalidTag");
+    }
+    assert!(server.fixtures.blob(&server.account, "tagged", "invalid.txt").await.is_err());
+}
+
+#[tokio::test]
+async fn test_put_blob_tags_need_sas_tag_permission() {
+    let server = TestServer::start().await;
+    create_container(&server, "sastags").await;
+    let client = reqwest::Client::new();
+    let put = |sas: Vec<(String, String)>, tags: Option<&'static str>| {
+        let mut request = client
+            .put(server.blob_url("sastags", "blob.txt"))
+            .query(&sas)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-blob-type", "BlockBlob");
+        if let Some(tags) = tags {
+            request = request.header("x-ms-tags", tags);
+        }
+        request.body("data").send()
+    };
+
+    let without_tag = common::create_blob_sas(&server.account, &server.key, "sastags", None, "cw", &[]);
+    let response = put(without_tag.clone(), Some("k=v")).await.unwrap();
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.headers()["x-ms-error-code"], "AuthorizationPermissionMismatch");
+    assert_eq!(put(without_tag, None).await.unwrap().status(), 201);
+
+    let with_tag = common::create_blob_sas(&server.account, &server.key, "sastags", None, "cwt", &[]);
+    assert_eq!(put(with_tag, Some("k=v")).await.unwrap().status(), 201);
+    let blob = server.fixtures.blob(&server.account, "sastags", "blob.txt").await.unwrap();
+    assert_eq!(blob.tags.get("k").map(String::as_str), Some("v"));
+}
+
+#[tokio::test]
+async fn test_versionid_parameter() {
+    let server = TestServer::start().await;
+    create_container(&server, "versions").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("versions", "current.txt");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+
+    let request = |method: reqwest::Method, query: &str| {
+        client
+            .request(method, format!("{}?{}", blob_url, query))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .send()
+    };
+
+    // No older versions are kept, so any version ID is unknown
+    let version = "versionid=2024-01-27T12:34:56.1234567Z";
+    for (method, query) in [
+        (reqwest::Method::GET, version.to_string()),
+        (reqwest::Method::HEAD, version.to_string()),
+        (reqwest::Method::GET, format!("{}&comp=tags", version)),
+        (reqwest::Method::DELETE, version.to_string()),
+    ] {
+        let response = request(method.clone(), &query).await.unwrap();
+        assert_eq!(response.status(), 404, "{} {}", method, query);
+    }
+    assert!(server.fixtures.blob(&server.account, "versions", "current.txt").await.is_ok());
+
+    let response = request(reqwest::Method::GET, "versionid=yesterday").await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidQueryParameterValue");
+
+    let response = request(
+        reqwest::Method::GET,
+        "snapshot=2024-01-27T12:34:56.1234567Z&versionid=2024-01-27T12:34:56.1234567Z",
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidQueryParameterValue");
+}
+
+#[tokio::test]
+async fn test_concurrent_appends_get_distinct_offsets() {
+    let server = TestServer::start().await;
+    create_container(&server, "appends").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("appends", "log.bin");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "AppendBlob")
+        .send()
+        .await
+        .unwrap();
+
+    let appends = (0..50u8).map(|i| {
+        client
+            .put(format!("{}?comp=appendblock", blob_url))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .body(vec![i; 1024])
+            .send()
+    });
+    let mut offsets = Vec::new();
+    for response in futures::future::join_all(appends).await {
+        let response = response.unwrap();
+        assert_eq!(response.status(), 201);
+        let offset: u64 = response.headers()["x-ms-blob-append-offset"].to_str().unwrap().parse().unwrap();
+        offsets.push(offset);
+    }
+    offsets.sort_unstable();
+    assert_eq!(offsets, (0..50).map(|i| i * 1024).collect::<Vec<u64>>());
+
+    let blob = server.fixtures.blob(&server.account, "appends", "log.bin").await.unwrap();
+    assert_eq!(blob.properties.content_length, 50 * 1024);
+    assert_eq!(blob.properties.committed_block_count, Some(50));
+
+    // Every block is readable at the offset it was given
+    let data = server.fixtures.blob_data(&server.account, "appends", "log.bin").await.unwrap();
+    assert_eq!(data.len(), 50 * 1024);
+    for block in data.chunks(1024) {
+        assert!(block.iter().all(|&b| b == block[0]));
+    }
+}
+
+#[tokio::test]
+async fn test_append_block_count_in_listing() {
+    let server = TestServer::start().await;
+    create_container(&server, "appendlist").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("appendlist", "log.txt");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "AppendBlob")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    for (i, block) in ["one", "two", "three"].into_iter().enumerate() {
+        let response = client
+            .put(format!("{}?comp=appendblock", blob_url))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .body(block)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers()["x-ms-blob-committed-block-count"], (i + 1).to_string().as_str());
+    }
+
+    let response = client
+        .head(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-ms-blob-committed-block-count"], "3");
+    assert_eq!(response.headers()["content-length"], "11");
+
+    let response = client
+        .get(format!("{}?restype=container&comp=list", server.container_url("appendlist")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Content-Length>11</Content-Length>"), "{}", body);
+    assert!(body.contains("<CommittedBlockCount>3</CommittedBlockCount><Sealed>false</Sealed>"), "{}", body);
+}
+
+async fn put_blob(server: &TestServer, container: &str, name: &str, data: Vec<u8>) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(server.blob_url(container, name))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body(data)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_blob(server: &TestServer, container: &str, name: &str) -> Vec<u8> {
+    let response = reqwest::Client::new()
+        .get(server.blob_url(container, name))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response.bytes().await.unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn test_extent_memory_limit_rejects_writes() {
+    let extents = Arc::new(MemoryExtentStore::with_limit(1024));
+    let server = TestServer::start_with_extents(BlobServerBuilder::new(), "127.0.0.1", extents.clone()).await;
+    create_container(&server, "limited").await;
+
+    assert_eq!(put_blob(&server, "limited", "first.bin", vec![1; 1000]).await.status(), 201);
+    let response = put_blob(&server, "limited", "second.bin", vec![2; 100]).await;
+    assert_eq!(response.status(), 507);
+    assert_eq!(response.headers()["x-ms-error-code"], "ServerBusy");
+    assert_eq!(extents.usage(), ExtentUsage { memory: 1000, spilled: 0, limit: 1024 });
+
+    // Deleting data makes room again
+    let response = reqwest::Client::new()
+        .delete(server.blob_url("limited", "first.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(put_blob(&server, "limited", "second.bin", vec![2; 100]).await.status(), 201);
+}
+
+#[tokio::test]
+async fn test_extent_memory_limit_spills_to_disk() {
+    let spill_dir = tempfile::tempdir().unwrap();
+    let extents = Arc::new(MemoryExtentStore::with_spill(1024, spill_dir.path()).unwrap());
+    let server = TestServer::start_with_extents(BlobServerBuilder::new(), "127.0.0.1", extents.clone()).await;
+    create_container(&server, "spilled").await;
+
+    for (i, name) in ["a.bin", "b.bin", "c.bin"].into_iter().enumerate() {
+        assert_eq!(put_blob(&server, "spilled", name, vec![i as u8; 600]).await.status(), 201);
+    }
+    let usage = extents.usage();
+    assert!(usage.memory <= 1024, "{:?}", usage);
+    assert_eq!(usage.memory + usage.spilled, 1800);
+    assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 2);
+
+    // Spilled extents are loaded back on read, evicting others
+    assert_eq!(get_blob(&server, "spilled", "a.bin").await, vec![0; 600]);
+    assert_eq!(get_blob(&server, "spilled", "b.bin").await, vec![1; 600]);
+    assert_eq!(get_blob(&server, "spilled", "c.bin").await, vec![2; 600]);
+    let usage = extents.usage();
+    assert!(usage.memory <= 1024, "{:?}", usage);
+    assert_eq!(usage.memory + usage.spilled, 1800);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_extent_delete_during_spill_leaves_nothing_behind() {
+    use azurite_rs::ExtentStore;
+
+    let spill_dir = tempfile::tempdir().unwrap();
+    let extents = Arc::new(MemoryExtentStore::with_spill(4096, spill_dir.path()).unwrap());
+    // Writers spill extents that deleters are removing at the same time
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let writer = extents.clone();
+        tasks.push(tokio::spawn(async move {
+            for i in 0..200u8 {
+                let chunk = writer.write(vec![i; 1000].into()).await.unwrap();
+                sender.send(chunk.id).unwrap();
+            }
+        }));
+        let deleter = extents.clone();
+        tasks.push(tokio::spawn(async move {
+            while let Some(id) = receiver.recv().await {
+                deleter.delete(&id).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let stats = extents.stats().await;
+    assert_eq!(stats.extents, 0);
+    assert_eq!(stats.bytes, 0);
+    assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_small_extents_share_segments() {
+    use azurite_rs::storage::EXTENT_SEGMENT_SIZE;
+    use azurite_rs::ExtentStore;
+
+    let extents = MemoryExtentStore::new();
+    let block_size = 64 * 1024;
+    let mut chunks = Vec::new();
+    for i in 0..1000 {
+        chunks.push(extents.write(vec![i as u8; block_size].into()).await.unwrap());
+    }
+    assert_eq!(extents.usage().memory, 1000 * block_size as u64);
+
+    // Consecutive blocks are adjacent in memory until a segment fills up
+    let mut segments = 0;
+    let mut previous_end = std::ptr::null();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let data = extents.read(chunk).await.unwrap();
+        assert_eq!(data, vec![i as u8; block_size]);
+        if data.as_ptr() != previous_end {
+            segments += 1;
+        }
+        previous_end = data.as_ptr().wrapping_add(data.len());
+    }
+    assert_eq!(segments, (1000 * block_size).div_ceil(EXTENT_SEGMENT_SIZE));
+
+    // Deleting an extent leaves the rest of its segment readable
+    for chunk in chunks.iter().step_by(2) {
+        extents.delete(&chunk.id).await.unwrap();
+    }
+    assert!(extents.read(&chunks[0]).await.is_err());
+    assert_eq!(extents.read(&chunks[1]).await.unwrap(), vec![1; block_size]);
+    assert_eq!(extents.usage().memory, 500 * block_size as u64);
+
+    // Large writes keep their own buffer
+    let large = bytes::Bytes::from(vec![7; 2 * 1024 * 1024]);
+    let chunk = extents.write(large.clone()).await.unwrap();
+    assert_eq!(extents.read(&chunk).await.unwrap().as_ptr(), large.as_ptr());
+}
+
+#[tokio::test]
+async fn test_extent_memory_limit_counts_shared_segments() {
+    use azurite_rs::ExtentStore;
+
+    // A 1 MiB limit packs 64 KiB extents into 256 KiB segments
+    let block_size = 64 * 1024;
+    let extents = MemoryExtentStore::with_limit(16 * block_size as u64);
+    let mut chunks = Vec::new();
+    for i in 0..16 {
+        chunks.push(extents.write(vec![i as u8; block_size].into()).await.unwrap());
+    }
+
+    // One extent left in each segment keeps all of them in memory
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i % 4 != 0 {
+            extents.delete(&chunk.id).await.unwrap();
+        }
+    }
+    assert_eq!(extents.usage().memory, 4 * block_size as u64);
+    let err = extents.write(vec![0; block_size].into()).await.unwrap_err();
+    assert_eq!(err.code, ErrorCode::ServerBusy);
+
+    // Freeing a whole segment makes room again
+    extents.delete(&chunks[0].id).await.unwrap();
+    extents.write(vec![0; block_size].into()).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_extent_memory_limit_holds_for_concurrent_writes() {
+    use azurite_rs::ExtentStore;
+
+    let extents = Arc::new(MemoryExtentStore::with_limit(10_000));
+    let tasks: Vec<_> = (0..40)
+        .map(|_| {
+            let extents = extents.clone();
+            tokio::spawn(async move { extents.write(vec![1; 1000].into()).await.is_ok() })
+        })
+        .collect();
+    let mut written = 0;
+    for task in tasks {
+        written += task.await.unwrap() as u64;
+    }
+    assert_eq!(written, 10);
+    assert_eq!(extents.usage().memory, 10_000);
+}
+
+#[tokio::test]
+async fn test_spilling_store_keeps_extents_in_separate_buffers() {
+    use azurite_rs::ExtentStore;
+
+    let spill_dir = tempfile::tempdir().unwrap();
+    let block_size = 64 * 1024;
+    let extents = MemoryExtentStore::with_spill(16 * block_size as u64, spill_dir.path()).unwrap();
+    let mut chunks = Vec::new();
+    for i in 0..100 {
+        chunks.push(extents.write(vec![i as u8; block_size].into()).await.unwrap());
+    }
+    assert_eq!(extents.usage().memory, 16 * block_size as u64);
+
+    // The extents left in memory retain one buffer each, not a shared
+    // segment that spilled extents would keep alive
+    let mut segments = 0;
+    let mut previous_end = std::ptr::null();
+    for (i, chunk) in chunks.iter().enumerate().skip(100 - 16) {
+        let data = extents.read(chunk).await.unwrap();
+        assert_eq!(data, vec![i as u8; block_size]);
+        if data.as_ptr() != previous_end {
+            segments += 1;
+        }
+        previous_end = data.as_ptr().wrapping_add(data.len());
+    }
+    assert_eq!(segments, 16);
+    assert_eq!(extents.usage().memory, 16 * block_size as u64);
+}
+
+#[tokio::test]
+async fn test_corrupted_extent_file_fails_download() {
+    let dir = tempfile::tempdir().unwrap();
+    let extents = Arc::new(FsExtentStore::new(dir.path().to_path_buf()).await.unwrap());
+    let config = Config {
+        anonymous_private_access: true,
+        ..Config::default()
+    };
+    let blob_server = BlobServer::with_storage(config, Arc::new(MemoryMetadataStore::new()), extents);
+    let fixtures = blob_server.fixtures();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/{}", listener.local_addr().unwrap(), azurite_rs::DEFAULT_ACCOUNT);
+    let app = blob_server.router();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let data: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+    fixtures.seed_container(azurite_rs::DEFAULT_ACCOUNT, "disk").await.unwrap();
+    let blob = fixtures
+        .seed_blob(azurite_rs::DEFAULT_ACCOUNT, "disk", "data.bin", data.clone().into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let extent_id = &blob.extent_chunks[0].id;
+
+    // Flip a byte in the third 64 KiB block of the extent file
+    let path = dir.path().join(extent_id);
+    let mut contents = std::fs::read(&path).unwrap();
+    contents[150 * 1024] ^= 0xff;
+    std::fs::write(&path, contents).unwrap();
+
+    let client = reqwest::Client::new();
+    let get = |range: Option<&'static str>| {
+        let mut request = client
+            .get(format!("{}/disk/data.bin", base_url))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        if let Some(range) = range {
+            request = request.header("x-ms-range", range);
+        }
+        request.send()
+    };
+
+    // Ranges in intact blocks are still served
+    let response = get(Some("bytes=0-1023")).await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.bytes().await.unwrap(), &data[..1024]);
+
+    let response = get(None).await.unwrap();
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.headers()["x-ms-error-code"], "InternalError");
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Checksum mismatch"), "{}", body);
+    assert!(body.contains(extent_id.as_str()), "{}", body);
+
+    let response = get(Some("bytes=131072-140000")).await.unwrap();
+    assert_eq!(response.status(), 500);
+}
+
+#[tokio::test]
+async fn test_overwrite_keeps_snapshot_data() {
+    let server = TestServer::start().await;
+    create_container(&server, "versions").await;
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("versions", "data.bin");
+    let v1: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+    let v2 = vec![0x42; 1000];
+
+    assert_eq!(put_blob(&server, "versions", "data.bin", v1.clone()).await.status(), 201);
+    let response = client
+        .put(format!("{}?comp=snapshot", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let snapshot = response.headers()["x-ms-snapshot"].to_str().unwrap().to_string();
+    let snapshot_url = format!("{}?snapshot={}", blob_url, snapshot);
+
+    assert_eq!(put_blob(&server, "versions", "data.bin", v2.clone()).await.status(), 201);
+    assert_eq!(get_blob(&server, "versions", "data.bin").await, v2);
+    let response = client
+        .get(&snapshot_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.bytes().await.unwrap().to_vec(), v1);
+    assert_eq!(server.extents.usage().memory, 4096 + 1000);
+
+    // Deleting the snapshot frees only the data the base no longer uses
+    let response = client
+        .delete(&snapshot_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(server.extents.usage().memory, 1000);
+    assert_eq!(get_blob(&server, "versions", "data.bin").await, v2);
+}
+
+#[tokio::test]
+async fn test_write_operations_report_server_encryption() {
+    let server = TestServer::start().await;
+    create_container(&server, "encrypted").await;
+    let client = reqwest::Client::new();
+    let block = server.blob_url("encrypted", "block.bin");
+    let append = server.blob_url("encrypted", "append.bin");
+    let page = server.blob_url("encrypted", "page.bin");
+    let block_list = r#"<?xml version="1.0" encoding="utf-8"?><BlockList><Latest>YmxvY2sx</Latest></BlockList>"#;
+
+    // (operation, url, extra headers, body), run in order
+    let writes = [
+        ("PutBlob", block.clone(), vec![("x-ms-blob-type", "BlockBlob")], "data"),
+        ("PutBlock", format!("{}?comp=block&blockid=YmxvY2sx", block), vec![], "data"),
+        ("PutBlockList", format!("{}?comp=blocklist", block), vec![], block_list),
+        ("SetBlobMetadata", format!("{}?comp=metadata", block), vec![("x-ms-meta-a", "b")], ""),
+        ("SnapshotBlob", format!("{}?comp=snapshot", block), vec![], ""),
+        ("CreateAppendBlob", append.clone(), vec![("x-ms-blob-type", "AppendBlob")], ""),
+        ("AppendBlock", format!("{}?comp=appendblock", append), vec![], "data"),
+        (
+            "CreatePageBlob",
+            page.clone(),
+            vec![("x-ms-blob-type", "PageBlob"), ("x-ms-blob-content-length", "1024")],
+            "",
+        ),
+        (
+            "PutPage",
+            format!("{}?comp=page", page),
+            vec![("x-ms-page-write", "update"), ("x-ms-range", "bytes=0-511")],
+            std::str::from_utf8(&[b'p'; 512]).unwrap(),
+        ),
+        (
+            "ClearPage",
+            format!("{}?comp=page", page),
+            vec![("x-ms-page-write", "clear"), ("x-ms-range", "bytes=0-511")],
+            "",
+        ),
+    ];
+
+    for (operation, url, headers, body) in writes {
+        let mut request = client
+            .put(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .body(body.to_string());
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.unwrap();
+        assert!(response.status().is_success(), "{}: {}", operation, response.status());
+        assert_eq!(
+            response.headers().get("x-ms-request-server-encrypted").map(|v| v.to_str().unwrap()),
+            Some("true"),
+            "{}",
+            operation
+        );
+    }
+
+    // Reads report how the blob is stored
+    for url in [&block, &append, &page] {
+        for request in [client.get(url), client.head(url)] {
+            let response = request
+                .header("x-ms-version", "2021-10-04")
+                .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200, "{}", url);
+            assert_eq!(response.headers()["x-ms-server-encrypted"], "true", "{}", url);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_blob_name_decoding_and_validation() {
+    let server = TestServer::start().await;
+    create_container(&server, "names").await;
+    let client = reqwest::Client::new();
+
+    // Percent-encoded spaces and '#' are decoded into the stored name
+    let encoded = "dir%20one/my%20file%231.txt";
+    assert_eq!(put_blob(&server, "names", encoded, b"hash".to_vec()).await.status(), 201);
+    assert_eq!(get_blob(&server, "names", encoded).await, b"hash");
+    let response = client
+        .get(format!("{}?restype=container&comp=list", server.container_url("names")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Name>dir one/my file#1.txt</Name>"), "{}", body);
+
+    // SharedKey signs the path as sent
+    let path = format!("/{}/names/{}", server.account, encoded);
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let auth = common::create_auth_header("GET", &server.account, &server.key, &path, &[], None, None, &date, &[]);
+    let response = client
+        .get(format!("{}{}", server.base_url, path))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", &date)
+        .header("Authorization", auth)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.bytes().await.unwrap(), "hash");
+
+    // Trailing slashes and dots are part of the name
+    for name in ["folder/", "ends.with.dot."] {
+        assert_eq!(put_blob(&server, "names", name, b"x".to_vec()).await.status(), 201, "{}", name);
+        assert_eq!(get_blob(&server, "names", name).await, b"x", "{}", name);
+    }
+
+    let too_long = "a".repeat(1025);
+    for name in [too_long.as_str(), "a//b", "a/%2F/b"] {
+        let response = put_blob(&server, "names", name, b"x".to_vec()).await;
+        assert_eq!(response.status(), 400, "{}", name);
+        assert_eq!(response.headers()["x-ms-error-code"], "InvalidResourceName", "{}", name);
+    }
+    assert_eq!(put_blob(&server, "names", &"a".repeat(1024), b"x".to_vec()).await.status(), 201);
+}
+
+#[tokio::test]
+async fn test_ranged_head_and_clamped_get() {
+    let server = TestServer::start().await;
+    create_container(&server, "ranges").await;
+    assert_eq!(put_blob(&server, "ranges", "ten.bin", b"0123456789".to_vec()).await.status(), 201);
+
+    let client = reqwest::Client::new();
+    let url = server.blob_url("ranges", "ten.bin");
+    let send = |request: reqwest::RequestBuilder, range: &str| {
+        request
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-range", range.to_string())
+            .send()
+    };
+
+    // A range ending past the blob is clamped
+    let response = send(client.get(&url), "bytes=5-999999").await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.headers()["content-length"], "5");
+    assert_eq!(response.headers()["content-range"], "bytes 5-9/10");
+    assert_eq!(response.bytes().await.unwrap(), "56789");
+
+    // HEAD reports the same headers as the ranged GET
+    for (range, length, content_range) in [("bytes=5-999999", "5", "bytes 5-9/10"), ("bytes=2-3", "2", "bytes 2-3/10")] {
+        let response = send(client.head(&url), range).await.unwrap();
+        assert_eq!(response.status(), 206, "{}", range);
+        assert_eq!(response.headers()["content-length"], length, "{}", range);
+        assert_eq!(response.headers()["content-range"], content_range, "{}", range);
+    }
+
+    let response = send(client.head(&url), "bytes=0-").await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.headers()["content-length"], "10");
+
+    let response = send(client.head(&url), "bytes=10-20").await.unwrap();
+    assert_eq!(response.status(), 416);
+}
+
+#[tokio::test]
+async fn test_object_replication_simulation() {
+    let get = |url: String| {
+        reqwest::Client::new().get(url).header("x-ms-version", "2021-10-04").send()
+    };
+    let or_headers = |response: &reqwest::Response| {
+        response
+            .headers()
+            .iter()
+            .filter(|(name, _)| name.as_str().starts_with("x-ms-or-"))
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    // Off by default
+    let server = TestServer::start().await;
+    server.fixtures.seed_container(&server.account, "replicated").await.unwrap();
+    server
+        .fixtures
+        .seed_blob(&server.account, "replicated", "a.txt", "hello".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let response = get(server.blob_url("replicated", "a.txt")).await.unwrap();
+    assert!(or_headers(&response).is_empty());
+    let list = format!(
+        "{}?restype=container&comp=list&include=objectreplicationmetadata",
+        server.container_url("replicated")
+    );
+    let body = get(list).await.unwrap().text().await.unwrap();
+    assert!(!body.contains("OrMetadata"));
+
+    let server = TestServer::start_with(BlobServerBuilder::new().object_replication(true)).await;
+    for container in ["replicated", "other"] {
+        server.fixtures.seed_container(&server.account, container).await.unwrap();
+        server
+            .fixtures
+            .seed_blob(&server.account, container, "a.txt", "hello".into(), BlobProperties::default())
+            .await
+            .unwrap();
+    }
+
+    // One completed rule per container, the same on every read
+    let response = get(server.blob_url("replicated", "a.txt")).await.unwrap();
+    let headers = or_headers(&response);
+    assert_eq!(headers.len(), 1);
+    let (name, status) = &headers[0];
+    assert_eq!(status, "complete");
+    let id = name.strip_prefix("x-ms-or-").unwrap();
+    let (policy, rule) = id.split_once('_').unwrap();
+    assert!(uuid::Uuid::parse_str(policy).is_ok() && uuid::Uuid::parse_str(rule).is_ok());
+
+    let response = reqwest::Client::new()
+        .head(server.blob_url("replicated", "a.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(or_headers(&response), headers);
+    let response = get(server.blob_url("other", "a.txt")).await.unwrap();
+    assert_ne!(or_headers(&response), headers);
+
+    // Listed only when asked for
+    let list = format!("{}?restype=container&comp=list", server.container_url("replicated"));
+    let body = get(list.clone()).await.unwrap().text().await.unwrap();
+    assert!(!body.contains("OrMetadata"));
+    let body = get(format!("{}&include=metadata,objectreplicationmetadata", list))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        body.contains(&format!("<OrMetadata><Or-{id}>complete</Or-{id}></OrMetadata>", id = id)),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn test_custom_authenticator() {
+    let server = TestServer::start_with(BlobServerBuilder::new().with_authenticator(|ctx, _| {
+        Err(azurite_rs::StorageError::authentication_failed(format!("{} is not allowed", ctx.operation.as_str())))
+    }))
+    .await;
+    server.fixtures.seed_container(&server.account, "locked").await.unwrap();
+    server
+        .fixtures
+        .seed_blob(&server.account, "locked", "a.txt", "hello".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let client = reqwest::Client::new();
+
+    // Anonymous and correctly signed requests alike, at every level
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let path = format!("/{}/locked/a.txt", server.account);
+    let auth = common::create_auth_header("GET", &server.account, &server.key, &path, &[], None, None, &date, &[]);
+    let requests = [
+        client.get(format!("{}/{}?comp=list", server.base_url, server.account)),
+        client.get(format!("{}?restype=container", server.container_url("locked"))),
+        client.put(server.blob_url("locked", "b.txt")).header("x-ms-blob-type", "BlockBlob").body("data"),
+        client.get(server.blob_url("locked", "a.txt")).header("x-ms-date", &date).header("Authorization", auth),
+    ];
+    for request in requests {
+        let response = request.header("x-ms-version", "2021-10-04").send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["x-ms-error-code"], "AuthenticationFailed");
+    }
+    assert!(server.fixtures.blob(&server.account, "locked", "b.txt").await.is_err());
+
+    // Accepting everything lets a bad signature through
+    let server = TestServer::start_with(BlobServerBuilder::new().with_authenticator(|_, _| Ok(()))).await;
+    server.fixtures.seed_container(&server.account, "open").await.unwrap();
+    let response = client
+        .put(server.blob_url("open", "a.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("Authorization", format!("SharedKey {}:bm90IGEgc2lnbmF0dXJl", server.account))
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+}
+
+#[tokio::test]
+async fn test_custom_layers() {
+    use axum::extract::Request;
+    use axum::middleware::{self, Next};
+    use axum::response::Response;
+
+    async fn tag(request: Request, next: Next) -> Response {
+        let mut response = next.run(request).await;
+        let value = match response.headers().get("x-test-layers") {
+            Some(inner) => format!("outer,{}", inner.to_str().unwrap()),
+            None => "inner".to_string(),
+        };
+        response.headers_mut().insert("x-test-layers", value.parse().unwrap());
+        response
+    }
+    async fn reject_puts(request: Request, next: Next) -> Response {
+        if request.method() == axum::http::Method::PUT {
+            return Response::builder().status(503).body("injected".into()).unwrap();
+        }
+        next.run(request).await
+    }
+
+    let server = TestServer::start_with(
+        BlobServerBuilder::new()
+            .layer(middleware::from_fn(reject_puts))
+            .layer(middleware::from_fn(tag))
+            .layer(middleware::from_fn(tag)),
+    )
+    .await;
+    server.fixtures.seed_container(&server.account, "layered").await.unwrap();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(server.container_url("layered"))
+        .query(&[("restype", "container")])
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-test-layers"], "outer,inner");
+
+    let response = client
+        .put(server.blob_url("layered", "a.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["x-test-layers"], "outer,inner");
+    assert!(server.fixtures.blob(&server.account, "layered", "a.txt").await.is_err());
+}
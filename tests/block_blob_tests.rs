@@ -2,8 +2,11 @@
 
 mod common;
 
+use azurite_rs::testing::RecordingObserver;
+use azurite_rs::{BlobServerBuilder, ExtentStore, Operation};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use common::TestServer;
+use std::sync::Arc;
 
 async fn create_container(server: &TestServer, name: &str) {
     let client = reqwest::Client::new();
@@ -84,6 +87,67 @@ async fn test_stage_and_commit_blocks() {
     assert!(body.contains("Content of block 2"));
 }
 
+#[tokio::test]
+async fn test_ranges_within_and_across_blocks() {
+    let server = TestServer::start().await;
+    create_container(&server, "blockranges").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("blockranges", "blocks.bin");
+    let content: Vec<u8> = (0..350u32).map(|i| (i % 251) as u8).collect();
+
+    // Blocks of 100, 50 and 200 bytes
+    let mut block_list = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?><BlockList>");
+    for (i, (start, end)) in [(0, 100), (100, 150), (150, 350)].into_iter().enumerate() {
+        let block_id = BASE64.encode(format!("block{:05}", i));
+        let response = client
+            .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+            .header("x-ms-version", "2021-10-04")
+            .body(content[start..end].to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+        block_list.push_str(&format!("<Latest>{}</Latest>", block_id));
+    }
+    block_list.push_str("</BlockList>");
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .body(block_list)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    for (start, end) in [(0, 99), (10, 20), (99, 100), (120, 140), (50, 200), (149, 150), (130, 349), (0, 349)] {
+        let response = client
+            .get(&blob_url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-range", format!("bytes={}-{}", start, end))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.headers()["content-length"], (end - start + 1).to_string());
+        assert_eq!(&response.bytes().await.unwrap()[..], &content[start..=end], "bytes={}-{}", start, end);
+    }
+
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-range", "bytes=275-")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-range"], "bytes 275-349/350");
+    assert_eq!(&response.bytes().await.unwrap()[..], &content[275..]);
+
+    let response = client.get(&blob_url).header("x-ms-version", "2021-10-04").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(&response.bytes().await.unwrap()[..], &content[..]);
+}
+
 #[tokio::test]
 async fn test_get_block_list() {
     let server = TestServer::start().await;
@@ -124,6 +188,115 @@ async fn test_get_block_list() {
     assert!(body.contains("UncommittedBlocks"));
 }
 
+#[tokio::test]
+async fn test_get_block_list_types() {
+    let server = TestServer::start().await;
+    create_container(&server, "blocklisttypes").await;
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("blocklisttypes", "staged.txt");
+    let get_list = |list_type: &str| {
+        client
+            .get(format!("{}?comp=blocklist&blocklisttype={}", blob_url, list_type))
+            .header("x-ms-version", "2021-10-04")
+            .send()
+    };
+
+    // Nothing staged or committed yet
+    for list_type in ["committed", "uncommitted", "all"] {
+        let response = get_list(list_type).await.unwrap();
+        assert_eq!(response.status(), 404, "blocklisttype={}", list_type);
+        assert_eq!(response.headers()["x-ms-error-code"], "BlobNotFound");
+    }
+
+    let block_id = BASE64.encode("block-0");
+    let response = client
+        .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+        .header("x-ms-version", "2021-10-04")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // Only staged blocks: there is no committed version to list
+    let response = get_list("committed").await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.headers()["x-ms-error-code"], "BlobNotFound");
+    for list_type in ["uncommitted", "all", "ALL", "Uncommitted"] {
+        let response = get_list(list_type).await.unwrap();
+        assert_eq!(response.status(), 200, "blocklisttype={}", list_type);
+        assert!(response.text().await.unwrap().contains(&block_id));
+    }
+
+    for list_type in ["latest", "", "committed,uncommitted"] {
+        let response = get_list(list_type).await.unwrap();
+        assert_eq!(response.status(), 400, "blocklisttype={}", list_type);
+        assert_eq!(response.headers()["x-ms-error-code"], "InvalidQueryParameterValue");
+    }
+
+    // Once committed, a committed list leaves out blocks staged since
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .body(format!("<BlockList><Latest>{}</Latest></BlockList>", block_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let staged_id = BASE64.encode("block-1");
+    client
+        .put(format!("{}?comp=block&blockid={}", blob_url, staged_id))
+        .header("x-ms-version", "2021-10-04")
+        .body("more")
+        .send()
+        .await
+        .unwrap();
+    let response = get_list("committed").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(!response.text().await.unwrap().contains(&staged_id));
+    let response = get_list("uncommitted").await.unwrap();
+    assert!(response.text().await.unwrap().contains(&staged_id));
+}
+
+#[tokio::test]
+async fn test_uncommitted_blocks_in_staging_order() {
+    let server = TestServer::start().await;
+    create_container(&server, "ordered").await;
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("ordered", "blocks.bin");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let block_ids: Vec<String> = ["C", "A", "B"].iter().map(|id| BASE64.encode(format!("block-{}", id))).collect();
+    for block_id in &block_ids {
+        let response = client
+            .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .body("data")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+    }
+
+    let expected: String = block_ids
+        .iter()
+        .map(|id| format!("<Block><Name>{}</Name><Size>4</Size></Block>", id))
+        .collect();
+    for _ in 0..5 {
+        let response = client
+            .get(format!("{}?comp=blocklist&blocklisttype=uncommitted", blob_url))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert!(body.contains(&format!("<UncommittedBlocks>{}</UncommittedBlocks>", expected)), "{}", body);
+    }
+}
+
 #[tokio::test]
 async fn test_large_blob_multipart() {
     let server = TestServer::start().await;
@@ -200,3 +373,565 @@ async fn test_large_blob_multipart() {
         .unwrap();
     assert_eq!(content_length, block_size * num_blocks);
 }
+
+#[tokio::test]
+async fn test_observer_records_operations() {
+    let observer = Arc::new(RecordingObserver::new());
+    let server = TestServer::start_with(BlobServerBuilder::new().observer(observer.clone())).await;
+    create_container(&server, "observed").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("observed", "parts.bin");
+    let block_ids: Vec<String> = (0..3).map(|i| BASE64.encode(format!("block{:05}", i))).collect();
+    for block_id in &block_ids {
+        client
+            .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .body("data")
+            .send()
+            .await
+            .unwrap();
+    }
+    let block_list: String = block_ids.iter().map(|id| format!("<Latest>{}</Latest>", id)).collect();
+    client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .body(format!("<BlockList>{}</BlockList>", block_list))
+        .send()
+        .await
+        .unwrap();
+
+    // Failed and unroutable requests are reported too
+    client
+        .get(server.blob_url("observed", "missing.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    client
+        .put(format!("{}?comp=bogus", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(observer.count(Operation::CreateContainer), 1);
+    assert_eq!(observer.count(Operation::PutBlock), 3);
+    assert_eq!(observer.count(Operation::PutBlockList), 1);
+
+    let operations = observer.operations();
+    assert_eq!(operations.len(), 7);
+    let commit = &operations[4];
+    assert_eq!(commit.operation, Operation::PutBlockList);
+    assert_eq!(commit.account, server.account);
+    assert_eq!(commit.container.as_deref(), Some("observed"));
+    assert_eq!(commit.blob.as_deref(), Some("parts.bin"));
+    assert_eq!(commit.status, 201);
+
+    assert_eq!(operations[5].operation, Operation::GetBlob);
+    assert_eq!(operations[5].status, 404);
+    assert_eq!(operations[6].operation, Operation::Unknown);
+    assert_eq!(operations[6].status, 400);
+
+    observer.clear();
+    assert!(observer.operations().is_empty());
+}
+
+/// Serves `/data.bin` as the copy source, `/missing.bin` and `/private.bin`
+/// as Azure-style failures, and `/token.bin` only to `Bearer token`.
+async fn start_source_server() -> String {
+    use axum::{
+        http::{HeaderMap, StatusCode},
+        routing::get,
+        Router,
+    };
+
+    let xml_error = |status: StatusCode, code: &'static str| {
+        (
+            status,
+            [("content-type", "application/xml")],
+            format!("<?xml version=\"1.0\" encoding=\"utf-8\"?><Error><Code>{}</Code></Error>", code),
+        )
+    };
+    let app = Router::new()
+        .route("/data.bin", get(|| async { "source bytes" }))
+        .route("/missing.bin", get(move || async move { xml_error(StatusCode::NOT_FOUND, "BlobNotFound") }))
+        .route(
+            "/private.bin",
+            get(move || async move { xml_error(StatusCode::FORBIDDEN, "AuthorizationFailure") }),
+        )
+        .route(
+            "/token.bin",
+            get(move |headers: HeaderMap| async move {
+                match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+                    Some("Bearer token") => (StatusCode::OK, [("content-type", "application/octet-stream")], "token bytes".to_string()),
+                    _ => xml_error(StatusCode::UNAUTHORIZED, "NoAuthenticationInformation"),
+                }
+            }),
+        );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    base_url
+}
+
+fn md5_base64(data: &[u8]) -> String {
+    use md5::{Digest, Md5};
+    BASE64.encode(Md5::digest(data))
+}
+
+#[tokio::test]
+async fn test_from_url_operations_copy_source() {
+    let server = TestServer::start().await;
+    create_container(&server, "fromurl").await;
+    let source = format!("{}/data.bin", start_source_server().await);
+    let client = reqwest::Client::new();
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    // Stage Block From URL with a matching source MD5, then commit
+    let blob_url = server.blob_url("fromurl", "staged.bin");
+    let block_id = BASE64.encode("block00000");
+    let response = client
+        .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-copy-source", &source)
+        .header("x-ms-source-content-md5", md5_base64(b"source bytes"))
+        .header("content-length", "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body(format!("<BlockList><Latest>{}</Latest></BlockList>", block_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let data = server.fixtures.blob_data(&server.account, "fromurl", "staged.bin").await.unwrap();
+    assert_eq!(&data[..], b"source bytes");
+
+    // Put Blob From URL
+    let response = client
+        .put(server.blob_url("fromurl", "put.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-copy-source", &source)
+        .header("content-length", "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let data = server.fixtures.blob_data(&server.account, "fromurl", "put.bin").await.unwrap();
+    assert_eq!(&data[..], b"source bytes");
+
+    // Synchronous copy
+    let response = client
+        .put(server.blob_url("fromurl", "copied.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-copy-source", &source)
+        .header("x-ms-requires-sync", "true")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.headers()["x-ms-copy-status"], "success");
+    let blob = server.fixtures.blob(&server.account, "fromurl", "copied.bin").await.unwrap();
+    assert_eq!(blob.properties.copy_source.as_deref(), Some(source.as_str()));
+    let data = server.fixtures.blob_data(&server.account, "fromurl", "copied.bin").await.unwrap();
+    assert_eq!(&data[..], b"source bytes");
+
+    // Append Block From URL
+    let append_url = server.blob_url("fromurl", "append.bin");
+    client
+        .put(&append_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "AppendBlob")
+        .send()
+        .await
+        .unwrap();
+    let response = client
+        .put(format!("{}?comp=appendblock", append_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-copy-source", &source)
+        .header("content-length", "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let data = server.fixtures.blob_data(&server.account, "fromurl", "append.bin").await.unwrap();
+    assert_eq!(&data[..], b"source bytes");
+}
+
+#[tokio::test]
+async fn test_from_url_operations_source_failures() {
+    let server = TestServer::start().await;
+    create_container(&server, "fromurl").await;
+    let source_base = start_source_server().await;
+    let client = reqwest::Client::new();
+
+    // A port nothing listens on
+    let unreachable = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/data.bin", listener.local_addr().unwrap())
+    };
+
+    let append_url = server.blob_url("fromurl", "append.bin");
+    client
+        .put(&append_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "AppendBlob")
+        .send()
+        .await
+        .unwrap();
+
+    let blob_url = server.blob_url("fromurl", "target.bin");
+    let requests: Vec<(String, Vec<(&str, &str)>)> = vec![
+        (format!("{}?comp=block&blockid={}", blob_url, BASE64.encode("block00000")), vec![]),
+        (format!("{}?comp=appendblock", append_url), vec![]),
+        (blob_url.clone(), vec![("x-ms-blob-type", "BlockBlob")]),
+        (blob_url.clone(), vec![("x-ms-requires-sync", "true")]),
+    ];
+
+    for (url, extra_headers) in &requests {
+        let send = |source: String, md5: Option<&'static str>| {
+            let mut request = client
+                .put(url)
+                .header("x-ms-version", "2021-10-04")
+                .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+                .header("x-ms-copy-source", source)
+                .header("content-length", "0");
+            for (name, value) in extra_headers {
+                request = request.header(*name, *value);
+            }
+            if let Some(md5) = md5 {
+                request = request.header("x-ms-source-content-md5", md5);
+            }
+            request.send()
+        };
+
+        let response = send(format!("{}/missing.bin", source_base), None).await.unwrap();
+        assert_eq!(response.status(), 404, "{}", url);
+        assert_eq!(response.headers()["x-ms-error-code"], "CannotVerifyCopySource");
+        let body = response.text().await.unwrap();
+        assert!(body.contains("<CopySourceStatusCode>404</CopySourceStatusCode>"), "{}", body);
+        assert!(body.contains("<CopySourceErrorCode>BlobNotFound</CopySourceErrorCode>"), "{}", body);
+
+        let response = send(format!("{}/private.bin", source_base), None).await.unwrap();
+        assert_eq!(response.status(), 403, "{}", url);
+        assert_eq!(response.headers()["x-ms-error-code"], "CannotVerifyCopySource");
+
+        let response = send(unreachable.clone(), None).await.unwrap();
+        assert_eq!(response.status(), 400, "{}", url);
+        assert_eq!(response.headers()["x-ms-error-code"], "CannotVerifyCopySource");
+
+        let response = send(format!("{}/data.bin", source_base), Some("AAAAAAAAAAAAAAAAAAAAAA==")).await.unwrap();
+        assert_eq!(response.status(), 400, "{}", url);
+        assert_eq!(response.headers()["x-ms-error-code"], "Md5Mismatch");
+    }
+
+    assert!(server.fixtures.blob(&server.account, "fromurl", "target.bin").await.is_err());
+}
+
+#[tokio::test]
+async fn test_from_url_source_larger_than_limit_is_not_read() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::start().await;
+    create_container(&server, "fromurl").await;
+
+    // Announces one byte more than 5000 MiB and then sends nothing
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let source = format!("http://{}/huge.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0u8; 4096];
+        let _ = stream.read(&mut request).await;
+        let length = 5000u64 * 1024 * 1024 + 1;
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", length);
+        stream.write_all(head.as_bytes()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+    });
+
+    let response = reqwest::Client::new()
+        .put(server.blob_url("fromurl", "huge.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-copy-source", &source)
+        .header("content-length", "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-ms-error-code"], "RequestBodyTooLarge");
+    assert!(server.fixtures.blob(&server.account, "fromurl", "huge.bin").await.is_err());
+}
+
+#[tokio::test]
+async fn test_copy_source_authorization_forwarded() {
+    let source = format!("{}/token.bin", start_source_server().await);
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let client = reqwest::Client::new();
+
+    // Credentials are not sent to an http source outside loose mode
+    let server = TestServer::start().await;
+    create_container(&server, "fromurl").await;
+    let response = client
+        .put(server.blob_url("fromurl", "token.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-copy-source", &source)
+        .header("x-ms-copy-source-authorization", "Bearer token")
+        .header("content-length", "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "CannotVerifyCopySource");
+
+    let server = TestServer::start_with(BlobServerBuilder::new().loose(true)).await;
+    create_container(&server, "fromurl").await;
+    let response = client
+        .put(server.blob_url("fromurl", "token.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-copy-source", &source)
+        .header("x-ms-copy-source-authorization", "Bearer token")
+        .header("content-length", "0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let data = server.fixtures.blob_data(&server.account, "fromurl", "token.bin").await.unwrap();
+    assert_eq!(&data[..], b"token bytes");
+}
+
+#[tokio::test]
+async fn test_block_list_and_blob_md5_headers() {
+    let server = TestServer::start().await;
+    create_container(&server, "md5").await;
+    let client = reqwest::Client::new();
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let blob_url = server.blob_url("md5", "committed.bin");
+    let block_id = BASE64.encode("block00000");
+    client
+        .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("block data")
+        .send()
+        .await
+        .unwrap();
+
+    // Content-MD5 of the block list XML is verified
+    let block_list = format!("<BlockList><Latest>{}</Latest></BlockList>", block_id);
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("Content-MD5", md5_base64(b"not the block list"))
+        .body(block_list.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "Md5Mismatch");
+
+    // x-ms-blob-content-md5 is stored as given, even if it does not match
+    let stored_md5 = md5_base64(b"some other content");
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("Content-MD5", md5_base64(block_list.as_bytes()))
+        .header("x-ms-blob-content-md5", &stored_md5)
+        .body(block_list)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-md5"], stored_md5.as_str());
+    assert_eq!(response.text().await.unwrap(), "block data");
+
+    // Put Blob verifies the blob-level MD5 against the body
+    let response = client
+        .put(server.blob_url("md5", "single.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-blob-content-md5", &stored_md5)
+        .body("single put")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "Md5Mismatch");
+
+    let response = client
+        .put(server.blob_url("md5", "single.bin"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-blob-content-md5", md5_base64(b"single put"))
+        .body("single put")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["content-md5"], md5_base64(b"single put").as_str());
+}
+
+#[tokio::test]
+async fn test_content_type_defaults() {
+    let server = TestServer::start().await;
+    create_container(&server, "types").await;
+    let client = reqwest::Client::new();
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let content_type = |name: &'static str| {
+        let request = client
+            .head(server.blob_url("types", name))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date());
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            response.headers()["content-type"].to_str().unwrap().to_string()
+        }
+    };
+    let put_blob = |name: &'static str, headers: &[(&'static str, &'static str)]| {
+        let mut request = client
+            .put(server.blob_url("types", name))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .header("x-ms-blob-type", "BlockBlob")
+            .body("data");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        async move { assert_eq!(request.send().await.unwrap().status(), 201) }
+    };
+
+    put_blob("plain.bin", &[]).await;
+    assert_eq!(content_type("plain.bin").await, "application/octet-stream");
+
+    put_blob("transport.txt", &[("Content-Type", "text/plain")]).await;
+    assert_eq!(content_type("transport.txt").await, "text/plain");
+
+    // An explicitly empty x-ms-blob-content-type keeps the default
+    put_blob(
+        "form.bin",
+        &[("Content-Type", "application/x-www-form-urlencoded"), ("x-ms-blob-content-type", "")],
+    )
+    .await;
+    assert_eq!(content_type("form.bin").await, "application/octet-stream");
+
+    // Put Block List ignores the Content-Type of the block list XML
+    let blob_url = server.blob_url("types", "committed.bin");
+    let block_id = BASE64.encode("block00000");
+    let response = client
+        .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("block data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("Content-Type", "application/xml")
+        .body(format!("<BlockList><Latest>{}</Latest></BlockList>", block_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(content_type("committed.bin").await, "application/octet-stream");
+}
+
+#[tokio::test]
+async fn test_put_blob_frees_staged_block_extents() {
+    let server = TestServer::start().await;
+    create_container(&server, "staged").await;
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("staged", "upload.bin");
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let baseline = server.extents.total_size().await;
+
+    // An abandoned multipart upload leaves staged blocks behind
+    for i in 0..3 {
+        let response = client
+            .put(format!("{}?comp=block&blockid={}", blob_url, BASE64.encode(format!("block{:05}", i))))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .body(vec![b'x'; 1024])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+    }
+    assert_eq!(server.extents.total_size().await, baseline + 3 * 1024);
+
+    // A single-shot Put Blob discards them along with their data
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("final")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(server.extents.total_size().await, baseline + 5);
+
+    // Committing keeps the listed blocks and frees the rest
+    let blob_url = server.blob_url("staged", "committed.bin");
+    let block_ids: Vec<String> = (0..2).map(|i| BASE64.encode(format!("block{:05}", i))).collect();
+    for block_id in &block_ids {
+        client
+            .put(format!("{}?comp=block&blockid={}", blob_url, block_id))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date())
+            .body(vec![b'y'; 100])
+            .send()
+            .await
+            .unwrap();
+    }
+    let response = client
+        .put(format!("{}?comp=blocklist", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body(format!("<BlockList><Latest>{}</Latest></BlockList>", block_ids[0]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(server.extents.total_size().await, baseline + 5 + 100);
+}
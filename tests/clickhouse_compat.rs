@@ -521,6 +521,94 @@ async fn test_clickhouse_batch_delete_not_found() {
     assert!(resp_body.contains("Content-ID: 1"));
 }
 
+/// Test a batch where one delete fails: the failed part carries its own
+/// status line, request ID, error code header and XML error body.
+#[tokio::test]
+async fn test_batch_delete_mixed_results() {
+    let server = TestServer::start().await;
+    create_container(&server, "batch-mixed").await;
+
+    let client = reqwest::Client::new();
+
+    for name in ["first", "third"] {
+        client
+            .put(server.blob_url("batch-mixed", name))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-blob-type", "BlockBlob")
+            .body("data")
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let batch_boundary = format!("batch_{}", uuid::Uuid::new_v4());
+    let mut batch_body = String::new();
+    for (i, name) in ["first", "second", "third"].iter().enumerate() {
+        batch_body.push_str(&format!("--{}\r\n", batch_boundary));
+        batch_body.push_str("Content-Type: application/http\r\n");
+        batch_body.push_str("Content-Transfer-Encoding: binary\r\n");
+        batch_body.push_str(&format!("Content-ID: {}\r\n", i));
+        batch_body.push_str("\r\n");
+        batch_body.push_str(&format!(
+            "DELETE /{}/batch-mixed/{} HTTP/1.1\r\n",
+            server.account, name
+        ));
+        batch_body.push_str("x-ms-version: 2021-10-04\r\n");
+        batch_body.push_str("\r\n");
+    }
+    batch_body.push_str(&format!("--{}--\r\n", batch_boundary));
+
+    let response = client
+        .post(format!("{}/{}?comp=batch", server.base_url, server.account))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header(
+            "Content-Type",
+            format!("multipart/mixed; boundary={}", batch_boundary),
+        )
+        .body(batch_body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+
+    let resp_body = response.text().await.unwrap();
+    let parts: Vec<&str> = resp_body
+        .split(&format!("--{}", batch_boundary))
+        .filter(|part| part.contains("HTTP/1.1"))
+        .collect();
+    assert_eq!(parts.len(), 3);
+
+    for (i, part) in parts.iter().enumerate() {
+        assert!(part.contains(&format!("Content-ID: {}\r\n", i)));
+        assert!(part.contains("x-ms-request-id: "));
+    }
+
+    assert!(parts[0].contains("HTTP/1.1 202 Accepted\r\n"));
+    assert!(!parts[0].contains("x-ms-error-code"));
+    assert!(parts[2].contains("HTTP/1.1 202 Accepted\r\n"));
+
+    let failed = parts[1];
+    assert!(failed.contains("HTTP/1.1 404 The specified blob does not exist.\r\n"));
+    assert!(failed.contains("x-ms-error-code: BlobNotFound\r\n"));
+    assert!(failed.contains("Content-Type: application/xml\r\n"));
+    let (headers, body) = failed.split_once("\r\n\r\n").unwrap();
+    let (_, error_body) = body.split_once("\r\n\r\n").unwrap();
+    let error_body = error_body.trim_end();
+    assert!(headers.contains("Content-ID: 1"));
+    assert!(error_body.contains("<Code>BlobNotFound</Code>"));
+    assert!(failed.contains(&format!("Content-Length: {}\r\n", error_body.len())));
+
+    // The request ID header matches the one stamped into the error body
+    let request_id = failed
+        .split("x-ms-request-id: ")
+        .nth(1)
+        .and_then(|rest| rest.split("\r\n").next())
+        .unwrap();
+    assert!(error_body.contains(&format!("RequestId:{}", request_id)));
+}
+
 /// Test that the batch response can be parsed exactly like the Azure C++ SDK does.
 /// This simulates blob_batch.cpp's ParseSubresponses algorithm.
 #[tokio::test]
@@ -1,36 +1,71 @@
 //! Common test utilities.
 
+#![allow(dead_code)]
+
 use std::sync::Arc;
 use tokio::net::TcpListener;
 
-use azurite_rs::{BlobServer, BlobServerBuilder, Config, MemoryExtentStore, MemoryMetadataStore};
+use azurite_rs::testing::Client;
+use azurite_rs::{BlobServerBuilder, Config, Fixtures, MemoryExtentStore, MemoryMetadataStore};
 
 /// Test server wrapper.
 pub struct TestServer {
     pub base_url: String,
     pub account: String,
     pub key: String,
+    pub extents: Arc<MemoryExtentStore>,
+    pub fixtures: Fixtures,
 }
 
 impl TestServer {
     /// Creates and starts a test server on a random port.
     pub async fn start() -> Self {
+        Self::start_with(BlobServerBuilder::new()).await
+    }
+
+    /// Starts a test server on a random port from the given builder, which
+    /// may carry an observer. Storage and address are set here.
+    pub async fn start_with(builder: BlobServerBuilder) -> Self {
+        Self::start_on(builder, "127.0.0.1").await
+    }
+
+    /// Starts a test server on a random port of `host`, which may be an
+    /// IPv6 literal.
+    pub async fn start_on(builder: BlobServerBuilder, host: &str) -> Self {
+        Self::start_with_extents(builder, host, Arc::new(MemoryExtentStore::new())).await
+    }
+
+    /// Starts a test server on a random port of `host` storing blob data in
+    /// `extents`. Anonymous requests may use private containers, as most
+    /// tests send no credentials.
+    pub async fn start_with_extents(builder: BlobServerBuilder, host: &str, extents: Arc<MemoryExtentStore>) -> Self {
+        Self::launch(builder.anonymous_private_access(true), host, extents).await
+    }
+
+    /// Starts a test server that, like Azure, hides private containers from
+    /// anonymous requests.
+    pub async fn start_private(builder: BlobServerBuilder) -> Self {
+        Self::launch(builder, "127.0.0.1", Arc::new(MemoryExtentStore::new())).await
+    }
+
+    async fn launch(builder: BlobServerBuilder, host: &str, extents: Arc<MemoryExtentStore>) -> Self {
         // Find an available port
-        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port = listener.local_addr().unwrap().port();
+        let listener = TcpListener::bind((host, 0)).await.unwrap();
+        let local_addr = listener.local_addr().unwrap();
         drop(listener);
 
-        let config = Config {
-            host: "127.0.0.1".to_string(),
-            blob_port: port,
-            ..Config::default()
-        };
-
+        let config = Config::default();
         let account = config.accounts[0].name.clone();
         let key = config.accounts[0].key.clone();
-        let base_url = format!("http://127.0.0.1:{}", port);
+        let base_url = format!("http://{}", local_addr);
 
-        let server = BlobServer::new(config);
+        let server = builder
+            .host(host)
+            .port(local_addr.port())
+            .metadata(Arc::new(MemoryMetadataStore::new()))
+            .extents(extents.clone())
+            .build();
+        let fixtures = server.fixtures();
 
         // Start server in background
         tokio::spawn(async move {
@@ -44,6 +79,8 @@ impl TestServer {
             base_url,
             account,
             key,
+            extents,
+            fixtures,
         }
     }
 
@@ -56,6 +93,11 @@ impl TestServer {
     pub fn blob_url(&self, container: &str, blob: &str) -> String {
         format!("{}/{}/{}/{}", self.base_url, self.account, container, blob)
     }
+
+    /// Returns a client signing requests with the test account's key.
+    pub fn client(&self) -> Client {
+        Client::new(&self.base_url, &self.account, &self.key)
+    }
 }
 
 /// Creates authorization header value for SharedKey.
@@ -64,6 +106,7 @@ pub fn create_auth_header(
     account: &str,
     key: &str,
     path: &str,
+    query: &[(&str, &str)],
     content_length: Option<u64>,
     content_type: Option<&str>,
     date: &str,
@@ -91,8 +134,17 @@ pub fn create_auth_header(
         .collect::<Vec<_>>()
         .join("\n");
 
-    // Build canonicalized resource
-    let canonicalized_resource = format!("/{}{}", account, path);
+    // Build canonicalized resource: query names lowercased and sorted, values
+    // of repeated parameters sorted and comma-joined
+    let mut canonicalized_resource = format!("/{}{}", account, path);
+    let mut query_groups: std::collections::BTreeMap<String, Vec<&str>> = Default::default();
+    for (k, v) in query {
+        query_groups.entry(k.to_lowercase()).or_default().push(v);
+    }
+    for (k, mut values) in query_groups {
+        values.sort();
+        canonicalized_resource.push_str(&format!("\n{}:{}", k, values.join(",")));
+    }
 
     // Build string to sign
     let content_length_str = content_length
@@ -117,3 +169,78 @@ pub fn create_auth_header(
 
     format!("SharedKey {}:{}", account, signature)
 }
+
+/// Creates the query parameters of a service SAS for a container (`blob` is
+/// `None`, sr=c) or a blob (sr=b). `overrides` holds the optional rscc, rscd,
+/// rsce, rscl and rsct response header overrides.
+pub fn create_blob_sas(
+    account: &str,
+    key: &str,
+    container: &str,
+    blob: Option<&str>,
+    permissions: &str,
+    overrides: &[(&str, &str)],
+) -> Vec<(String, String)> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    let expiry = (chrono::Utc::now() + chrono::Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string();
+    let version = "2021-10-04";
+    let resource = if blob.is_some() { "b" } else { "c" };
+
+    let mut canonicalized_resource = format!("/blob/{}/{}", account, container);
+    if let Some(blob) = blob {
+        canonicalized_resource.push('/');
+        canonicalized_resource.push_str(blob);
+    }
+
+    let override_value = |name: &str| {
+        overrides
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| *v)
+            .unwrap_or("")
+    };
+
+    let string_to_sign = [
+        permissions,
+        "",
+        &expiry,
+        &canonicalized_resource,
+        "",
+        "",
+        "",
+        version,
+        resource,
+        "",
+        "",
+        override_value("rscc"),
+        override_value("rscd"),
+        override_value("rsce"),
+        override_value("rscl"),
+        override_value("rsct"),
+    ]
+    .join("\n");
+
+    let key_bytes = BASE64.decode(key).unwrap();
+    let mut mac = HmacSha256::new_from_slice(&key_bytes).unwrap();
+    mac.update(string_to_sign.as_bytes());
+    let signature = BASE64.encode(mac.finalize().into_bytes());
+
+    let mut params = vec![
+        ("sv".to_string(), version.to_string()),
+        ("sr".to_string(), resource.to_string()),
+        ("sp".to_string(), permissions.to_string()),
+        ("se".to_string(), expiry),
+    ];
+    for (k, v) in overrides {
+        params.push((k.to_string(), v.to_string()));
+    }
+    params.push(("sig".to_string(), signature));
+    params
+}
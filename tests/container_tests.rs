@@ -2,20 +2,23 @@
 
 mod common;
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use azurite_rs::models::{BlobProperties, PublicAccessLevel};
+use azurite_rs::testing::{ClientError, MockClock};
+use azurite_rs::config::AccountConfig;
+use azurite_rs::{BlobServerBuilder, Config, ExtentStore};
+use bytes::Bytes;
 use common::TestServer;
 
 #[tokio::test]
 async fn test_create_container() {
     let server = TestServer::start().await;
 
-    let client = reqwest::Client::new();
-    let url = format!("{}?restype=container", server.container_url("testcontainer"));
-
-    let response = client
-        .put(&url)
-        .header("x-ms-version", "2021-10-04")
-        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
-        .send()
+    let response = server
+        .client()
+        .send_signed(reqwest::Method::PUT, Some("testcontainer"), None, &[("restype", "container")], &[], Bytes::new())
         .await
         .unwrap();
 
@@ -27,29 +30,14 @@ async fn test_create_container() {
 #[tokio::test]
 async fn test_create_duplicate_container() {
     let server = TestServer::start().await;
+    let client = server.client();
 
-    let client = reqwest::Client::new();
-    let url = format!("{}?restype=container", server.container_url("dupcontainer"));
-
-    // Create first time
-    let response = client
-        .put(&url)
-        .header("x-ms-version", "2021-10-04")
-        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
-        .send()
-        .await
-        .unwrap();
-    assert_eq!(response.status(), 201);
+    client.create_container("dupcontainer").await.unwrap();
 
     // Create second time - should fail
-    let response = client
-        .put(&url)
-        .header("x-ms-version", "2021-10-04")
-        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
-        .send()
-        .await
-        .unwrap();
-    assert_eq!(response.status(), 409);
+    let err = client.create_container("dupcontainer").await.unwrap_err();
+    assert!(matches!(err, ClientError::Service { status: 409, .. }), "{}", err);
+    assert_eq!(err.code(), Some("ContainerAlreadyExists"));
 }
 
 #[tokio::test]
@@ -147,6 +135,60 @@ async fn test_list_containers() {
     assert!(body.contains("listcontainer2"));
 }
 
+#[tokio::test]
+async fn test_bare_service_requests() {
+    let server = TestServer::start().await;
+    let client = reqwest::Client::new();
+    let account_url = format!("{}/{}", server.base_url, server.account);
+
+    // The account root needs comp or restype
+    for url in [account_url.clone(), format!("{}/", account_url)] {
+        let response = client.get(&url).header("x-ms-version", "2021-10-04").send().await.unwrap();
+        assert_eq!(response.status(), 400, "url={}", url);
+        assert_eq!(response.headers()["x-ms-error-code"], "MissingRequiredQueryParameter");
+        let body = response.text().await.unwrap();
+        assert!(body.contains("<QueryParameterName>comp</QueryParameterName>"), "{}", body);
+
+        let response = client.head(&url).header("x-ms-version", "2021-10-04").send().await.unwrap();
+        assert_eq!(response.status(), 400);
+        assert_eq!(response.headers()["x-ms-error-code"], "MissingRequiredQueryParameter");
+    }
+
+    // A browser at the server root gets a plain-text pointer to the endpoint
+    let response = client.get(format!("{}/", server.base_url)).send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "MissingRequiredQueryParameter");
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains(&account_url), "{}", body);
+
+    // A signed request there gets the error document
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let auth = common::create_auth_header("GET", &server.account, &server.key, "/", &[], None, None, &date, &[]);
+    let response = client
+        .get(format!("{}/", server.base_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", &date)
+        .header("Authorization", auth)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "MissingRequiredQueryParameter");
+    assert!(response.text().await.unwrap().contains("<Code>MissingRequiredQueryParameter</Code>"));
+
+    // Unknown operations are still reported as such
+    let response = client
+        .get(&account_url)
+        .query(&[("comp", "bogus")])
+        .header("x-ms-version", "2021-10-04")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidQueryParameterValue");
+}
+
 #[tokio::test]
 async fn test_container_metadata() {
     let server = TestServer::start().await;
@@ -178,3 +220,1309 @@ async fn test_container_metadata() {
     assert_eq!(response.headers().get("x-ms-meta-key1").map(|v| v.to_str().unwrap()), Some("value1"));
     assert_eq!(response.headers().get("x-ms-meta-key2").map(|v| v.to_str().unwrap()), Some("value2"));
 }
+
+#[tokio::test]
+async fn test_timeout_query_parameter() {
+    let server = TestServer::start().await;
+
+    let client = reqwest::Client::new();
+
+    // A valid timeout is accepted
+    let url = format!("{}?restype=container&timeout=30", server.container_url("timeoutcontainer"));
+    let response = client
+        .put(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // Non-numeric, zero and oversized timeouts are rejected
+    for (timeout, code) in [
+        ("abc", "InvalidQueryParameterValue"),
+        ("0", "OutOfRangeQueryParameterValue"),
+        ("-5", "OutOfRangeQueryParameterValue"),
+        ("100000", "OutOfRangeQueryParameterValue"),
+    ] {
+        let url = format!(
+            "{}?restype=container&timeout={}",
+            server.container_url("timeoutcontainer"),
+            timeout
+        );
+        let response = client
+            .get(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400, "timeout={}", timeout);
+        assert_eq!(
+            response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+            Some(code)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_container_name() {
+    let server = TestServer::start().await;
+
+    let client = reqwest::Client::new();
+    for name in ["AB", "Upper", "a", "bad--name", "-start", "end-"] {
+        let url = format!("{}?restype=container", server.container_url(name));
+        let response = client
+            .get(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400, "container {}", name);
+        assert_eq!(
+            response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+            Some("InvalidResourceName")
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_root_container() {
+    let server = TestServer::start().await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put(format!("{}?restype=container", server.container_url("$root")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // A single segment after the account addresses a blob in $root
+    let blob_url = format!("{}/{}/hello.txt", server.base_url, server.account);
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("hello root")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "hello root");
+
+    // The explicit $root path reaches the same blob
+    let response = client
+        .get(server.blob_url("$root", "hello.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_container_conditional_headers() {
+    let server = TestServer::start().await;
+
+    let client = reqwest::Client::new();
+    let url = format!("{}?restype=container", server.container_url("condcontainer"));
+    let response = client
+        .put(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    let created = response.headers()["last-modified"].to_str().unwrap().to_string();
+
+    // Nothing changed since creation
+    let response = client
+        .put(format!("{}&comp=metadata", url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("If-Modified-Since", &created)
+        .header("x-ms-meta-key", "value")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 412);
+
+    // Modify the container after it was read
+    tokio::time::sleep(tokio::time::Duration::from_millis(1100)).await;
+    let response = client
+        .put(format!("{}&comp=metadata", url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-meta-key", "value")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let updated = response.headers()["last-modified"].to_str().unwrap().to_string();
+
+    // Delete conditioned on the stale read fails
+    let response = client
+        .delete(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("If-Unmodified-Since", &created)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 412);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("ConditionNotMet")
+    );
+
+    // Delete conditioned on the latest Last-Modified succeeds
+    let response = client
+        .delete(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("If-Unmodified-Since", &updated)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+}
+
+#[tokio::test]
+async fn test_invalid_maxresults() {
+    let server = TestServer::start().await;
+
+    let client = reqwest::Client::new();
+    let cases = [
+        ("0", 400, "OutOfRangeQueryParameterValue"),
+        ("-1", 400, "OutOfRangeQueryParameterValue"),
+        ("5001", 400, "OutOfRangeQueryParameterValue"),
+        ("ten", 400, "InvalidQueryParameterValue"),
+    ];
+
+    for (value, status, code) in cases {
+        let url = format!("{}/{}?comp=list&maxresults={}", server.base_url, server.account, value);
+        let response = client
+            .get(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status, "maxresults={}", value);
+        assert_eq!(
+            response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+            Some(code)
+        );
+        let body = response.text().await.unwrap();
+        assert!(body.contains("<QueryParameterName>maxresults</QueryParameterName>"));
+        assert!(body.contains(&format!("<QueryParameterValue>{}</QueryParameterValue>", value)));
+        assert!(body.contains("<Reason>"));
+    }
+
+    let url = format!("{}/{}?comp=list&maxresults=5000", server.base_url, server.account);
+    let response = client
+        .get(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_delete_container_cascades() {
+    let server = TestServer::start().await;
+
+    let client = reqwest::Client::new();
+    let container_url = format!("{}?restype=container", server.container_url("cascade"));
+    let send = |request: reqwest::RequestBuilder| async move {
+        request
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+            .await
+            .unwrap()
+    };
+
+    let response = send(client.put(&container_url)).await;
+    assert_eq!(response.status(), 201);
+
+    for name in ["a.txt", "dir/b.txt"] {
+        let response = send(
+            client
+                .put(server.blob_url("cascade", name))
+                .header("x-ms-blob-type", "BlockBlob")
+                .body("blob data"),
+        )
+        .await;
+        assert_eq!(response.status(), 201);
+    }
+
+    // A snapshot and an uncommitted block
+    let response =
+        send(client.put(format!("{}?comp=snapshot", server.blob_url("cascade", "a.txt"))).body("")).await;
+    assert_eq!(response.status(), 201);
+    let response = send(
+        client
+            .put(format!("{}?comp=block&blockid=YmxvY2sx", server.blob_url("cascade", "staged.txt")))
+            .body("block data"),
+    )
+    .await;
+    assert_eq!(response.status(), 201);
+    assert!(server.extents.total_size().await > 0);
+
+    let response = send(client.delete(&container_url)).await;
+    assert_eq!(response.status(), 202);
+    assert_eq!(server.extents.total_size().await, 0);
+
+    // Recreating the container does not resurrect old blobs
+    let response = send(client.put(&container_url)).await;
+    assert_eq!(response.status(), 201);
+    let response = send(client.get(format!("{}&comp=list&include=snapshots", container_url))).await;
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("<Blob>"), "unexpected blobs: {}", body);
+
+    let response = send(client.get(format!(
+        "{}?comp=blocklist&blocklisttype=all",
+        server.blob_url("cascade", "staged.txt")
+    )))
+    .await;
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("YmxvY2sx"), "staged block survived: {}", body);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_container_delete_races_blob_writes() {
+    use azurite_rs::models::{BlobModel, BlobType, BlockModel, ContainerModel, ExtentChunk};
+    use azurite_rs::{ErrorCode, MemoryMetadataStore, MetadataStore, DEFAULT_ACCOUNT};
+
+    let store = Arc::new(MemoryMetadataStore::new());
+    let container = || ContainerModel::new(DEFAULT_ACCOUNT.to_string(), "race".to_string(), chrono::Utc::now());
+
+    for round in 0..20 {
+        store.create_container(container()).await.unwrap();
+
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    for n in 0..200 {
+                        let name = format!("w{}-{}", writer, n);
+                        let blob = BlobModel::new(DEFAULT_ACCOUNT.to_string(), "race".to_string(), name.clone(), BlobType::BlockBlob, 4, chrono::Utc::now());
+                        let chunk = ExtentChunk::new(format!("{}-{}-{}", round, writer, n), 0, 4);
+                        let block = BlockModel::new(DEFAULT_ACCOUNT.to_string(), "race".to_string(), name, "YQ==".to_string(), 4, chunk, chrono::Utc::now());
+                        for result in [store.create_blob(blob).await, store.stage_block(block).await] {
+                            if let Err(e) = result {
+                                assert_eq!(e.code, ErrorCode::ContainerNotFound);
+                            }
+                        }
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+
+        // Delete mid-way, and in odd rounds recreate the container at once
+        for _ in 0..50 {
+            tokio::task::yield_now().await;
+        }
+        store.delete_container(DEFAULT_ACCOUNT, "race", None).await.unwrap();
+        if round % 2 == 1 {
+            store.create_container(container()).await.unwrap();
+        }
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        // Whatever landed is either gone with the old container or listed,
+        // counted and indexed in the new one; nothing is orphaned
+        let state = store.export_state().await.unwrap();
+        let exists = store.container_exists(DEFAULT_ACCOUNT, "race").await;
+        assert_eq!(exists, round % 2 == 1);
+        if !exists {
+            assert!(state.blobs.is_empty(), "round {}: {} orphaned blobs", round, state.blobs.len());
+            assert!(state.blocks.is_empty(), "round {}: {} orphaned blocks", round, state.blocks.len());
+            continue;
+        }
+        let mut stored: Vec<String> = state.blobs.iter().map(|blob| blob.name.clone()).collect();
+        stored.sort();
+        let (listed, _, _) = store
+            .list_blobs(DEFAULT_ACCOUNT, "race", None, None, None, Some(5000), false, false)
+            .await
+            .unwrap();
+        let listed: Vec<String> = listed.into_iter().map(|blob| blob.name).collect();
+        assert_eq!(listed, stored, "round {}", round);
+        let stats = store.container_stats(DEFAULT_ACCOUNT, "race").await;
+        assert_eq!(stats.blobs, stored.len() as u64, "round {}", round);
+        assert_eq!(stats.bytes, 4 * stored.len() as u64, "round {}", round);
+        for block in &state.blocks {
+            assert_eq!(store.get_staged_blocks(DEFAULT_ACCOUNT, "race", &block.blob).await.unwrap().len(), 1);
+        }
+
+        store.delete_container(DEFAULT_ACCOUNT, "race", None).await.unwrap();
+    }
+
+    let stats = store.stats().await;
+    assert_eq!(stats.accounts.get(DEFAULT_ACCOUNT).map(|account| account.containers), Some(0));
+}
+
+#[tokio::test]
+async fn test_serve_over_ipv6() {
+    let server = TestServer::start_on(BlobServerBuilder::new(), "::1").await;
+    assert!(server.base_url.starts_with("http://[::1]:"));
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put(format!("{}?restype=container", server.container_url("ipv6")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(format!("{}/{}?comp=list", server.base_url, server.account))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("<Name>ipv6</Name>"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_serve_over_unix_socket() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("azurite.sock");
+    let server = BlobServerBuilder::new().unix_socket(&socket).build();
+    tokio::spawn(async move {
+        server.run().await.unwrap();
+    });
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    // The URL has no authority; the account comes from the path and the
+    // Host header is arbitrary
+    let send = |request: String| {
+        let socket = socket.clone();
+        async move {
+            let mut stream = UnixStream::connect(&socket).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        }
+    };
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let response = send(format!(
+        "PUT /devstoreaccount1/uds?restype=container HTTP/1.1\r\nHost: localhost\r\n\
+         x-ms-version: 2021-10-04\r\nx-ms-date: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        date
+    ))
+    .await;
+    assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
+
+    let response = send(format!(
+        "GET /devstoreaccount1?comp=list HTTP/1.1\r\nHost: azurite\r\n\
+         x-ms-version: 2021-10-04\r\nx-ms-date: {}\r\nConnection: close\r\n\r\n",
+        date
+    ))
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("<Name>uds</Name>"));
+}
+
+#[tokio::test]
+async fn test_connection_limit() {
+    let server = TestServer::start_with(BlobServerBuilder::new().max_connections(1).tcp_nodelay(true)).await;
+    let client = reqwest::Client::builder().pool_max_idle_per_host(0).build().unwrap();
+    let url = format!("{}/{}?comp=list", server.base_url, server.account);
+    let list = || {
+        client
+            .get(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+    };
+
+    // An idle connection takes the only slot
+    let idle = tokio::net::TcpStream::connect(server.base_url.trim_start_matches("http://"))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let response = list().await.unwrap();
+    assert_eq!(response.status(), 503);
+    assert!(response.text().await.unwrap().contains("<Code>ServerBusy</Code>"));
+
+    // The slot is free again once the connection closes
+    drop(idle);
+    let mut status = 0;
+    for _ in 0..20 {
+        status = list().await.unwrap().status().as_u16();
+        if status == 200 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn test_keep_alive_timeout_closes_idle_connections() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::start_with(BlobServerBuilder::new().keep_alive_timeout(Duration::from_millis(200))).await;
+    let mut stream = tokio::net::TcpStream::connect(server.base_url.trim_start_matches("http://"))
+        .await
+        .unwrap();
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    stream
+        .write_all(
+            format!(
+                "GET /{}?comp=list HTTP/1.1\r\nHost: localhost\r\nx-ms-version: 2021-10-04\r\nx-ms-date: {}\r\n\r\n",
+                server.account, date
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    // The response leaves the connection open, until it has idled too long
+    let mut response = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await;
+    assert!(read.is_ok(), "idle connection was not closed");
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 200"));
+}
+
+#[tokio::test]
+async fn test_keep_alive_timeout_counts_from_the_last_response() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::start_with(BlobServerBuilder::new().keep_alive_timeout(Duration::from_millis(300))).await;
+    let mut stream = tokio::net::TcpStream::connect(server.base_url.trim_start_matches("http://"))
+        .await
+        .unwrap();
+
+    // Requests spaced under the timeout keep the connection open for longer
+    // than the timeout in total
+    for _ in 0..4 {
+        let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        stream
+            .write_all(
+                format!(
+                    "GET /{}?comp=list HTTP/1.1\r\nHost: localhost\r\nx-ms-version: 2021-10-04\r\nx-ms-date: {}\r\n\r\n",
+                    server.account, date
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed while in use");
+            response.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&response);
+            let length = text.find("\r\n\r\n").map(|head_end| {
+                let length = text[..head_end]
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length: ").map(str::to_string));
+                (head_end + 4, length.and_then(|length| length.parse::<usize>().ok()))
+            });
+            match length {
+                Some((body, Some(length))) if response.len() >= body + length => break,
+                Some((_, None)) if response.ends_with(b"\r\n0\r\n\r\n") => break,
+                _ => {}
+            }
+        }
+        assert!(response.starts_with(b"HTTP/1.1 200"));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_router_nested_under_prefix() {
+    use axum::{routing::get, Router};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let blob_server = BlobServerBuilder::new().build();
+    let app = Router::new()
+        .nest("/azure", blob_server.router())
+        .route("/health", get(|| async { "ok" }));
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let account = azurite_rs::DEFAULT_ACCOUNT;
+
+    // SharedKey signatures cover the mount prefix
+    let path = format!("/azure/{}/mounted", account);
+    let query = [("restype", "container")];
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let auth = common::create_auth_header(
+        "PUT",
+        account,
+        azurite_rs::DEFAULT_ACCOUNT_KEY,
+        &path,
+        &query,
+        None,
+        None,
+        &date,
+        &[],
+    );
+    let response = client
+        .put(format!("{}{}", base_url, path))
+        .query(&query)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", &date)
+        .header("Authorization", auth)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(format!("{}/azure/{}?comp=list", base_url, account))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains(&format!(r#"ServiceEndpoint="{}/azure/{}/""#, base_url, account)));
+    assert!(body.contains("<Name>mounted</Name>"));
+
+    let response = client.get(format!("{}/health", base_url)).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "ok");
+}
+
+#[tokio::test]
+async fn test_list_containers_include() {
+    let server = TestServer::start().await;
+    let fixtures = &server.fixtures;
+
+    let mut tagged = fixtures.seed_container(&server.account, "tagged").await.unwrap();
+    tagged.metadata.insert("owner".to_string(), "ci".to_string());
+    fixtures.metadata().update_container(tagged).await.unwrap();
+
+    let mut removed = fixtures.seed_container(&server.account, "removed").await.unwrap();
+    removed.deleted = true;
+    removed.deleted_version = Some("01D60F8BB59A4652".to_string());
+    removed.remaining_retention_days = Some(7);
+    fixtures.metadata().update_container(removed).await.unwrap();
+
+    fixtures.seed_container(&server.account, "$logs").await.unwrap();
+
+    let client = reqwest::Client::new();
+    let list = |include: &'static str| {
+        let mut url = format!("{}/{}?comp=list", server.base_url, server.account);
+        if !include.is_empty() {
+            url.push_str("&include=");
+            url.push_str(include);
+        }
+        let request = client
+            .get(url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            response.text().await.unwrap()
+        }
+    };
+
+    // By default only live user containers are listed, without metadata
+    let body = list("").await;
+    assert!(body.contains("<Name>tagged</Name>"));
+    assert!(!body.contains("<Name>removed</Name>"));
+    assert!(!body.contains("<Name>$logs</Name>"));
+    assert!(!body.contains("<Metadata>"));
+
+    let body = list("metadata").await;
+    assert!(body.contains("<Metadata><owner>ci</owner></Metadata>"));
+    assert!(!body.contains("<Name>removed</Name>"));
+
+    let body = list("deleted").await;
+    assert!(body.contains(
+        "<Name>removed</Name><Deleted>true</Deleted><Version>01D60F8BB59A4652</Version>"
+    ));
+    assert!(body.contains("<RemainingRetentionDays>7</RemainingRetentionDays>"));
+    assert!(!body.contains("<Name>$logs</Name>"));
+    assert!(!body.contains("<Metadata>"));
+
+    let body = list("system").await;
+    assert!(body.contains("<Name>$logs</Name>"));
+    assert!(!body.contains("<Name>removed</Name>"));
+}
+
+#[tokio::test]
+async fn test_system_containers() {
+    let server = TestServer::start().await;
+    let client = reqwest::Client::new();
+
+    let create = |name: &'static str| {
+        client
+            .put(format!("{}?restype=container", server.container_url(name)))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+    };
+    assert_eq!(create("$web").await.unwrap().status(), 201);
+    assert_eq!(create("$root").await.unwrap().status(), 201);
+
+    let response = create("$foo").await.unwrap();
+    assert_eq!(response.status(), 400);
+    assert!(response.text().await.unwrap().contains("<Code>InvalidResourceName</Code>"));
+
+    let list = |query: &'static str| {
+        client
+            .get(format!("{}/{}?comp=list{}", server.base_url, server.account, query))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+    };
+
+    // The root container is an ordinary container, $web is a system one
+    let body = list("").await.unwrap().text().await.unwrap();
+    assert!(body.contains("<Name>$root</Name>"));
+    assert!(!body.contains("<Name>$web</Name>"));
+
+    let body = list("&include=system").await.unwrap().text().await.unwrap();
+    assert!(body.contains("<Name>$root</Name>"));
+    assert!(body.contains("<Name>$web</Name>"));
+    assert!(!body.contains("<Name>$foo</Name>"));
+}
+
+#[tokio::test]
+async fn test_container_being_deleted() {
+    let clock = Arc::new(MockClock::default());
+    let builder = BlobServerBuilder::new()
+        .clock(clock.clone())
+        .container_delete_linger(Duration::from_secs(30));
+    let server = TestServer::start_with(builder).await;
+
+    let client = reqwest::Client::new();
+    let url = format!("{}?restype=container", server.container_url("lingering"));
+    let send = |method: reqwest::Method, url: String| client.request(method, url).header("x-ms-version", "2021-10-04").send();
+
+    assert_eq!(send(reqwest::Method::PUT, url.clone()).await.unwrap().status(), 201);
+    assert_eq!(send(reqwest::Method::DELETE, url.clone()).await.unwrap().status(), 202);
+
+    // The name stays reserved and the container is gone from listings
+    for (method, url) in [
+        (reqwest::Method::PUT, url.clone()),
+        (reqwest::Method::GET, url.clone()),
+        (reqwest::Method::GET, format!("{}&comp=list", url)),
+        (reqwest::Method::GET, server.blob_url("lingering", "blob.txt")),
+    ] {
+        let response = send(method.clone(), url.clone()).await.unwrap();
+        assert_eq!(response.status(), 409, "{} {}", method, url);
+        assert_eq!(response.headers()["x-ms-error-code"], "ContainerBeingDeleted");
+    }
+    let list_url = format!("{}/{}?comp=list", server.base_url, server.account);
+    let body = send(reqwest::Method::GET, list_url).await.unwrap().text().await.unwrap();
+    assert!(!body.contains("<Name>lingering</Name>"), "{}", body);
+
+    clock.advance(chrono::Duration::seconds(31));
+    assert_eq!(send(reqwest::Method::GET, url.clone()).await.unwrap().status(), 404);
+    assert_eq!(send(reqwest::Method::PUT, url).await.unwrap().status(), 201);
+}
+
+#[tokio::test]
+async fn test_set_service_properties_validation() {
+    let server = TestServer::start().await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/{}?restype=service&comp=properties", server.base_url, server.account);
+
+    let set = |body: String| {
+        client
+            .put(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .body(body)
+            .send()
+    };
+    let cors_rule = "<CorsRule><AllowedOrigins>*</AllowedOrigins><AllowedMethods>GET,PUT</AllowedMethods>\
+                     <AllowedHeaders>*</AllowedHeaders><ExposedHeaders>*</ExposedHeaders>\
+                     <MaxAgeInSeconds>60</MaxAgeInSeconds></CorsRule>";
+
+    let response = set(format!(
+        "<StorageServiceProperties><Cors>{}</Cors></StorageServiceProperties>",
+        cors_rule.repeat(6)
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidXmlDocument");
+
+    let response = set(
+        "<StorageServiceProperties><DeleteRetentionPolicy><Enabled>true</Enabled><Days>0</Days>\
+         </DeleteRetentionPolicy></StorageServiceProperties>"
+            .to_string(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidXmlNodeValue");
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<XmlNodeName>Days</XmlNodeName>"), "{}", body);
+    assert!(body.contains("<XmlNodeValue>0</XmlNodeValue>"), "{}", body);
+
+    let response = set(format!(
+        "<StorageServiceProperties><Cors>{}</Cors></StorageServiceProperties>",
+        cors_rule.replace("GET,PUT", "GET,FETCH")
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<XmlNodeName>AllowedMethods</XmlNodeName>"), "{}", body);
+
+    let response = set(
+        "<StorageServiceProperties><DefaultServiceVersion>2000-01-01</DefaultServiceVersion>\
+         </StorageServiceProperties>"
+            .to_string(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.headers()["x-ms-error-code"], "InvalidXmlNodeValue");
+
+    // Within the limits the properties are stored
+    let response = set(format!(
+        "<StorageServiceProperties><Cors>{}</Cors><DefaultServiceVersion>2021-10-04</DefaultServiceVersion>\
+         <DeleteRetentionPolicy><Enabled>true</Enabled><Days>7</Days></DeleteRetentionPolicy>\
+         </StorageServiceProperties>",
+        cors_rule.repeat(5)
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 202);
+}
+
+#[tokio::test]
+async fn test_service_properties_per_account_and_merged() {
+    let mut config = Config::default();
+    config.accounts.push(AccountConfig {
+        name: "second".to_string(),
+        key: config.accounts[0].key.clone(),
+    });
+    let server = TestServer::start_with(BlobServerBuilder::new().config(config)).await;
+    let client = reqwest::Client::new();
+    let url = |account: &str| format!("{}/{}?restype=service&comp=properties", server.base_url, account);
+    let set = |account: &str, body: &str| {
+        client
+            .put(url(account))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .body(body.to_string())
+            .send()
+    };
+    let get = |account: &str| {
+        let request = client
+            .get(url(account))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        async move { request.send().await.unwrap().text().await.unwrap() }
+    };
+
+    // An account that never set properties reports the defaults
+    let defaults = get("second").await;
+    assert!(defaults.contains("<Logging><Version>1.0</Version><Read>false</Read>"), "{}", defaults);
+    assert!(defaults.contains("<HourMetrics><Version>1.0</Version><Enabled>false</Enabled>"), "{}", defaults);
+    assert!(defaults.contains("<DeleteRetentionPolicy><Enabled>false</Enabled></DeleteRetentionPolicy>"), "{}", defaults);
+
+    let cors_rule = "<Cors><CorsRule><AllowedOrigins>*</AllowedOrigins><AllowedMethods>GET</AllowedMethods>\
+                     <AllowedHeaders>*</AllowedHeaders><ExposedHeaders>*</ExposedHeaders>\
+                     <MaxAgeInSeconds>60</MaxAgeInSeconds></CorsRule></Cors>";
+    let response = set(
+        &server.account,
+        &format!(
+            "<StorageServiceProperties>{}<DeleteRetentionPolicy><Enabled>true</Enabled><Days>7</Days>\
+             </DeleteRetentionPolicy></StorageServiceProperties>",
+            cors_rule
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 202);
+
+    // Nothing bleeds into the other account
+    assert_eq!(get("second").await, defaults);
+
+    // Omitted elements keep their values
+    let response = set(
+        &server.account,
+        "<StorageServiceProperties><DefaultServiceVersion>2021-10-04</DefaultServiceVersion></StorageServiceProperties>",
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 202);
+    let body = get(&server.account).await;
+    assert!(body.contains("<AllowedOrigins>*</AllowedOrigins>"), "{}", body);
+    assert!(body.contains("<Enabled>true</Enabled><Days>7</Days>"), "{}", body);
+    assert!(body.contains("<DefaultServiceVersion>2021-10-04</DefaultServiceVersion>"), "{}", body);
+
+    // An empty Cors element removes the rules
+    let response = set(&server.account, "<StorageServiceProperties><Cors /></StorageServiceProperties>")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let body = get(&server.account).await;
+    assert!(!body.contains("<CorsRule>"), "{}", body);
+    assert!(body.contains("<Enabled>true</Enabled><Days>7</Days>"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_set_service_properties_partial_document() {
+    let server = TestServer::start().await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/{}?restype=service&comp=properties", server.base_url, server.account);
+    let set = |body: &str| {
+        client
+            .put(&url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .body(body.to_string())
+            .send()
+    };
+
+    let response = set(
+        "<StorageServiceProperties><Logging><Version>1.0</Version><Read>true</Read><Write>false</Write>\
+         <Delete>false</Delete><RetentionPolicy><Enabled>false</Enabled></RetentionPolicy></Logging>\
+         <Cors><CorsRule><AllowedOrigins>https://example.com</AllowedOrigins><AllowedMethods>GET</AllowedMethods>\
+         <AllowedHeaders>*</AllowedHeaders><ExposedHeaders>*</ExposedHeaders>\
+         <MaxAgeInSeconds>60</MaxAgeInSeconds></CorsRule></Cors></StorageServiceProperties>",
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 202);
+
+    let response = set(
+        "<StorageServiceProperties><DeleteRetentionPolicy><Enabled>true</Enabled><Days>3</Days>\
+         </DeleteRetentionPolicy></StorageServiceProperties>",
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 202);
+
+    let body = client
+        .get(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("<AllowedOrigins>https://example.com</AllowedOrigins>"), "{}", body);
+    assert!(body.contains("<Read>true</Read>"), "{}", body);
+    assert!(body.contains("<DeleteRetentionPolicy><Enabled>true</Enabled><Days>3</Days>"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_public_access_levels() {
+    let server = TestServer::start_private(BlobServerBuilder::new()).await;
+    let fixtures = &server.fixtures;
+
+    for (name, level) in [
+        ("private", PublicAccessLevel::None),
+        ("blob-level", PublicAccessLevel::Blob),
+        ("container-level", PublicAccessLevel::Container),
+    ] {
+        let mut container = fixtures.seed_container(&server.account, name).await.unwrap();
+        container.properties.public_access = level;
+        fixtures.metadata().update_container(container).await.unwrap();
+        fixtures
+            .seed_blob(&server.account, name, "public.txt", "hello".into(), BlobProperties::default())
+            .await
+            .unwrap();
+    }
+
+    let client = reqwest::Client::new();
+    let get = |url: String| {
+        client
+            .get(url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .send()
+    };
+
+    // Private containers grant anonymous requests nothing
+    let response = get(server.blob_url("private", "public.txt")).await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.headers().get("x-ms-error-code").unwrap(), "ResourceNotFound");
+    let response = get(format!("{}?restype=container&comp=list", server.container_url("private"))).await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    // Blob-level access grants blob reads only, and hides the container
+    let response = get(server.blob_url("blob-level", "public.txt")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "hello");
+    let response = get(format!("{}?restype=container&comp=list", server.container_url("blob-level"))).await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.headers().get("x-ms-error-code").unwrap(), "ResourceNotFound");
+    let response = get(format!("{}?restype=container", server.container_url("blob-level"))).await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    // Container-level access also grants listing and container properties
+    let response = get(format!("{}?restype=container&comp=list", server.container_url("container-level"))).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("<Name>public.txt</Name>"));
+    let response = get(format!("{}?restype=container", server.container_url("container-level"))).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    // No level grants anonymous writes
+    for name in ["private", "container-level"] {
+        let response = client
+            .put(server.blob_url(name, "public.txt"))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .header("x-ms-blob-type", "BlockBlob")
+            .body("overwritten")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404, "{}", name);
+    }
+
+    // Loose mode opens private containers to anonymous requests
+    let server = TestServer::start_private(BlobServerBuilder::new().loose(true)).await;
+    server.fixtures.seed_container(&server.account, "private").await.unwrap();
+    server
+        .fixtures
+        .seed_blob(&server.account, "private", "public.txt", "hello".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let response = get(server.blob_url("private", "public.txt")).await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_container_stats_headers() {
+    let client = reqwest::Client::new();
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let send = |method: reqwest::Method, url: String, headers: &[(&'static str, &'static str)], body: &'static str| {
+        let mut request = client
+            .request(method, url)
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", date());
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(body).send()
+    };
+
+    // Off by default
+    let server = TestServer::start().await;
+    server.fixtures.seed_container(&server.account, "plain").await.unwrap();
+    let url = format!("{}?restype=container", server.container_url("plain"));
+    let response = send(reqwest::Method::HEAD, url, &[], "").await.unwrap();
+    assert!(response.headers().get("x-azurite-blob-count").is_none());
+
+    let server = TestServer::start_with(BlobServerBuilder::new().container_stats_headers(true).admin(true)).await;
+    server.fixtures.seed_container(&server.account, "counted").await.unwrap();
+    let stats = || async {
+        let url = format!("{}?restype=container", server.container_url("counted"));
+        let response = send(reqwest::Method::HEAD, url, &[], "").await.unwrap();
+        let header = |name: &str| response.headers()[name].to_str().unwrap().parse::<u64>().unwrap();
+        (header("x-azurite-blob-count"), header("x-azurite-blob-bytes"))
+    };
+    let block = [("x-ms-blob-type", "BlockBlob")];
+    assert_eq!(stats().await, (0, 0));
+
+    send(reqwest::Method::PUT, server.blob_url("counted", "a.txt"), &block, "hello").await.unwrap();
+    send(reqwest::Method::PUT, server.blob_url("counted", "b.txt"), &block, "world!").await.unwrap();
+    assert_eq!(stats().await, (2, 11));
+
+    // Overwrites replace the bytes, snapshots are not counted
+    send(reqwest::Method::PUT, server.blob_url("counted", "a.txt"), &block, "hi").await.unwrap();
+    let url = format!("{}?comp=snapshot", server.blob_url("counted", "a.txt"));
+    assert_eq!(send(reqwest::Method::PUT, url, &[], "").await.unwrap().status(), 201);
+    assert_eq!(stats().await, (2, 8));
+
+    // Appends grow the count in place
+    let append_url = server.blob_url("counted", "log.txt");
+    send(reqwest::Method::PUT, append_url.clone(), &[("x-ms-blob-type", "AppendBlob")], "").await.unwrap();
+    let response = send(reqwest::Method::PUT, format!("{}?comp=appendblock", append_url), &[], "12345").await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(stats().await, (3, 13));
+
+    send(reqwest::Method::DELETE, server.blob_url("counted", "b.txt"), &[], "").await.unwrap();
+    assert_eq!(stats().await, (2, 7));
+
+    let admin: serde_json::Value = client
+        .get(format!("{}/__admin/stats", server.base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        admin["containers"][&server.account]["counted"],
+        serde_json::json!({"blobs": 2, "bytes": 7})
+    );
+}
+
+#[tokio::test]
+async fn test_admin_stats_endpoint() {
+    let client = reqwest::Client::new();
+
+    // Disabled by default
+    let server = TestServer::start().await;
+    let response = client.get(format!("{}/__admin/stats", server.base_url)).send().await.unwrap();
+    assert_ne!(response.status(), 200);
+
+    let server = TestServer::start_with(BlobServerBuilder::new().admin(true)).await;
+    let fixtures = &server.fixtures;
+    fixtures.seed_container(&server.account, "stats").await.unwrap();
+    fixtures.seed_container(&server.account, "empty").await.unwrap();
+    for name in ["a.txt", "b.txt"] {
+        fixtures
+            .seed_blob(&server.account, "stats", name, "hello".into(), BlobProperties::default())
+            .await
+            .unwrap();
+    }
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let response = client
+        .put(format!("{}?comp=snapshot", server.blob_url("stats", "a.txt")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client
+        .put(format!("{}?comp=block&blockid=YmxvY2sx", server.blob_url("stats", "c.txt")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("staged")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // No credentials needed
+    let response = client.get(format!("{}/__admin/stats", server.base_url)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        stats["accounts"][&server.account],
+        serde_json::json!({"containers": 2, "blobs": 2, "snapshots": 1, "stagedBlocks": 1, "bytes": 15})
+    );
+    assert_eq!(stats["extents"], serde_json::json!({"extents": 3, "bytes": 16, "missingReads": 0}));
+
+    // Counters follow deletes
+    let response = client
+        .delete(format!("{}?restype=container", server.container_url("stats")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let stats: serde_json::Value = client
+        .get(format!("{}/__admin/stats", server.base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        stats["accounts"][&server.account],
+        serde_json::json!({"containers": 1, "blobs": 0, "snapshots": 0, "stagedBlocks": 0, "bytes": 0})
+    );
+}
+
+#[tokio::test]
+async fn test_missing_extents_are_reported() {
+    let server = TestServer::start_with(BlobServerBuilder::new().admin(true)).await;
+    let client = reqwest::Client::new();
+    let fixtures = &server.fixtures;
+    let date = || chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    fixtures.seed_container(&server.account, "broken").await.unwrap();
+    let blob = fixtures
+        .seed_blob(&server.account, "broken", "lost.txt", "hello".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let response = client
+        .put(format!("{}?comp=block&blockid=YmxvY2sx", server.blob_url("broken", "staged.txt")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("staged")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client.get(format!("{}/__admin/verify", server.base_url)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.json::<serde_json::Value>().await.unwrap(), serde_json::json!([]));
+
+    // Remove both extents behind the stores' backs
+    let extents = fixtures.extents();
+    let lost_id = blob.extent_chunks[0].id.clone();
+    let block = &fixtures.metadata().get_staged_blocks(&server.account, "broken", "staged.txt").await.unwrap()[0];
+    let block_extent_id = block.extent_chunk.id.clone();
+    extents.delete(&lost_id).await.unwrap();
+    extents.delete(&block_extent_id).await.unwrap();
+
+    let response = client
+        .get(server.blob_url("broken", "lost.txt"))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 500);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<Code>InternalError</Code>"));
+    assert!(body.contains("broken/lost.txt"));
+    assert!(body.contains(&lost_id));
+    assert_eq!(extents.stats().await.missing_reads, 1);
+
+    // Committing the dangling block is refused and leaves no blob
+    let response = client
+        .put(format!("{}?comp=blocklist", server.blob_url("broken", "staged.txt")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", date())
+        .body("<?xml version=\"1.0\" encoding=\"utf-8\"?><BlockList><Latest>YmxvY2sx</Latest></BlockList>")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 500);
+    assert!(response.text().await.unwrap().contains(&block_extent_id));
+    assert!(fixtures.blob(&server.account, "broken", "staged.txt").await.is_err());
+
+    let dangling = fixtures.verify().await.unwrap();
+    assert_eq!(dangling.len(), 2);
+    assert_eq!(dangling[0].blob, "lost.txt");
+    assert_eq!(dangling[0].block_id, None);
+    assert_eq!(dangling[0].extent_id, lost_id);
+    assert_eq!(dangling[1].blob, "staged.txt");
+    assert_eq!(dangling[1].block_id.as_deref(), Some("YmxvY2sx"));
+
+    let response = client.get(format!("{}/__admin/verify", server.base_url)).send().await.unwrap();
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report[0]["extentId"], serde_json::json!(lost_id));
+    assert_eq!(report[1]["blockId"], "YmxvY2sx");
+    let response = client.get(format!("{}/__admin/stats", server.base_url)).send().await.unwrap();
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["extents"]["missingReads"], 1);
+}
+
+#[tokio::test]
+async fn test_missing_page_blob_extent_is_reported_once() {
+    let server = TestServer::start().await;
+    let client = reqwest::Client::new();
+    let fixtures = &server.fixtures;
+    fixtures.seed_container(&server.account, "disks").await.unwrap();
+    let blob_url = server.blob_url("disks", "disk.vhd");
+    let response = client
+        .put(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-blob-type", "PageBlob")
+        .header("x-ms-blob-content-length", "1024")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = client
+        .put(format!("{}?comp=page", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-range", "bytes=0-511")
+        .header("x-ms-page-write", "update")
+        .body(vec![7; 512])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // The blob references the extent both as a chunk and from a page range
+    let blob = fixtures.blob(&server.account, "disks", "disk.vhd").await.unwrap();
+    let extent_id = blob.page_ranges[0].extent_chunk.as_ref().unwrap().id.clone();
+    assert!(blob.extent_chunks.iter().any(|chunk| chunk.id == extent_id));
+    fixtures.extents().delete(&extent_id).await.unwrap();
+
+    let dangling = fixtures.verify().await.unwrap();
+    assert_eq!(dangling.len(), 1, "{:?}", dangling);
+    assert_eq!(dangling[0].extent_id, extent_id);
+}
+
+#[tokio::test]
+async fn test_deterministic_request_ids_snapshot() {
+    let time = chrono::DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+    let builder = || {
+        BlobServerBuilder::new()
+            .clock(Arc::new(MockClock::new(time)))
+            .deterministic_request_ids(7)
+    };
+    let client = reqwest::Client::new();
+
+    // Status, sorted headers and body of a service properties read, a miss
+    // and a blob write and read, whose ETags and times follow the clock
+    let transcript = |server: &TestServer| {
+        let requests = [
+            client.get(format!("{}/{}?restype=service&comp=properties", server.base_url, server.account)),
+            client.get(server.blob_url("missing", "a.txt")),
+            client.put(format!("{}?restype=container", server.container_url("golden"))),
+            client.put(server.blob_url("golden", "a.txt")).header("x-ms-blob-type", "BlockBlob").body("hello"),
+            client.get(server.blob_url("golden", "a.txt")),
+        ];
+        async move {
+            let mut transcript = String::new();
+            for request in requests {
+                let response = request.header("x-ms-version", "2021-10-04").send().await.unwrap();
+                transcript.push_str(&format!("{}\n", response.status()));
+                let mut headers: Vec<String> = response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}\n", name, value.to_str().unwrap()))
+                    .collect();
+                headers.sort();
+                transcript.extend(headers);
+                transcript.push_str(&format!("\n{}\n\n", response.text().await.unwrap()));
+            }
+            transcript
+        }
+    };
+
+    let server = TestServer::start_with(builder()).await;
+    let first = transcript(&server).await;
+    let other = TestServer::start_with(builder()).await;
+    assert_eq!(transcript(&other).await, first);
+    assert_eq!(first, include_str!("snapshots/deterministic_responses.txt"));
+}
@@ -0,0 +1,99 @@
+//! Garbage collection tests, driven with a mock clock.
+
+use std::sync::Arc;
+
+use chrono::Duration;
+use azurite_rs::models::{BlobProperties, BlockModel, DeleteRetentionPolicy, LeaseState, LeaseStatus};
+use azurite_rs::storage::{GcStats, UNCOMMITTED_BLOCK_RETENTION};
+use azurite_rs::testing::MockClock;
+use azurite_rs::{BlobServerBuilder, Clock, ExtentStore, MemoryExtentStore, DEFAULT_ACCOUNT};
+
+#[tokio::test]
+async fn test_gc_purges_expired_data_and_settles_leases() {
+    let clock = Arc::new(MockClock::default());
+    let extents = Arc::new(MemoryExtentStore::new());
+    let server = BlobServerBuilder::new().clock(clock.clone()).extents(extents.clone()).build();
+    let fixtures = server.fixtures();
+    let metadata = fixtures.metadata();
+    let gc = server.garbage_collector();
+    let now = clock.now();
+
+    // The account keeps soft-deleted data for a day
+    let mut properties = metadata.get_service_properties(DEFAULT_ACCOUNT).await.unwrap();
+    properties.delete_retention_policy = DeleteRetentionPolicy {
+        enabled: true,
+        days: Some(1),
+        allow_permanent_delete: false,
+    };
+    metadata.set_service_properties(DEFAULT_ACCOUNT, properties).await.unwrap();
+
+    // A container deleted 23 hours ago, so its retention ends in an hour
+    fixtures.seed_container(DEFAULT_ACCOUNT, "trash").await.unwrap();
+    fixtures
+        .seed_blob(DEFAULT_ACCOUNT, "trash", "old.txt", "old".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    let mut container = fixtures.container(DEFAULT_ACCOUNT, "trash").await.unwrap();
+    container.deleted = true;
+    container.deleted_time = Some(now - Duration::hours(23));
+    metadata.update_container(container).await.unwrap();
+
+    // A blob deleted 22 hours ago, a blob with a breaking lease and staged
+    // blocks
+    fixtures.seed_container(DEFAULT_ACCOUNT, "live").await.unwrap();
+    let mut deleted = fixtures
+        .seed_blob(DEFAULT_ACCOUNT, "live", "deleted.txt", "gone".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    deleted.deleted = true;
+    deleted.deleted_time = Some(now - Duration::hours(22));
+    metadata.update_blob(deleted).await.unwrap();
+
+    let mut leased = fixtures
+        .seed_blob(DEFAULT_ACCOUNT, "live", "leased.txt", "kept".into(), BlobProperties::default())
+        .await
+        .unwrap();
+    leased.properties.lease_state = LeaseState::Breaking;
+    leased.properties.lease_status = LeaseStatus::Locked;
+    leased.properties.lease_id = Some("lease".to_string());
+    leased.properties.lease_break_time = Some(now + Duration::seconds(30));
+    metadata.update_blob(leased).await.unwrap();
+
+    for (i, block_id) in ["YQ==", "Yg=="].into_iter().enumerate() {
+        let chunk = extents.write(vec![i as u8; 8].into()).await.unwrap();
+        let block = BlockModel::new(
+            DEFAULT_ACCOUNT.to_string(),
+            "live".to_string(),
+            "staged.txt".to_string(),
+            block_id.to_string(),
+            8,
+            chunk,
+            now,
+        );
+        metadata.stage_block(block).await.unwrap();
+    }
+    assert_eq!(extents.stats().await.extents, 5);
+
+    // Nothing is due yet
+    assert_eq!(gc.run_once().await.unwrap(), GcStats::default());
+
+    clock.advance(Duration::minutes(1));
+    assert_eq!(gc.run_once().await.unwrap(), GcStats { leases: 1, ..GcStats::default() });
+    let leased = fixtures.blob(DEFAULT_ACCOUNT, "live", "leased.txt").await.unwrap();
+    assert_eq!(leased.properties.lease_state, LeaseState::Broken);
+    assert_eq!(leased.properties.lease_id, None);
+
+    clock.advance(Duration::hours(2));
+    assert_eq!(gc.run_once().await.unwrap(), GcStats { containers: 1, blobs: 1, ..GcStats::default() });
+    assert!(metadata.get_container(DEFAULT_ACCOUNT, "trash").await.is_err());
+    assert!(!metadata.blob_exists(DEFAULT_ACCOUNT, "live", "deleted.txt", "").await);
+    assert_eq!(extents.stats().await.extents, 3);
+
+    clock.advance(UNCOMMITTED_BLOCK_RETENTION);
+    assert_eq!(gc.run_once().await.unwrap(), GcStats { blocks: 2, ..GcStats::default() });
+    assert!(metadata.get_staged_blocks(DEFAULT_ACCOUNT, "live", "staged.txt").await.unwrap().is_empty());
+    assert_eq!(extents.stats().await.extents, 1);
+    assert_eq!(fixtures.blob_data(DEFAULT_ACCOUNT, "live", "leased.txt").await.unwrap(), "kept");
+
+    assert_eq!(gc.totals(), GcStats { containers: 1, blobs: 1, blocks: 2, leases: 1 });
+}
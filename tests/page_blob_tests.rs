@@ -0,0 +1,479 @@
+//! Page blob operation tests.
+
+mod common;
+
+use azurite_rs::ExtentStore;
+use common::TestServer;
+
+async fn create_container(server: &TestServer, name: &str) {
+    let client = reqwest::Client::new();
+    let url = format!("{}?restype=container", server.container_url(name));
+    client
+        .put(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+}
+
+async fn create_page_blob(
+    client: &reqwest::Client,
+    url: &str,
+    size: &str,
+    extra_headers: &[(&str, &str)],
+    body: &'static str,
+) -> reqwest::Response {
+    let mut request = client
+        .put(url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "PageBlob")
+        .header("x-ms-blob-content-length", size);
+    for (name, value) in extra_headers {
+        request = request.header(*name, *value);
+    }
+    request.body(body).send().await.unwrap()
+}
+
+fn error_code(response: &reqwest::Response) -> Option<&str> {
+    response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn test_create_page_blob_validation() {
+    let server = TestServer::start().await;
+    create_container(&server, "pagecreate").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("pagecreate", "disk.vhd");
+
+    // Data must be written with Put Page, not on create
+    let response = create_page_blob(&client, &blob_url, "1024", &[], "unexpected").await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(error_code(&response), Some("InvalidOperation"));
+
+    // 8 TiB is the largest page blob
+    let response = create_page_blob(&client, &blob_url, "8796093022208", &[], "").await;
+    assert_eq!(response.status(), 201);
+    let response = create_page_blob(&client, &blob_url, "8796093023232", &[], "").await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(error_code(&response), Some("InvalidHeaderValue"));
+
+    // Sequence numbers are limited to 0..=2^63-1
+    let response = create_page_blob(
+        &client,
+        &blob_url,
+        "1024",
+        &[("x-ms-blob-sequence-number", "9223372036854775807")],
+        "",
+    )
+    .await;
+    assert_eq!(response.status(), 201);
+    for sequence_number in ["9223372036854775808", "-1", "abc"] {
+        let response = create_page_blob(
+            &client,
+            &blob_url,
+            "1024",
+            &[("x-ms-blob-sequence-number", sequence_number)],
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), 400, "sequence number {}", sequence_number);
+        assert_eq!(error_code(&response), Some("InvalidHeaderValue"));
+    }
+}
+
+async fn put_pages(client: &reqwest::Client, url: &str, start: u64, data: Vec<u8>) -> reqwest::Response {
+    let end = start + data.len() as u64 - 1;
+    client
+        .put(format!("{}?comp=page", url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-page-write", "update")
+        .header("x-ms-range", format!("bytes={}-{}", start, end))
+        .body(data)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn resize(client: &reqwest::Client, url: &str, size: u64) -> reqwest::Response {
+    client
+        .put(format!("{}?comp=properties", url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-content-length", size.to_string())
+        .body("")
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_page_list(client: &reqwest::Client, url: &str) -> String {
+    let response = client
+        .get(format!("{}?comp=pagelist", url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_shrink_page_blob_discards_pages() {
+    let server = TestServer::start().await;
+    create_container(&server, "pageshrink").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("pageshrink", "disk.vhd");
+
+    let response = create_page_blob(&client, &blob_url, "4096", &[], "").await;
+    assert_eq!(response.status(), 201);
+    let response = put_pages(&client, &blob_url, 0, vec![b'a'; 4096]).await;
+    assert_eq!(response.status(), 201);
+    assert_eq!(server.extents.total_size().await, 4096);
+
+    let response = resize(&client, &blob_url, 1024).await;
+    assert_eq!(response.status(), 200);
+    let page_list = get_page_list(&client, &blob_url).await;
+    assert!(page_list.contains("<PageRange><Start>0</Start><End>1023</End></PageRange>"), "{}", page_list);
+
+    let response = resize(&client, &blob_url, 4096).await;
+    assert_eq!(response.status(), 200);
+    let page_list = get_page_list(&client, &blob_url).await;
+    assert_eq!(page_list.matches("<PageRange>").count(), 1, "{}", page_list);
+    assert!(page_list.contains("<End>1023</End>"), "{}", page_list);
+
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let data = response.bytes().await.unwrap();
+    assert_eq!(data.len(), 4096);
+    assert!(data[..1024].iter().all(|&b| b == b'a'));
+    assert!(data[1024..].iter().all(|&b| b == 0), "tail must read as zeros");
+
+    // Rewriting a page inside the kept range splits it
+    let response = put_pages(&client, &blob_url, 512, vec![b'b'; 512]).await;
+    assert_eq!(response.status(), 201);
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-range", "bytes=0-2047")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 206);
+    let data = response.bytes().await.unwrap();
+    assert!(data[..512].iter().all(|&b| b == b'a'));
+    assert!(data[512..1024].iter().all(|&b| b == b'b'));
+    assert!(data[1024..].iter().all(|&b| b == 0));
+}
+
+#[tokio::test]
+async fn test_get_sparse_page_blob_streams_zeros() {
+    let server = TestServer::start().await;
+    create_container(&server, "pagesparse").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("pagesparse", "disk.vhd");
+
+    // The largest page blob, with one page written after a megabyte of zeros
+    let response = create_page_blob(&client, &blob_url, "8796093022208", &[], "").await;
+    assert_eq!(response.status(), 201);
+    let response = put_pages(&client, &blob_url, 1024 * 1024, vec![b'a'; 512]).await;
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-range", "bytes=1048000-1049599")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 206);
+    let data = response.bytes().await.unwrap();
+    assert_eq!(data.len(), 1600);
+    assert!(data[..576].iter().all(|&b| b == 0));
+    assert!(data[576..1088].iter().all(|&b| b == b'a'));
+    assert!(data[1088..].iter().all(|&b| b == 0));
+
+    // A full read is sent as it is consumed, not allocated up front
+    let mut response = client
+        .get(&blob_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.content_length(), Some(8796093022208));
+    let mut received = Vec::new();
+    while received.len() < 1024 * 1024 + 512 {
+        received.extend_from_slice(&response.chunk().await.unwrap().unwrap());
+    }
+    assert!(received[..1024 * 1024].iter().all(|&b| b == 0));
+    assert!(received[1024 * 1024..1024 * 1024 + 512].iter().all(|&b| b == b'a'));
+}
+
+#[tokio::test]
+async fn test_get_page_ranges_scoped_and_paginated() {
+    let server = TestServer::start().await;
+    create_container(&server, "pagelist").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("pagelist", "sparse.vhd");
+
+    create_page_blob(&client, &blob_url, "8192", &[], "").await;
+    for start in [0, 1024, 4096] {
+        let response = put_pages(&client, &blob_url, start, vec![b'x'; 512]).await;
+        assert_eq!(response.status(), 201);
+    }
+
+    let get = |query: &'static str, range: Option<&'static str>| {
+        let mut request = client
+            .get(format!("{}?comp=pagelist{}", blob_url, query))
+            .header("x-ms-version", "2021-10-04")
+            .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        if let Some(range) = range {
+            request = request.header("x-ms-range", range);
+        }
+        async move { request.send().await.unwrap() }
+    };
+
+    // First page stops after two ranges
+    let body = get("&maxresults=2", None).await.text().await.unwrap();
+    assert_eq!(body.matches("<PageRange>").count(), 2, "{}", body);
+    assert!(body.contains("<NextMarker>4096</NextMarker>"), "{}", body);
+
+    // Resuming from the marker returns the rest without another marker
+    let body = get("&maxresults=2&marker=4096", None).await.text().await.unwrap();
+    assert_eq!(body.matches("<PageRange>").count(), 1, "{}", body);
+    assert!(body.contains("<Start>4096</Start>"), "{}", body);
+    assert!(!body.contains("<NextMarker>"), "{}", body);
+
+    // A Range header clips the results to the window
+    let body = get("", Some("bytes=512-1279")).await.text().await.unwrap();
+    assert_eq!(body.matches("<PageRange>").count(), 1, "{}", body);
+    assert!(body.contains("<Start>1024</Start><End>1279</End>"), "{}", body);
+
+    let response = get("&marker=bogus", None).await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(error_code(&response), Some("InvalidQueryParameterValue"));
+
+    // Diff against a snapshot reports changed and cleared pages
+    let response = client
+        .put(format!("{}?comp=snapshot", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    let snapshot = response.headers().get("x-ms-snapshot").unwrap().to_str().unwrap().to_string();
+
+    put_pages(&client, &blob_url, 0, vec![b'y'; 512]).await;
+    let response = client
+        .put(format!("{}?comp=page", blob_url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-page-write", "clear")
+        .header("x-ms-range", "bytes=1024-1535")
+        .body("")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(format!("{}?comp=pagelist", blob_url))
+        .query(&[("prevsnapshot", snapshot.as_str()), ("maxresults", "1")])
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<PageRange><Start>0</Start><End>511</End></PageRange>"), "{}", body);
+    assert!(body.contains("<NextMarker>1024</NextMarker>"), "{}", body);
+
+    let response = client
+        .get(format!("{}?comp=pagelist", blob_url))
+        .query(&[("prevsnapshot", snapshot.as_str()), ("marker", "1024")])
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<ClearRange><Start>1024</Start><End>1535</End></ClearRange>"), "{}", body);
+    assert!(!body.contains("<PageRange>"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_snapshot_page_ranges() {
+    let server = TestServer::start().await;
+    create_container(&server, "pagesnap").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("pagesnap", "disk.vhd");
+    assert_eq!(create_page_blob(&client, &blob_url, "4096", &[], "").await.status(), 201);
+
+    let snapshot = || async {
+        let response = client
+            .put(format!("{}?comp=snapshot", blob_url))
+            .header("x-ms-version", "2021-10-04")
+            .body("")
+            .send()
+            .await
+            .unwrap();
+        response.headers()["x-ms-snapshot"].to_str().unwrap().to_string()
+    };
+    let page_list = |query: Vec<(&'static str, String)>| {
+        let request = client
+            .get(format!("{}?comp=pagelist", blob_url))
+            .query(&query)
+            .header("x-ms-version", "2021-10-04");
+        async move { request.send().await.unwrap().text().await.unwrap() }
+    };
+
+    put_pages(&client, &blob_url, 0, vec![b'a'; 512]).await;
+    let first = snapshot().await;
+    put_pages(&client, &blob_url, 1024, vec![b'b'; 512]).await;
+    let second = snapshot().await;
+    put_pages(&client, &blob_url, 0, vec![b'c'; 512]).await;
+    put_pages(&client, &blob_url, 2048, vec![b'd'; 512]).await;
+
+    // Each snapshot lists the pages written before it was taken
+    let body = page_list(vec![("snapshot", first.clone())]).await;
+    assert!(body.contains("<PageRange><Start>0</Start><End>511</End></PageRange>"), "{}", body);
+    assert!(!body.contains("<Start>1024</Start>"), "{}", body);
+    let body = page_list(vec![("snapshot", second.clone())]).await;
+    assert!(body.contains("<PageRange><Start>1024</Start><End>1535</End></PageRange>"), "{}", body);
+    assert!(!body.contains("<Start>2048</Start>"), "{}", body);
+
+    // Diffs compare the two snapshots named, not the base blob
+    let body = page_list(vec![("snapshot", second.clone()), ("prevsnapshot", first.clone())]).await;
+    assert!(body.contains("<PageRange><Start>1024</Start><End>1535</End></PageRange>"), "{}", body);
+    assert!(!body.contains("<Start>0</Start>"), "{}", body);
+    assert!(!body.contains("<Start>2048</Start>"), "{}", body);
+
+    // Overwritten pages keep their snapshot content
+    let response = client
+        .get(&blob_url)
+        .query(&[("snapshot", first.as_str())])
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-range", "bytes=0-511")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.bytes().await.unwrap().as_ref(), &[b'a'; 512][..]);
+}
+
+async fn set_tier(client: &reqwest::Client, url: &str, tier: &str) -> reqwest::Response {
+    client
+        .put(format!("{}?comp=tier", url))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-access-tier", tier)
+        .body("")
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_premium_page_blob_tiers() {
+    let server = TestServer::start().await;
+    create_container(&server, "premium").await;
+
+    let client = reqwest::Client::new();
+    let disk_url = server.blob_url("premium", "disk.vhd");
+    let response = create_page_blob(&client, &disk_url, "1024", &[], "").await;
+    assert_eq!(response.status(), 201);
+
+    // A standard page blob reports no tier until a premium one is set
+    let response = client
+        .head(&disk_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert!(response.headers().get("x-ms-access-tier").is_none());
+
+    let response = set_tier(&client, &disk_url, "Hot").await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(error_code(&response), Some("InvalidBlobTier"));
+
+    let response = set_tier(&client, &disk_url, "P10").await;
+    assert_eq!(response.status(), 200);
+    let response = client
+        .head(&disk_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("x-ms-access-tier").unwrap(), "P10");
+
+    let response = client
+        .get(format!("{}?restype=container&comp=list", server.container_url("premium")))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+    assert!(response.text().await.unwrap().contains("<AccessTier>P10</AccessTier>"));
+
+    // Premium tiers only go up
+    let response = set_tier(&client, &disk_url, "P4").await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(error_code(&response), Some("CannotChangeToLowerTier"));
+    assert_eq!(set_tier(&client, &disk_url, "P20").await.status(), 200);
+
+    // A tier must hold the blob: P4 is 32 GiB
+    let big_url = server.blob_url("premium", "big.vhd");
+    let response = create_page_blob(&client, &big_url, "34359738880", &[], "").await;
+    assert_eq!(response.status(), 201);
+    let response = set_tier(&client, &big_url, "P4").await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(error_code(&response), Some("BlobTierInadequateForContentLength"));
+    assert_eq!(set_tier(&client, &big_url, "P6").await.status(), 200);
+    let response = resize(&client, &big_url, 128 << 30).await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(error_code(&response), Some("BlobTierInadequateForContentLength"));
+
+    // Premium tiers do not apply to block blobs
+    let block_url = server.blob_url("premium", "block.txt");
+    let response = client
+        .put(&block_url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = set_tier(&client, &block_url, "P10").await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(error_code(&response), Some("InvalidBlobTier"));
+
+    // Archiving an archived blob changes nothing
+    assert_eq!(set_tier(&client, &block_url, "Archive").await.status(), 200);
+    let archived = server.fixtures.blob(&server.account, "premium", "block.txt").await.unwrap();
+    assert_eq!(set_tier(&client, &block_url, "Archive").await.status(), 200);
+    let unchanged = server.fixtures.blob(&server.account, "premium", "block.txt").await.unwrap();
+    assert_eq!(unchanged.properties.etag, archived.properties.etag);
+}
@@ -195,3 +195,18 @@ def test_batch_delete_then_recreate(container_client: ContainerClient):
         assert _blob_exists(container_client, name)
         downloaded = container_client.get_blob_client(name).download_blob().readall()
         assert downloaded == b"new content"
+
+
+def test_batch_delete_reports_per_item_errors(container_client: ContainerClient):
+    """Test that per-item batch results carry their own status and error code."""
+    _upload_blobs(container_client, ["item-0.txt", "item-2.txt"])
+
+    responses = list(
+        container_client.delete_blobs(
+            "item-0.txt", "item-1.txt", "item-2.txt", raise_on_any_failure=False
+        )
+    )
+
+    assert [r.status_code for r in responses] == [202, 404, 202]
+    assert responses[1].headers["x-ms-error-code"] == "BlobNotFound"
+    assert "x-ms-error-code" not in responses[0].headers
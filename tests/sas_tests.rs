@@ -0,0 +1,347 @@
+//! SAS authorization tests.
+
+mod common;
+
+use common::TestServer;
+
+async fn create_container(server: &TestServer, name: &str) {
+    let client = reqwest::Client::new();
+    let url = format!("{}?restype=container", server.container_url(name));
+    client
+        .put(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+}
+
+async fn upload_blob(server: &TestServer, container: &str, blob: &str) {
+    let client = reqwest::Client::new();
+    client
+        .put(server.blob_url(container, blob))
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+}
+
+/// Runs download, overwrite, delete and list with a container SAS granting
+/// `permissions`, returning the four status codes.
+async fn run_operations(server: &TestServer, container: &str, permissions: &str) -> [u16; 4] {
+    let client = reqwest::Client::new();
+    let sas = common::create_blob_sas(&server.account, &server.key, container, None, permissions, &[]);
+    let blob_url = server.blob_url(container, "existing.txt");
+
+    let download = client.get(&blob_url).query(&sas).send().await.unwrap();
+    let overwrite = client
+        .put(&blob_url)
+        .query(&sas)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("new data")
+        .send()
+        .await
+        .unwrap();
+    let list = client
+        .get(server.container_url(container))
+        .query(&[("restype", "container"), ("comp", "list")])
+        .query(&sas)
+        .send()
+        .await
+        .unwrap();
+    let delete = client.delete(&blob_url).query(&sas).send().await.unwrap();
+
+    [
+        download.status().as_u16(),
+        overwrite.status().as_u16(),
+        delete.status().as_u16(),
+        list.status().as_u16(),
+    ]
+}
+
+#[tokio::test]
+async fn test_sas_permission_matrix() {
+    let server = TestServer::start().await;
+
+    // (permissions, [download, overwrite, delete, list])
+    let cases = [
+        ("r", [200, 403, 403, 403]),
+        ("w", [403, 201, 403, 403]),
+        ("d", [403, 403, 202, 403]),
+        ("l", [403, 403, 403, 200]),
+    ];
+
+    for (i, (permissions, expected)) in cases.iter().enumerate() {
+        let container = format!("matrix{}", i);
+        create_container(&server, &container).await;
+        upload_blob(&server, &container, "existing.txt").await;
+
+        let statuses = run_operations(&server, &container, permissions).await;
+        assert_eq!(&statuses, expected, "sp={}", permissions);
+    }
+}
+
+#[tokio::test]
+async fn test_sas_container_listing_permissions() {
+    let server = TestServer::start().await;
+    create_container(&server, "listing").await;
+    upload_blob(&server, "listing", "existing.txt").await;
+
+    let client = reqwest::Client::new();
+    let container_url = server.container_url("listing");
+
+    // (blob, permissions, [read, list, filter by tags])
+    let cases = [
+        (None, "r", [200, 403, 403]),
+        (None, "lf", [403, 200, 200]),
+        // A blob SAS never authorizes container-level requests
+        (Some("existing.txt"), "rlf", [200, 403, 403]),
+    ];
+
+    for (blob, permissions, expected) in cases {
+        let sas = common::create_blob_sas(&server.account, &server.key, "listing", blob, permissions, &[]);
+        let read = client.get(server.blob_url("listing", "existing.txt")).query(&sas).send().await.unwrap();
+        let list = client
+            .get(&container_url)
+            .query(&[("restype", "container"), ("comp", "list")])
+            .query(&sas)
+            .send()
+            .await
+            .unwrap();
+        let filter = client
+            .get(&container_url)
+            .query(&[("restype", "container"), ("comp", "blobs"), ("where", "\"k\"='v'")])
+            .query(&sas)
+            .send()
+            .await
+            .unwrap();
+
+        if blob.is_some() {
+            assert_eq!(list.headers()["x-ms-error-code"], "AuthorizationResourceTypeMismatch");
+        }
+        let statuses = [read.status().as_u16(), list.status().as_u16(), filter.status().as_u16()];
+        assert_eq!(statuses, expected, "sr={:?} sp={}", blob, permissions);
+    }
+}
+
+#[tokio::test]
+async fn test_sas_create_permission_cannot_overwrite() {
+    let server = TestServer::start().await;
+    create_container(&server, "createonly").await;
+    upload_blob(&server, "createonly", "existing.txt").await;
+
+    let client = reqwest::Client::new();
+    let sas = common::create_blob_sas(&server.account, &server.key, "createonly", None, "c", &[]);
+
+    let response = client
+        .put(server.blob_url("createonly", "new.txt"))
+        .query(&sas)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .put(server.blob_url("createonly", "existing.txt"))
+        .query(&sas)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("AuthorizationPermissionMismatch")
+    );
+}
+
+#[tokio::test]
+async fn test_sas_preferred_over_authorization_header() {
+    let server = TestServer::start().await;
+    create_container(&server, "mixedauth").await;
+    upload_blob(&server, "mixedauth", "existing.txt").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("mixedauth", "existing.txt");
+    let sas = common::create_blob_sas(&server.account, &server.key, "mixedauth", None, "r", &[]);
+
+    // A valid SAS wins over a placeholder Authorization header
+    for authorization in ["", "Bearer placeholder", "SharedKey devstoreaccount1:bogus"] {
+        let response = client
+            .get(&blob_url)
+            .query(&sas)
+            .header("authorization", authorization)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "authorization={:?}", authorization);
+        assert_eq!(response.text().await.unwrap(), "data");
+    }
+
+    // With both invalid, the SAS error is reported
+    let mut forged = sas.clone();
+    forged.retain(|(name, _)| name != "sig");
+    forged.push(("sig".to_string(), "Zm9yZ2Vk".to_string()));
+    let response = client
+        .get(&blob_url)
+        .query(&forged)
+        .header("authorization", "SharedKey devstoreaccount1:bogus")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["x-ms-error-code"], "AuthenticationFailed");
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<AuthenticationMechanism>ServiceSas</AuthenticationMechanism>"), "{}", body);
+
+    let response = client
+        .get(&blob_url)
+        .header("authorization", "SharedKey devstoreaccount1:bogus")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<AuthenticationMechanism>SharedKey</AuthenticationMechanism>"), "{}", body);
+
+    // No credentials at all, for an account anonymous requests cannot use
+    let response = client
+        .get(format!("{}/unknownaccount/mixedauth/existing.txt", server.base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("No credentials were found"), "{}", body);
+    assert!(body.contains("<AuthenticationMechanism>Anonymous</AuthenticationMechanism>"), "{}", body);
+}
+
+/// Extracts the text of the first `<tag>` element.
+fn xml_value(xml: &str, tag: &str) -> String {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open).unwrap() + open.len();
+    let end = xml[start..].find(&close).unwrap() + start;
+    xml[start..end].to_string()
+}
+
+/// Requests a user delegation key valid between `start` and `expiry`.
+async fn get_user_delegation_key(server: &TestServer, start: &str, expiry: &str) -> String {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/{}", server.base_url, server.account))
+        .query(&[("restype", "service"), ("comp", "userdelegationkey")])
+        .header("x-ms-version", "2021-10-04")
+        .body(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><KeyInfo><Start>{}</Start><Expiry>{}</Expiry></KeyInfo>",
+            start, expiry
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response.text().await.unwrap()
+}
+
+/// Builds a user delegation SAS for a blob from a Get User Delegation Key response.
+fn create_user_delegation_sas(
+    key_xml: &str,
+    account: &str,
+    container: &str,
+    blob: &str,
+    permissions: &str,
+) -> Vec<(String, String)> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let expiry = (chrono::Utc::now() + chrono::Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string();
+    let skoid = xml_value(key_xml, "SignedOid");
+    let sktid = xml_value(key_xml, "SignedTid");
+    let skt = xml_value(key_xml, "SignedStart");
+    let ske = xml_value(key_xml, "SignedExpiry");
+    let sks = xml_value(key_xml, "SignedService");
+    let skv = xml_value(key_xml, "SignedVersion");
+    let value = xml_value(key_xml, "Value");
+    let resource = format!("/blob/{}/{}/{}", account, container, blob);
+
+    let string_to_sign = [
+        permissions, "", &expiry, &resource, &skoid, &sktid, &skt, &ske, &sks, &skv, "", "", "",
+        "", "", "2021-10-04", "b", "", "", "", "", "", "", "",
+    ]
+    .join("\n");
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&BASE64.decode(value).unwrap()).unwrap();
+    mac.update(string_to_sign.as_bytes());
+    let signature = BASE64.encode(mac.finalize().into_bytes());
+
+    [
+        ("sv", "2021-10-04"),
+        ("sr", "b"),
+        ("sp", permissions),
+        ("se", &expiry),
+        ("skoid", &skoid),
+        ("sktid", &sktid),
+        ("skt", &skt),
+        ("ske", &ske),
+        ("sks", &sks),
+        ("skv", &skv),
+        ("sig", &signature),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
+
+#[tokio::test]
+async fn test_user_delegation_sas() {
+    let server = TestServer::start().await;
+    create_container(&server, "delegation").await;
+    upload_blob(&server, "delegation", "blob.txt").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = server.blob_url("delegation", "blob.txt");
+    let now = chrono::Utc::now();
+    let format = |dt: chrono::DateTime<chrono::Utc>| dt.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+    // A SAS signed with an issued key is accepted
+    let key_xml = get_user_delegation_key(
+        &server,
+        &format(now - chrono::Duration::minutes(5)),
+        &format(now + chrono::Duration::hours(1)),
+    )
+    .await;
+    let sas = create_user_delegation_sas(&key_xml, &server.account, "delegation", "blob.txt", "r");
+    let response = client.get(&blob_url).query(&sas).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "data");
+
+    // A tampered signature is rejected
+    let mut tampered = sas.clone();
+    tampered.last_mut().unwrap().1 = "AAAA".to_string();
+    let response = client.get(&blob_url).query(&tampered).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // A SAS signed with an expired key is rejected
+    let expired_xml = get_user_delegation_key(
+        &server,
+        &format(now - chrono::Duration::hours(2)),
+        &format(now - chrono::Duration::hours(1)),
+    )
+    .await;
+    let sas = create_user_delegation_sas(&expired_xml, &server.account, "delegation", "blob.txt", "r");
+    let response = client.get(&blob_url).query(&sas).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers().get("x-ms-error-code").map(|v| v.to_str().unwrap()),
+        Some("AuthenticationFailed")
+    );
+}
@@ -0,0 +1,236 @@
+//! State archive export/import tests.
+
+mod common;
+
+use common::TestServer;
+
+async fn create_container(server: &TestServer, name: &str) {
+    let client = reqwest::Client::new();
+    let url = format!("{}?restype=container", server.container_url(name));
+    client
+        .put(&url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .send()
+        .await
+        .unwrap();
+}
+
+fn request(client: &reqwest::Client, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+    client
+        .request(method, url)
+        .header("x-ms-version", "2021-10-04")
+        .header("x-ms-date", chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+async fn list_blobs(client: &reqwest::Client, server: &TestServer, container: &str) -> String {
+    let url = format!(
+        "{}?restype=container&comp=list&include=snapshots,metadata,tags",
+        server.container_url(container)
+    );
+    request(client, reqwest::Method::GET, &url)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_export_import_round_trip() {
+    let source = TestServer::start().await;
+    create_container(&source, "archived").await;
+    create_container(&source, "leased").await;
+
+    let client = reqwest::Client::new();
+    let blob_url = source.blob_url("archived", "dir/a.txt");
+    request(&client, reqwest::Method::PUT, &blob_url)
+        .header("x-ms-blob-type", "BlockBlob")
+        .header("x-ms-meta-owner", "tests")
+        .body("archived content")
+        .send()
+        .await
+        .unwrap();
+    request(&client, reqwest::Method::PUT, &format!("{}?comp=tags", blob_url))
+        .body(r#"<?xml version="1.0" encoding="utf-8"?><Tags><TagSet><Tag><Key>env</Key><Value>dev</Value></Tag></TagSet></Tags>"#)
+        .send()
+        .await
+        .unwrap();
+    let response = request(&client, reqwest::Method::PUT, &format!("{}?comp=snapshot", blob_url))
+        .send()
+        .await
+        .unwrap();
+    let snapshot = response.headers().get("x-ms-snapshot").unwrap().to_str().unwrap().to_string();
+    request(&client, reqwest::Method::PUT, &blob_url)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("updated content")
+        .send()
+        .await
+        .unwrap();
+
+    // An uncommitted block
+    let pending_url = source.blob_url("archived", "pending.txt");
+    request(&client, reqwest::Method::PUT, &format!("{}?comp=block&blockid=YmxvY2sx", pending_url))
+        .body("staged")
+        .send()
+        .await
+        .unwrap();
+
+    // A leased blob
+    let leased_url = source.blob_url("leased", "b.txt");
+    request(&client, reqwest::Method::PUT, &leased_url)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("leased content")
+        .send()
+        .await
+        .unwrap();
+    let response = request(&client, reqwest::Method::PUT, &format!("{}?comp=lease", leased_url))
+        .header("x-ms-lease-action", "acquire")
+        .header("x-ms-lease-duration", "-1")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let leased_etag = response.headers().get("etag").unwrap().to_str().unwrap().to_string();
+
+    let listing = list_blobs(&client, &source, "archived").await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("state.tar.zst");
+    source.fixtures.export_state(&archive).await.unwrap();
+
+    // Restore into a fresh server
+    let target = TestServer::start().await;
+    target.fixtures.import_state(&archive).await.unwrap();
+
+    // Listings differ only in the ServiceEndpoint of each server
+    assert_eq!(
+        list_blobs(&client, &target, "archived").await.replace(&target.base_url, &source.base_url),
+        listing
+    );
+    assert!(listing.contains(&snapshot));
+    assert!(listing.contains("<Key>env</Key>"));
+
+    let response = request(&client, reqwest::Method::GET, &target.blob_url("archived", "dir/a.txt"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "updated content");
+
+    let snapshot_url = format!("{}?snapshot={}", target.blob_url("archived", "dir/a.txt"), snapshot);
+    let response = request(&client, reqwest::Method::GET, &snapshot_url).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "archived content");
+
+    let block_list_url = format!(
+        "{}?comp=blocklist&blocklisttype=uncommitted",
+        target.blob_url("archived", "pending.txt")
+    );
+    let response = request(&client, reqwest::Method::GET, &block_list_url).send().await.unwrap();
+    assert!(response.text().await.unwrap().contains("YmxvY2sx"));
+
+    // Leases are released, other properties kept
+    let response = request(&client, reqwest::Method::HEAD, &target.blob_url("leased", "b.txt"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("etag").unwrap().to_str().unwrap(), leased_etag);
+    assert_eq!(response.headers().get("x-ms-lease-state").unwrap(), "available");
+    assert_eq!(response.headers().get("x-ms-lease-status").unwrap(), "unlocked");
+}
+
+/// Reads the entries of an archive, in order.
+fn archive_entries(path: &std::path::Path) -> Vec<(String, Vec<u8>)> {
+    use std::io::Read;
+
+    let decoder = zstd::Decoder::new(std::fs::File::open(path).unwrap()).unwrap();
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        entries.push((name, data));
+    }
+    entries
+}
+
+fn write_archive(path: &std::path::Path, entries: &[(String, Vec<u8>)]) {
+    let encoder = zstd::Encoder::new(std::fs::File::create(path).unwrap(), 3).unwrap();
+    let mut builder = tar::Builder::new(encoder);
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, &data[..]).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[tokio::test]
+async fn test_import_frees_replaced_and_abandoned_extents() {
+    use azurite_rs::ExtentStore;
+
+    let source = TestServer::start().await;
+    create_container(&source, "archived").await;
+    let client = reqwest::Client::new();
+    let blob_url = source.blob_url("archived", "a.txt");
+    request(&client, reqwest::Method::PUT, &blob_url)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("first")
+        .send()
+        .await
+        .unwrap();
+    request(&client, reqwest::Method::PUT, &format!("{}?comp=snapshot", blob_url))
+        .send()
+        .await
+        .unwrap();
+    request(&client, reqwest::Method::PUT, &blob_url)
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("second")
+        .send()
+        .await
+        .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("state.tar.zst");
+    source.fixtures.export_state(&archive).await.unwrap();
+    let entries = archive_entries(&archive);
+    assert_eq!(entries[0].0, "manifest.json");
+
+    let target = TestServer::start().await;
+    create_container(&target, "archived").await;
+    request(&client, reqwest::Method::PUT, &target.blob_url("archived", "a.txt"))
+        .header("x-ms-blob-type", "BlockBlob")
+        .body("replaced by the import")
+        .send()
+        .await
+        .unwrap();
+    let before = target.extents.total_size().await;
+
+    // An archive whose manifest is not the first entry is rejected unread
+    let shuffled = dir.path().join("shuffled.tar.zst");
+    let mut reordered = entries.clone();
+    reordered.rotate_left(1);
+    write_archive(&shuffled, &reordered);
+    let error = target.fixtures.import_state(&shuffled).await.unwrap_err();
+    assert!(error.to_string().contains("missing manifest"), "{}", error);
+    assert_eq!(target.extents.total_size().await, before);
+
+    // A payload missing from the archive fails the import after the others
+    // were written; those are freed again
+    let truncated = dir.path().join("truncated.tar.zst");
+    write_archive(&truncated, &entries[..entries.len() - 1]);
+    let error = target.fixtures.import_state(&truncated).await.unwrap_err();
+    assert!(error.to_string().contains("missing extent payload"), "{}", error);
+    assert_eq!(target.extents.total_size().await, before);
+
+    // The extent of the replaced blob is freed with the import
+    target.fixtures.import_state(&archive).await.unwrap();
+    assert_eq!(target.extents.total_size().await, ("first".len() + "second".len()) as u64);
+    let data = target.fixtures.blob_data(&target.account, "archived", "a.txt").await.unwrap();
+    assert_eq!(&data[..], b"second");
+}
@@ -0,0 +1,28 @@
+//! The storage conformance suites run against the bundled stores.
+
+use azurite_rs::storage::conformance::{run_extent_store_tests, run_metadata_store_tests};
+use azurite_rs::storage::FsExtentStore;
+use azurite_rs::{MemoryExtentStore, MemoryMetadataStore};
+
+#[tokio::test]
+async fn test_memory_metadata_store_conformance() {
+    run_metadata_store_tests(&MemoryMetadataStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_memory_extent_store_conformance() {
+    run_extent_store_tests(&MemoryExtentStore::new()).await;
+}
+
+#[tokio::test]
+async fn test_spilling_memory_extent_store_conformance() {
+    // Small enough that the larger extents are served from disk
+    let dir = tempfile::tempdir().unwrap();
+    run_extent_store_tests(&MemoryExtentStore::with_spill(64 * 1024, dir.path()).unwrap()).await;
+}
+
+#[tokio::test]
+async fn test_fs_extent_store_conformance() {
+    let dir = tempfile::tempdir().unwrap();
+    run_extent_store_tests(&FsExtentStore::new(dir.path().to_path_buf()).await.unwrap()).await;
+}